@@ -0,0 +1,354 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use jsonpath::Selector;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use serde_json::Value as JsonValue;
+
+use crate::{
+    directive::{Directives, ASSERT},
+    plugin::Plugin,
+    util::parse_duration,
+};
+
+/// An assertion about a response, written in a request file as a line like
+/// `# @assert status == 200` or `# @assert jsonpath $.id exists`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub source: String,
+    subject: Subject,
+    op: Op,
+    expected: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Subject {
+    Status,
+    Time,
+    Body,
+    Header(String),
+    JsonPath(String),
+    Plugin(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Matches,
+    Exists,
+}
+
+/// The parts of a response that assertions are evaluated against
+pub struct ResponseInfo<'a> {
+    pub status: u16,
+    pub headers: &'a HeaderMap,
+    pub body: &'a str,
+    pub elapsed: Duration,
+    /// Plugins that can evaluate `# @assert plugin <name> <args>`
+    pub plugins: &'a [Plugin],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionResult {
+    pub source: String,
+    pub passed: bool,
+    pub message: Option<String>,
+}
+
+/// The assertions of a request
+pub fn assertions(directives: &Directives) -> Result<Vec<Assertion>> {
+    directives
+        .all(ASSERT)
+        .map(|directive| parse_assertion(&directive.value))
+        .collect()
+}
+
+fn parse_assertion(source: &str) -> Result<Assertion> {
+    let (subject, rest) = split_word(source);
+
+    // The plugin decides how its arguments are used
+    if subject == "plugin" {
+        let (name, args) = split_word(rest);
+        if name.is_empty() {
+            bail!("Missing plugin name in assertion: {source}");
+        }
+        return Ok(Assertion {
+            source: source.to_string(),
+            subject: Subject::Plugin(name.to_string()),
+            op: Op::Exists,
+            expected: args.trim().to_string(),
+        });
+    }
+
+    let (subject, rest) = match subject {
+        "status" => (Subject::Status, rest),
+        "time" => (Subject::Time, rest),
+        "body" => (Subject::Body, rest),
+        "header" | "jsonpath" => {
+            let (arg, rest) = split_word(rest);
+            if arg.is_empty() {
+                bail!("Missing {subject} name in assertion: {source}");
+            }
+            let subject = if subject == "header" {
+                Subject::Header(arg.to_lowercase())
+            } else {
+                Selector::new(arg).map_err(|err| {
+                    anyhow::anyhow!("Invalid jsonpath: {err}")
+                })?;
+                Subject::JsonPath(arg.to_string())
+            };
+            (subject, rest)
+        }
+        _ => bail!("Unknown assertion subject: {source}"),
+    };
+
+    let (op, expected) = split_word(rest);
+    let op = match op {
+        "==" => Op::Eq,
+        "!=" => Op::Ne,
+        "<" => Op::Lt,
+        "<=" => Op::Le,
+        ">" => Op::Gt,
+        ">=" => Op::Ge,
+        "contains" => Op::Contains,
+        "matches" => Op::Matches,
+        "exists" => Op::Exists,
+        _ => bail!("Unknown assertion operator: {source}"),
+    };
+
+    let expected = unquote(expected.trim()).to_string();
+    if op != Op::Exists && expected.is_empty() {
+        bail!("Missing expected value in assertion: {source}");
+    }
+    if op == Op::Matches {
+        Regex::new(&expected).context("Invalid regex in assertion")?;
+    }
+
+    Ok(Assertion {
+        source: source.to_string(),
+        subject,
+        op,
+        expected,
+    })
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    match s.find(char::is_whitespace) {
+        Some(pos) => (&s[..pos], &s[pos..]),
+        None => (s, ""),
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+impl Assertion {
+    pub fn evaluate(&self, response: &ResponseInfo) -> AssertionResult {
+        if let Subject::Plugin(name) = &self.subject {
+            return self.evaluate_plugin(name, response);
+        }
+
+        let actual = self.actual(response);
+
+        let passed = match (&actual, self.op) {
+            (None, _) => false,
+            (Some(_), Op::Exists) => true,
+            (Some(actual), op) => compare(actual, op, &self.expected),
+        };
+
+        let message = (!passed).then(|| match &actual {
+            None => "no value found".to_string(),
+            Some(actual) => format!("got {actual}"),
+        });
+
+        AssertionResult {
+            source: self.source.clone(),
+            passed,
+            message,
+        }
+    }
+
+    fn evaluate_plugin(
+        &self,
+        name: &str,
+        response: &ResponseInfo,
+    ) -> AssertionResult {
+        let result = match response.plugins.iter().find(|p| p.name == name) {
+            Some(plugin) => plugin.assert(&self.expected, response),
+            None => Err(anyhow::anyhow!("unknown plugin {name}")),
+        };
+
+        let (passed, message) = match result {
+            Ok((passed, message)) => (passed, message),
+            Err(err) => (false, Some(format!("{err:#}"))),
+        };
+
+        AssertionResult {
+            source: self.source.clone(),
+            passed,
+            message: message.filter(|_| !passed),
+        }
+    }
+
+    fn actual(&self, response: &ResponseInfo) -> Option<String> {
+        match &self.subject {
+            Subject::Status => Some(response.status.to_string()),
+            Subject::Time => Some(response.elapsed.as_millis().to_string()),
+            Subject::Body => Some(response.body.to_string()),
+            Subject::Header(name) => response
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from),
+            Subject::Plugin(_) => None,
+            Subject::JsonPath(path) => {
+                let json: JsonValue =
+                    serde_json::from_str(response.body).ok()?;
+                let selector = Selector::new(path).ok()?;
+                let value = selector.find(&json).next()?;
+                Some(match value {
+                    JsonValue::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+            }
+        }
+    }
+}
+
+fn compare(actual: &str, op: Op, expected: &str) -> bool {
+    // Durations like `500ms` are compared in milliseconds
+    let number = |s: &str| {
+        s.parse::<f64>()
+            .ok()
+            .or_else(|| parse_duration(s).map(|d| d.as_millis() as f64))
+    };
+
+    match op {
+        Op::Contains => actual.contains(expected),
+        Op::Matches => Regex::new(expected).is_ok_and(|re| re.is_match(actual)),
+        Op::Exists => true,
+        Op::Eq => match (number(actual), number(expected)) {
+            (Some(a), Some(e)) => a == e,
+            _ => actual == expected,
+        },
+        Op::Ne => match (number(actual), number(expected)) {
+            (Some(a), Some(e)) => a != e,
+            _ => actual != expected,
+        },
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+            let (Some(a), Some(e)) = (number(actual), number(expected)) else {
+                return false;
+            };
+            match op {
+                Op::Lt => a < e,
+                Op::Le => a <= e,
+                Op::Gt => a > e,
+                _ => a >= e,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response<'a>(headers: &'a HeaderMap, body: &'a str) -> ResponseInfo<'a> {
+        ResponseInfo {
+            status: 201,
+            headers,
+            body,
+            elapsed: Duration::from_millis(120),
+            plugins: &[],
+        }
+    }
+
+    fn check(source: &str, response: &ResponseInfo) -> bool {
+        parse_assertion(source).unwrap().evaluate(response).passed
+    }
+
+    #[test]
+    fn extracts_assertions_from_request() {
+        let buf = "# @assert status == 200\nGET http://example.com HTTP/1.1\n  # @assert body contains ok\nAccept: */*\n";
+
+        let assertions = assertions(&Directives::parse(buf).unwrap()).unwrap();
+
+        assert_eq!(assertions.len(), 2);
+        assert_eq!(assertions[1].source, "body contains ok");
+    }
+
+    #[test]
+    fn evaluates_status_and_time() {
+        let headers = HeaderMap::new();
+        let res = response(&headers, "");
+
+        assert!(check("status == 201", &res));
+        assert!(check("status < 300", &res));
+        assert!(!check("status == 200", &res));
+        assert!(check("time < 500ms", &res));
+        assert!(!check("time < 0.1", &res));
+    }
+
+    #[test]
+    fn evaluates_jsonpath() {
+        let headers = HeaderMap::new();
+        let res = response(&headers, r#"{"id": 42, "name": "apple"}"#);
+
+        assert!(check("jsonpath $.id exists", &res));
+        assert!(check("jsonpath $.id == 42", &res));
+        assert!(check(r#"jsonpath $.name == "apple""#, &res));
+        assert!(!check("jsonpath $.missing exists", &res));
+    }
+
+    #[test]
+    fn evaluates_headers_and_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", "application/json".parse().unwrap());
+        let res = response(&headers, "all ok");
+
+        assert!(check("header Content-Type contains json", &res));
+        assert!(check("body matches ^all", &res));
+        assert!(!check("header X-Missing exists", &res));
+    }
+
+    #[test]
+    fn reports_what_was_found() {
+        let headers = HeaderMap::new();
+        let res = response(&headers, "");
+
+        let result = parse_assertion("status == 200").unwrap().evaluate(&res);
+
+        assert_eq!(result.message.as_deref(), Some("got 201"));
+    }
+
+    #[test]
+    fn fails_assertions_for_unknown_plugins() {
+        let headers = HeaderMap::new();
+        let res = response(&headers, "");
+
+        let assertion = parse_assertion("plugin schema apples.json").unwrap();
+        let result = assertion.evaluate(&res);
+
+        assert_eq!(assertion.expected, "apples.json");
+        assert!(!result.passed);
+        assert_eq!(result.message.as_deref(), Some("unknown plugin schema"));
+    }
+
+    #[test]
+    fn rejects_invalid_assertions() {
+        assert!(parse_assertion("latency < 3").is_err());
+        assert!(parse_assertion("status ~ 200").is_err());
+        assert!(parse_assertion("status ==").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    fs::read_to_string,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::warn;
+
+use crate::{
+    discovery::is_request_file,
+    monitor::{find_checks, run_check_expecting, run_check_with, CheckResult},
+    report::{write_report, Report},
+    scenario::Run,
+};
+
+/// The key of the expected status of a request in a batch list
+const EXPECT_KEY: &str = "expect";
+
+/// A request of a batch, with values to substitute in it and the status it
+/// should respond with
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchStep {
+    pub file: PathBuf,
+    pub options: Vec<(String, String)>,
+    pub status: Option<u16>,
+}
+
+impl From<PathBuf> for BatchStep {
+    fn from(file: PathBuf) -> Self {
+        Self {
+            file,
+            options: Vec::new(),
+            status: None,
+        }
+    }
+}
+
+/// The requests of a batch, given as a directory of requests, a single
+/// request, or a list file with one request per line, relative to the list
+/// file. Empty lines and lines starting with `#` are skipped. With `-`, the
+/// list is read from stdin, relative to the current directory.
+pub fn batch_files(path: &Path) -> Result<Vec<BatchStep>> {
+    if path == Path::new("-") {
+        let content = io::read_to_string(io::stdin())?;
+        return parse_batch_list(&content, Path::new(""));
+    }
+
+    let is_request = is_request_file(&path.to_string_lossy());
+    if path.is_dir() || is_request {
+        return Ok(find_checks(path)?.into_iter().map(Into::into).collect());
+    }
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    parse_batch_list(&read_to_string(path)?, base)
+}
+
+/// Each line is a request, optionally followed by `key=value` values to
+/// substitute, and `expect=<status>` for the status it should respond with:
+///
+/// ```text
+/// orders/get.http id=42
+/// orders/get.http id=0 expect=404
+/// ```
+fn parse_batch_list(content: &str, base: &Path) -> Result<Vec<BatchStep>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_batch_line(line, base))
+        .collect()
+}
+
+fn parse_batch_line(line: &str, base: &Path) -> Result<BatchStep> {
+    let mut words = line.split_whitespace();
+    let file = words.next().unwrap_or_default();
+    let mut step = BatchStep::from(base.join(file));
+
+    for word in words {
+        let Some((key, value)) = word.split_once('=') else {
+            bail!("Expected key=value, got {word} in: {line}");
+        };
+        if key == EXPECT_KEY {
+            step.status =
+                Some(value.parse().map_err(|_| {
+                    anyhow!("Invalid status {value} in: {line}")
+                })?);
+        } else {
+            step.options.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    Ok(step)
+}
+
+/// The results of a batch, with the time it took
+pub struct BatchResult {
+    pub results: Vec<CheckResult>,
+    pub elapsed: Duration,
+}
+
+impl BatchResult {
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| !r.passed()).count()
+    }
+}
+
+/// Run each request of a batch once, in order, and evaluate its assertions.
+/// Values extracted by a request are available to the requests after it.
+/// The requests are tagged with the id of the run, and the data they create
+/// is kept, to be removed with `hitman cleanup`.
+pub async fn run_batch(
+    root_dir: &Path,
+    steps: &[BatchStep],
+    options: &[(String, String)],
+) -> Result<BatchResult> {
+    let start = Instant::now();
+    let mut run = Run::start();
+    let headers = run.headers();
+
+    let mut results = Vec::new();
+    for step in steps {
+        let options: Vec<_> = run
+            .values()
+            .iter()
+            .chain(options)
+            .chain(&step.options)
+            .cloned()
+            .collect();
+        let file = &step.file;
+        let result = match step.status {
+            Some(status) => {
+                run_check_expecting(root_dir, file, &options, &headers, status)
+                    .await
+            }
+            None => run_check_with(root_dir, file, &options, &headers).await,
+        };
+        println!("{}", result.summary());
+        run.register_rollbacks(root_dir, file, &result).await?;
+        results.push(result);
+    }
+
+    run.finish(root_dir, true).await?;
+
+    Ok(BatchResult {
+        results,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Run a batch, write the report if one is asked for, and fail if any
+/// request failed
+pub async fn batch(
+    root_dir: &Path,
+    path: &Path,
+    options: &[(String, String)],
+    report: Option<&Report>,
+) -> Result<()> {
+    let steps = batch_files(path)?;
+    if steps.is_empty() {
+        bail!("No requests found in {}", path.display());
+    }
+
+    let result = run_batch(root_dir, &steps, options).await?;
+
+    if let Some(report) = report {
+        write_report(report, &result)?;
+        warn!("# Wrote {report}");
+    }
+
+    let failed = result.failed();
+    if failed > 0 {
+        bail!("{} of {} requests failed", failed, result.results.len());
+    }
+    warn!(
+        "# {} requests passed in {:.2?}",
+        result.results.len(),
+        result.elapsed
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_batch_lists() {
+        let content = "# Smoke tests\nlogin.http\n\n  orders/list.http  \n";
+
+        assert_eq!(
+            parse_batch_list(content, Path::new("smoke")).unwrap(),
+            vec![
+                BatchStep::from(PathBuf::from("smoke/login.http")),
+                BatchStep::from(PathBuf::from("smoke/orders/list.http")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_overrides_and_expected_status() {
+        let content = "orders/get.http id=0 user=alice expect=404\n";
+
+        assert_eq!(
+            parse_batch_list(content, Path::new("")).unwrap(),
+            vec![BatchStep {
+                file: PathBuf::from("orders/get.http"),
+                options: vec![
+                    ("id".into(), "0".into()),
+                    ("user".into(), "alice".into()),
+                ],
+                status: Some(404),
+            }]
+        );
+        assert!(parse_batch_list("get.http id", Path::new("")).is_err());
+        assert!(parse_batch_list("get.http expect=ok", Path::new("")).is_err());
+    }
+}
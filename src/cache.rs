@@ -0,0 +1,305 @@
+use chrono::{DateTime, FixedOffset};
+use reqwest::header::{
+    HeaderMap, AGE, CACHE_CONTROL, DATE, ETAG, EXPIRES, LAST_MODIFIED, PRAGMA,
+    VARY,
+};
+use std::time::Duration;
+
+use crate::util::format_duration;
+
+/// Explain how HTTP caches will treat a response, based on its
+/// `Cache-Control`, `Expires`, `ETag`, `Last-Modified` and `Vary` headers.
+pub fn explain_caching(headers: &HeaderMap) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    let directives = cache_directives(headers);
+    let has = |name: &str| directives.iter().any(|(n, _)| n == name);
+    let seconds = |name: &str| {
+        directives
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref()?.parse::<u64>().ok())
+            .map(Duration::from_secs)
+    };
+
+    if has("no-store") {
+        notes.push("Not stored by any cache (no-store)".to_string());
+        return notes;
+    }
+
+    if has("private") {
+        notes.push(
+            "Only stored by the client's own cache, not by shared caches \
+             like proxies and CDNs (private)"
+                .to_string(),
+        );
+    } else if has("public") {
+        notes.push("May be stored by shared caches (public)".to_string());
+    }
+
+    let date = header_date(headers, DATE);
+    let age = header_str(headers, AGE)
+        .and_then(|a| a.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+
+    let lifetime = if has("no-cache") || is_pragma_no_cache(headers) {
+        notes.push(
+            "Stored, but must be revalidated with the server before every \
+             use (no-cache)"
+                .to_string(),
+        );
+        None
+    } else if let Some(max_age) = seconds("max-age") {
+        notes.push(format!("Fresh for {} (max-age)", format_duration(max_age)));
+        if let Some(s_maxage) = seconds("s-maxage") {
+            notes.push(format!(
+                "Fresh for {} in shared caches (s-maxage)",
+                format_duration(s_maxage)
+            ));
+        }
+        Some(max_age)
+    } else if let Some(expires) = header_str(headers, EXPIRES) {
+        let lifetime =
+            parse_http_date(expires).zip(date).map(|(expires, date)| {
+                (expires - date).to_std().unwrap_or_default()
+            });
+        match lifetime {
+            Some(lifetime) if !lifetime.is_zero() => notes.push(format!(
+                "Fresh for {} (Expires, relative to Date)",
+                format_duration(lifetime)
+            )),
+            _ => notes.push(
+                "Already expired, or invalid Expires header, so it is stale \
+                 immediately"
+                    .to_string(),
+            ),
+        }
+        lifetime
+    } else if let Some(last_modified) = header_date(headers, LAST_MODIFIED) {
+        // RFC 9111 suggests 10% of the time since last modification
+        let lifetime = date.map(|date| {
+            (date - last_modified).to_std().unwrap_or_default() / 10
+        });
+        match lifetime {
+            Some(lifetime) => notes.push(format!(
+                "No explicit lifetime, caches will likely use a heuristic of \
+                 {} (10% of the time since Last-Modified)",
+                format_duration(lifetime)
+            )),
+            None => notes.push(
+                "No explicit lifetime, caches may use a heuristic based on \
+                 Last-Modified"
+                    .to_string(),
+            ),
+        }
+        lifetime
+    } else {
+        notes.push(
+            "No explicit lifetime, so most caches will not reuse it without \
+             revalidation"
+                .to_string(),
+        );
+        None
+    };
+
+    if let (Some(lifetime), Some(age)) = (lifetime, age) {
+        if age >= lifetime {
+            notes.push(format!(
+                "Already stale, served from a cache after {} (Age)",
+                format_duration(age)
+            ));
+        } else {
+            notes.push(format!(
+                "Served from a cache, fresh for another {} (Age)",
+                format_duration(lifetime - age)
+            ));
+        }
+    }
+
+    if has("immutable") {
+        notes.push(
+            "Will not be revalidated while fresh, even on reload (immutable)"
+                .to_string(),
+        );
+    }
+    if has("must-revalidate") || has("proxy-revalidate") {
+        notes.push(
+            "Must not be used when stale without a successful revalidation \
+             (must-revalidate)"
+                .to_string(),
+        );
+    }
+    if let Some(swr) = seconds("stale-while-revalidate") {
+        notes.push(format!(
+            "May be used for {} after becoming stale, while revalidating in \
+             the background (stale-while-revalidate)",
+            format_duration(swr)
+        ));
+    }
+    if let Some(sie) = seconds("stale-if-error") {
+        notes.push(format!(
+            "May be used for {} after becoming stale if the server fails \
+             (stale-if-error)",
+            format_duration(sie)
+        ));
+    }
+
+    match (
+        header_str(headers, ETAG),
+        header_str(headers, LAST_MODIFIED),
+    ) {
+        (Some(etag), _) => {
+            let kind = if etag.starts_with("W/") {
+                "weak"
+            } else {
+                "strong"
+            };
+            notes.push(format!(
+                "Revalidated with If-None-Match, using the {kind} ETag {etag}"
+            ));
+        }
+        (None, Some(last_modified)) => notes.push(format!(
+            "Revalidated with If-Modified-Since: {last_modified}"
+        )),
+        (None, None) => notes.push(
+            "No validators (ETag or Last-Modified), so revalidation means \
+             downloading the full response again"
+                .to_string(),
+        ),
+    }
+
+    if let Some(vary) = header_str(headers, VARY) {
+        if vary.trim() == "*" {
+            notes.push(
+                "Can never be reused for another request (Vary: *)".to_string(),
+            );
+        } else {
+            notes.push(format!(
+                "Stored separately for each value of the request headers: \
+                 {vary} (Vary)"
+            ));
+        }
+    }
+
+    notes
+}
+
+/// Parse the `Cache-Control` directives into names and optional values
+fn cache_directives(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| match d.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_lowercase(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (d.to_lowercase(), None),
+        })
+        .collect()
+}
+
+fn is_pragma_no_cache(headers: &HeaderMap) -> bool {
+    !headers.contains_key(CACHE_CONTROL)
+        && header_str(headers, PRAGMA)
+            .is_some_and(|p| p.to_lowercase().contains("no-cache"))
+}
+
+fn header_str(
+    headers: &HeaderMap,
+    name: reqwest::header::HeaderName,
+) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn header_date(
+    headers: &HeaderMap,
+    name: reqwest::header::HeaderName,
+) -> Option<DateTime<FixedOffset>> {
+    header_str(headers, name).and_then(parse_http_date)
+}
+
+fn parse_http_date(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc2822(s.trim()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn explains_no_store() {
+        let notes = explain_caching(&headers(&[(
+            "cache-control",
+            "no-store, max-age=60",
+        )]));
+
+        assert_eq!(notes, vec!["Not stored by any cache (no-store)"]);
+    }
+
+    #[test]
+    fn explains_max_age_and_validators() {
+        let notes = explain_caching(&headers(&[
+            ("cache-control", "public, max-age=3600"),
+            ("etag", "W/\"abc\""),
+            ("vary", "Accept-Encoding"),
+        ]));
+
+        assert_eq!(
+            notes,
+            vec![
+                "May be stored by shared caches (public)",
+                "Fresh for 1h (max-age)",
+                "Revalidated with If-None-Match, using the weak ETag W/\"abc\"",
+                "Stored separately for each value of the request headers: \
+                 Accept-Encoding (Vary)",
+            ]
+        );
+    }
+
+    #[test]
+    fn computes_lifetime_from_expires() {
+        let notes = explain_caching(&headers(&[
+            ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("expires", "Wed, 21 Oct 2015 09:28:00 GMT"),
+        ]));
+
+        assert!(
+            notes.contains(&"Fresh for 2h (Expires, relative to Date)".into())
+        );
+    }
+
+    #[test]
+    fn subtracts_age_from_lifetime() {
+        let notes = explain_caching(&headers(&[
+            ("cache-control", "max-age=600"),
+            ("age", "120"),
+        ]));
+
+        assert!(notes.contains(
+            &"Served from a cache, fresh for another 8m (Age)".into()
+        ));
+    }
+
+    #[test]
+    fn uses_heuristic_for_last_modified() {
+        let notes = explain_caching(&headers(&[
+            ("date", "Sun, 11 Oct 2015 00:00:00 GMT"),
+            ("last-modified", "Thu, 01 Oct 2015 00:00:00 GMT"),
+        ]));
+
+        assert!(notes[0].contains("heuristic of 1d"));
+    }
+}
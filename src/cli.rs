@@ -1,27 +1,23 @@
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use hitman::{graph::GraphFormat, report::Report, util::parse_duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// The name of a request file to execute and exit.
-    /// Omit this argument to run an interactive prompt.
-    pub name: Option<String>,
-
-    /// Optional Name=Value pairs to substitute in the request.
-    /// These will override values in the config file.
-    #[arg(value_parser = parse_key_val)]
-    pub options: Vec<(String, String)>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
 
-    /// When running interactively (no name argument specified),
-    /// repeat asking for requests until cancelled.
-    #[arg(short, long)]
-    pub repeat: bool,
+    /// Without a command, hitman runs a request like `hitman run`
+    #[command(flatten)]
+    pub request: RequestArgs,
 
-    /// Select a target from the config file
+    /// Select a target from the config file. Same as `hitman env select`.
     #[arg(
         short,
         long,
+        hide = true,
         conflicts_with = "name",
         conflicts_with = "repeat",
         conflicts_with = "flurry",
@@ -29,14 +25,122 @@ pub struct Args {
     )]
     pub select: bool,
 
+    /// Use the root directory of a project listed in the user config
+    #[arg(short, long, global = true)]
+    pub project: Option<String>,
+
     /// Show more output
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub verbose: bool,
 
     /// Show no output except the returned data
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub quiet: bool,
 
+    /// Same as `hitman test`
+    #[arg(
+        long,
+        value_name = "PATH",
+        hide = true,
+        conflicts_with = "name",
+        conflicts_with = "repeat",
+        conflicts_with = "select"
+    )]
+    pub batch: Option<PathBuf>,
+
+    /// Same as `hitman history browse`
+    #[arg(
+        long,
+        hide = true,
+        conflicts_with = "name",
+        conflicts_with = "repeat",
+        conflicts_with = "select",
+        conflicts_with = "batch"
+    )]
+    pub history: bool,
+
+    /// Write a summary of a batch run, like `junit:results.xml`
+    #[arg(long, value_name = "FORMAT:PATH", hide = true, requires = "batch")]
+    pub report: Option<Report>,
+
+    /// Write every request of the run, and its response, to an HTTP Archive
+    /// (HAR) file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub har: Option<PathBuf>,
+
+    /// Save the responses, to serve them with --offline later
+    #[arg(long, global = true, conflicts_with = "offline")]
+    pub record: bool,
+
+    /// Serve responses saved with --record, without network access
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Send every request through this proxy, like `http://localhost:8080`,
+    /// instead of the proxy configured for the target
+    #[arg(long, value_name = "URL", global = true)]
+    pub proxy: Option<String>,
+
+    /// Generate the same random values on every run, and freeze the time
+    /// when hitman starts, to reproduce a run exactly
+    #[arg(long, value_name = "N", global = true)]
+    pub seed: Option<u64>,
+}
+
+impl Args {
+    /// The command to run, with the flags that used to be commands turned
+    /// into their commands. `None` means running the request given in
+    /// `self.request`, or picking one.
+    pub fn take_command(&mut self) -> Option<Command> {
+        if self.select {
+            return Some(Command::Env(EnvCommand::Select));
+        }
+        if let Some(path) = self.batch.take() {
+            return Some(Command::Test {
+                path,
+                options: std::mem::take(&mut self.request.options),
+                report: self.report.take(),
+            });
+        }
+        if self.history {
+            return Some(Command::History(HistoryCommand::Browse));
+        }
+
+        match self.command.take() {
+            Some(Command::Run { request, keep }) => {
+                match request.name.as_deref().map(PathBuf::from) {
+                    // Scenarios are run with `hitman run` too
+                    Some(path) if path.is_dir() => {
+                        Some(Command::Scenario { path, keep })
+                    }
+                    _ => {
+                        self.request = request;
+                        None
+                    }
+                }
+            }
+            command => command,
+        }
+    }
+}
+
+/// How to send a request
+#[derive(clap::Args, Debug, Default)]
+pub struct RequestArgs {
+    /// The name of a request file to execute and exit.
+    /// Omit this argument to run an interactive prompt.
+    pub name: Option<String>,
+
+    /// Optional Name=Value pairs to substitute in the request.
+    /// These will override values in the config file.
+    #[arg(value_parser = parse_key_val)]
+    pub options: Vec<(String, String)>,
+
+    /// When running interactively (no name argument specified),
+    /// repeat asking for requests until cancelled.
+    #[arg(short, long)]
+    pub repeat: bool,
+
     /// Do not ask questions
     #[arg(short, long, requires = "name")]
     pub non_interactive: bool,
@@ -50,11 +154,484 @@ pub struct Args {
     #[arg(short, long, requires = "flurry")]
     pub connections: Option<i32>,
 
+    /// Load test an API by sending the request over and over, and report
+    /// latency percentiles, errors and throughput
+    #[arg(
+        long,
+        requires = "name",
+        conflicts_with = "flurry",
+        conflicts_with = "repeat",
+        conflicts_with = "watch"
+    )]
+    pub load: bool,
+
+    /// How long to run a load test, like `30s` or `5m`
+    #[arg(
+        long,
+        default_value = "10s",
+        value_parser = parse_duration_arg,
+        requires = "load"
+    )]
+    pub duration: Duration,
+
+    /// Requests started per second in a load test. Without a rate, requests
+    /// are sent as fast as responses come back.
+    #[arg(long, requires = "load")]
+    pub rate: Option<u32>,
+
+    /// Number of concurrent connections used in a load test
+    #[arg(long, default_value_t = 10, requires = "load")]
+    pub concurrency: u32,
+
+    /// Explain how HTTP caches will treat the response
+    #[arg(long, requires = "name", conflicts_with = "flurry")]
+    pub explain_cache: bool,
+
+    /// Grade the security headers of the response
+    #[arg(long, requires = "name", conflicts_with = "flurry")]
+    pub security_report: bool,
+
+    /// Open the response body in an editor, or the viewer configured for
+    /// its content type
+    #[arg(long, requires = "name", conflicts_with = "flurry")]
+    pub open: bool,
+
+    /// Compare the response with the previous response for the same request
+    #[arg(long, requires = "name", conflicts_with = "flurry")]
+    pub diff: bool,
+
+    /// Print the request as a curl command instead of sending it
+    #[arg(
+        long,
+        requires = "name",
+        conflicts_with = "flurry",
+        conflicts_with = "watch"
+    )]
+    pub curl: bool,
+
+    /// Send the request once for each row of a CSV file, or each object of
+    /// a JSON array, with the columns as values
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "name",
+        conflicts_with = "flurry",
+        conflicts_with = "watch",
+        conflicts_with = "accept"
+    )]
+    pub data: Option<PathBuf>,
+
+    /// Write the response body to a file, such as
+    /// `responses/{{request_name}}-{{timestamp}}.json`, with values substituted
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        requires = "name",
+        conflicts_with = "flurry",
+        conflicts_with = "load"
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Don't follow redirects, to show the redirect responses themselves
+    #[arg(long)]
+    pub no_follow: bool,
+
+    /// Show the response headers hidden by the `hide_headers` setting
+    #[arg(long, conflicts_with = "flurry")]
+    pub all_headers: bool,
+
+    /// Only show the response headers matching a pattern, such as
+    /// `x-ratelimit-*`
+    #[arg(long, value_name = "PATTERN", conflicts_with = "flurry")]
+    pub headers: Option<String>,
+
+    /// Remove a header from the request, including default headers from the
+    /// config
+    #[arg(long, value_name = "NAME", conflicts_with = "flurry")]
+    pub unset_header: Vec<String>,
+
+    /// Send the request with each of these Accept values, such as
+    /// `json,xml,problem+json`, and compare the responses
+    #[arg(
+        long,
+        value_name = "TYPES",
+        requires = "name",
+        conflicts_with = "flurry",
+        conflicts_with = "watch"
+    )]
+    pub accept: Option<String>,
+
     /// Watch file for changes (implies non-interactove).
     #[arg(short, long, requires = "name", conflicts_with = "flurry")]
     pub watch: bool,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Send a request, or pick one to send. This is what hitman does without
+    /// a command. A directory is run as a scenario, like `hitman scenario`.
+    Run {
+        #[command(flatten)]
+        request: RequestArgs,
+
+        /// Keep the data created by a scenario
+        #[arg(long, requires = "name")]
+        keep: bool,
+    },
+
+    /// Run the requests in a directory, or listed in a file, once, and exit
+    /// with an error if any assertion fails
+    Test {
+        /// A directory, or a file listing a request on each line. Use `-` to
+        /// read the list from stdin.
+        path: PathBuf,
+
+        /// Optional Name=Value pairs to substitute in the requests
+        #[arg(value_parser = parse_key_val)]
+        options: Vec<(String, String)>,
+
+        /// Write a summary of the run, like `junit:results.xml`
+        #[arg(long, value_name = "FORMAT:PATH")]
+        report: Option<Report>,
+    },
+
+    /// Select a target, and inspect the targets in the config file
+    #[command(subcommand)]
+    Env(EnvCommand),
+
+    /// Diagnose connectivity and configuration problems for a target
+    Doctor {
+        /// The target to diagnose. Defaults to the selected target.
+        target: Option<String>,
+    },
+
+    /// Check the health endpoint of every target, and show which are up
+    Ping,
+
+    /// Check the request files for problems, such as duplicated requests
+    Lint,
+
+    /// Show which requests produce the values that other requests use, as a
+    /// graph
+    Graph {
+        /// `dot` for Graphviz, or `mermaid`
+        #[arg(long, default_value = "dot")]
+        format: GraphFormat,
+    },
+
+    /// List the values a request needs, and which of them would be asked
+    /// for with the selected target
+    Inputs {
+        /// The request file
+        file: PathBuf,
+
+        /// Print a config with the values that would be asked for, to fill
+        /// in for running without questions
+        #[arg(long)]
+        answers: bool,
+    },
+
+    /// Change request files and config across the project
+    #[command(subcommand)]
+    Refactor(RefactorCommand),
+
+    /// Run requests on a schedule, and report failed assertions
+    Monitor {
+        /// A request file, or a directory of request files
+        path: PathBuf,
+
+        /// Time between runs, like `30s` or `5m`
+        #[arg(long, default_value = "60s", value_parser = parse_duration_arg)]
+        every: Duration,
+
+        /// Shell command to run when a check fails. The failed request files
+        /// are given in the HITMAN_FAILED environment variable.
+        #[arg(long)]
+        on_failure: Option<String>,
+
+        /// Append results to a log file
+        #[arg(long)]
+        log: Option<PathBuf>,
+
+        /// Serve Prometheus metrics on /metrics at this address, like
+        /// `127.0.0.1:9100`
+        #[arg(long)]
+        metrics: Option<SocketAddr>,
+    },
+
+    /// Run the requests in a directory once, in order, and roll back the
+    /// changes of the steps that succeeded
+    Scenario {
+        /// A request file, or a directory of request files
+        path: PathBuf,
+
+        /// Keep the created data, to be removed later with `hitman cleanup`
+        #[arg(long)]
+        keep: bool,
+    },
+
+    /// Send the rollback requests registered by a run. Lists the runs with
+    /// pending rollbacks when no run is given.
+    Cleanup {
+        /// The id of the run, as shown when it started
+        run_id: Option<String>,
+    },
+
+    /// Manage the plugins installed for all projects
+    #[command(subcommand)]
+    Plugin(PluginCommand),
+
+    /// Review the commands that the project files run, such as hooks, and
+    /// trust them on this machine. Nothing from a project runs before.
+    Trust {
+        /// Trust all the commands without asking
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Get or share the requests and config of the project, from the
+    /// location given in `[_sync]` in hitman.toml
+    #[command(subcommand)]
+    Sync(SyncCommand),
+
+    /// Remove old state, such as history and caches, according to the
+    /// retention limits in the config file
+    Clean {
+        /// Remove all state, including extracted data and cookies
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Create request files and a config from another tool's format
+    #[command(subcommand)]
+    Import(ImportCommand),
+
+    /// Manage the values captured from responses
+    #[command(subcommand)]
+    Data(DataCommand),
+
+    /// Use captured responses in other tests
+    #[command(subcommand)]
+    Fixtures(FixturesCommand),
+
+    /// Find responses received earlier
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// Show a request and its response from the history as markdown, with
+    /// credentials redacted, to paste in a chat or an issue
+    Share {
+        /// The id of the response, as listed by `hitman history search`, or
+        /// `last`
+        #[arg(default_value = "last")]
+        id: String,
+
+        /// Upload it to the paste endpoint given in `[_share]` in
+        /// hitman.toml, and show the link
+        #[arg(long)]
+        upload: bool,
+    },
+
+    /// Open the interactive view, optionally with a request selected
+    Ui {
+        /// A request file to select on startup
+        file: Option<PathBuf>,
+
+        /// Send the request right away
+        #[arg(long, requires = "file")]
+        send: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// Pick a response, and show it again without sending the request
+    Browse,
+
+    /// List the responses matching a search, newest first, like
+    /// `order_id:12345 status:2xx target:prod since:2024-05-01`
+    Search {
+        /// Words to look for, `key:value` for a JSON field or query
+        /// parameter, or a filter on status, target, method, file, since,
+        /// until, on or `is:bookmarked`. Lists all responses when empty.
+        query: Vec<String>,
+    },
+
+    /// Attach a note to a response, or remove it when no note is given
+    Note {
+        /// The id of the response, as listed by search, or `last`
+        id: String,
+
+        note: Option<String>,
+    },
+
+    /// Bookmark a response, to keep it when the history is pruned
+    Bookmark {
+        /// The id of the response, as listed by search, or `last`
+        id: String,
+
+        /// Remove the bookmark
+        #[arg(long)]
+        remove: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DataCommand {
+    /// Add values produced by another tool, such as `terraform output
+    /// -json`, from a JSON object or a dotenv file
+    Import {
+        /// The file with the values. Files ending with `.json` are read as
+        /// JSON, and others as dotenv files.
+        file: PathBuf,
+
+        /// Show the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FixturesCommand {
+    /// Write the newest response to each request as a JSON file, with
+    /// secrets redacted
+    Export {
+        /// Where to write the files
+        #[arg(long, default_value = "fixtures")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportCommand {
+    /// Import a Postman collection, with folders as directories
+    Postman {
+        /// The exported collection
+        collection: PathBuf,
+
+        /// An exported Postman environment, to add as a target. Can be
+        /// given more than once.
+        #[arg(long = "env")]
+        environments: Vec<PathBuf>,
+
+        /// Where to create the files
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Import an OpenAPI or Swagger spec, with a request for each operation
+    Openapi {
+        /// The spec, as YAML or JSON
+        spec: PathBuf,
+
+        /// Where to create the files
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PluginCommand {
+    /// Install a plugin module. Projects use it by its name in the
+    /// `[_plugins]` table of hitman.toml, such as `vault = {}`.
+    Install {
+        /// The WebAssembly module of the plugin
+        path: PathBuf,
+
+        /// The name of the plugin. Defaults to the file name.
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Show the installed plugins, and whether this project uses them
+    List,
+
+    /// Remove an installed plugin
+    Remove {
+        /// The name of the plugin
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RefactorCommand {
+    /// Rename a value in every request, request config and config file
+    Rename {
+        /// The current name
+        old: String,
+
+        /// The new name
+        new: String,
+
+        /// Show the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Move values that are the same in every target to the global defaults
+    Hoist {
+        /// The value to move. Defaults to every value that is the same in
+        /// every target.
+        key: Option<String>,
+
+        /// Show the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Move a global default to every target that doesn't have its own value
+    PushDown {
+        /// The value to move
+        key: String,
+
+        /// Show the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SyncCommand {
+    /// Get the latest requests. Asks before overwriting files changed
+    /// locally since the last sync.
+    Pull {
+        /// Overwrite local changes without asking
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Share the local requests, without secret values
+    Push,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum EnvCommand {
+    /// Select the target to use
+    Select,
+
+    /// Show which values differ between two targets
+    Diff {
+        /// The first target to compare
+        left: String,
+
+        /// The second target to compare
+        right: String,
+    },
+
+    /// Print the values captured from responses as environment variables,
+    /// like `eval "$(hitman env export)"`, for other tools to use
+    Export {
+        /// Print `export NAME='value'` lines for a shell (the default)
+        #[arg(long, conflicts_with = "dotenv")]
+        shell: bool,
+
+        /// Print `NAME="value"` lines for a `.env` file
+        #[arg(long)]
+        dotenv: bool,
+    },
+}
+
 /// Parse a single key-value pair
 fn parse_key_val(s: &str) -> Result<(String, String)> {
     match s.find('=') {
@@ -66,6 +643,13 @@ fn parse_key_val(s: &str) -> Result<(String, String)> {
     }
 }
 
+fn parse_duration_arg(s: &str) -> Result<Duration> {
+    match parse_duration(s) {
+        Some(d) if !d.is_zero() => Ok(d),
+        _ => bail!("invalid duration `{s}`"),
+    }
+}
+
 pub fn parse_args() -> Args {
     Args::parse()
 }
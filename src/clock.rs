@@ -0,0 +1,195 @@
+use std::{
+    net::UdpSocket,
+    sync::atomic::{AtomicI64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use toml::{Table, Value};
+
+use crate::util::parse_duration;
+
+const DEFAULT_NTP_SERVER: &str = "pool.ntp.org:123";
+const NTP_TIMEOUT: Duration = Duration::from_secs(3);
+/// Seconds between the NTP epoch (1900) and the unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Milliseconds to add to the system time
+static OFFSET_MS: AtomicI64 = AtomicI64::new(0);
+
+/// Milliseconds since the unix epoch of a frozen clock, or [NOT_FROZEN]
+static FROZEN_MS: AtomicI64 = AtomicI64::new(NOT_FROZEN);
+const NOT_FROZEN: i64 = i64::MIN;
+
+/// The current time, as used for generated values such as one-time codes.
+/// This is the system time, adjusted by the configured clock offset.
+pub fn now() -> SystemTime {
+    let frozen = FROZEN_MS.load(Ordering::Relaxed);
+    if frozen != NOT_FROZEN {
+        return UNIX_EPOCH + Duration::from_millis(frozen.max(0) as u64);
+    }
+
+    let offset = OFFSET_MS.load(Ordering::Relaxed);
+    let magnitude = Duration::from_millis(offset.unsigned_abs());
+
+    if offset < 0 {
+        SystemTime::now() - magnitude
+    } else {
+        SystemTime::now() + magnitude
+    }
+}
+
+/// Stop the clock at the current time, so that generated times are the same
+/// for the rest of the run
+pub fn freeze() {
+    let ms = now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+
+    FROZEN_MS.store(ms, Ordering::Relaxed);
+}
+
+/// Seconds since the unix epoch, according to [now]
+pub fn unix_now() -> u64 {
+    now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Set up the clock from `[_settings]`. A fixed offset can be given with
+/// `clock_offset = "-2s"`, and `time_source = "ntp"` corrects the clock
+/// against an NTP server (`ntp_server`), for servers that reject requests
+/// with even small clock skews.
+pub fn configure(settings: &Table) -> Result<()> {
+    let mut offset = match settings.get("clock_offset") {
+        Some(Value::String(s)) => parse_offset(s)
+            .with_context(|| format!("Invalid clock_offset: {s}"))?,
+        Some(Value::Integer(secs)) => secs * 1000,
+        Some(other) => bail!("Invalid clock_offset: {other}"),
+        None => 0,
+    };
+
+    match settings.get("time_source").and_then(Value::as_str) {
+        None | Some("system") => (),
+        Some("ntp") => {
+            let server = settings
+                .get("ntp_server")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_NTP_SERVER);
+            match ntp_offset(server) {
+                Ok(skew) => {
+                    info!("# Clock differs from {server} by {skew}ms");
+                    offset += skew;
+                }
+                Err(err) => {
+                    warn!("# Failed to check time with {server}: {err}")
+                }
+            }
+        }
+        Some(other) => bail!("Unknown time_source: {other}"),
+    }
+
+    OFFSET_MS.store(offset, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Parse a signed offset like `+1.5s` or `-200ms`, in milliseconds
+pub(crate) fn parse_offset(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    Some(sign * parse_duration(rest)?.as_millis() as i64)
+}
+
+/// Ask an NTP server for the time, and find how many milliseconds the local
+/// clock is behind (SNTP, RFC 4330)
+fn ntp_offset(server: &str) -> Result<i64> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(NTP_TIMEOUT))?;
+
+    let server = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{server}:123")
+    };
+
+    // Version 4, client mode
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+
+    let sent = SystemTime::now();
+    socket.send_to(&request, &server)?;
+
+    let mut response = [0u8; 48];
+    let (len, _) = socket.recv_from(&mut response)?;
+    let received = SystemTime::now();
+
+    let (server_received, server_sent) =
+        parse_ntp_response(&response[..len]).context("Invalid NTP response")?;
+
+    // The average of the differences on the way there and back
+    let ms = |t: SystemTime| {
+        t.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0)
+    };
+    let offset = ((ms(server_received) - ms(sent))
+        + (ms(server_sent) - ms(received)))
+        / 2;
+
+    Ok(offset)
+}
+
+/// The receive and transmit timestamps of an NTP response
+fn parse_ntp_response(data: &[u8]) -> Option<(SystemTime, SystemTime)> {
+    if data.len() < 48 {
+        return None;
+    }
+
+    let timestamp = |at: usize| {
+        let secs = u32::from_be_bytes(data[at..at + 4].try_into().ok()?);
+        let frac = u32::from_be_bytes(data[at + 4..at + 8].try_into().ok()?);
+
+        let secs = (secs as u64).checked_sub(NTP_UNIX_OFFSET)?;
+        let nanos = ((frac as u64) * 1_000_000_000) >> 32;
+
+        Some(UNIX_EPOCH + Duration::new(secs, nanos as u32))
+    };
+
+    Some((timestamp(32)?, timestamp(40)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signed_offsets() {
+        assert_eq!(parse_offset("2s"), Some(2000));
+        assert_eq!(parse_offset("+1.5s"), Some(1500));
+        assert_eq!(parse_offset("-200ms"), Some(-200));
+        assert_eq!(parse_offset("soon"), None);
+    }
+
+    #[test]
+    fn reads_ntp_timestamps() {
+        let mut data = [0u8; 48];
+        // 2024-01-01T00:00:00Z, and half a second later
+        let secs = (1_704_067_200 + NTP_UNIX_OFFSET) as u32;
+        data[32..36].copy_from_slice(&secs.to_be_bytes());
+        data[40..44].copy_from_slice(&secs.to_be_bytes());
+        data[44..48].copy_from_slice(&0x8000_0000u32.to_be_bytes());
+
+        let (received, sent) = parse_ntp_response(&data).unwrap();
+
+        assert_eq!(received, UNIX_EPOCH + Duration::from_secs(1_704_067_200));
+        assert_eq!(sent, UNIX_EPOCH + Duration::from_millis(1_704_067_200_500));
+    }
+}
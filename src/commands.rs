@@ -0,0 +1,615 @@
+use anyhow::{Context, Result};
+use inquire::{list_option::ListOption, Select};
+use log::warn;
+use std::env::{consts::EXE_SUFFIX, current_exe};
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+use toml::{Table, Value};
+
+use hitman::batch::batch;
+use hitman::data::{
+    coerce_types, import_changes, import_values, read_values, stored_values,
+    ImportChange,
+};
+use hitman::diff::{diff_lines, format_diff, with_context, DIFF_CONTEXT};
+use hitman::doctor::diagnose;
+use hitman::env::{
+    diff_targets, get_target, is_secret_key, load_env, load_target_env,
+    select_env, target_of,
+};
+use hitman::export::{captured_values, export_lines, ExportFormat};
+use hitman::fixtures::export_fixtures;
+use hitman::format::align_table;
+use hitman::graph::{request_graph, write_graph};
+use hitman::history::{
+    history_entry, load_history, search_history, set_bookmark, set_note,
+    HistoryQuery,
+};
+use hitman::import::write_imported;
+use hitman::inputs::{answers_file, request_inputs, InputStatus};
+use hitman::lint::lint;
+use hitman::monitor::{monitor, MonitorOptions};
+use hitman::openapi::import_openapi;
+use hitman::ping::{ping_all, PingStatus};
+use hitman::plugin::{
+    install_plugin, installed_plugins, plugins_from, remove_plugin,
+};
+use hitman::postman::import_postman;
+use hitman::prompt::{fuzzy_match, CliUserInteraction, UserInteraction};
+use hitman::refactor::{
+    apply_changes, hoist_values, push_down_value, rename_variable, FileChange,
+};
+use hitman::request::{print_history_entry, read_request};
+use hitman::scenario::{cleanup, pending_runs, run_scenario, ScenarioOptions};
+use hitman::script::RequestScripts;
+use hitman::scrub::ScrubRules;
+use hitman::security::Grade;
+use hitman::share::{share_markdown, upload};
+use hitman::state::{clean_all, prune, Retention};
+use hitman::sync::{pull, push};
+use hitman::trust::{trust_commands, untrusted_commands};
+
+use crate::cli::{
+    Command, DataCommand, EnvCommand, FixturesCommand, HistoryCommand,
+    ImportCommand, PluginCommand, RefactorCommand, SyncCommand,
+};
+
+const UI_PROGRAM: &str = "hitman-ui";
+
+pub async fn run(command: Command, root_dir: &Path) -> Result<()> {
+    match command {
+        Command::Env(EnvCommand::Select) => select_env(root_dir),
+        Command::Env(EnvCommand::Diff { left, right }) => {
+            env_diff(root_dir, &left, &right)
+        }
+        Command::Env(EnvCommand::Export { dotenv, .. }) => {
+            let format = if dotenv {
+                ExportFormat::Dotenv
+            } else {
+                ExportFormat::Shell
+            };
+            env_export(root_dir, format)
+        }
+        Command::Doctor { target } => doctor(root_dir, target).await,
+        Command::Ping => ping(root_dir).await,
+        Command::Lint => lint_requests(root_dir),
+        Command::Graph { format } => {
+            print!("{}", write_graph(&request_graph(root_dir)?, format));
+            Ok(())
+        }
+        Command::Inputs { file, answers } => {
+            list_inputs(root_dir, &file, answers)
+        }
+        Command::Refactor(RefactorCommand::Rename { old, new, dry_run }) => {
+            let changes = rename_variable(root_dir, &old, &new)?;
+            let summary = format!("Rename `{old}` to `{new}`");
+            refactor(root_dir, &changes, &summary, dry_run)
+        }
+        Command::Refactor(RefactorCommand::Hoist { key, dry_run }) => {
+            let changes = hoist_values(root_dir, key.as_deref())?;
+            refactor(
+                root_dir,
+                &changes,
+                "Move shared values to the global defaults",
+                dry_run,
+            )
+        }
+        Command::Refactor(RefactorCommand::PushDown { key, dry_run }) => {
+            let changes = push_down_value(root_dir, &key)?;
+            let summary = format!("Move `{key}` to the targets");
+            refactor(root_dir, &changes, &summary, dry_run)
+        }
+        Command::Monitor {
+            path,
+            every,
+            on_failure,
+            log,
+            metrics,
+        } => {
+            let options = MonitorOptions {
+                every,
+                on_failure,
+                log_file: log,
+                metrics,
+            };
+            monitor(root_dir, &path, &options).await
+        }
+        // Taken out by Args::take_command, for main to run
+        Command::Run { .. } => unreachable!("requests are run by main"),
+        Command::Test {
+            path,
+            options,
+            report,
+        } => batch(root_dir, &path, &options, report.as_ref()).await,
+        Command::Scenario { path, keep } => {
+            run_scenario(root_dir, &path, &ScenarioOptions { keep }).await
+        }
+        Command::Cleanup {
+            run_id: Some(run_id),
+        } => cleanup(root_dir, &run_id).await,
+        Command::Cleanup { run_id: None } => list_runs(root_dir),
+        Command::Plugin(PluginCommand::Install { path, name }) => {
+            let name = install_plugin(&path, name.as_deref())?;
+            println!("# Installed plugin {name}");
+            println!(
+                "# Use it in a project with `{name} = {{}}` in [_plugins]"
+            );
+            Ok(())
+        }
+        Command::Plugin(PluginCommand::List) => list_plugins(root_dir),
+        Command::Plugin(PluginCommand::Remove { name }) => {
+            remove_plugin(&name)?;
+            println!("# Removed plugin {name}");
+            Ok(())
+        }
+        Command::Trust { yes } => trust(root_dir, yes),
+        Command::Sync(SyncCommand::Pull { force }) => {
+            pull(root_dir, force, &CliUserInteraction).await
+        }
+        Command::Sync(SyncCommand::Push) => push(root_dir).await,
+        Command::Clean { all } => clean(root_dir, all),
+        Command::Import(command) => import(command),
+        Command::History(HistoryCommand::Browse) => browse_history(root_dir),
+        Command::History(HistoryCommand::Search { query }) => {
+            search(root_dir, &query.join(" "))
+        }
+        Command::History(HistoryCommand::Note { id, note }) => {
+            let entry = set_note(root_dir, &id, note.as_deref())?;
+            println!("{} {}", entry.id, entry.summary());
+            Ok(())
+        }
+        Command::History(HistoryCommand::Bookmark { id, remove }) => {
+            let entry = set_bookmark(root_dir, &id, !remove)?;
+            println!("{} {}", entry.id, entry.summary());
+            Ok(())
+        }
+        Command::Share { id, upload } => share(root_dir, &id, upload).await,
+        Command::Ui { file, send } => ui(file, send),
+        Command::Data(DataCommand::Import { file, dry_run }) => {
+            data_import(root_dir, &file, dry_run)
+        }
+        Command::Fixtures(FixturesCommand::Export { dir }) => {
+            let files = export_fixtures(root_dir, &dir)?;
+            println!(
+                "# Exported {} fixtures to {}",
+                files.len(),
+                dir.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Imports make new projects, so they don't need an existing config
+pub fn import(command: ImportCommand) -> Result<()> {
+    let (imported, dir) = match command {
+        ImportCommand::Postman {
+            collection,
+            environments,
+            dir,
+        } => (import_postman(&collection, &environments)?, dir),
+        ImportCommand::Openapi { spec, dir } => (import_openapi(&spec)?, dir),
+    };
+
+    write_imported(&dir, &imported)?;
+    println!(
+        "# Imported {} requests into {}",
+        imported.requests.len(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
+fn list_plugins(root_dir: &Path) -> Result<()> {
+    let plugins = installed_plugins()?;
+    if plugins.is_empty() {
+        println!("# No plugins installed");
+        return Ok(());
+    }
+
+    let used: Vec<String> =
+        plugins_from(&load_target_env(root_dir, &get_target(root_dir))?)?
+            .into_iter()
+            .filter(|p| p.installed)
+            .map(|p| p.name)
+            .collect();
+
+    let mut rows = vec![vec![
+        "Plugin".to_string(),
+        "Used".to_string(),
+        "Path".to_string(),
+    ]];
+    for (name, path) in plugins {
+        let used = if used.contains(&name) { "yes" } else { "no" };
+        rows.push(vec![name, used.to_string(), path.display().to_string()]);
+    }
+    print!("{}", align_table(&rows));
+
+    Ok(())
+}
+
+fn trust(root_dir: &Path, yes: bool) -> Result<()> {
+    let commands = untrusted_commands(root_dir)?;
+    if commands.is_empty() {
+        println!("# All commands of the project are trusted");
+        return Ok(());
+    }
+
+    println!("# Commands that the project runs:");
+    for (what, command) in &commands {
+        println!("{what}: {command}");
+    }
+
+    if !yes && !CliUserInteraction.confirm("Trust these commands?")? {
+        println!("# Nothing trusted");
+        return Ok(());
+    }
+
+    let commands: Vec<String> = commands.into_iter().map(|(_, c)| c).collect();
+    trust_commands(root_dir, &commands)?;
+    println!("# Trusted {} commands", commands.len());
+
+    Ok(())
+}
+
+fn list_runs(root_dir: &Path) -> Result<()> {
+    let runs = pending_runs(root_dir)?;
+    if runs.is_empty() {
+        println!("# No runs to clean up");
+        return Ok(());
+    }
+
+    let mut rows = vec![vec!["Run".to_string(), "Rollbacks".to_string()]];
+    for (run_id, count) in runs {
+        rows.push(vec![run_id, count.to_string()]);
+    }
+    print!("{}", align_table(&rows));
+
+    Ok(())
+}
+
+/// Select a response from the history, and show it again
+fn browse_history(root_dir: &Path) -> Result<()> {
+    let entries = load_history(root_dir)?;
+    if entries.is_empty() {
+        warn!("# No responses in the history yet");
+        return Ok(());
+    }
+
+    let options: Vec<ListOption<String>> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| ListOption::new(i, e.summary()))
+        .collect();
+
+    // Searches like `hitman history search`, with a fuzzy search while a
+    // filter is still being typed
+    let selected = Select::new("Select response", options)
+        .with_filter(&|filter, _, value, i| match HistoryQuery::parse(filter) {
+            Ok(query) => query.matches(&entries[i]),
+            Err(_) => fuzzy_match(filter, value),
+        })
+        .with_page_size(15)
+        .prompt()?;
+
+    print_history_entry(&entries[selected.index])
+}
+
+fn search(root_dir: &Path, query: &str) -> Result<()> {
+    let entries = search_history(root_dir, &HistoryQuery::parse(query)?)?;
+    if entries.is_empty() {
+        match query {
+            "" => println!("# No responses in the history yet"),
+            query => println!("# No responses match {query}"),
+        }
+        return Ok(());
+    }
+
+    for entry in &entries {
+        println!("{} {}", entry.id, entry.summary());
+    }
+
+    Ok(())
+}
+
+async fn share(root_dir: &Path, id: &str, to_paste: bool) -> Result<()> {
+    let entry = history_entry(root_dir, id)?;
+    let markdown = share_markdown(&entry, &ScrubRules::load(root_dir)?);
+
+    if to_paste {
+        println!("{}", upload(root_dir, &markdown).await?);
+    } else {
+        print!("{markdown}");
+    }
+
+    Ok(())
+}
+
+/// Run the `hitman-ui` program installed next to this one, or else the one
+/// in the path
+fn ui(file: Option<PathBuf>, send: bool) -> Result<()> {
+    let program = current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name(format!("{UI_PROGRAM}{EXE_SUFFIX}")))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(UI_PROGRAM));
+
+    let mut command = process::Command::new(&program);
+    command.args(file);
+    if send {
+        command.arg("--send");
+    }
+
+    let status = command
+        .status()
+        .with_context(|| format!("Could not run {}", program.display()))?;
+    // It has shown its own errors
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+async fn doctor(root_dir: &Path, target: Option<String>) -> Result<()> {
+    let target = target.unwrap_or_else(|| get_target(root_dir));
+    let env = load_target_env(root_dir, &target)?;
+
+    println!("# Diagnosing {target}");
+
+    let checks = diagnose(root_dir, &env).await;
+    for check in &checks {
+        println!("[{}] {}: {}", check.grade, check.name, check.message);
+    }
+
+    let failed = checks.iter().filter(|c| c.grade == Grade::Fail).count();
+    let warnings = checks.iter().filter(|c| c.grade == Grade::Warn).count();
+    match (failed, warnings) {
+        (0, 0) => println!("# No problems found"),
+        (0, _) => println!("# No problems found, {warnings} warnings"),
+        _ => println!("# {failed} problems found, {warnings} warnings"),
+    }
+
+    Ok(())
+}
+
+fn lint_requests(root_dir: &Path) -> Result<()> {
+    let warnings = lint(root_dir)?;
+    for warning in &warnings {
+        println!(
+            "[{}] {}: {} ({})",
+            Grade::Warn,
+            warning.file.display(),
+            warning.message,
+            warning.rule
+        );
+    }
+
+    match warnings.len() {
+        0 => println!("# No problems found"),
+        n => println!("# {n} warnings"),
+    }
+
+    Ok(())
+}
+
+fn list_inputs(root_dir: &Path, file: &Path, answers: bool) -> Result<()> {
+    let env = load_env(root_dir, file, &[])?;
+    let target = target_of(&env).unwrap_or_default().to_string();
+    let (request, _) = RequestScripts::extract(&read_request(file, &env)?)?;
+    let inputs = request_inputs(&request, &env);
+
+    if answers {
+        print!("{}", answers_file(&inputs, &target));
+        return Ok(());
+    }
+
+    // Values that would be asked for may be extracted by other requests
+    let graph = request_graph(root_dir).unwrap_or_default();
+    let produced_by = |key: &str| {
+        graph
+            .iter()
+            .filter(|node| node.produces.iter().any(|p| p == key))
+            .map(|node| node.file.display().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    println!("# Inputs of {} for {target}", file.display());
+    let mut rows = vec![vec![
+        "Input".to_string(),
+        "Status".to_string(),
+        "Details".to_string(),
+    ]];
+    for input in &inputs {
+        let (status, note) = match &input.status {
+            InputStatus::Set => {
+                let value = env.get(&input.key);
+                let value = value.map(|v| display_value(&input.key, v));
+                ("set", value.unwrap_or_default())
+            }
+            InputStatus::EnvVar => ("env", String::new()),
+            InputStatus::Select(n) => ("select", format!("{n} values")),
+            InputStatus::Fallback(fallback) => {
+                ("fallback", format!("defaults to {fallback}"))
+            }
+            InputStatus::Prompt => match produced_by(&input.key)[..] {
+                [] => ("prompt", String::new()),
+                ref files => {
+                    ("prompt", format!("extracted by {}", files.join(", ")))
+                }
+            },
+        };
+        rows.push(vec![input.key.clone(), status.to_string(), note]);
+    }
+    print!("{}", align_table(&rows));
+
+    match inputs.iter().filter(|i| i.prompts()).count() {
+        0 => println!("# Nothing is asked for"),
+        n => println!("# {n} values are asked for"),
+    }
+
+    Ok(())
+}
+
+/// Show the changes of a refactoring, and make them unless it's a dry run
+fn refactor(
+    root_dir: &Path,
+    changes: &[FileChange],
+    summary: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let color = io::stdout().is_terminal();
+    for change in changes {
+        println!("# {}", change.path.display());
+        let diff = diff_lines(&change.old, &change.new);
+        print!("{}", format_diff(&with_context(diff, DIFF_CONTEXT), color));
+    }
+
+    let files = match changes.len() {
+        1 => "1 file".to_string(),
+        n => format!("{n} files"),
+    };
+    match (changes.is_empty(), dry_run) {
+        (true, _) => println!("# Nothing to change"),
+        (false, true) => println!("# {summary}: would change {files}"),
+        (false, false) => {
+            apply_changes(root_dir, changes)?;
+            println!("# {summary}: changed {files}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn ping(root_dir: &Path) -> Result<()> {
+    let results = ping_all(root_dir).await?;
+
+    let mut rows = vec![vec![
+        "Target".to_string(),
+        "Status".to_string(),
+        "Latency".to_string(),
+        "Version".to_string(),
+    ]];
+    for result in results {
+        let status = match result.status {
+            PingStatus::Up(code) => format!("up ({code})"),
+            PingStatus::Down(reason) => format!("down ({reason})"),
+        };
+        let latency = result
+            .latency
+            .map(|l| format!("{:.0?}", l))
+            .unwrap_or_default();
+
+        rows.push(vec![
+            result.target,
+            status,
+            latency,
+            result.version.unwrap_or_default(),
+        ]);
+    }
+
+    print!("{}", align_table(&rows));
+
+    Ok(())
+}
+
+fn clean(root_dir: &Path, all: bool) -> Result<()> {
+    if all {
+        clean_all(root_dir)?;
+        warn!("# Removed all state");
+    } else {
+        let stats = prune(root_dir, &Retention::load(root_dir)?)?;
+        warn!(
+            "# Removed {} entries ({} bytes)",
+            stats.removed, stats.bytes
+        );
+    }
+
+    Ok(())
+}
+
+fn env_diff(root_dir: &Path, left: &str, right: &str) -> Result<()> {
+    let diff = diff_targets(root_dir, left, right)?;
+
+    println!("# Different values:");
+    for (key, l, r) in &diff.differs {
+        println!("~ {key}");
+        println!("    {left}: {}", display_value(key, l));
+        println!("    {right}: {}", display_value(key, r));
+    }
+
+    println!("# Missing in {right}:");
+    for (key, value) in &diff.only_left {
+        println!("- {key} = {}", display_value(key, value));
+    }
+
+    println!("# Missing in {left}:");
+    for (key, value) in &diff.only_right {
+        println!("+ {key} = {}", display_value(key, value));
+    }
+
+    println!("# Identical:");
+    for (key, value) in &diff.identical {
+        println!("  {key} = {}", display_value(key, value));
+    }
+
+    Ok(())
+}
+
+fn env_export(root_dir: &Path, format: ExportFormat) -> Result<()> {
+    let target = get_target(root_dir);
+    println!("# Values captured by hitman, with {target}");
+    for line in export_lines(&captured_values(root_dir, &target), format) {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn data_import(root_dir: &Path, file: &Path, dry_run: bool) -> Result<()> {
+    let target = get_target(root_dir);
+    let stored = stored_values(root_dir, &target);
+    let values = coerce_types(&stored, read_values(file)?);
+
+    let mut changed = Table::new();
+    for (key, change) in import_changes(&stored, &values) {
+        match change {
+            ImportChange::Added(new) => {
+                println!("+ {key} = {}", display_value(&key, &new));
+                changed.insert(key, new);
+            }
+            ImportChange::Changed(old, new) => {
+                println!("~ {key}");
+                println!("    stored: {}", display_value(&key, &old));
+                println!("    imported: {}", display_value(&key, &new));
+                changed.insert(key, new);
+            }
+            ImportChange::Unchanged(_) => (),
+        }
+    }
+
+    let count = match changed.len() {
+        1 => "1 value".to_string(),
+        n => format!("{n} values"),
+    };
+    match (changed.is_empty(), dry_run) {
+        (true, _) => println!("# Nothing to import"),
+        (false, true) => println!("# Would import {count}"),
+        (false, false) => {
+            import_values(root_dir, &target, &changed)?;
+            println!("# Imported {count} into {target}");
+        }
+    }
+
+    Ok(())
+}
+
+fn display_value(key: &str, value: &Value) -> String {
+    if is_secret_key(key) {
+        "********".into()
+    } else {
+        value.to_string()
+    }
+}
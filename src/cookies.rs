@@ -0,0 +1,385 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use reqwest::{cookie::CookieStore, header::HeaderValue, Url};
+use toml::{Table, Value};
+
+use crate::{
+    clock::unix_now,
+    env::{find_root_dir, get_target, read_toml, target_of},
+    state::{cookie_jar_file, ensure_state_dir},
+};
+
+const COOKIES_KEY: &str = "cookies";
+
+/// Cookies kept by earlier versions, as `Set-Cookie` headers without the URL
+/// that set them. They are given to the host of the next request.
+const LEGACY_KEY: &str = "legacy";
+
+/// Cookies set by responses, kept for each target in `.hitman/cookies`, and
+/// sent with later requests to the same host, like a browser does
+pub struct HitmanCookieJar {
+    file: Option<PathBuf>,
+}
+
+impl HitmanCookieJar {
+    /// The cookie jar of the target of the environment
+    pub fn for_env(env: &Table) -> Self {
+        let file = find_root_dir().ok().flatten().map(|root_dir| {
+            let target = match target_of(env) {
+                Some(target) => target.to_string(),
+                None => get_target(&root_dir),
+            };
+            cookie_jar_file(&root_dir, &target)
+        });
+
+        Self { file }
+    }
+
+    fn load(&self, url: &Url, now: i64) -> Vec<StoredCookie> {
+        let Some(file) = &self.file else {
+            return Vec::new();
+        };
+        let Ok(content) = read_toml(file) else {
+            return Vec::new();
+        };
+
+        let mut cookies: Vec<StoredCookie> = match content.get(COOKIES_KEY) {
+            Some(Value::Array(cookies)) => cookies
+                .iter()
+                .filter_map(StoredCookie::from_value)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        if let Some(Value::Array(legacy)) = content.get(LEGACY_KEY) {
+            for cookie in legacy
+                .iter()
+                .filter_map(Value::as_str)
+                .filter_map(|header| StoredCookie::parse(header, url, now))
+            {
+                cookies.retain(|c| !c.replaces(&cookie));
+                cookies.push(cookie);
+            }
+            let _ = self.save(&cookies);
+        }
+
+        cookies
+    }
+
+    fn save(&self, cookies: &[StoredCookie]) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+
+        if let Some(root_dir) = find_root_dir()? {
+            ensure_state_dir(&root_dir)?;
+        }
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = Table::new();
+        content.insert(
+            COOKIES_KEY.to_string(),
+            Value::Array(cookies.iter().map(StoredCookie::to_value).collect()),
+        );
+        fs::write(file, toml::to_string_pretty(&content)?)?;
+
+        Ok(())
+    }
+}
+
+impl CookieStore for HitmanCookieJar {
+    fn set_cookies(
+        &self,
+        cookie_headers: &mut dyn Iterator<Item = &HeaderValue>,
+        url: &Url,
+    ) {
+        let now = unix_now() as i64;
+        let received: Vec<StoredCookie> = cookie_headers
+            .filter_map(|h| std::str::from_utf8(h.as_bytes()).ok())
+            .filter_map(|h| StoredCookie::parse(h, url, now))
+            .collect();
+        if received.is_empty() {
+            return;
+        }
+
+        let mut cookies = self.load(url, now);
+        for cookie in received {
+            cookies.retain(|c| !c.replaces(&cookie));
+            cookies.push(cookie);
+        }
+        // Cookies are removed by setting them again, with an expiry time in
+        // the past
+        cookies.retain(|c| !c.is_expired(now));
+
+        let _ = self.save(&cookies);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let now = unix_now() as i64;
+        let header = self
+            .load(url, now)
+            .iter()
+            .filter(|c| c.matches(url, now))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if header.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+/// Add cookies kept by earlier versions to a cookie jar, as `Set-Cookie`
+/// headers
+pub(crate) fn import_legacy_cookies(
+    file: &Path,
+    headers: Vec<Value>,
+) -> anyhow::Result<()> {
+    let mut content = read_toml(file).unwrap_or_default();
+    match content.get_mut(LEGACY_KEY) {
+        Some(Value::Array(legacy)) => legacy.extend(headers),
+        _ => {
+            content.insert(LEGACY_KEY.to_string(), Value::Array(headers));
+        }
+    }
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, toml::to_string_pretty(&content)?)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    /// Only sent to the host that set it, when no domain was given
+    host_only: bool,
+    path: String,
+    secure: bool,
+    /// Seconds since the unix epoch. Cookies without expiry are kept until
+    /// they are replaced.
+    expires: Option<i64>,
+}
+
+impl StoredCookie {
+    /// A cookie from a `Set-Cookie` header received from a URL
+    fn parse(header: &str, url: &Url, now: i64) -> Option<Self> {
+        let cookie = cookie::Cookie::parse(header).ok()?;
+        let host = url.host_str()?.to_lowercase();
+
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) => {
+                let domain = domain.trim_start_matches('.').to_lowercase();
+                // Servers can't set cookies for other sites
+                if !domain_matches(&host, &domain) {
+                    return None;
+                }
+                (domain, false)
+            }
+            None => (host, true),
+        };
+
+        let expires = match (cookie.max_age(), cookie.expires_datetime()) {
+            (Some(max_age), _) => Some(now + max_age.whole_seconds()),
+            (None, Some(expires)) => Some(expires.unix_timestamp()),
+            (None, None) => None,
+        };
+
+        Some(Self {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain,
+            host_only,
+            path: cookie
+                .path()
+                .filter(|p| p.starts_with('/'))
+                .map(String::from)
+                .unwrap_or_else(|| default_path(url)),
+            secure: cookie.secure().unwrap_or(false),
+            expires,
+        })
+    }
+
+    fn replaces(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.domain == other.domain
+            && self.path == other.path
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Whether the cookie is sent with a request to a URL
+    fn matches(&self, url: &Url, now: i64) -> bool {
+        let Some(host) = url.host_str().map(str::to_lowercase) else {
+            return false;
+        };
+
+        let host_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+
+        host_matches
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired(now)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut table = Table::new();
+        table.insert("name".into(), Value::String(self.name.clone()));
+        table.insert("value".into(), Value::String(self.value.clone()));
+        table.insert("domain".into(), Value::String(self.domain.clone()));
+        table.insert("host_only".into(), Value::Boolean(self.host_only));
+        table.insert("path".into(), Value::String(self.path.clone()));
+        table.insert("secure".into(), Value::Boolean(self.secure));
+        if let Some(expires) = self.expires {
+            table.insert("expires".into(), Value::Integer(expires));
+        }
+
+        Value::Table(table)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let table = value.as_table()?;
+        let string = |key: &str| Some(table.get(key)?.as_str()?.to_string());
+        let boolean = |key: &str| table.get(key).and_then(Value::as_bool);
+
+        Some(Self {
+            name: string("name")?,
+            value: string("value")?,
+            domain: string("domain")?,
+            host_only: boolean("host_only").unwrap_or(true),
+            path: string("path").unwrap_or("/".into()),
+            secure: boolean("secure").unwrap_or(false),
+            expires: table.get("expires").and_then(Value::as_integer),
+        })
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/')
+                || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The path of cookies set without one, which is the directory of the URL
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(pos) => url.path()[..pos].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn sends_cookies_to_the_host_that_set_them() {
+        let login = url("https://api.example.com/auth/login");
+        let cookie =
+            StoredCookie::parse("session=abc; HttpOnly", &login, 0).unwrap();
+
+        assert_eq!(cookie.path, "/auth");
+        assert!(cookie.matches(&url("https://api.example.com/auth/me"), 0));
+        assert!(!cookie.matches(&url("https://api.example.com/orders"), 0));
+        assert!(!cookie.matches(&url("https://example.com/auth/me"), 0));
+    }
+
+    #[test]
+    fn sends_domain_cookies_to_subdomains() {
+        let cookie = StoredCookie::parse(
+            "id=1; Domain=.example.com; Path=/; Secure",
+            &url("https://www.example.com/"),
+            0,
+        )
+        .unwrap();
+
+        assert!(cookie.matches(&url("https://api.example.com/orders"), 0));
+        assert!(!cookie.matches(&url("http://api.example.com/orders"), 0));
+        assert!(!cookie.matches(&url("https://example.org/"), 0));
+
+        assert!(StoredCookie::parse(
+            "id=1; Domain=other.com",
+            &url("https://www.example.com/"),
+            0
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn expires_cookies() {
+        let cookie = StoredCookie::parse(
+            "session=abc; Max-Age=60",
+            &url("http://localhost:8080/"),
+            1000,
+        )
+        .unwrap();
+
+        assert_eq!(cookie.expires, Some(1060));
+        assert!(cookie.matches(&url("http://localhost:8080/"), 1059));
+        assert!(!cookie.matches(&url("http://localhost:8080/"), 1060));
+    }
+
+    #[test]
+    fn gives_legacy_cookies_to_the_next_host() {
+        let file = std::env::temp_dir()
+            .join(format!("hitman-cookies-test-{}.toml", std::process::id()));
+        let _ = fs::remove_file(&file);
+        import_legacy_cookies(
+            &file,
+            vec![Value::String("session=abc; Path=/".into())],
+        )
+        .unwrap();
+        let jar = HitmanCookieJar {
+            file: Some(file.clone()),
+        };
+
+        let cookies = jar.load(&url("https://api.example.com/me"), 0);
+
+        assert_eq!(cookies.len(), 1);
+        assert!(cookies[0].matches(&url("https://api.example.com/orders"), 0));
+        let content = read_toml(&file).unwrap();
+        assert!(content.get(LEGACY_KEY).is_none());
+        assert_eq!(jar.load(&url("https://other.example.com/"), 0), cookies);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn keeps_cookies_as_toml() {
+        let cookie = StoredCookie::parse(
+            "session=abc; Max-Age=60; Path=/",
+            &url("http://localhost:8080/login"),
+            1000,
+        )
+        .unwrap();
+
+        assert_eq!(StoredCookie::from_value(&cookie.to_value()), Some(cookie));
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use reqwest::{Client, Method, Request};
+use toml::Table;
+
+use crate::{directive::Directives, request::build_request};
+
+/// A `curl` command that sends the same request as a prepared request, with
+/// the ETags captured for requests that change a resource
+pub fn curl_command(buf: &str, etags: &Table) -> Result<String> {
+    let request =
+        build_request(&Client::new(), &Directives::parse(buf)?, etags)?;
+
+    Ok(format_curl(&request))
+}
+
+/// One argument per line, so that long requests are easy to read and edit
+fn format_curl(request: &Request) -> String {
+    let mut args = vec!["curl".to_string()];
+
+    match request.method() {
+        &Method::GET => (),
+        &Method::HEAD => args.push("--head".to_string()),
+        method => args.push(format!("-X {}", method)),
+    }
+
+    args.push(shell_quote(request.url().as_str()));
+
+    for (name, value) in request.headers() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        args.push(format!("-H {}", shell_quote(&format!("{name}: {value}"))));
+    }
+
+    if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+        if !body.is_empty() {
+            let body = String::from_utf8_lossy(body);
+            args.push(format!("--data-raw {}", shell_quote(&body)));
+        }
+    }
+
+    args.join(" \\\n  ")
+}
+
+/// Quote a value for a POSIX shell, unless it is safe as it is
+fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+
+    if safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_curl_commands() {
+        let buf = "POST https://example.com/orders?lang=en&page=2 HTTP/1.1\n\
+                   Content-Type: application/json\n\
+                   Authorization: Bearer abc\n\
+                   \n\
+                   {\"note\": \"it's here\"}";
+
+        assert_eq!(
+            curl_command(buf, &Table::new()).unwrap(),
+            "curl \\\n  \
+             -X POST \\\n  \
+             'https://example.com/orders?lang=en&page=2' \\\n  \
+             -H 'content-type: application/json' \\\n  \
+             -H 'authorization: Bearer abc' \\\n  \
+             --data-raw '{\"note\": \"it'\\''s here\"}'"
+        );
+    }
+
+    #[test]
+    fn leaves_out_directives() {
+        let buf = "# @assert status == 200\n\
+                   # @depends on: login.http\n\
+                   # @timeout 5s\n\
+                   # @retries 2\n\
+                   # @redirects 0\n\
+                   # @etag off\n\
+                   # @output responses/{{request_name}}.json\n\
+                   PUT https://example.com/orders/1 HTTP/1.1\n\
+                   \n\
+                   {}";
+
+        assert_eq!(
+            curl_command(buf, &Table::new()).unwrap(),
+            "curl \\\n  \
+             -X PUT \\\n  \
+             https://example.com/orders/1 \\\n  \
+             --data-raw '{}'"
+        );
+    }
+
+    #[test]
+    fn leaves_out_what_curl_does_by_default() {
+        let buf = "GET https://example.com/orders HTTP/1.1\n\n";
+
+        assert_eq!(
+            curl_command(buf, &Table::new()).unwrap(),
+            "curl \\\n  https://example.com/orders"
+        );
+    }
+
+    #[test]
+    fn sends_graphql_as_json() {
+        let buf = "POST https://example.com/graphql HTTP/1.1\n\
+                   \n\
+                   query { orders { id } }";
+
+        assert!(curl_command(buf, &Table::new())
+            .unwrap()
+            .ends_with(r#"--data-raw '{"query":"query { orders { id } }"}'"#));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::{
+    env::{load_settings, read_toml, update_toml_file, without_sensitive},
+    expiry::clear_expiry,
+    state::{data_file, ensure_state_dir},
+};
+
+/// What importing a value does to the data file
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportChange {
+    Added(Value),
+    /// The stored value, and the value replacing it
+    Changed(Value, Value),
+    Unchanged(Value),
+}
+
+/// Read values produced by another tool, from a JSON object, such as
+/// `terraform output -json`, or from a dotenv file
+pub fn read_values(path: &Path) -> Result<Table> {
+    let content = read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => json_values(&content)
+            .with_context(|| format!("Invalid values in {}", path.display())),
+        _ => dotenv_values(&content)
+            .with_context(|| format!("Invalid values in {}", path.display())),
+    }
+}
+
+/// Give imported strings the type of the stored value they replace, when
+/// they can have it, so that a port exported as `PORT="8080"` is imported
+/// as a number again
+pub fn coerce_types(stored: &Table, values: Table) -> Table {
+    values
+        .into_iter()
+        .map(|(key, value)| {
+            let coerced = match (stored.get(&key), &value) {
+                (Some(Value::Integer(_)), Value::String(s)) => {
+                    s.parse().ok().map(Value::Integer)
+                }
+                (Some(Value::Float(_)), Value::String(s)) => {
+                    s.parse().ok().map(Value::Float)
+                }
+                (Some(Value::Boolean(_)), Value::String(s)) => {
+                    s.parse().ok().map(Value::Boolean)
+                }
+                _ => None,
+            };
+            (key, coerced.unwrap_or(value))
+        })
+        .collect()
+}
+
+/// How each value would change the data file
+pub fn import_changes(
+    stored: &Table,
+    values: &Table,
+) -> Vec<(String, ImportChange)> {
+    values
+        .iter()
+        .map(|(key, new)| {
+            let change = match stored.get(key) {
+                None => ImportChange::Added(new.clone()),
+                Some(old) if old == new => ImportChange::Unchanged(new.clone()),
+                Some(old) => ImportChange::Changed(old.clone(), new.clone()),
+            };
+            (key.clone(), change)
+        })
+        .collect()
+}
+
+/// Store imported values in the data file of a target, replacing the values
+/// stored before, and return the values that were stored. Sensitive values
+/// are not stored, and imported values don't expire.
+pub fn import_values(
+    root_dir: &Path,
+    target: &str,
+    values: &Table,
+) -> Result<Table> {
+    let values = without_sensitive(&load_settings(root_dir)?, values);
+
+    ensure_state_dir(root_dir)?;
+    update_toml_file(&data_file(root_dir, target), &values)?;
+    clear_expiry(root_dir, target, &values)?;
+
+    Ok(values)
+}
+
+/// The values stored in the data file of a target, expired or not
+pub fn stored_values(root_dir: &Path, target: &str) -> Table {
+    read_toml(&data_file(root_dir, target)).unwrap_or_default()
+}
+
+fn json_values(content: &str) -> Result<Table> {
+    let JsonValue::Object(fields) = serde_json::from_str(content)? else {
+        bail!("Expected an object");
+    };
+
+    let mut out = Table::new();
+    for (key, value) in fields {
+        // Terraform outputs are objects with the value in `value`
+        let value = match value {
+            JsonValue::Object(mut output) if output.contains_key("value") => {
+                output.remove("value").unwrap_or_default()
+            }
+            value => value,
+        };
+        // TOML has no null
+        if value.is_null() {
+            continue;
+        }
+        out.insert(key, Value::try_from(value)?);
+    }
+
+    Ok(out)
+}
+
+/// Values from `KEY=value` lines, with optional `export` and quotes. Names
+/// are lower case, like the values of hitman, so `ACCESS_TOKEN` is stored as
+/// `access_token`.
+fn dotenv_values(content: &str) -> Result<Table> {
+    let mut out = Table::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("No `=` found on line {}", i + 1);
+        };
+
+        let key = key.trim();
+        let key = if key.chars().any(|c| c.is_ascii_lowercase()) {
+            key.to_string()
+        } else {
+            key.to_ascii_lowercase()
+        };
+
+        out.insert(key, dotenv_value(value.trim()));
+    }
+
+    Ok(out)
+}
+
+/// A quoted value is always a string. Other values are numbers or booleans
+/// when they look like one.
+fn dotenv_value(value: &str) -> Value {
+    if let Some(s) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\''))
+    {
+        return Value::String(s.to_string());
+    }
+    if let Some(s) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        return Value::String(
+            s.replace("\\n", "\n")
+                .replace("\\\"", "\"")
+                .replace("\\\\", "\\"),
+        );
+    }
+
+    // Comments after unquoted values
+    let value = value.split(" #").next().unwrap_or(value).trim();
+
+    // Like zip codes and ids, which are not numbers
+    let padded =
+        value.len() > 1 && value.starts_with('0') && !value.starts_with("0.");
+    if padded {
+        Value::String(value.to_string())
+    } else if let Ok(i) = value.parse() {
+        Value::Integer(i)
+    } else if let Some(f) = value
+        .parse::<f64>()
+        .ok()
+        .filter(|_| value.contains(|c: char| c.is_ascii_digit()))
+    {
+        Value::Float(f)
+    } else if let Ok(b) = value.parse() {
+        Value::Boolean(b)
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_json_values() {
+        let json = r#"{
+            "base_url": "https://api.example.com",
+            "db_port": {"value": 5432, "type": "number", "sensitive": false},
+            "zones": ["a", "b"],
+            "unset": null
+        }"#;
+
+        assert_eq!(
+            json_values(json).unwrap(),
+            toml::from_str(
+                r#"
+                base_url = "https://api.example.com"
+                db_port = 5432
+                zones = ["a", "b"]
+                "#
+            )
+            .unwrap()
+        );
+        assert!(json_values("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn reads_dotenv_values() {
+        let dotenv = r#"
+            # Exported by hitman
+            export ACCESS_TOKEN='it is secret'
+            PORT=8080
+            DEBUG=true # for now
+            ratio=0.5
+            note="a \"b\"\nc"
+            userId=007x
+            ZIP=0150
+            LIMIT=inf
+        "#;
+
+        assert_eq!(
+            dotenv_values(dotenv).unwrap(),
+            toml::from_str(
+                r#"
+                access_token = "it is secret"
+                port = 8080
+                debug = true
+                ratio = 0.5
+                note = "a \"b\"\nc"
+                userId = "007x"
+                zip = "0150"
+                limit = "inf"
+                "#
+            )
+            .unwrap()
+        );
+        assert!(dotenv_values("PORT 8080").is_err());
+    }
+
+    #[test]
+    fn finds_import_changes() {
+        let stored = toml::from_str("a = 1\nb = 2").unwrap();
+        let values = toml::from_str("a = 1\nb = 3\nc = 4").unwrap();
+
+        assert_eq!(
+            import_changes(&stored, &values),
+            vec![
+                ("a".to_string(), ImportChange::Unchanged(Value::Integer(1))),
+                (
+                    "b".to_string(),
+                    ImportChange::Changed(Value::Integer(2), Value::Integer(3))
+                ),
+                ("c".to_string(), ImportChange::Added(Value::Integer(4))),
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_values_for_a_target() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-data-import-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("hitman.toml"),
+            "[dev]\n[prod]\n[_settings]\nsensitive = [\"otp\"]\n",
+        )
+        .unwrap();
+
+        let values = toml::from_str("token = \"t\"\notp = \"123\"").unwrap();
+        let imported = import_values(&root, "prod", &values).unwrap();
+        let prod = stored_values(&root, "prod");
+        let dev = stored_values(&root, "dev");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(imported, toml::from_str("token = \"t\"").unwrap());
+        assert_eq!(prod, imported);
+        assert!(dev.is_empty());
+    }
+
+    #[test]
+    fn coerces_to_stored_types() {
+        let stored = toml::from_str(
+            "port = 1
+debug = false
+id = 2",
+        )
+        .unwrap();
+        let values = toml::from_str(
+            "port = \"8080\"\ndebug = \"true\"\nid = \"abc\"\nname = \"1\"",
+        )
+        .unwrap();
+
+        assert_eq!(
+            coerce_types(&stored, values),
+            toml::from_str(
+                "port = 8080\ndebug = true\nid = \"abc\"\nname = \"1\""
+            )
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::format::parse_csv;
+
+/// The values of one run of a request, as `(key, value)` pairs
+pub type Row = Vec<(String, String)>;
+
+/// Read the rows of a data file, to run a request once per row. A CSV file
+/// has the keys in its first line, and a JSON file is an array of objects.
+pub fn load_rows(path: &Path) -> Result<Vec<Row>> {
+    let content = read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => json_rows(&content)
+            .with_context(|| format!("Invalid data in {}", path.display())),
+        _ => Ok(csv_rows(&content)),
+    }
+}
+
+fn csv_rows(content: &str) -> Vec<Row> {
+    let mut lines = parse_csv(content).into_iter();
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    let header: Vec<String> =
+        header.iter().map(|key| key.trim().to_string()).collect();
+
+    lines
+        .filter(|line| line.iter().any(|field| !field.is_empty()))
+        .map(|line| header.iter().cloned().zip(line).collect())
+        .collect()
+}
+
+fn json_rows(content: &str) -> Result<Vec<Row>> {
+    let Value::Array(items) = serde_json::from_str(content)? else {
+        bail!("Expected an array of objects");
+    };
+
+    items
+        .into_iter()
+        .map(|item| {
+            let Value::Object(fields) = item else {
+                bail!("Expected an array of objects");
+            };
+            Ok(fields
+                .into_iter()
+                .map(|(key, value)| match value {
+                    Value::String(s) => (key, s),
+                    value => (key, value.to_string()),
+                })
+                .collect())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pairs: &[(&str, &str)]) -> Row {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_csv_rows() {
+        let csv = "name, age\nAlice,30\n\n\"Smith, Bob\",41\n";
+
+        assert_eq!(
+            csv_rows(csv),
+            vec![
+                row(&[("name", "Alice"), ("age", "30")]),
+                row(&[("name", "Smith, Bob"), ("age", "41")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_json_rows() {
+        let json = r#"[{"name": "Alice", "age": 30, "tags": ["a"]}]"#;
+
+        assert_eq!(
+            json_rows(json).unwrap(),
+            vec![row(&[
+                ("age", "30"),
+                ("name", "Alice"),
+                ("tags", "[\"a\"]")
+            ])]
+        );
+        assert!(json_rows(r#"{"name": "Alice"}"#).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashSet,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+
+use crate::{
+    directive::{Directives, DEPENDS},
+    monitor::run_check_with,
+};
+
+/// The requests to run before a request, such as a login that extracts a
+/// token, like `# @depends on: login.http`. Paths are relative to the
+/// request, and several can be given, separated by commas.
+pub fn dependencies(directives: &Directives) -> Vec<String> {
+    directives
+        .all(DEPENDS)
+        .filter_map(|directive| directive.value.strip_prefix("on:"))
+        .flat_map(|deps| deps.split(','))
+        .map(|dep| dep.trim().to_string())
+        .filter(|dep| !dep.is_empty())
+        .collect()
+}
+
+/// All requests that must run before a request, with the dependencies of
+/// each request before the request itself. Every request runs once, even when
+/// several requests depend on it.
+pub fn dependency_order(file: &Path) -> Result<Vec<PathBuf>> {
+    let mut order = Vec::new();
+    let mut done = HashSet::new();
+    let mut chain = Vec::new();
+
+    visit(file, &mut chain, &mut done, &mut order)?;
+
+    // The request itself is last
+    order.pop();
+
+    Ok(order)
+}
+
+fn visit(
+    file: &Path,
+    chain: &mut Vec<PathBuf>,
+    done: &mut HashSet<PathBuf>,
+    order: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let key = file
+        .canonicalize()
+        .with_context(|| format!("Request not found: {}", file.display()))?;
+
+    if done.contains(&key) {
+        return Ok(());
+    }
+    if let Some(pos) = chain.iter().position(|f| f == &key) {
+        let cycle: Vec<String> = chain[pos..]
+            .iter()
+            .chain(std::iter::once(&key))
+            .map(|f| f.display().to_string())
+            .collect();
+        bail!("Circular dependency: {}", cycle.join(" -> "));
+    }
+
+    chain.push(key.clone());
+    let dir = file.parent().unwrap_or(Path::new(""));
+    for dep in dependencies(&Directives::parse(&read_to_string(file)?)?) {
+        visit(&dir.join(dep), chain, done, order)?;
+    }
+    chain.pop();
+
+    done.insert(key);
+    order.push(file.to_path_buf());
+
+    Ok(())
+}
+
+/// Run the dependencies of a request, so that the values they extract are
+/// available to it. Stops at the first dependency that fails.
+pub async fn run_dependencies(
+    root_dir: &Path,
+    file: &Path,
+    options: &[(String, String)],
+) -> Result<()> {
+    for dep in dependency_order(file)? {
+        let result = run_check_with(root_dir, &dep, options, &[]).await;
+        warn!("# {}", result.summary());
+
+        if !result.passed() {
+            bail!("Dependency {} failed", dep.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_dependencies() {
+        let buf = "# @depends on: login.http, ../tenant.http\n# @depends on: csrf.http\nGET http://example.com HTTP/1.1\n";
+
+        assert_eq!(
+            dependencies(&Directives::parse(buf).unwrap()),
+            vec!["login.http", "../tenant.http", "csrf.http"]
+        );
+    }
+
+    #[test]
+    fn orders_dependencies_before_dependents() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-depends-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("orders")).unwrap();
+        fs::write(dir.join("login.http"), "GET /login\n").unwrap();
+        fs::write(
+            dir.join("tenant.http"),
+            "# @depends on: login.http\nGET /tenant\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("orders/list.http"),
+            "# @depends on: ../login.http, ../tenant.http\nGET /orders\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            dependency_order(&dir.join("orders/list.http")).unwrap(),
+            vec![
+                dir.join("orders/../login.http"),
+                dir.join("orders/../tenant.http"),
+            ]
+        );
+
+        fs::write(
+            dir.join("login.http"),
+            "# @depends on: tenant.http\nGET /login\n",
+        )
+        .unwrap();
+        let err = dependency_order(&dir.join("tenant.http")).unwrap_err();
+        assert!(err.to_string().starts_with("Circular dependency"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::history::{load_history, HistoryEntry};
+
+/// Lines of unchanged text shown around each change
+pub const DIFF_CONTEXT: usize = 3;
+
+/// The number of lines compared in full. Larger changes are shown as
+/// replacing all the changed lines, instead of line by line.
+const MAX_COMPARED: usize = 4_000_000;
+
+/// Headers that differ between every response
+pub(crate) const VOLATILE_HEADERS: [&str; 2] = ["date", "age"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+    /// Unchanged lines that are left out
+    Skipped(usize),
+}
+
+impl DiffLine {
+    pub fn is_change(&self) -> bool {
+        matches!(self, DiffLine::Added(_) | DiffLine::Removed(_))
+    }
+}
+
+/// The lines that were removed from, and added to, the old text
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut diff: Vec<DiffLine> = old[..prefix]
+        .iter()
+        .map(|l| DiffLine::Same(l.to_string()))
+        .collect();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    if old_mid.len() * new_mid.len() > MAX_COMPARED {
+        diff.extend(old_mid.iter().map(|l| DiffLine::Removed(l.to_string())));
+        diff.extend(new_mid.iter().map(|l| DiffLine::Added(l.to_string())));
+    } else {
+        diff.extend(lcs_diff(old_mid, new_mid));
+    }
+
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|l| DiffLine::Same(l.to_string())),
+    );
+
+    diff
+}
+
+/// A line diff from the longest common subsequence of the lines
+fn lcs_diff(old: &[&str], new: &[&str]) -> Vec<DiffLine> {
+    let (n, m) = (old.len(), new.len());
+
+    // Length of the common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![0usize; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[at(i, j)] = if old[i] == new[j] {
+                lengths[at(i + 1, j + 1)] + 1
+            } else {
+                lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lengths[at(i + 1, j)] >= lengths[at(i, j + 1)] {
+            diff.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|l| DiffLine::Removed(l.to_string())));
+    diff.extend(new[j..].iter().map(|l| DiffLine::Added(l.to_string())));
+
+    diff
+}
+
+/// Leave out unchanged lines that are further than `context` lines from a
+/// change
+pub fn with_context(diff: Vec<DiffLine>, context: usize) -> Vec<DiffLine> {
+    let changes: Vec<usize> = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.is_change())
+        .map(|(i, _)| i)
+        .collect();
+    let near_change = |i: usize| {
+        let pos = changes.partition_point(|&c| c < i);
+        let after = changes.get(pos).is_some_and(|&c| c - i <= context);
+        let before = pos > 0 && i - changes[pos - 1] <= context;
+        after || before
+    };
+
+    let mut out = Vec::new();
+    let mut skipped = 0;
+    for (i, line) in diff.into_iter().enumerate() {
+        if line.is_change() || near_change(i) {
+            if skipped > 0 {
+                out.push(DiffLine::Skipped(skipped));
+                skipped = 0;
+            }
+            out.push(line);
+        } else {
+            skipped += 1;
+        }
+    }
+    if skipped > 0 {
+        out.push(DiffLine::Skipped(skipped));
+    }
+
+    out
+}
+
+/// A response as text to compare, with the status, the headers in a stable
+/// order, and the body, formatted if it is JSON
+pub fn response_text(entry: &HistoryEntry) -> String {
+    let mut headers: Vec<&(String, String)> = entry
+        .headers
+        .iter()
+        .filter(|(name, _)| !VOLATILE_HEADERS.contains(&name.as_str()))
+        .collect();
+    headers.sort();
+
+    let mut text = format!("HTTP/1.1 {}\n", entry.status);
+    for (name, value) in headers {
+        text.push_str(&format!("{name}: {value}\n"));
+    }
+    text.push('\n');
+
+    let body = String::from_utf8_lossy(&entry.body);
+    match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) => text
+            .push_str(&serde_json::to_string_pretty(&json).unwrap_or_default()),
+        Err(_) => text.push_str(&body),
+    }
+
+    text
+}
+
+/// The response received for the same request file before this one
+pub fn previous_response(
+    root_dir: &Path,
+    entry: &HistoryEntry,
+) -> Result<Option<HistoryEntry>> {
+    Ok(load_history(root_dir)?
+        .into_iter()
+        .find(|e| e.file == entry.file && e.time < entry.time))
+}
+
+/// Compare a response with an earlier response, with unchanged lines left
+/// out
+pub fn diff_responses(
+    previous: &HistoryEntry,
+    current: &HistoryEntry,
+) -> Vec<DiffLine> {
+    with_context(
+        diff_lines(&response_text(previous), &response_text(current)),
+        DIFF_CONTEXT,
+    )
+}
+
+/// A diff as text, with added and removed lines colored when it's shown in
+/// a terminal
+pub fn format_diff(diff: &[DiffLine], color: bool) -> String {
+    let paint = |code: &str, text: String| {
+        if color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+
+    diff.iter()
+        .map(|line| match line {
+            DiffLine::Same(text) => format!("  {text}"),
+            DiffLine::Added(text) => paint("32", format!("+ {text}")),
+            DiffLine::Removed(text) => paint("31", format!("- {text}")),
+            DiffLine::Skipped(n) => {
+                paint("2", format!("@@ {n} unchanged lines @@"))
+            }
+        })
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_changed_lines() {
+        let old = "a\nb\nc\nd\n";
+        let new = "a\nc\nx\nd\n";
+
+        assert_eq!(
+            diff_lines(old, new),
+            vec![
+                DiffLine::Same("a".into()),
+                DiffLine::Removed("b".into()),
+                DiffLine::Same("c".into()),
+                DiffLine::Added("x".into()),
+                DiffLine::Same("d".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_lines_far_from_changes() {
+        let old: String = (0..20).map(|i| format!("{i}\n")).collect();
+        let new = old.replace("10\n", "ten\n");
+
+        let diff = with_context(diff_lines(&old, &new), 2);
+
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Skipped(8),
+                DiffLine::Same("8".into()),
+                DiffLine::Same("9".into()),
+                DiffLine::Removed("10".into()),
+                DiffLine::Added("ten".into()),
+                DiffLine::Same("11".into()),
+                DiffLine::Same("12".into()),
+                DiffLine::Skipped(7),
+            ]
+        );
+        assert_eq!(
+            format_diff(&diff[3..5], false),
+            "- 10\n+ ten\n".to_string()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+
+pub const ASSERT: &str = "assert";
+pub const DEPENDS: &str = "depends";
+pub const ETAG: &str = "etag";
+pub const OUTPUT: &str = "output";
+pub const REDIRECTS: &str = "redirects";
+pub const RETRIES: &str = "retries";
+pub const SCRIPT: &str = "script";
+pub const TIMEOUT: &str = "timeout";
+
+/// Directives that are followed by a block of comments, up to `# @end`
+const BLOCK_DIRECTIVES: &[&str] = &[SCRIPT];
+const END_PREFIX: &str = "# @end";
+
+const KNOWN_DIRECTIVES: &[&str] = &[
+    ASSERT, DEPENDS, ETAG, OUTPUT, REDIRECTS, RETRIES, SCRIPT, TIMEOUT,
+];
+
+/// A `# @name value` line of a request, like `# @timeout 5s`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub name: String,
+    pub value: String,
+    /// The code of a block directive, without the comment markers
+    pub block: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    /// With the line ending
+    Request(String),
+    /// With the lines as they were written
+    Directive(Directive, String),
+}
+
+/// A request split into its directives and the lines to send. Lines that
+/// look like directives, but with an unknown name, are comments of the
+/// request.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Directives {
+    lines: Vec<Line>,
+}
+
+impl Directives {
+    pub fn parse(buf: &str) -> Result<Self> {
+        let mut lines = Vec::new();
+        // With the line endings, so that the request is sent as written
+        let mut input = buf.split_inclusive('\n');
+
+        while let Some(line) = input.next() {
+            let Some((name, value)) = directive(line) else {
+                lines.push(Line::Request(line.to_string()));
+                continue;
+            };
+
+            let mut source = line.to_string();
+            let mut block = None;
+            if BLOCK_DIRECTIVES.contains(&name) {
+                let code = block.insert(String::new());
+                loop {
+                    let line = input.next().with_context(|| {
+                        format!("# @{name} without {END_PREFIX}")
+                    })?;
+                    source.push_str(line);
+                    if line.trim_start().starts_with(END_PREFIX) {
+                        break;
+                    }
+                    let comment =
+                        line.trim().strip_prefix('#').with_context(|| {
+                            format!(
+                                "Line of # @{name} must be a comment: {line}"
+                            )
+                        })?;
+                    code.push_str(comment.strip_prefix(' ').unwrap_or(comment));
+                    code.push('\n');
+                }
+            }
+
+            let directive = Directive {
+                name: name.to_string(),
+                value: value.to_string(),
+                block,
+            };
+            lines.push(Line::Directive(directive, source));
+        }
+
+        Ok(Self { lines })
+    }
+
+    /// The directives with a name, in the order they are given
+    pub fn all<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Directive> + 'a {
+        self.lines.iter().filter_map(move |line| match line {
+            Line::Directive(directive, _) if directive.name == name => {
+                Some(directive)
+            }
+            _ => None,
+        })
+    }
+
+    /// The value of a directive, from the last line when it is given more
+    /// than once
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Directive(directive, _) if directive.name == name => {
+                Some(directive.value.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// The request to send, without any directives
+    pub fn request(&self) -> String {
+        self.without(KNOWN_DIRECTIVES)
+    }
+
+    /// The request as it was written, without some of the directives
+    pub fn without(&self, names: &[&str]) -> String {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Request(line) => Some(line.as_str()),
+                Line::Directive(directive, source) => {
+                    let removed = names.contains(&directive.name.as_str());
+                    (!removed).then_some(source.as_str())
+                }
+            })
+            .collect()
+    }
+}
+
+/// The name and value of a directive line
+fn directive(line: &str) -> Option<(&'static str, &str)> {
+    let rest = line.trim_start().strip_prefix("# @")?;
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == ':')
+        .unwrap_or(rest.len());
+    let name = &rest[..end];
+    let name = KNOWN_DIRECTIVES.iter().find(|known| **known == name)?;
+
+    Some((name, rest[end..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = "\
+# Create an order
+# @depends on: login.http
+# @timeout 5s
+# @script before
+# vars.id = 1;
+# @end
+# @output out/{{request_name}}.json
+POST https://example.com/orders
+# @assert status == 201
+# @unknown stays
+";
+
+    #[test]
+    fn splits_directives_from_requests() {
+        let directives = Directives::parse(REQUEST).unwrap();
+
+        assert_eq!(directives.value(TIMEOUT), Some("5s"));
+        assert_eq!(directives.value(DEPENDS), Some("on: login.http"));
+        assert_eq!(directives.value(RETRIES), None);
+        let script = directives.all(SCRIPT).next().unwrap();
+        assert_eq!(script.value, "before");
+        assert_eq!(script.block.as_deref(), Some("vars.id = 1;\n"));
+
+        assert_eq!(
+            directives.request(),
+            "# Create an order\n\
+             POST https://example.com/orders\n\
+             # @unknown stays\n"
+        );
+        assert_eq!(
+            directives.without(&[SCRIPT, OUTPUT]),
+            "# Create an order\n\
+             # @depends on: login.http\n\
+             # @timeout 5s\n\
+             POST https://example.com/orders\n\
+             # @assert status == 201\n\
+             # @unknown stays\n"
+        );
+    }
+
+    #[test]
+    fn fails_on_unfinished_blocks() {
+        assert!(Directives::parse("# @script before\n# 1\n").is_err());
+        assert!(Directives::parse("# @script after\nx\n# @end\n").is_err());
+    }
+
+    #[test]
+    fn keeps_requests_as_written() {
+        let buf = "POST /\r\n# @timeout 1s\r\n\r\n{\"a\": 1}";
+
+        let directives = Directives::parse(buf).unwrap();
+
+        assert_eq!(directives.value(TIMEOUT), Some("1s"));
+        assert_eq!(directives.request(), "POST /\r\n\r\n{\"a\": 1}");
+        assert_eq!(directives.without(&[]), buf);
+    }
+}
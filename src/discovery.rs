@@ -0,0 +1,431 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::channel,
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    WalkBuilder, WalkState,
+};
+use notify::{
+    recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode,
+    Watcher,
+};
+use toml::{Table, Value};
+
+use crate::env::read_and_merge_config;
+
+/// Can hide requests that are in git, such as generated ones
+const HITMAN_IGNORE_FILE: &str = ".hitmanignore";
+
+/// Files with patterns of paths to leave out when looking for requests, in
+/// the format of `.gitignore`
+const IGNORE_FILES: &[&str] = &[".gitignore", HITMAN_IGNORE_FILE];
+
+/// Extensions of request files, with `.ws` for WebSocket connections
+const REQUEST_EXTENSIONS: &[&str] = &[".http", ".ws"];
+
+/// Never looked into for requests
+const SKIPPED_DIRS: &[&str] = &[".git", ".hitman", "node_modules"];
+
+/// How long the files must be left alone before searching again, so that a
+/// checkout or a build changing many files leads to one search
+const RESCAN_DELAY: Duration = Duration::from_millis(300);
+
+/// Where to look for requests in a project, such as:
+///
+/// ```toml
+/// [_discovery]
+/// include = ["api/"]
+/// exclude = ["**/generated/", "*.gen.http"]
+/// roots = ["../shared-requests"]
+/// ```
+///
+/// Patterns have the format of `.gitignore`, relative to the project root,
+/// and extra roots are relative to the project root too.
+const DISCOVERY_KEY: &str = "_discovery";
+
+#[derive(Debug, Clone, Default)]
+pub struct Discovery {
+    /// Only requests matching one of these are found, when given
+    include: Vec<String>,
+    exclude: Vec<String>,
+    roots: Vec<PathBuf>,
+}
+
+impl Discovery {
+    pub fn from_config(config: &Table) -> Result<Self> {
+        let Some(conf) = config.get(DISCOVERY_KEY) else {
+            return Ok(Self::default());
+        };
+        let Value::Table(conf) = conf else {
+            bail!("Invalid {DISCOVERY_KEY} section");
+        };
+
+        let strings = |key: &str| -> Result<Vec<String>> {
+            match conf.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::String(s)) => Ok(vec![s.clone()]),
+                Some(Value::Array(values)) => Ok(values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()),
+                Some(_) => bail!("Invalid {key} in {DISCOVERY_KEY}"),
+            }
+        };
+
+        // Invalid patterns fail here, rather than on every search
+        let include = strings("include")?;
+        let exclude = strings("exclude")?;
+        patterns(Path::new(""), &include)?;
+        patterns(Path::new(""), &exclude)?;
+
+        Ok(Self {
+            include,
+            exclude,
+            roots: strings("roots")?.into_iter().map(PathBuf::from).collect(),
+        })
+    }
+
+    fn is_included(&self, path: &Path) -> Result<bool> {
+        if self.include.is_empty() {
+            return Ok(true);
+        }
+
+        // A file is included by its own path, or by one of its directories
+        Ok(patterns(Path::new(""), &self.include)?
+            .matched_path_or_any_parents(path, false)
+            .is_ignore())
+    }
+}
+
+/// Patterns in the format of `.gitignore`, relative to a directory
+fn patterns(root: &Path, lines: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for line in lines {
+        builder.add_line(None, line)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+/// The patterns of the ignore files at the root, and the patterns excluded
+/// by the discovery config
+fn root_ignores(root: &Path, exclude: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for name in IGNORE_FILES {
+        let file = root.join(name);
+        if file.exists() {
+            if let Some(err) = builder.add(file) {
+                bail!(err);
+            }
+        }
+    }
+    for line in exclude {
+        builder.add_line(None, line)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+/// The requests of a project, with paths relative to the project root, as
+/// given by the discovery config of the project
+pub fn find_project_requests(root_dir: &Path) -> Result<Vec<PathBuf>> {
+    let discovery = Discovery::from_config(&read_and_merge_config(root_dir)?)?;
+
+    let mut files = Vec::new();
+    for path in walk_requests(root_dir, &discovery.exclude)? {
+        if discovery.is_included(&path)? {
+            files.push(path);
+        }
+    }
+
+    for root in &discovery.roots {
+        let found = walk_requests(&root_dir.join(root), &discovery.exclude)?;
+        files.extend(found.into_iter().map(|path| root.join(path)));
+    }
+
+    Ok(files)
+}
+
+/// Whether a file name is that of a request
+pub fn is_request_file(name: &str) -> bool {
+    REQUEST_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Find all request files below a directory, with paths relative to it.
+/// Paths in ignore files are left out, and the directories are searched in
+/// parallel.
+pub fn find_requests(root: &Path) -> Result<Vec<PathBuf>> {
+    walk_requests(root, &[])
+}
+
+fn walk_requests(root: &Path, exclude: &[String]) -> Result<Vec<PathBuf>> {
+    let exclude = patterns(root, exclude)?;
+
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(false)
+        .ignore(false)
+        .parents(false)
+        .require_git(false)
+        .git_global(false)
+        .git_exclude(false)
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            let name = entry.file_name().to_string_lossy();
+            let skipped = is_dir && SKIPPED_DIRS.contains(&name.as_ref());
+
+            entry.depth() == 0
+                || !(skipped
+                    || exclude.matched(entry.path(), is_dir).is_ignore())
+        });
+    builder.add_custom_ignore_filename(HITMAN_IGNORE_FILE);
+
+    let found = Mutex::new(Vec::new());
+    // Unreadable directories are skipped, like before
+    builder.build_parallel().run(|| {
+        Box::new(|entry| {
+            let Ok(entry) = entry else {
+                return WalkState::Continue;
+            };
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            let name = entry.file_name().to_string_lossy();
+            if is_file && is_request_file(&name) {
+                if let Ok(path) = entry.path().strip_prefix(root) {
+                    found.lock().unwrap().push(path.to_path_buf());
+                }
+            }
+
+            WalkState::Continue
+        })
+    });
+
+    let mut files = found.into_inner().unwrap();
+    files.sort();
+
+    Ok(files)
+}
+
+/// The requests of a project, found once and kept up to date by watching
+/// the files of the project
+pub struct RequestIndex {
+    root: PathBuf,
+    requests: Arc<Mutex<Option<Vec<PathBuf>>>>,
+    changed: Arc<AtomicBool>,
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl RequestIndex {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            requests: Arc::new(Mutex::new(None)),
+            changed: Arc::new(AtomicBool::new(false)),
+            watcher: None,
+        }
+    }
+
+    /// Search again in the background when requests or ignore files are
+    /// added, removed or renamed, once the files are left alone for a while
+    pub fn watch(&mut self) -> Result<()> {
+        let root = self.root.clone();
+        let requests = self.requests.clone();
+        let changed = self.changed.clone();
+
+        let exclude =
+            Discovery::from_config(&read_and_merge_config(&root)?)?.exclude;
+        let ignores = Arc::new(Mutex::new(root_ignores(&root, &exclude)?));
+
+        let (sender, events) = channel();
+        let watcher = {
+            let root = root.clone();
+            let ignores = ignores.clone();
+            recommended_watcher(move |res: notify::Result<Event>| {
+                let Ok(event) = res else {
+                    return;
+                };
+                if affects_requests(&root, &ignores.lock().unwrap(), &event) {
+                    let _ = sender.send(());
+                }
+            })
+        };
+        let mut watcher = watcher?;
+
+        // Ends when the watcher, and with it the sender, is dropped
+        thread::spawn(move || {
+            while events.recv().is_ok() {
+                while events.recv_timeout(RESCAN_DELAY).is_ok() {}
+
+                if let Ok(found) = find_project_requests(&root) {
+                    let mut requests = requests.lock().unwrap();
+                    if requests.as_ref() != Some(&found) {
+                        *requests = Some(found);
+                        changed.store(true, Ordering::Relaxed);
+                    }
+                }
+                if let Ok(updated) = root_ignores(&root, &exclude) {
+                    *ignores.lock().unwrap() = updated;
+                }
+            }
+        });
+
+        watcher.watch(&self.root, RecursiveMode::Recursive)?;
+        self.watcher = Some(watcher);
+
+        Ok(())
+    }
+
+    /// The requests, searched for when not known yet
+    pub fn requests(&self) -> Result<Vec<PathBuf>> {
+        let mut requests = self.requests.lock().unwrap();
+        if let Some(requests) = requests.as_ref() {
+            return Ok(requests.clone());
+        }
+
+        let found = find_project_requests(&self.root)?;
+        *requests = Some(found.clone());
+
+        Ok(found)
+    }
+
+    /// Whether the requests changed since last asked
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Whether an event may change the requests found. Events in skipped or
+/// ignored directories, such as `node_modules`, are left alone.
+fn affects_requests(root: &Path, ignores: &Gitignore, event: &Event) -> bool {
+    let relevant_kind = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_)
+    );
+
+    relevant_kind
+        && event.paths.iter().any(|path| {
+            let Ok(rel) = path.strip_prefix(root) else {
+                return false;
+            };
+            let name = rel
+                .file_name()
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default();
+            let in_skipped_dir = rel.components().any(|c| {
+                SKIPPED_DIRS.contains(&c.as_os_str().to_string_lossy().as_ref())
+            });
+            let is_dir = path.is_dir();
+
+            !in_skipped_dir
+                && !ignores.matched_path_or_any_parents(rel, is_dir).is_ignore()
+                && (is_request_file(&name)
+                    || IGNORE_FILES.contains(&name.as_ref())
+                    || is_dir)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use notify::event::CreateKind;
+    use std::fs;
+
+    #[test]
+    fn reads_discovery_config() {
+        let config: Table = toml::from_str(
+            r#"
+            [_discovery]
+            include = ["api/", "login.http"]
+            exclude = "**/generated/"
+            roots = ["../shared"]
+            "#,
+        )
+        .unwrap();
+
+        let discovery = Discovery::from_config(&config).unwrap();
+
+        let included = |path| discovery.is_included(Path::new(path)).unwrap();
+        assert!(included("api/v1/orders.http"));
+        assert!(included("login.http"));
+        assert!(!included("web/test.http"));
+        assert!(patterns(Path::new(""), &discovery.exclude)
+            .unwrap()
+            .matched(Path::new("api/generated"), true)
+            .is_ignore());
+        assert_eq!(discovery.roots, vec![PathBuf::from("../shared")]);
+    }
+
+    #[test]
+    fn leaves_ignored_paths_out_of_watching() {
+        let root = Path::new("/project");
+        let ignores = patterns(root, &["build/".into()]).unwrap();
+        let created = |path: &str| {
+            Event::new(EventKind::Create(CreateKind::File))
+                .add_path(root.join(path))
+        };
+
+        assert!(affects_requests(root, &ignores, &created("api/new.http")));
+        assert!(affects_requests(root, &ignores, &created(".hitmanignore")));
+        assert!(!affects_requests(root, &ignores, &created("notes.txt")));
+        assert!(!affects_requests(
+            root,
+            &ignores,
+            &created("web/node_modules/pkg/test.http")
+        ));
+        assert!(!affects_requests(
+            root,
+            &ignores,
+            &created("build/out/test.http")
+        ));
+    }
+
+    #[test]
+    fn finds_requests_outside_ignored_paths() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-discovery-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["api/v1", "web/dist", "web/node_modules/pkg", ".git"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "dist/\n").unwrap();
+        fs::write(dir.join("api/.hitmanignore"), "*.gen.http\n").unwrap();
+        for file in [
+            "login.http",
+            "api/v1/orders.http",
+            "api/v1/orders.gen.http",
+            "web/dist/test.http",
+            "web/node_modules/pkg/test.http",
+            ".git/x.http",
+            "notes.txt",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        assert_eq!(
+            find_requests(&dir).unwrap(),
+            vec![
+                PathBuf::from("api/v1/orders.http"),
+                PathBuf::from("login.http"),
+            ]
+        );
+
+        fs::write(dir.join("hitman.toml"), "[dev]\n").unwrap();
+        let index = RequestIndex::new(&dir);
+        assert_eq!(index.requests().unwrap().len(), 2);
+        fs::write(dir.join("new.http"), "").unwrap();
+        // Without watching, the index is kept as it was
+        assert_eq!(index.requests().unwrap().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    fs::read_to_string,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use reqwest::Url;
+use toml::{Table, Value};
+
+use crate::{
+    directive::{Directives, OUTPUT},
+    env::find_available_requests,
+    include::include_body,
+    proxy::target_proxy,
+    request::build_client,
+    security::Grade,
+    substitute::{substitute, SubstituteError},
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The result of one diagnostic check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub grade: Grade,
+    pub name: String,
+    pub message: String,
+}
+
+impl Check {
+    fn new(grade: Grade, name: &str, message: impl Into<String>) -> Self {
+        Self {
+            grade,
+            name: name.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Diagnose why requests to a target might fail: resolve the hosts of all
+/// URLs in the target, test TCP and TLS connectivity, show which proxy is
+/// used, and check that the values used by request files resolve.
+pub async fn diagnose(root_dir: &Path, env: &Table) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let urls = base_urls(env);
+    if urls.is_empty() {
+        checks.push(Check::new(
+            Grade::Warn,
+            "urls",
+            "No URL values found in the target",
+        ));
+    }
+
+    for (key, url) in &urls {
+        checks.extend(check_url(key, url, env).await);
+    }
+
+    checks.extend(check_requests(root_dir, env));
+
+    checks
+}
+
+async fn check_url(key: &str, url: &Url, env: &Table) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let Some(host) = url.host_str() else {
+        checks.push(Check::new(Grade::Fail, key, format!("No host in {url}")));
+        return checks;
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    match target_proxy(url, env) {
+        Ok(Some(proxy)) => checks.push(Check::new(
+            Grade::Warn,
+            key,
+            format!("Using proxy {proxy}"),
+        )),
+        Ok(None) => checks.push(Check::new(Grade::Pass, key, "No proxy")),
+        Err(err) => checks.push(Check::new(Grade::Fail, key, err.to_string())),
+    }
+
+    let addrs: Vec<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs.collect(),
+        Err(err) => {
+            checks.push(Check::new(
+                Grade::Fail,
+                key,
+                format!("Could not resolve {host}: {err}"),
+            ));
+            return checks;
+        }
+    };
+    let ips: Vec<String> = addrs.iter().map(|a| a.ip().to_string()).collect();
+    checks.push(Check::new(
+        Grade::Pass,
+        key,
+        format!("{host} resolves to {}", ips.join(", ")),
+    ));
+
+    let t = Instant::now();
+    match addrs
+        .iter()
+        .find_map(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).ok())
+    {
+        Some(_) => checks.push(Check::new(
+            Grade::Pass,
+            key,
+            format!("TCP connection to port {port} in {:.2?}", t.elapsed()),
+        )),
+        None => {
+            checks.push(Check::new(
+                Grade::Fail,
+                key,
+                format!("Could not connect to port {port}"),
+            ));
+            return checks;
+        }
+    }
+
+    // An HTTP request shows if TLS and the proxy work too
+    let protocol = if url.scheme() == "https" {
+        "TLS and HTTP"
+    } else {
+        "HTTP"
+    };
+    let result = match build_client(env) {
+        Ok(client) => client.head(url.clone()).send().await.map_err(|err| {
+            let mut message = err.to_string();
+            let mut source = std::error::Error::source(&err);
+            while let Some(err) = source {
+                message.push_str(&format!(": {err}"));
+                source = err.source();
+            }
+            message
+        }),
+        Err(err) => Err(err.to_string()),
+    };
+    match result {
+        Ok(response) => checks.push(Check::new(
+            Grade::Pass,
+            key,
+            format!("{protocol} OK (status {})", response.status()),
+        )),
+        Err(err) => checks.push(Check::new(
+            Grade::Fail,
+            key,
+            format!("{protocol} failed: {err}"),
+        )),
+    }
+
+    checks
+}
+
+fn check_requests(root_dir: &Path, env: &Table) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let Ok(files) = find_available_requests(root_dir) else {
+        return checks;
+    };
+
+    for file in files {
+        let Ok(content) = read_to_string(root_dir.join(&file)) else {
+            continue;
+        };
+        let dir = root_dir.join(&file);
+        let dir = dir.parent().unwrap_or(root_dir);
+        let content = match Directives::parse(&content)
+            .and_then(|d| include_body(&d.without(&[OUTPUT]), dir))
+        {
+            Ok(content) => content,
+            Err(err) => {
+                checks.push(Check::new(
+                    Grade::Warn,
+                    &file.display().to_string(),
+                    format!("{err:#}"),
+                ));
+                continue;
+            }
+        };
+        let missing = missing_keys(&content, env);
+        if !missing.is_empty() {
+            checks.push(Check::new(
+                Grade::Warn,
+                &file.display().to_string(),
+                format!("Missing values for {}", missing.join(", ")),
+            ));
+        }
+    }
+
+    if checks.is_empty() {
+        checks.push(Check::new(
+            Grade::Pass,
+            "requests",
+            "All values used by requests are available",
+        ));
+    }
+
+    checks
+}
+
+/// Find the string values of a target that are HTTP URLs, after substituting
+/// references to other values.
+pub fn base_urls(env: &Table) -> Vec<(String, Url)> {
+    env.iter()
+        .filter_map(|(key, value)| {
+            let Value::String(s) = value else {
+                return None;
+            };
+            let s = substitute(s, env).ok()?;
+            let url = Url::parse(s.trim()).ok()?;
+            matches!(url.scheme(), "http" | "https").then(|| (key.clone(), url))
+        })
+        .collect()
+}
+
+/// Find the keys used in a template, that have no value and no fallback.
+/// Values that would be prompted for, or selected from a list, are fine.
+pub fn missing_keys(template: &str, env: &Table) -> Vec<String> {
+    let mut env = env.clone();
+    let mut missing = Vec::new();
+
+    loop {
+        match substitute(template, &env) {
+            Err(SubstituteError::ValueNotFound { key, fallback })
+                if !env.contains_key(&key) =>
+            {
+                if fallback.is_none() {
+                    missing.push(key.clone());
+                }
+                env.insert(key, Value::String(String::new()));
+            }
+            Err(SubstituteError::MultipleValuesFound { key, .. }) => {
+                env.insert(key, Value::String(String::new()));
+            }
+            _ => break,
+        }
+    }
+
+    missing
+}
+
+/// The proxy that will be used for a URL, from the standard environment
+/// variables
+pub fn proxy_for(url: &Url) -> Option<String> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .or_else(|_| std::env::var(name.to_lowercase()))
+            .ok()
+            .filter(|v| !v.is_empty())
+    };
+
+    if let (Some(host), Some(no_proxy)) = (url.host_str(), var("NO_PROXY")) {
+        if no_proxy_matches(host, &no_proxy) {
+            return None;
+        }
+    }
+
+    match url.scheme() {
+        "https" => var("HTTPS_PROXY"),
+        _ => var("HTTP_PROXY"),
+    }
+    .or_else(|| var("ALL_PROXY"))
+}
+
+/// Check if a host is excluded from proxying by a `NO_PROXY` value
+pub fn no_proxy_matches(host: &str, no_proxy: &str) -> bool {
+    no_proxy.split(',').map(str::trim).any(|entry| {
+        if entry == "*" {
+            return true;
+        }
+        let domain = entry.trim_start_matches('*').trim_start_matches('.');
+
+        !domain.is_empty()
+            && (host == domain || host.ends_with(&format!(".{domain}")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_base_urls() {
+        let env: Table = toml::from_str(
+            r#"
+            host = "example.com"
+            base_url = "https://{{host}}/api"
+            name = "apple"
+            ftp = "ftp://example.com"
+            "#,
+        )
+        .unwrap();
+
+        let urls = base_urls(&env);
+
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].0, "base_url");
+        assert_eq!(urls[0].1.as_str(), "https://example.com/api");
+    }
+
+    #[test]
+    fn finds_missing_keys() {
+        let env: Table = toml::from_str(
+            r#"
+            base_url = "https://example.com"
+            apple_id = [1, 2]
+            "#,
+        )
+        .unwrap();
+        let template = "GET {{base_url}}/apples/{{apple_id}}?q={{query}}&limit={{limit | 10}}\nAuthorization: {{token}}";
+
+        assert_eq!(missing_keys(template, &env), vec!["query", "token"]);
+    }
+
+    #[test]
+    fn matches_no_proxy_entries() {
+        assert!(no_proxy_matches(
+            "api.example.com",
+            "localhost,.example.com"
+        ));
+        assert!(no_proxy_matches("example.com", "example.com"));
+        assert!(no_proxy_matches("anything", "*"));
+        assert!(!no_proxy_matches("example.org", "localhost,example.com"));
+        assert!(!no_proxy_matches("notexample.com", "example.com"));
+    }
+}
@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::Response;
+use sha2::{Digest, Sha256};
+
+use crate::format::BodyFormat;
+use crate::util::format_size;
+
+/// Width of the bar drawn by [progress_bar]
+const BAR_WIDTH: usize = 20;
+
+/// Whether a response is downloaded as a file, with progress, instead of
+/// being read as text
+pub fn is_download(format: BodyFormat) -> bool {
+    matches!(format, BodyFormat::Binary | BodyFormat::Image)
+}
+
+/// Read the body of a response, calling `progress` with the bytes received
+/// so far, and the size of the body when the server gave it
+pub async fn download(
+    mut response: Response,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Vec<u8>> {
+    let total = response.content_length();
+    let mut data = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        progress(data.len() as u64, total);
+    }
+
+    Ok(data)
+}
+
+/// How far a download has come, like `[=====>    ] 52% 1.5 MB of 2.9 MB`.
+/// Without the total size, only the size received is shown.
+pub fn progress_bar(received: u64, total: Option<u64>) -> String {
+    let Some(total) = total.filter(|t| *t > 0) else {
+        return format!("{} received", format_size(received));
+    };
+
+    let ratio = (received as f64 / total as f64).min(1.0);
+    let filled = (ratio * BAR_WIDTH as f64) as usize;
+    let bar = match filled {
+        0 => " ".repeat(BAR_WIDTH),
+        n if n >= BAR_WIDTH => "=".repeat(BAR_WIDTH),
+        n => format!("{}>{}", "=".repeat(n - 1), " ".repeat(BAR_WIDTH - n)),
+    };
+
+    format!(
+        "[{bar}] {:.0}% {} of {}",
+        ratio * 100.0,
+        format_size(received),
+        format_size(total)
+    )
+}
+
+/// The SHA-256 checksum of a body, as hex
+pub fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The size, speed and checksum of a finished download
+pub fn download_summary(data: &[u8], started: Instant) -> String {
+    let elapsed = started.elapsed().max(Duration::from_millis(1));
+
+    format!(
+        "Downloaded {} in {:.2?} ({}/s), sha256 {}",
+        format_size(data.len() as u64),
+        elapsed,
+        format_size((data.len() as f64 / elapsed.as_secs_f64()) as u64),
+        checksum(data)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_progress_bars() {
+        assert_eq!(
+            progress_bar(512, Some(1024)),
+            "[=========>          ] 50% 512 B of 1.0 KB"
+        );
+        assert_eq!(
+            progress_bar(2048, Some(2048)),
+            "[====================] 100% 2.0 KB of 2.0 KB"
+        );
+        assert_eq!(
+            progress_bar(0, Some(2048)),
+            "[                    ] 0% 0 B of 2.0 KB"
+        );
+        assert_eq!(progress_bar(1536, None), "1.5 KB received");
+    }
+
+    #[test]
+    fn computes_checksums() {
+        assert_eq!(
+            checksum(b"hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+}
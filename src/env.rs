@@ -1,64 +1,37 @@
 use anyhow::{bail, Result};
 use inquire::Select;
-use log::warn;
-use reqwest::cookie::CookieStore;
-use reqwest::Url;
-use std::env::current_dir;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::env::{self, current_dir};
 use std::fs::{self, read_to_string};
 use std::path::{Path, PathBuf};
 use toml::{Table as TomlTable, Value};
-use walkdir::WalkDir;
-
-use crate::prompt::fuzzy_match;
-
-const CONFIG_FILE: &str = "hitman.toml";
-const LOCAL_CONFIG_FILE: &str = "hitman.local.toml";
-const TARGET_FILE: &str = ".hitman-target";
-const DATA_FILE: &str = ".hitman-data.toml";
-
-const COOKIE_KEY: &str = "Cookies";
-pub struct HitmanCookieJar;
-
-impl CookieStore for HitmanCookieJar {
-    fn set_cookies(
-        &self,
-        cookie_headers: &mut dyn Iterator<Item = &reqwest::header::HeaderValue>,
-        _: &Url,
-    ) {
-        let cookies = cookie_headers
-            .filter_map(|c| {
-                let s = std::str::from_utf8(c.as_bytes()).ok()?;
-                Some(Value::String(s.to_string()))
-            })
-            .collect::<Vec<_>>();
-
-        let mut out = TomlTable::new();
-        out.insert(COOKIE_KEY.to_string(), Value::Array(cookies));
-
-        let _ = update_data(&out);
-    }
 
-    fn cookies(&self, _: &Url) -> Option<reqwest::header::HeaderValue> {
-        let root_dir = find_root_dir().ok()??;
-        let data_file = read_toml(&root_dir.join(DATA_FILE)).ok()?;
-
-        match data_file.get(COOKIE_KEY)? {
-            Value::Array(arr) => {
-                let headers = arr
-                    .iter()
-                    .filter_map(|it| cookie::Cookie::parse(it.as_str()?).ok())
-                    .map(|cookie| {
-                        format!("{}={}", cookie.name(), cookie.value())
-                    })
-                    .collect::<Vec<_>>()
-                    .join("; ");
-
-                reqwest::header::HeaderValue::from_str(&headers).ok()
-            }
-            _ => None,
-        }
-    }
-}
+use crate::discovery::{find_project_requests, find_requests};
+use crate::expiry::without_expired;
+use crate::headers::HEADERS_KEY;
+use crate::oauth::AUTH_KEY;
+use crate::overwrite::resolve_overwrites;
+use crate::plugin::PLUGINS_KEY;
+use crate::prompt::{fuzzy_match, get_interaction};
+use crate::proxy::PROXY_KEY;
+use crate::script::SCRIPTS_KEY;
+use crate::shared::shared_values;
+use crate::state::{
+    data_file, ensure_state_dir, session_target_file, target_file,
+};
+use crate::timeout::HTTP_KEY;
+use crate::tls::TLS_KEY;
+
+pub(crate) const CONFIG_FILE: &str = "hitman.toml";
+pub(crate) const LOCAL_CONFIG_FILE: &str = "hitman.local.toml";
+const USER_CONFIG_FILE: &str = "config.toml";
+pub(crate) const SETTINGS_KEY: &str = "_settings";
+/// The name of the target, added to the values of a target
+const TARGET_KEY: &str = "_target";
+const PINS_KEY: &str = "_pins";
+const TARGET_ENV_VAR: &str = "HITMAN_TARGET";
+const SESSION_ENV_VAR: &str = "HITMAN_SESSION";
 
 pub fn select_env(root_dir: &Path) -> Result<()> {
     let items = find_environments(root_dir)?;
@@ -74,17 +47,34 @@ pub fn select_env(root_dir: &Path) -> Result<()> {
 }
 
 pub fn set_target(root_dir: &Path, selected: &str) -> Result<()> {
-    fs::write(root_dir.join(TARGET_FILE), selected)?;
+    let file_path = selected_target_file(root_dir);
+    ensure_state_dir(root_dir)?;
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file_path, selected)?;
     warn!("Target set to {}", selected);
 
+    if env::var_os(TARGET_ENV_VAR).is_some() {
+        warn!(
+            "# Overridden by the {} environment variable",
+            TARGET_ENV_VAR
+        );
+    }
+
     Ok(())
 }
 
 // The root dir is where we find hitman.toml,
 // scanning parent directories until we find it
 pub fn find_root_dir() -> Result<Option<PathBuf>> {
-    let mut dir = current_dir()?;
-    let res = loop {
+    Ok(find_root_dir_from(&current_dir()?))
+}
+
+/// The closest directory with a hitman.toml, from a directory and up
+fn find_root_dir_from(dir: &Path) -> Option<PathBuf> {
+    let mut dir = dir.to_path_buf();
+    loop {
         if dir.join(CONFIG_FILE).exists() {
             break Some(dir);
         }
@@ -93,21 +83,43 @@ pub fn find_root_dir() -> Result<Option<PathBuf>> {
         } else {
             break None;
         }
-    };
+    }
+}
+
+/// The directory for user specific configuration, shared by all projects
+pub fn user_config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|h| PathBuf::from(h).join(".config"))
+        })
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
 
-    Ok(res)
+    Some(base.join("hitman"))
+}
+
+pub fn read_user_config() -> Result<TomlTable> {
+    match user_config_dir() {
+        Some(dir) if dir.join(USER_CONFIG_FILE).exists() => {
+            read_toml(&dir.join(USER_CONFIG_FILE))
+        }
+        _ => Ok(TomlTable::new()),
+    }
 }
 
 pub fn find_environments(root_dir: &Path) -> Result<Vec<String>> {
     let config = read_and_merge_config(root_dir)?;
-    let keys: Vec<String> = config
+
+    Ok(list_environments(&config))
+}
+
+fn list_environments(config: &TomlTable) -> Vec<String> {
+    config
         .keys()
         .filter(|k| !k.starts_with('_'))
         .filter(|k| config.get(*k).expect("key must exist").is_table())
         .map(|k| k.to_string())
-        .collect();
-
-    Ok(keys)
+        .collect()
 }
 
 /// Get all files to watch for changes in watch mode.
@@ -118,7 +130,7 @@ pub fn watch_list(root_dir: &Path, file_path: &Path) -> Vec<PathBuf> {
     vec![
         file_path.into(),
         file_path.with_extension("http.toml"),
-        root_dir.join(TARGET_FILE),
+        selected_target_file(root_dir),
         root_dir.join(CONFIG_FILE),
         root_dir.join(LOCAL_CONFIG_FILE),
     ]
@@ -129,14 +141,102 @@ pub fn load_env(
     file_path: &Path,
     options: &[(String, String)],
 ) -> Result<TomlTable> {
-    use Value::Table;
+    let config = read_and_merge_config(root_dir)?;
 
-    let target = get_target(root_dir);
+    let target = pins_from(&config)
+        .remove(&request_key(root_dir, file_path))
+        .unwrap_or_else(|| get_target(root_dir));
 
-    let mut env = TomlTable::new();
+    // Values shared by the team, which local values override
+    let mut env = shared_values(root_dir, &config, &target)?;
+    env.extend(target_env(&config, &target)?);
+
+    env.insert(SETTINGS_KEY.into(), Value::Table(settings_from(&config)));
+    env.insert(TARGET_KEY.into(), Value::String(target.clone()));
+
+    if let Ok(content) = read_toml(&file_path.with_extension("http.toml")) {
+        env.extend(content)
+    }
+
+    // Values captured with the target, so that a token of one target is
+    // never sent to another
+    if let Ok(content) = read_toml(&data_file(root_dir, &target)) {
+        env.extend(without_expired(root_dir, &target, content))
+    }
+
+    // Extra values passed on the command line
+    for (k, v) in options {
+        env.insert(k.clone(), Value::String(v.clone()));
+    }
+
+    Ok(env)
+}
+
+/// Requests that always use a fixed target, whatever target is selected.
+/// These are given in the `[_pins]` table of the config, with paths relative
+/// to the root directory, such as `"stripe/charge.http" = "stripe"`.
+pub fn find_pins(root_dir: &Path) -> Result<HashMap<String, String>> {
+    let config = read_and_merge_config(root_dir)?;
+
+    Ok(pins_from(&config))
+}
+
+/// The target a request is pinned to, if any
+pub fn pinned_target(
+    root_dir: &Path,
+    file_path: &Path,
+) -> Result<Option<String>> {
+    Ok(find_pins(root_dir)?.remove(&request_key(root_dir, file_path)))
+}
+
+fn pins_from(config: &TomlTable) -> HashMap<String, String> {
+    match config.get(PINS_KEY) {
+        Some(Value::Table(pins)) => pins
+            .iter()
+            .filter_map(|(path, target)| {
+                Some((normalize_path(path), target.as_str()?.to_string()))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
 
+/// The path of a request relative to the root directory, as used in config
+pub(crate) fn request_key(root_dir: &Path, file_path: &Path) -> String {
+    let path = current_dir()
+        .map(|cwd| cwd.join(file_path))
+        .unwrap_or_else(|_| file_path.to_path_buf());
+    let path = path.strip_prefix(root_dir).unwrap_or(file_path);
+
+    normalize_path(&path.to_string_lossy())
+}
+
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    path.strip_prefix("./").unwrap_or(&path).to_string()
+}
+
+/// The values of a target, as used by requests, but without any request
+/// specific values
+pub fn load_target_env(root_dir: &Path, target: &str) -> Result<TomlTable> {
     let config = read_and_merge_config(root_dir)?;
 
+    let mut env = target_env(&config, target)?;
+
+    env.insert(SETTINGS_KEY.into(), Value::Table(settings_from(&config)));
+    env.insert(TARGET_KEY.into(), Value::String(target.to_string()));
+
+    if let Ok(content) = read_toml(&data_file(root_dir, target)) {
+        env.extend(without_expired(root_dir, target, content))
+    }
+
+    Ok(env)
+}
+
+/// The values defined for a target, including the global defaults
+fn target_env(config: &TomlTable, target: &str) -> Result<TomlTable> {
+    let mut env = TomlTable::new();
+
     // Global defaults
     env.extend(
         config
@@ -146,59 +246,310 @@ pub fn load_env(
             .collect::<Vec<_>>(),
     );
 
-    if let Some(Table(t)) = config.get(&target) {
+    if let Some(Value::Table(t)) = config.get(target) {
         env.extend(t.clone());
     } else {
         bail!("`{}` not found in config", target);
     }
 
-    if let Ok(content) = read_toml(&file_path.with_extension("http.toml")) {
-        env.extend(content)
+    // Default headers, plugins, scripts, proxy, timeouts, TLS and
+    // authentication of the target are merged with the global ones
+    for key in [
+        HEADERS_KEY,
+        PLUGINS_KEY,
+        SCRIPTS_KEY,
+        PROXY_KEY,
+        HTTP_KEY,
+        TLS_KEY,
+        AUTH_KEY,
+    ] {
+        let mut table = match config.get(key) {
+            Some(Value::Table(t)) => t.clone(),
+            _ => TomlTable::new(),
+        };
+        if let Some(Value::Table(t)) = env.get(key) {
+            table.extend(t.clone());
+        }
+        if !table.is_empty() {
+            env.insert(key.into(), Value::Table(table));
+        }
     }
 
-    // FIXME state per environment
-    if let Ok(content) = read_toml(&root_dir.join(DATA_FILE)) {
-        env.extend(content)
+    Ok(env)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct EnvDiff {
+    pub differs: Vec<(String, Value, Value)>,
+    pub only_left: Vec<(String, Value)>,
+    pub only_right: Vec<(String, Value)>,
+    pub identical: Vec<(String, Value)>,
+}
+
+/// Compare the values of two targets, to find configuration drift
+pub fn diff_targets(
+    root_dir: &Path,
+    left: &str,
+    right: &str,
+) -> Result<EnvDiff> {
+    let config = read_and_merge_config(root_dir)?;
+
+    Ok(diff_envs(
+        &target_env(&config, left)?,
+        &target_env(&config, right)?,
+    ))
+}
+
+fn diff_envs(left: &TomlTable, right: &TomlTable) -> EnvDiff {
+    let mut diff = EnvDiff::default();
+
+    for (key, value) in left {
+        match right.get(key) {
+            Some(other) if other == value => {
+                diff.identical.push((key.clone(), value.clone()));
+            }
+            Some(other) => {
+                diff.differs
+                    .push((key.clone(), value.clone(), other.clone()));
+            }
+            None => diff.only_left.push((key.clone(), value.clone())),
+        }
     }
 
-    // Extra values passed on the command line
-    for (k, v) in options {
-        env.insert(k.clone(), Value::String(v.clone()));
+    for (key, value) in right {
+        if !left.contains_key(key) {
+            diff.only_right.push((key.clone(), value.clone()));
+        }
     }
 
-    Ok(env)
+    diff
+}
+
+/// Guess if a key holds a secret that should not be shown on screen or
+/// shared, such as a password, an `Authorization` header or a `private_key`
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+
+    [
+        "password",
+        "passwd",
+        "secret",
+        "token",
+        "credential",
+        "authorization",
+        "cookie",
+    ]
+    .iter()
+    .any(|s| key.contains(s))
+        || key.ends_with("key")
+}
+
+/// The file where the selected target is stored. This is shared by all
+/// terminals, unless `target_scope = "session"` is set, in which case each
+/// shell session has its own selection.
+fn selected_target_file(root_dir: &Path) -> PathBuf {
+    let session_scope = load_settings(root_dir)
+        .ok()
+        .and_then(|s| s.get("target_scope").cloned())
+        .is_some_and(|scope| scope.as_str() == Some("session"));
+
+    match session_id() {
+        Some(id) if session_scope => session_target_file(root_dir, &id),
+        _ => target_file(root_dir),
+    }
+}
+
+/// Identify the shell session, by `HITMAN_SESSION` if set, or else by the
+/// parent process, which is usually the shell.
+fn session_id() -> Option<String> {
+    if let Ok(id) = env::var(SESSION_ENV_VAR) {
+        return Some(id);
+    }
+
+    #[cfg(unix)]
+    return Some(std::os::unix::process::parent_id().to_string());
+
+    #[cfg(not(unix))]
+    return None;
 }
 
 pub fn get_target(root_dir: &Path) -> String {
-    let target = read_to_string(root_dir.join(TARGET_FILE))
+    if let Ok(target) = env::var(TARGET_ENV_VAR) {
+        return target;
+    }
+
+    let target = read_to_string(selected_target_file(root_dir))
         .map(|t| t.trim().to_string())
         .unwrap_or("default".to_string());
     target
 }
 
-pub fn update_data(vars: &TomlTable) -> Result<()> {
+/// Store values in the data file of the target of `env`, and return the
+/// values that were stored
+pub fn update_data(vars: &TomlTable, env: &TomlTable) -> Result<TomlTable> {
     if vars.is_empty() {
-        return Ok(());
+        return Ok(TomlTable::new());
     }
 
     let root_dir = find_root_dir()?;
     let Some(root_dir) = root_dir else {
         bail!("Could not find project root");
     };
-    let data_file = root_dir.join(DATA_FILE);
 
-    let content = fs::read_to_string(&data_file).unwrap_or("".to_string());
+    let settings = load_settings(&root_dir)?;
+    let vars = without_sensitive(&settings, vars);
+    if vars.is_empty() {
+        return Ok(vars);
+    }
+
+    // Stored values are replaced, kept or asked about, per `on_overwrite`
+    let file = data_file(&root_dir, &data_target(&root_dir, env));
+    let stored = read_toml(&file).unwrap_or_default();
+    let vars = resolve_overwrites(
+        &settings,
+        &stored,
+        &vars,
+        get_interaction().as_ref(),
+    )?;
+
+    ensure_state_dir(&root_dir)?;
+    update_toml_file(&file, &vars)?;
+
+    Ok(vars)
+}
+
+/// Add values to the lists in the data file of the target of `env`, and
+/// return the lists that were stored. A stored value that isn't a list
+/// becomes the first item.
+pub fn append_data(vars: &TomlTable, env: &TomlTable) -> Result<TomlTable> {
+    if vars.is_empty() {
+        return Ok(TomlTable::new());
+    }
+
+    let Some(root_dir) = find_root_dir()? else {
+        bail!("Could not find project root");
+    };
+
+    let settings = load_settings(&root_dir)?;
+    let vars = without_sensitive(&settings, vars);
+    if vars.is_empty() {
+        return Ok(vars);
+    }
+
+    let file = data_file(&root_dir, &data_target(&root_dir, env));
+    let stored = read_toml(&file).unwrap_or_default();
+    let lists: TomlTable = vars
+        .into_iter()
+        .map(|(key, value)| {
+            let items = appended(stored.get(&key), value);
+            info!("# '{}' has {} items", key, items.len());
+            (key, Value::Array(items))
+        })
+        .collect();
+
+    ensure_state_dir(&root_dir)?;
+    update_toml_file(&file, &lists)?;
+
+    Ok(lists)
+}
+
+/// The items of a stored list, with a value or a list of values added
+fn appended(stored: Option<&Value>, value: Value) -> Vec<Value> {
+    let mut items = match stored {
+        Some(Value::Array(items)) => items.clone(),
+        Some(item) => vec![item.clone()],
+        None => Vec::new(),
+    };
+    match value {
+        Value::Array(new) => items.extend(new),
+        new => items.push(new),
+    }
+
+    items
+}
+
+/// Sensitive values are only kept in memory
+pub(crate) fn without_sensitive(
+    settings: &TomlTable,
+    vars: &TomlTable,
+) -> TomlTable {
+    vars.iter()
+        .filter(|(key, _)| {
+            let sensitive = is_sensitive_key(settings, key);
+            if sensitive {
+                info!("# Not saving sensitive value '{}'", key);
+            }
+            !sensitive
+        })
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// Check if a key is marked as sensitive, with `sensitive = ["otp"]` in
+/// `[_settings]`. Values of sensitive keys are never written to disk.
+pub fn is_sensitive_key(settings: &TomlTable, key: &str) -> bool {
+    match settings.get("sensitive") {
+        Some(Value::Array(keys)) => keys
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|k| k.eq_ignore_ascii_case(key)),
+        _ => false,
+    }
+}
+
+pub(crate) fn update_toml_file(
+    file_path: &Path,
+    vars: &TomlTable,
+) -> Result<()> {
+    let content = fs::read_to_string(file_path).unwrap_or("".to_string());
 
     let mut state = toml::from_str::<TomlTable>(&content).unwrap_or_default();
 
     state.extend(vars.clone());
-    fs::write(&data_file, toml::to_string_pretty(&state)?)?;
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file_path, toml::to_string_pretty(&state)?)?;
 
     Ok(())
 }
 
-fn read_and_merge_config(root_dir: &Path) -> Result<TomlTable> {
-    let mut config = TomlTable::new();
+/// Personal preferences, such as editor and theme, usually given in the user
+/// config file.
+pub fn load_settings(root_dir: &Path) -> Result<TomlTable> {
+    let config = read_and_merge_config(root_dir)?;
+
+    Ok(settings_from(&config))
+}
+
+/// The name of the target of the values, as loaded by [load_env]
+pub fn target_of(env: &TomlTable) -> Option<&str> {
+    env.get(TARGET_KEY).and_then(Value::as_str)
+}
+
+/// The target whose data file keeps the values captured with an environment,
+/// which is the selected target for an environment without one
+pub fn data_target(root_dir: &Path, env: &TomlTable) -> String {
+    target_of(env)
+        .map(str::to_string)
+        .unwrap_or_else(|| get_target(root_dir))
+}
+
+/// Get the settings from an environment returned by `load_env`
+pub fn get_settings(env: &TomlTable) -> TomlTable {
+    settings_from(env)
+}
+
+fn settings_from(config: &TomlTable) -> TomlTable {
+    match config.get(SETTINGS_KEY) {
+        Some(Value::Table(t)) => t.clone(),
+        _ => TomlTable::new(),
+    }
+}
+
+pub(crate) fn read_and_merge_config(root_dir: &Path) -> Result<TomlTable> {
+    // User config is the base, so that projects can override it
+    let mut config = read_user_config()?;
 
     merge(&mut config, read_toml(&root_dir.join(CONFIG_FILE))?);
 
@@ -229,7 +580,7 @@ fn merge(config: &mut TomlTable, other: TomlTable) {
     });
 }
 
-fn read_toml(file_path: &Path) -> Result<TomlTable> {
+pub(crate) fn read_toml(file_path: &Path) -> Result<TomlTable> {
     let content = fs::read_to_string(file_path)?;
 
     let cfg = toml::from_str::<TomlTable>(&content)?;
@@ -237,30 +588,20 @@ fn read_toml(file_path: &Path) -> Result<TomlTable> {
     Ok(cfg)
 }
 
+/// The request files below a directory, with paths relative to it. In a
+/// project, these are the requests found by the discovery config, and extra
+/// roots are only included from the project root.
 pub fn find_available_requests(cwd: &Path) -> Result<Vec<PathBuf>> {
-    let files: Vec<_> = WalkDir::new(cwd)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_name()
-                .to_str()
-                .map(|s| s.ends_with(".http"))
-                .unwrap_or(false)
-        })
-        .map(|p| {
-            // Convert to relative path, based on depth
-            let components: Vec<_> = p.path().components().collect();
-            let relative_components: Vec<_> = components
-                [(components.len() - p.depth())..]
-                .iter()
-                .map(|c| c.as_os_str())
-                .collect();
-
-            PathBuf::from_iter(&relative_components)
-        })
-        .collect();
+    let dir = current_dir()?.join(cwd);
+    let Some(root_dir) = find_root_dir_from(&dir) else {
+        return find_requests(cwd);
+    };
+    let rel = dir.strip_prefix(&root_dir)?;
 
-    Ok(files)
+    Ok(find_project_requests(&root_dir)?
+        .into_iter()
+        .filter_map(|path| Some(path.strip_prefix(rel).ok()?.to_path_buf()))
+        .collect())
 }
 
 #[cfg(test)]
@@ -289,11 +630,101 @@ mod tests {
         "#
         };
 
-        let envs = find_environments(&config).unwrap();
+        let envs = list_environments(&config);
 
         assert_eq!(envs, vec!["bar", "foo"]);
     }
 
+    #[test]
+    fn diffs_target_values() {
+        let config = toml! {
+        r#"
+            global = "shared"
+
+            [staging]
+            base_url = "https://staging.example.com"
+            user = "admin"
+            debug = true
+
+            [prod]
+            base_url = "https://example.com"
+            user = "admin"
+            region = "eu"
+        "#
+        };
+
+        let diff = diff_envs(
+            &target_env(&config, "staging").unwrap(),
+            &target_env(&config, "prod").unwrap(),
+        );
+
+        let differs: Vec<_> = diff.differs.iter().map(|d| &d.0).collect();
+        let only_left: Vec<_> = diff.only_left.iter().map(|d| &d.0).collect();
+        let only_right: Vec<_> = diff.only_right.iter().map(|d| &d.0).collect();
+        let identical: Vec<_> = diff.identical.iter().map(|d| &d.0).collect();
+
+        assert_eq!(differs, vec!["base_url"]);
+        assert_eq!(only_left, vec!["debug"]);
+        assert_eq!(only_right, vec!["region"]);
+        assert_eq!(identical, vec!["global", "user"]);
+    }
+
+    #[test]
+    fn reads_pinned_requests() {
+        let config: TomlTable = toml! {
+        r#"
+            [_pins]
+            "./stripe/charge.http" = "stripe"
+            "apples/get.http" = "prod"
+
+            [stripe]
+            base_url = "https://api.stripe.com"
+        "#
+        };
+
+        let pins = pins_from(&config);
+
+        assert_eq!(pins.len(), 2);
+        assert_eq!(pins["stripe/charge.http"], "stripe");
+        assert_eq!(pins["apples/get.http"], "prod");
+    }
+
+    #[test]
+    fn finds_request_paths_relative_to_root() {
+        let root = Path::new("/projects/api");
+
+        assert_eq!(
+            request_key(root, Path::new("/projects/api/stripe/charge.http")),
+            "stripe/charge.http"
+        );
+    }
+
+    #[test]
+    fn detects_sensitive_keys() {
+        let settings: TomlTable = toml! {
+        r#"
+            sensitive = ["otp", "Password"]
+        "#
+        };
+
+        assert!(is_sensitive_key(&settings, "otp"));
+        assert!(is_sensitive_key(&settings, "password"));
+        assert!(!is_sensitive_key(&settings, "token"));
+        assert!(!is_sensitive_key(&TomlTable::new(), "otp"));
+    }
+
+    #[test]
+    fn detects_secret_keys() {
+        assert!(is_secret_key("api_password"));
+        assert!(is_secret_key("AccessToken"));
+        assert!(is_secret_key("Authorization"));
+        assert!(is_secret_key("Set-Cookie"));
+        assert!(is_secret_key("private_key"));
+        assert!(is_secret_key("apiKey"));
+        assert!(!is_secret_key("base_url"));
+        assert!(!is_secret_key("key_id"));
+    }
+
     #[test]
     fn merges_mested_tables() {
         let shared = toml! {
@@ -337,4 +768,19 @@ mod tests {
 
         assert_eq!(merged, expected);
     }
+
+    #[test]
+    fn appends_to_stored_lists() {
+        let int = |i| Value::Integer(i);
+
+        assert_eq!(appended(None, int(1)), vec![int(1)]);
+        assert_eq!(appended(Some(&int(1)), int(2)), vec![int(1), int(2)]);
+        assert_eq!(
+            appended(
+                Some(&Value::Array(vec![int(1)])),
+                Value::Array(vec![int(2), int(3)])
+            ),
+            vec![int(1), int(2), int(3)]
+        );
+    }
 }
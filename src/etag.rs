@@ -0,0 +1,235 @@
+use log::info;
+use reqwest::{
+    header::{HeaderMap, ETAG},
+    Url,
+};
+use toml::{Table, Value};
+
+use crate::{
+    directive::{self, Directives},
+    env::{find_root_dir, read_toml, update_toml_file},
+    state::{ensure_state_dir, etags_file},
+};
+
+/// Sent with `# @etag stale` when no earlier ETag is known, to make sure the
+/// precondition fails
+const STALE_ETAG: &str = "\"hitman-stale\"";
+
+/// How a request uses the ETag captured for its URL, given with a line like
+/// `# @etag stale`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EtagMode {
+    /// Send the latest ETag as `If-Match`
+    Current,
+    /// Send the ETag from before the latest change, to check that the server
+    /// rejects it with `412 Precondition Failed`
+    Stale,
+    /// Don't send `If-Match`
+    Off,
+}
+
+/// The request to send, with an `If-Match` header with the ETag captured for
+/// the URL, for requests that change a resource. An `If-Match` header written
+/// in the request takes precedence.
+pub fn apply_etag(directives: &Directives, etags: &Table) -> String {
+    let mode = match directives.value(directive::ETAG) {
+        Some("stale") => EtagMode::Stale,
+        Some("off") => EtagMode::Off,
+        _ => EtagMode::Current,
+    };
+
+    let buf = directives.request();
+    let lines: Vec<&str> = buf.split_inclusive('\n').collect();
+
+    let Some(request_line) = lines
+        .iter()
+        .position(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+    else {
+        return buf;
+    };
+
+    let mut parts = lines[request_line].split_whitespace();
+    let (Some(method), Some(url)) = (parts.next(), parts.next()) else {
+        return buf;
+    };
+    if !["PUT", "PATCH", "DELETE"].contains(&method) {
+        return buf;
+    }
+
+    let has_if_match = lines[request_line + 1..]
+        .iter()
+        .take_while(|l| !l.trim().is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .any(|(name, _)| name.trim().eq_ignore_ascii_case("if-match"));
+    if has_if_match {
+        return buf;
+    }
+
+    // Saved by the parsed URL, as sent
+    let url = Url::parse(url).map(String::from).unwrap_or(url.to_string());
+    let known = etags.get(&url).and_then(Value::as_table);
+    let etag = match mode {
+        EtagMode::Off => None,
+        EtagMode::Current => known
+            .and_then(|t| t.get("current"))
+            .and_then(Value::as_str)
+            .map(String::from),
+        EtagMode::Stale => Some(
+            known
+                .and_then(|t| t.get("previous"))
+                .and_then(Value::as_str)
+                .unwrap_or(STALE_ETAG)
+                .to_string(),
+        ),
+    };
+
+    let Some(etag) = etag else {
+        return buf;
+    };
+
+    let mut output = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        output.push_str(line);
+        if i == request_line {
+            output.push_str(&format!("If-Match: {etag}\n"));
+        }
+    }
+
+    output
+}
+
+/// Record the ETag of a response, keeping the one it replaces, so that a
+/// stale ETag can be sent later
+pub fn update_etag(etags: &Table, url: &str, headers: &HeaderMap) -> Table {
+    let Some(etag) = headers.get(ETAG).and_then(|v| v.to_str().ok()) else {
+        return Table::new();
+    };
+
+    let current = etags
+        .get(url)
+        .and_then(Value::as_table)
+        .and_then(|t| t.get("current"))
+        .and_then(Value::as_str);
+    if current == Some(etag) {
+        return Table::new();
+    }
+
+    let mut entry = Table::new();
+    entry.insert("current".to_string(), Value::String(etag.to_string()));
+    if let Some(previous) = current {
+        entry.insert("previous".to_string(), Value::String(previous.into()));
+    }
+
+    let mut out = Table::new();
+    out.insert(url.to_string(), Value::Table(entry));
+    out
+}
+
+/// The ETags captured in this project
+pub fn load_etags() -> Table {
+    find_root_dir()
+        .ok()
+        .flatten()
+        .and_then(|root_dir| read_toml(&etags_file(&root_dir)).ok())
+        .unwrap_or_default()
+}
+
+/// Save the ETag of a successful response, for the URL it was requested from
+pub fn save_etag(url: &Url, status: u16, headers: &HeaderMap) {
+    if !(200..300).contains(&status) {
+        return;
+    }
+
+    let update = update_etag(&load_etags(), url.as_str(), headers);
+    if update.is_empty() {
+        return;
+    }
+
+    if let Ok(Some(root_dir)) = find_root_dir() {
+        info!("# Captured ETag for {url}");
+        let _ = ensure_state_dir(&root_dir)
+            .and_then(|_| update_toml_file(&etags_file(&root_dir), &update));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn apply(buf: &str) -> String {
+        apply_etag(&Directives::parse(buf).unwrap(), &etags())
+    }
+
+    fn etags() -> Table {
+        toml::from_str(
+            r#"
+            ["http://example.com/apples/1"]
+            current = '"v2"'
+            previous = '"v1"'
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn sends_current_etag_on_put() {
+        let buf =
+            "PUT http://example.com/apples/1 HTTP/1.1\nAccept: */*\n\n{}\n";
+
+        assert_eq!(
+            apply(buf),
+            "PUT http://example.com/apples/1 HTTP/1.1\nIf-Match: \"v2\"\nAccept: */*\n\n{}\n"
+        );
+    }
+
+    #[test]
+    fn sends_stale_etag_when_asked() {
+        let buf = "# @etag stale\n# @assert status == 412\nPUT http://example.com/apples/1 HTTP/1.1\n";
+
+        let res = apply(buf);
+
+        assert!(res.contains("If-Match: \"v1\"\n"));
+        assert!(!res.contains("# @"));
+
+        let buf = "# @etag stale\nDELETE http://example.com/pears/1 HTTP/1.1\n";
+        assert!(apply(buf).contains("If-Match: \"hitman-stale\""));
+    }
+
+    #[test]
+    fn leaves_other_requests_alone() {
+        let get = "GET http://example.com/apples/1 HTTP/1.1\n";
+        let own = "PUT http://example.com/apples/1 HTTP/1.1\nif-match: *\n";
+        let off = "# @etag off\nPUT http://example.com/apples/1 HTTP/1.1\n";
+
+        assert_eq!(apply(get), get);
+        assert_eq!(apply(own), own);
+        assert_eq!(apply(off), "PUT http://example.com/apples/1 HTTP/1.1\n");
+    }
+
+    #[test]
+    fn keeps_previous_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"v3\""));
+
+        let res =
+            update_etag(&etags(), "http://example.com/apples/1", &headers);
+
+        let expected: Table = toml::from_str(
+            r#"
+            ["http://example.com/apples/1"]
+            current = '"v3"'
+            previous = '"v2"'
+            "#,
+        )
+        .unwrap();
+        assert_eq!(res, expected);
+
+        headers.insert(ETAG, HeaderValue::from_static("\"v2\""));
+        assert!(
+            update_etag(&etags(), "http://example.com/apples/1", &headers)
+                .is_empty()
+        );
+    }
+}
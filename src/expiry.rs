@@ -0,0 +1,213 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use jsonpath::Selector;
+use log::info;
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::clock::unix_now;
+use crate::env::{data_target, find_root_dir, read_toml};
+use crate::state::{ensure_state_dir, expiry_file};
+use crate::util::duration_value;
+
+/// How long extracted values are valid, per key, given next to `_extract`:
+///
+/// ```toml
+/// [_expires]
+/// access_token = "$.expires_in"
+/// session_id = "30m"
+/// ```
+///
+/// A JSON path takes the number of seconds from the response, and other
+/// values are fixed durations.
+pub const EXPIRES_KEY: &str = "_expires";
+
+/// Keep the time that stored values expire, for the target of the scope. A
+/// value stored without an expiry doesn't expire.
+pub fn record_expiry(
+    scope: &Table,
+    data: Option<&JsonValue>,
+    stored: &Table,
+) -> Result<()> {
+    if stored.is_empty() {
+        return Ok(());
+    }
+
+    let Some(root_dir) = find_root_dir()? else {
+        bail!("Could not find project root");
+    };
+
+    let expires = expiry_times(scope, data, stored, unix_now())?;
+    let target = data_target(&root_dir, scope);
+
+    update_expiry(&root_dir, &target, stored, &expires)
+}
+
+/// Forget when values expire, such as values that were given by hand
+pub fn clear_expiry(
+    root_dir: &Path,
+    target: &str,
+    values: &Table,
+) -> Result<()> {
+    update_expiry(root_dir, target, values, &Table::new())
+}
+
+fn update_expiry(
+    root_dir: &Path,
+    target: &str,
+    stored: &Table,
+    expires: &Table,
+) -> Result<()> {
+    let file = expiry_file(root_dir, target);
+    let mut expiry = read_toml(&file).unwrap_or_default();
+
+    for key in stored.keys() {
+        match expires.get(key) {
+            Some(at) => expiry.insert(key.clone(), at.clone()),
+            None => expiry.remove(key),
+        };
+    }
+
+    ensure_state_dir(root_dir)?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file, toml::to_string_pretty(&expiry)?)?;
+
+    Ok(())
+}
+
+/// The stored values, without those that have expired. Expired values are
+/// missing, so they are asked for, or extracted again by a dependency.
+pub fn without_expired(
+    root_dir: &Path,
+    target: &str,
+    mut data: Table,
+) -> Table {
+    let expiry = read_toml(&expiry_file(root_dir, target)).unwrap_or_default();
+
+    for key in expired_keys(&expiry, unix_now()) {
+        if data.remove(&key).is_some() {
+            info!("# '{}' has expired", key);
+        }
+    }
+
+    data
+}
+
+/// When the stored values expire, as unix timestamps
+fn expiry_times(
+    scope: &Table,
+    data: Option<&JsonValue>,
+    stored: &Table,
+    now: u64,
+) -> Result<Table> {
+    let rules = match scope.get(EXPIRES_KEY) {
+        Some(Value::Table(rules)) => rules,
+        Some(_) => bail!("Invalid {} section", EXPIRES_KEY),
+        None => return Ok(Table::new()),
+    };
+
+    let mut out = Table::new();
+    for (key, rule) in rules {
+        if !stored.contains_key(key) {
+            continue;
+        }
+
+        let ttl = match rule {
+            Value::String(path) if path.starts_with('$') => data
+                .and_then(|data| seconds_at(path, data).transpose())
+                .transpose()?,
+            rule => Some(duration_value(rule).ok_or_else(|| {
+                anyhow!("Invalid {} rule: {}", EXPIRES_KEY, rule)
+            })?),
+        };
+
+        match ttl {
+            Some(ttl) => {
+                let at = now.saturating_add(ttl.as_secs());
+                out.insert(key.clone(), Value::Integer(at as i64));
+            }
+            None => info!("# No expiry found for '{}'", key),
+        }
+    }
+
+    Ok(out)
+}
+
+/// The number of seconds at a JSON path, as a number or a string
+fn seconds_at(path: &str, data: &JsonValue) -> Result<Option<Duration>> {
+    let selector = Selector::new(path)
+        .map_err(|err| anyhow!("Invalid jsonpath: {}", err))?;
+
+    let seconds = match selector.find(data).next() {
+        Some(JsonValue::Number(n)) => n.as_f64(),
+        Some(JsonValue::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    };
+
+    Ok(seconds.filter(|s| *s >= 0.0).map(Duration::from_secs_f64))
+}
+
+fn expired_keys(expiry: &Table, now: u64) -> Vec<String> {
+    expiry
+        .iter()
+        .filter(|(_, at)| at.as_integer().is_some_and(|at| at <= now as i64))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_expiry_times() {
+        let scope: Table = toml::from_str(
+            r#"
+            [_expires]
+            access_token = "$.expires_in"
+            refresh_token = "$.refresh_expires_in"
+            session_id = "30m"
+            csrf = 60
+            "#,
+        )
+        .unwrap();
+        let stored: Table = toml::from_str(
+            "access_token = \"a\"\nrefresh_token = \"b\"\nsession_id = \"c\"",
+        )
+        .unwrap();
+        let data = serde_json::json!({
+            "expires_in": 3600,
+            "refresh_expires_in": "7200",
+        });
+
+        let times = expiry_times(&scope, Some(&data), &stored, 1000).unwrap();
+
+        assert_eq!(
+            times,
+            toml::from_str(
+                "access_token = 4600\nrefresh_token = 8200\nsession_id = 2800"
+            )
+            .unwrap()
+        );
+
+        // Without a JSON body, only fixed durations are known
+        let times = expiry_times(&scope, None, &stored, 1000).unwrap();
+        assert_eq!(times, toml::from_str("session_id = 2800").unwrap());
+
+        let scope = toml::from_str("[_expires]\ntoken = \"soon\"").unwrap();
+        let stored = toml::from_str("token = \"a\"").unwrap();
+        assert!(expiry_times(&scope, None, &stored, 1000).is_err());
+    }
+
+    #[test]
+    fn finds_expired_keys() {
+        let expiry: Table =
+            toml::from_str("access_token = 900\nsession_id = 1100").unwrap();
+
+        assert_eq!(expired_keys(&expiry, 1000), vec!["access_token"]);
+        assert!(expired_keys(&expiry, 800).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::{env::read_toml, expiry::without_expired, state::data_file};
+
+/// How captured values are written for other tools
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExportFormat {
+    /// `export ACCESS_TOKEN='...'`, for `eval "$(hitman env export)"`
+    #[default]
+    Shell,
+    /// `ACCESS_TOKEN="..."`, for tools that read `.env` files
+    Dotenv,
+}
+
+/// The values captured from responses with a target, without those that
+/// have expired
+pub fn captured_values(root_dir: &Path, target: &str) -> Table {
+    let data = read_toml(&data_file(root_dir, target)).unwrap_or_default();
+
+    without_expired(root_dir, target, data)
+}
+
+/// Lines that set an environment variable for each value. Names are upper
+/// case, so `access_token` becomes `ACCESS_TOKEN`. Lists and tables have no
+/// value to give a variable, so they are left out with a comment.
+pub fn export_lines(values: &Table, format: ExportFormat) -> Vec<String> {
+    values
+        .iter()
+        .map(|(key, value)| {
+            let name = variable_name(key);
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Array(_) | Value::Table(_) => {
+                    return format!("# {key} is not a single value");
+                }
+                other => other.to_string(),
+            };
+
+            match format {
+                ExportFormat::Shell => {
+                    format!("export {name}='{}'", value.replace('\'', "'\\''"))
+                }
+                ExportFormat::Dotenv => format!(
+                    "{name}=\"{}\"",
+                    value
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('\n', "\\n")
+                ),
+            }
+        })
+        .collect()
+}
+
+fn variable_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Table {
+        toml::from_str(
+            r#"
+            access_token = "it's secret"
+            "order-id" = 42
+            "2fa" = true
+            note = "a \"b\"\nc"
+            order_ids = [1, 2]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn exports_values_of_a_target() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-export-{}", std::process::id()));
+        let file = data_file(&root, "prod");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, "token = \"p\"\nsession = \"s\"\n").unwrap();
+        std::fs::write(data_file(&root, "dev"), "token = \"d\"\n").unwrap();
+        let expiry = crate::state::expiry_file(&root, "prod");
+        std::fs::create_dir_all(expiry.parent().unwrap()).unwrap();
+        std::fs::write(&expiry, "session = 1\n").unwrap();
+
+        let prod = captured_values(&root, "prod");
+        let dev = captured_values(&root, "dev");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(prod, toml::from_str("token = \"p\"").unwrap());
+        assert_eq!(dev, toml::from_str("token = \"d\"").unwrap());
+    }
+
+    #[test]
+    fn exports_shell_lines() {
+        assert_eq!(
+            export_lines(&values(), ExportFormat::Shell),
+            vec![
+                "export _2FA='true'",
+                "export ACCESS_TOKEN='it'\\''s secret'",
+                "export NOTE='a \"b\"\nc'",
+                "export ORDER_ID='42'",
+                "# order_ids is not a single value",
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_dotenv_lines() {
+        assert_eq!(
+            export_lines(&values(), ExportFormat::Dotenv),
+            vec![
+                "_2FA=\"true\"",
+                "ACCESS_TOKEN=\"it's secret\"",
+                "NOTE=\"a \\\"b\\\"\\nc\"",
+                "ORDER_ID=\"42\"",
+                "# order_ids is not a single value",
+            ]
+        );
+    }
+}
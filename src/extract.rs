@@ -1,11 +1,56 @@
 use anyhow::{anyhow, bail, Result};
 use log::info;
+use reqwest::header::{HeaderMap, SET_COOKIE};
 use toml::{Table, Value};
 
-use crate::util::truncate;
+use crate::{
+    clock::unix_now,
+    env::{append_data, update_data},
+    expiry::record_expiry,
+    markup::Document,
+    util::truncate,
+};
 use jsonpath::Selector;
 use serde_json::Value as JsonValue;
 
+/// Keys whose extracted values are added to a stored list, instead of
+/// replacing the stored value, such as `_append = ["order_ids"]`
+pub const APPEND_KEY: &str = "_append";
+
+/// Store extracted values, with the time they expire. Values of the keys in
+/// `_append` are added to the stored lists.
+pub fn store_extracted(
+    vars: &Table,
+    scope: &Table,
+    data: Option<&JsonValue>,
+) -> Result<()> {
+    let append = append_keys(scope)?;
+    let (lists, values): (Table, Table) = vars
+        .clone()
+        .into_iter()
+        .partition(|(key, _)| append.contains(key));
+
+    let mut stored = update_data(&values, scope)?;
+    stored.extend(append_data(&lists, scope)?);
+
+    record_expiry(scope, data, &stored)
+}
+
+fn append_keys(scope: &Table) -> Result<Vec<String>> {
+    match scope.get(APPEND_KEY) {
+        None => Ok(Vec::new()),
+        Some(Value::String(key)) => Ok(vec![key.clone()]),
+        Some(Value::Array(keys)) => keys
+            .iter()
+            .map(|key| match key {
+                Value::String(key) => Ok(key.clone()),
+                other => bail!("Invalid {}: {}", APPEND_KEY, other),
+            })
+            .collect(),
+        Some(other) => bail!("Invalid {}: {}", APPEND_KEY, other),
+    }
+}
+
 pub fn extract_variables(data: &JsonValue, scope: &Table) -> Result<Table> {
     let mut out = Table::new();
 
@@ -14,36 +59,14 @@ pub fn extract_variables(data: &JsonValue, scope: &Table) -> Result<Table> {
         Some(Value::Table(table)) => {
             for (key, value) in table {
                 match value {
+                    // Taken from the headers by extract_header_variables
+                    Value::String(rule) if header_rule(rule).is_some() => (),
+                    // Taken by extract_markup_variables, from other bodies
+                    Value::String(rule) if markup_rule(rule).is_some() => (),
                     Value::String(jsonpath) => {
                         let selector = make_selector(jsonpath)?;
-
-                        if let Some(JsonValue::String(val)) =
-                            selector.find(data).next()
-                        {
-                            let msg =
-                                format!("# Got string '{}' = '{}'", key, val);
-                            info!("{}", truncate(&msg));
-
-                            out.insert(
-                                key.clone(),
-                                Value::String(String::from(val)),
-                            );
-                        }
-                        if let Some(JsonValue::Number(val)) =
-                            selector.find(data).next()
-                        {
-                            if let Some(integer) = val.as_i64() {
-                                let msg = format!(
-                                    "# Got integer '{}' = '{}'",
-                                    key, val
-                                );
-                                info!("{}", truncate(&msg));
-                                out.insert(
-                                    key.clone(),
-                                    Value::Integer(integer),
-                                );
-                            }
-                        }
+                        let value = extracted_value(&selector, data);
+                        insert_value(&mut out, key, jsonpath, value);
                     }
                     Value::Table(conf) => {
                         let items_selector =
@@ -101,6 +124,210 @@ pub fn extract_variables(data: &JsonValue, scope: &Table) -> Result<Table> {
     Ok(out)
 }
 
+/// Where a value is taken from, when it is not in the body
+enum HeaderRule<'a> {
+    /// `header:Location`
+    Header(&'a str),
+    /// `cookie:session_id`, from the `Set-Cookie` headers
+    Cookie(&'a str),
+}
+
+fn header_rule(rule: &str) -> Option<HeaderRule<'_>> {
+    if let Some(name) = rule.strip_prefix("header:") {
+        Some(HeaderRule::Header(name.trim()))
+    } else {
+        rule.strip_prefix("cookie:")
+            .map(|name| HeaderRule::Cookie(name.trim()))
+    }
+}
+
+/// The value of a cookie set by a `Set-Cookie` header, without quotes. A
+/// header that removes the cookie, by expiring it, has no value.
+fn set_cookie_value(header: &str, name: &str, now: i64) -> Option<String> {
+    let cookie = cookie::Cookie::parse(header).ok()?;
+    if cookie.name() != name {
+        return None;
+    }
+
+    let expired = match (cookie.max_age(), cookie.expires_datetime()) {
+        (Some(max_age), _) => max_age.whole_seconds() <= 0,
+        (None, Some(expires)) => expires.unix_timestamp() <= now,
+        (None, None) => false,
+    };
+
+    (!expired).then(|| cookie.value_trimmed().to_string())
+}
+
+/// Extract the values given as `header:Name` or `cookie:name` in `_extract`,
+/// such as the location of a created resource
+pub fn extract_header_variables(
+    headers: &HeaderMap,
+    scope: &Table,
+) -> Result<Table> {
+    let mut out = Table::new();
+
+    let Some(Value::Table(table)) = scope.get("_extract") else {
+        return Ok(out);
+    };
+
+    for (key, value) in table {
+        let Value::String(rule) = value else {
+            continue;
+        };
+        let value = match header_rule(rule) {
+            Some(HeaderRule::Header(name)) => headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            Some(HeaderRule::Cookie(name)) => headers
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .find_map(|header| {
+                    set_cookie_value(header, name, unix_now() as i64)
+                }),
+            None => continue,
+        };
+
+        match value {
+            Some(value) => {
+                let msg = format!("# Got string '{}' = '{}'", key, value);
+                info!("{}", truncate(&msg));
+                out.insert(key.clone(), Value::String(value));
+            }
+            None => {
+                let msg = format!("# No value for '{}' at {}", key, rule);
+                info!("{}", truncate(&msg));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// How a value is taken from an XML or HTML body
+enum MarkupRule<'a> {
+    /// `xpath://order/id`
+    XPath(&'a str),
+    /// `css:a.next@href`
+    Css(&'a str),
+}
+
+fn markup_rule(rule: &str) -> Option<MarkupRule<'_>> {
+    if let Some(expr) = rule.strip_prefix("xpath:") {
+        Some(MarkupRule::XPath(expr.trim()))
+    } else {
+        rule.strip_prefix("css:")
+            .map(|selector| MarkupRule::Css(selector.trim()))
+    }
+}
+
+/// Extract the values given as `xpath:expression` or `css:selector` in
+/// `_extract`, from an XML or HTML body, such as the result of a SOAP call
+/// or a token in a form
+pub fn extract_markup_variables(body: &str, scope: &Table) -> Result<Table> {
+    let mut out = Table::new();
+
+    let Some(Value::Table(table)) = scope.get("_extract") else {
+        return Ok(out);
+    };
+
+    let mut document = None;
+    for (key, value) in table {
+        let Value::String(rule) = value else {
+            continue;
+        };
+        let Some(markup) = markup_rule(rule) else {
+            continue;
+        };
+
+        if document.is_none() {
+            match Document::parse(body) {
+                Ok(doc) => document = Some(doc),
+                Err(err) => {
+                    info!("# No values extracted: {:#}", err);
+                    break;
+                }
+            }
+        }
+        let Some(doc) = &document else {
+            break;
+        };
+
+        let values = match markup {
+            MarkupRule::XPath(_) if doc.is_html() => {
+                info!("# No value for '{}', XPath needs an XML body", key);
+                continue;
+            }
+            MarkupRule::XPath(expr) => doc.xpath(expr)?,
+            MarkupRule::Css(selector) => doc.css(selector)?,
+        };
+        let value = one_or_all(values.into_iter().map(Value::String).collect());
+        insert_value(&mut out, key, rule, value);
+    }
+
+    Ok(out)
+}
+
+fn insert_value(out: &mut Table, key: &str, rule: &str, value: Option<Value>) {
+    let msg = match &value {
+        Some(Value::Array(values)) => {
+            format!("# Got '{}' with {} elements", key, values.len())
+        }
+        Some(value) => format!(
+            "# Got {} '{}' = '{}'",
+            value.type_str(),
+            key,
+            display_value(value)
+        ),
+        None => format!("# No value for '{}' at {}", key, rule),
+    };
+    info!("{}", truncate(&msg));
+
+    if let Some(value) = value {
+        out.insert(key.to_string(), value);
+    }
+}
+
+/// The value selected by a path. Paths matching several values, like
+/// `$.items.*.id`, give an array of them.
+fn extracted_value(selector: &Selector, data: &JsonValue) -> Option<Value> {
+    one_or_all(selector.find(data).filter_map(toml_value).collect())
+}
+
+/// A single value, or an array of several
+fn one_or_all(mut values: Vec<Value>) -> Option<Value> {
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(Value::Array(values)),
+    }
+}
+
+/// Objects and arrays are kept as JSON, to be used as JSON values in other
+/// requests
+fn toml_value(value: &JsonValue) -> Option<Value> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::Bool(b) => Some(Value::Boolean(*b)),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(integer) => Some(Value::Integer(integer)),
+            None => n.as_f64().map(Value::Float),
+        },
+        JsonValue::String(s) => Some(Value::String(s.clone())),
+        JsonValue::Array(_) | JsonValue::Object(_) => {
+            Some(Value::String(value.to_string()))
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
 fn make_item_selectors(conf: &Table) -> Result<Vec<(String, Selector)>> {
     conf.iter()
         .filter_map(|(k, v)| {
@@ -158,6 +385,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extracts_all_json_types() {
+        let env = toml::from_str(
+            r#"
+        [_extract]
+        price = "$.order.price"
+        paid = "$.order.paid"
+        address = "$.order.customer.address"
+        note = "$.order.note"
+        missing = "$.order.missing"
+        ids = "$.order.lines.*.id"
+        "#,
+        )
+        .unwrap();
+
+        let data = serde_json::json!({
+            "order": {
+                "price": 9.5,
+                "paid": true,
+                "customer": {"address": {"city": "Oslo"}},
+                "note": null,
+                "lines": [{"id": 1}, {"id": 2}],
+            }
+        });
+
+        let res = extract_variables(&data, &env).unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            price = 9.5
+            paid = true
+            address = '{"city":"Oslo"}'
+            ids = [1, 2]
+        "#,
+        )
+        .unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn extracts_headers_and_cookies() {
+        let env = toml::from_str(
+            r#"
+        [_extract]
+        token = "$.token"
+        order_url = "header:Location"
+        session = "cookie:session_id"
+        missing = "header:ETag"
+        "#,
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("location", "/orders/42".parse().unwrap());
+        headers.append(SET_COOKIE, "theme=dark".parse().unwrap());
+        headers.append(
+            SET_COOKIE,
+            "session_id=abc123; Path=/; HttpOnly".parse().unwrap(),
+        );
+
+        let res = extract_header_variables(&headers, &env).unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            order_url = "/orders/42"
+            session = "abc123"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(res, expected);
+
+        let json = serde_json::json!({"token": "xyz"});
+        let res = extract_variables(&json, &env).unwrap();
+        assert_eq!(res.len(), 1);
+    }
+
+    #[test]
+    fn reads_set_cookie_values() {
+        let value = |header| set_cookie_value(header, "sid", 1_000_000_000);
+
+        assert_eq!(value("sid=abc; Path=/; HttpOnly"), Some("abc".into()));
+        assert_eq!(value("sid=\"a b\"; Secure"), Some("a b".into()));
+        assert_eq!(value("sid=a=b"), Some("a=b".into()));
+        assert_eq!(value("other=abc"), None);
+        assert_eq!(value("sid=; Max-Age=0"), None);
+        assert_eq!(
+            value("sid=old; Expires=Thu, 01 Jan 1970 00:00:00 GMT"),
+            None
+        );
+        assert_eq!(
+            value("sid=new; Expires=Fri, 01 Jan 2100 00:00:00 GMT"),
+            Some("new".into())
+        );
+    }
+
+    #[test]
+    fn extracts_from_xml_and_html() {
+        let env = toml::from_str(
+            r#"
+        [_extract]
+        token = "$.token"
+        price = "xpath://soap:Body/GetPriceResponse/Price"
+        currencies = "xpath://Price/@currency"
+        csrf = "css:input[name=csrf]@value"
+        missing = "css:#missing"
+        "#,
+        )
+        .unwrap();
+
+        let xml = r#"<soap:Envelope xmlns:soap="urn:soap"><soap:Body>
+            <m:GetPriceResponse xmlns:m="urn:m">
+              <m:Price currency="NOK">12.50</m:Price>
+              <m:Price currency="EUR">1.10</m:Price>
+            </m:GetPriceResponse>
+        </soap:Body></soap:Envelope>"#;
+        let res = extract_markup_variables(xml, &env).unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            price = ["12.50", "1.10"]
+            currencies = ["NOK", "EUR"]
+        "#,
+        )
+        .unwrap();
+        assert_eq!(res, expected);
+
+        let html = r#"<!DOCTYPE html><form><input name=csrf value=t0k3n>"#;
+        let res = extract_markup_variables(html, &env).unwrap();
+        assert_eq!(res.get("csrf"), Some(&Value::String("t0k3n".into())));
+        assert_eq!(res.len(), 1);
+
+        // Not markup at all
+        let res = extract_markup_variables("OK", &env).unwrap();
+        assert!(res.is_empty());
+
+        let json = serde_json::json!({"token": "xyz"});
+        let res = extract_variables(&json, &env).unwrap();
+        assert_eq!(res.len(), 1);
+    }
+
     #[test]
     fn extracts_multiple_values_into_array() {
         // workaround: Jsonpath crate doesn't support array
@@ -200,4 +567,16 @@ mod tests {
         assert!(res.get("ToolId").is_some());
         assert_eq!(res.get("ToolId").unwrap(), expected.get("ToolId").unwrap(),);
     }
+
+    #[test]
+    fn reads_append_keys() {
+        let scope = toml::from_str("_append = [\"order_ids\"]").unwrap();
+        assert_eq!(append_keys(&scope).unwrap(), vec!["order_ids"]);
+
+        let scope = toml::from_str("_append = \"order_ids\"").unwrap();
+        assert_eq!(append_keys(&scope).unwrap(), vec!["order_ids"]);
+
+        assert!(append_keys(&Table::new()).unwrap().is_empty());
+        assert!(append_keys(&toml::from_str("_append = 1").unwrap()).is_err());
+    }
 }
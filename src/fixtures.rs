@@ -0,0 +1,296 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Url;
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::{
+    diff::VOLATILE_HEADERS,
+    directive::Directives,
+    env::is_secret_key,
+    history::{load_history, HistoryEntry},
+    scrub::{ScrubRules, REDACTED},
+};
+
+/// Bumped when the layout of the fixture files changes
+const FIXTURE_VERSION: u64 = 1;
+
+/// Headers that carry credentials, whatever their names look like
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Write the newest response of each request in the history as a fixture,
+/// with secrets and the values given by the scrub rules redacted. A request
+/// `api/orders/get.http` is written to `api/orders/get.json` in the
+/// directory. Gives the files written.
+pub fn export_fixtures(root_dir: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let rules = ScrubRules::load(root_dir)?;
+    let mut seen = HashSet::new();
+    let mut written = Vec::new();
+
+    // Newest first, so the first entry of each request is the one to keep
+    for mut entry in load_history(root_dir)? {
+        if !seen.insert(entry.file.clone()) {
+            continue;
+        }
+        // The rules may have changed since the response was kept
+        rules.scrub_entry(&mut entry);
+
+        let path = dir.join(Path::new(&entry.file).with_extension("json"));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let fixture = serde_json::to_string_pretty(&fixture(&entry))?;
+        fs::write(&path, format!("{fixture}\n"))?;
+        written.push(path);
+    }
+
+    written.sort();
+
+    Ok(written)
+}
+
+/// A request and its response, as:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "file": "api/orders/get.http",
+///   "request": { "method": "GET", "url": "...", "headers": {}, "body": null },
+///   "response": { "status": 200, "headers": {}, "body": {} }
+/// }
+/// ```
+///
+/// JSON bodies are given as JSON, other text as a string, and binary bodies
+/// as `body_base64` instead of `body`.
+fn fixture(entry: &HistoryEntry) -> JsonValue {
+    let (headers, body) = split_request(&entry.request);
+
+    let mut request = Map::new();
+    request.insert("method".into(), json!(entry.method));
+    request.insert("url".into(), json!(redact_url(&entry.url)));
+    request.insert("headers".into(), redact_headers(&headers));
+    insert_body(&mut request, body.as_bytes());
+
+    let mut response = Map::new();
+    response.insert("status".into(), json!(entry.status));
+    response.insert("headers".into(), redact_headers(&entry.headers));
+    insert_body(&mut response, &entry.body);
+
+    json!({
+        "version": FIXTURE_VERSION,
+        "file": entry.file,
+        "request": request,
+        "response": response,
+    })
+}
+
+/// The headers and body of a request, as it was sent
+pub(crate) fn split_request(request: &str) -> (Vec<(String, String)>, String) {
+    // Sent requests have no blocks left that could fail to parse
+    let request = Directives::parse(request)
+        .map_or_else(|_| request.to_string(), |d| d.request());
+    let mut lines = request
+        .lines()
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'))
+        // The request line
+        .skip(1);
+
+    let headers = lines
+        .by_ref()
+        .take_while(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+    (headers, body)
+}
+
+fn insert_body(map: &mut Map<String, JsonValue>, body: &[u8]) {
+    let value = match std::str::from_utf8(body) {
+        Ok("") => JsonValue::Null,
+        Ok(text) => match serde_json::from_str(text) {
+            Ok(json) => redact_json(json),
+            Err(_) => JsonValue::String(text.to_string()),
+        },
+        Err(_) => {
+            map.insert("body_base64".into(), json!(STANDARD.encode(body)));
+            return;
+        }
+    };
+
+    map.insert("body".into(), value);
+}
+
+/// Headers by lower case name, with repeated headers joined by commas.
+/// Headers that change with every response are left out, so that exporting
+/// again only changes the fixtures that matter.
+fn redact_headers(headers: &[(String, String)]) -> JsonValue {
+    let mut map = Map::new();
+    for (name, value) in headers {
+        let name = name.to_lowercase();
+        if VOLATILE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let value = redact_header(&name, value);
+
+        match map.get_mut(&name) {
+            Some(JsonValue::String(existing)) if existing != REDACTED => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            }
+            Some(_) => (),
+            None => {
+                map.insert(name, JsonValue::String(value));
+            }
+        }
+    }
+
+    JsonValue::Object(map)
+}
+
+/// The value of a header, or [REDACTED] if it carries credentials
+pub(crate) fn redact_header(name: &str, value: &str) -> String {
+    let name = name.to_lowercase();
+    if SECRET_HEADERS.contains(&name.as_str()) || is_secret_key(&name) {
+        REDACTED.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+pub(crate) fn redact_url(url: &str) -> String {
+    let Ok(mut url) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if url.query().is_none() {
+        return url.to_string();
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = if is_secret_key(&key) {
+                REDACTED.to_string()
+            } else {
+                value.to_string()
+            };
+            (key.to_string(), value)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+
+    url.to_string()
+}
+
+pub(crate) fn redact_json(value: JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(object) => JsonValue::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = if is_secret_key(&key) && !value.is_null() {
+                        JsonValue::String(REDACTED.into())
+                    } else {
+                        redact_json(value)
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        JsonValue::Array(values) => {
+            JsonValue::Array(values.into_iter().map(redact_json).collect())
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry() -> HistoryEntry {
+        HistoryEntry {
+            id: "1714651200000".into(),
+            time: 1714651200000,
+            file: "api/login.http".into(),
+            target: "default".into(),
+            method: "POST".into(),
+            url: "http://example.com/login?api_key=abc&lang=en".into(),
+            request: "POST http://example.com/login HTTP/1.1\n\
+                      Authorization: Bearer abc\n\
+                      Content-Type: application/json\n\
+                      \n\
+                      {\"user\": \"admin\", \"password\": \"hunter2\"}\n"
+                .into(),
+            status: 200,
+            elapsed: Duration::from_millis(85),
+            headers: vec![
+                ("Set-Cookie".into(), "a=1".into()),
+                ("Set-Cookie".into(), "b=2".into()),
+                ("Vary".into(), "Accept".into()),
+                ("Vary".into(), "Origin".into()),
+                ("Date".into(), "Thu, 02 May 2024 12:00:00 GMT".into()),
+            ],
+            content_type: "application/json".into(),
+            body: b"{\"items\": [{\"id\": 1, \"token\": \"xyz\"}]}".to_vec(),
+            note: None,
+            bookmarked: false,
+        }
+    }
+
+    #[test]
+    fn writes_sanitized_fixtures() {
+        assert_eq!(
+            fixture(&entry()),
+            json!({
+                "version": 1,
+                "file": "api/login.http",
+                "request": {
+                    "method": "POST",
+                    "url": "http://example.com/login?api_key=********&lang=en",
+                    "headers": {
+                        "authorization": "********",
+                        "content-type": "application/json",
+                    },
+                    "body": {"user": "admin", "password": "********"},
+                },
+                "response": {
+                    "status": 200,
+                    "headers": {
+                        "set-cookie": "********",
+                        "vary": "Accept, Origin",
+                    },
+                    "body": {"items": [{"id": 1, "token": "********"}]},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_other_bodies() {
+        let mut entry = entry();
+        entry.request = "GET http://example.com/ HTTP/1.1\n".into();
+        entry.body = vec![0xff, 0xd8];
+
+        let fixture = fixture(&entry);
+
+        assert_eq!(fixture["request"]["body"], JsonValue::Null);
+        assert_eq!(fixture["response"]["body_base64"], "/9g=");
+        assert!(fixture["response"].get("body").is_none());
+    }
+}
@@ -3,14 +3,13 @@ use futures::future::join_all;
 use log::warn;
 use spinoff::{spinners, Color, Spinner, Streams};
 
-use std::fs::read_to_string;
 use std::path::Path;
 use std::time::Duration;
 use tokio::spawn;
 use toml::Table;
 
-use crate::prompt::{get_interaction, substitute_interactive};
-use crate::request::{build_client, do_request};
+use crate::request::{build_client, do_request, prepare_request};
+use crate::retry::RetryPolicy;
 use crate::util::{split_work, IterExt};
 
 pub async fn flurry_attack(
@@ -26,17 +25,11 @@ pub async fn flurry_attack(
         bail!("Connections must be at least 1");
     }
 
-    let client = build_client()?;
+    let (buf, env, _) = prepare_request(file_path, env, &[]).await?;
+    let client = build_client(&env)?;
 
     warn!("# Sending {flurry_size} requests on {connections} parallel connections...");
 
-    let interaction = get_interaction();
-    let buf = substitute_interactive(
-        &read_to_string(file_path)?,
-        env,
-        interaction.as_ref(),
-    )?;
-
     let t = std::time::Instant::now();
     let mut spinner = Spinner::new_with_stream(
         spinners::BouncingBall,
@@ -54,7 +47,9 @@ pub async fn flurry_attack(
         spawn(async move {
             let mut results = Vec::new();
             for _ in 0..size {
-                let res = match do_request(&client, &buf).await {
+                // Failed requests are counted, not retried
+                let retry = RetryPolicy::default();
+                let res = match do_request(&client, &buf, &retry).await {
                     Ok((res, elapsed)) => {
                         Some((res.status().as_u16(), elapsed))
                     }
@@ -0,0 +1,339 @@
+use crate::image::image_info;
+
+use syntect::{
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+/// How a response body is presented, based on its content type
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BodyFormat {
+    Json,
+    Csv,
+    Yaml,
+    Image,
+    Binary,
+    #[default]
+    Text,
+}
+
+impl BodyFormat {
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+
+        if mime.ends_with("json") {
+            Self::Json
+        } else if mime == "text/csv" {
+            Self::Csv
+        } else if mime.ends_with("yaml") || mime.ends_with("yml") {
+            Self::Yaml
+        } else if mime.starts_with("image/") && !mime.contains("svg") {
+            Self::Image
+        } else if is_binary_mime(&mime) {
+            Self::Binary
+        } else {
+            Self::Text
+        }
+    }
+
+    /// The file extension used to find a syntax for highlighting
+    pub fn syntax_extension(&self) -> Option<&'static str> {
+        match self {
+            Self::Json => Some("json"),
+            Self::Yaml => Some("yaml"),
+            Self::Csv | Self::Image | Self::Binary | Self::Text => None,
+        }
+    }
+}
+
+/// Format a response body for display
+pub fn format_body(format: BodyFormat, body: &str) -> String {
+    match format {
+        BodyFormat::Json => {
+            match serde_json::from_str::<serde_json::Value>(body) {
+                Ok(json) => serde_json::to_string_pretty(&json)
+                    .unwrap_or_else(|_| body.to_string()),
+                Err(_) => body.to_string(),
+            }
+        }
+        BodyFormat::Csv => align_table(&parse_csv(body)),
+        BodyFormat::Yaml
+        | BodyFormat::Image
+        | BodyFormat::Binary
+        | BodyFormat::Text => body.to_string(),
+    }
+}
+
+/// Format a binary response body for display. Returns `None` for text.
+pub fn describe_binary(format: BodyFormat, data: &[u8]) -> Option<String> {
+    match format {
+        BodyFormat::Image => Some(match image_info(data) {
+            Some(info) => info.summary(data.len()),
+            None => format!("Image, {} bytes", data.len()),
+        }),
+        BodyFormat::Binary => Some(describe_file(data)),
+        // Content that isn't text, even if not declared as binary
+        BodyFormat::Text if std::str::from_utf8(data).is_err() => {
+            Some(describe_file(data))
+        }
+        _ => None,
+    }
+}
+
+fn is_binary_mime(mime: &str) -> bool {
+    let (kind, subtype) = mime.split_once('/').unwrap_or((mime, ""));
+
+    match kind {
+        "audio" | "video" | "font" => true,
+        "application" => {
+            !(subtype.contains("json")
+                || subtype.contains("xml")
+                || subtype.contains("javascript")
+                || subtype.contains("yaml")
+                || subtype.starts_with("x-www-form-urlencoded"))
+        }
+        _ => false,
+    }
+}
+
+/// Known file types, by the magic bytes at the start of the file
+const MAGIC_TYPES: &[(&[u8], &str, &str)] = &[
+    (b"%PDF-", "PDF document", "pdf"),
+    (b"PK\x03\x04", "ZIP archive", "zip"),
+    (b"\x1f\x8b", "GZIP compressed data", "gz"),
+    (b"\x89PNG", "PNG image", "png"),
+    (b"\xff\xd8\xff", "JPEG image", "jpg"),
+    (b"GIF8", "GIF image", "gif"),
+    (b"\x7fELF", "ELF executable", "bin"),
+    (b"MZ", "Windows executable", "exe"),
+    (b"OggS", "Ogg media", "ogg"),
+    (b"ID3", "MP3 audio", "mp3"),
+    (b"\x00asm", "WebAssembly module", "wasm"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive", "7z"),
+];
+
+/// The type of a file, and a typical extension, based on its magic bytes
+pub fn magic_type(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    MAGIC_TYPES
+        .iter()
+        .find(|(magic, _, _)| data.starts_with(magic))
+        .map(|(_, name, ext)| (*name, *ext))
+}
+
+/// Summarize binary data, with details for some types of files
+pub fn describe_file(data: &[u8]) -> String {
+    let mut parts = Vec::new();
+
+    match magic_type(data) {
+        Some(("PDF document", _)) => {
+            let version = data
+                .get(5..8)
+                .and_then(|v| std::str::from_utf8(v).ok())
+                .unwrap_or("?");
+            parts.push(format!("PDF document (version {version})"));
+            parts.push(format!("{} pages", pdf_page_count(data)));
+        }
+        Some((name, _)) => parts.push(name.to_string()),
+        None => parts.push("Binary data".to_string()),
+    }
+
+    parts.push(format!("{} bytes", data.len()));
+
+    parts.join(", ")
+}
+
+/// Count page objects in a PDF. This doesn't handle compressed object
+/// streams, but works for most documents.
+fn pdf_page_count(data: &[u8]) -> usize {
+    let pattern = regex::bytes::Regex::new(r"/Type\s*/Page([^s]|$)")
+        .expect("valid regex");
+
+    pattern.find_iter(data).count()
+}
+
+/// Highlight text with terminal escape codes
+pub fn highlight_for_terminal(text: &str, format: BodyFormat) -> String {
+    let Some(ext) = format.syntax_extension() else {
+        return text.to_string();
+    };
+
+    let ps = SyntaxSet::load_defaults_newlines();
+    let ts = ThemeSet::load_defaults();
+    let Some(syntax) = ps.find_syntax_by_extension(ext) else {
+        return text.to_string();
+    };
+
+    let mut h = HighlightLines::new(syntax, &ts.themes["Solarized (dark)"]);
+    let mut out = String::new();
+    for line in LinesWithEndings::from(text) {
+        match h.highlight_line(line, &ps) {
+            Ok(ranges) => {
+                out.push_str(&as_24_bit_terminal_escaped(&ranges, false))
+            }
+            Err(_) => out.push_str(line),
+        }
+    }
+    out.push_str("\x1b[0m");
+
+    out
+}
+
+/// Parse CSV, with support for quoted fields containing separators, quotes
+/// and line breaks.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => (),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
+
+/// Render rows as a table with aligned columns, and a line under the header
+pub fn align_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|r| r.get(i))
+                .map(|f| f.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut out = String::new();
+    for (n, row) in rows.iter().enumerate() {
+        let line: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                let field = row.get(i).map(String::as_str).unwrap_or("");
+                format!("{:w$}", field, w = w)
+            })
+            .collect();
+        out.push_str(line.join(" | ").trim_end());
+        out.push('\n');
+
+        if n == 0 {
+            let sep: Vec<String> =
+                widths.iter().map(|w| "-".repeat(*w)).collect();
+            out.push_str(&sep.join("-+-"));
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_format_from_content_type() {
+        use BodyFormat::*;
+
+        let cases = [
+            ("application/json; charset=utf-8", Json),
+            ("application/problem+json", Json),
+            ("text/csv", Csv),
+            ("application/yaml", Yaml),
+            ("text/x-yaml", Yaml),
+            ("image/png", Image),
+            ("text/plain", Text),
+        ];
+
+        for (content_type, expected) in cases {
+            assert_eq!(BodyFormat::from_content_type(content_type), expected);
+        }
+    }
+
+    #[test]
+    fn detects_binary_content_types() {
+        use BodyFormat::*;
+
+        let cases = [
+            ("application/pdf", Binary),
+            ("application/octet-stream", Binary),
+            ("video/mp4", Binary),
+            ("application/xml", Text),
+            ("application/x-www-form-urlencoded", Text),
+        ];
+
+        for (content_type, expected) in cases {
+            assert_eq!(BodyFormat::from_content_type(content_type), expected);
+        }
+    }
+
+    #[test]
+    fn describes_pdf_documents() {
+        let pdf = b"%PDF-1.7\n1 0 obj << /Type /Pages /Count 2 >>\n\
+            2 0 obj << /Type /Page >>\n3 0 obj << /Type/Page /Parent 1 0 R >>";
+
+        assert_eq!(
+            describe_file(pdf),
+            format!("PDF document (version 1.7), 2 pages, {} bytes", pdf.len())
+        );
+    }
+
+    #[test]
+    fn describes_unknown_binary_data() {
+        assert_eq!(describe_file(&[0, 1, 2]), "Binary data, 3 bytes");
+    }
+
+    #[test]
+    fn describes_non_utf8_text_as_binary() {
+        let data = [0xfe, 0xff, 0x00];
+
+        assert!(describe_binary(BodyFormat::Text, &data).is_some());
+        assert!(describe_binary(BodyFormat::Text, b"hello").is_none());
+    }
+
+    #[test]
+    fn parses_quoted_csv_fields() {
+        let rows = parse_csv("a,b\n\"x, y\",\"say \"\"hi\"\"\"\n");
+
+        assert_eq!(rows, vec![vec!["a", "b"], vec!["x, y", "say \"hi\""]]);
+    }
+
+    #[test]
+    fn aligns_table_columns() {
+        let rows = parse_csv("id,name\n1,apple\n100,kiwi\n");
+
+        assert_eq!(
+            align_table(&rows),
+            "id  | name\n----+------\n1   | apple\n100 | kiwi\n"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+use toml::{Table, Value};
+
+use crate::{
+    clock::unix_now,
+    env::{read_toml, request_key, update_toml_file},
+    state::{ensure_state_dir, usage_file},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// The most a request can gain from frecency when searching, so that a
+/// request that is used often doesn't beat a much better match
+const MAX_MATCH_BONUS: i64 = 50;
+
+/// Remember that a request was sent, to rank it higher in the selector
+pub fn record_use(root_dir: &Path, file_path: &Path) -> Result<()> {
+    ensure_state_dir(root_dir)?;
+    let file = usage_file(root_dir);
+    let key = request_key(root_dir, file_path);
+
+    let count = read_toml(&file)
+        .ok()
+        .and_then(|usage| usage.get(&key)?.get("count")?.as_integer())
+        .unwrap_or(0);
+
+    let mut entry = Table::new();
+    entry.insert("count".into(), Value::Integer(count + 1));
+    entry.insert("last_used".into(), Value::Integer(unix_now() as i64));
+
+    let mut usage = Table::new();
+    usage.insert(key, Value::Table(entry));
+
+    update_toml_file(&file, &usage)
+}
+
+/// The frecency of the requests that have been sent, by their path relative
+/// to the project root
+pub fn frecency_scores(root_dir: &Path) -> HashMap<String, i64> {
+    let Ok(usage) = read_toml(&usage_file(root_dir)) else {
+        return HashMap::new();
+    };
+    let now = unix_now();
+
+    usage
+        .iter()
+        .filter_map(|(key, entry)| {
+            let count = entry.get("count")?.as_integer()?;
+            let last_used = entry.get("last_used")?.as_integer()?;
+            Some((key.clone(), frecency(count, last_used as u64, now)))
+        })
+        .collect()
+}
+
+/// The frecency of a request, for each request file given
+pub fn request_scores(root_dir: &Path, files: &[&Path]) -> Vec<i64> {
+    let scores = frecency_scores(root_dir);
+
+    files
+        .iter()
+        .map(|f| scores.get(&request_key(root_dir, f)).copied().unwrap_or(0))
+        .collect()
+}
+
+/// How often a request is used, weighted by how recently it was last used
+fn frecency(count: i64, last_used: u64, now: u64) -> i64 {
+    let age = now.saturating_sub(last_used);
+    let weight = match age {
+        a if a < 4 * DAY => 100,
+        a if a < 14 * DAY => 70,
+        a if a < 31 * DAY => 50,
+        a if a < 90 * DAY => 30,
+        _ => 10,
+    };
+
+    count * weight
+}
+
+/// The score of a fuzzy match, raised by the frecency of the request
+pub fn match_score(match_score: i64, frecency: i64) -> i64 {
+    match_score + (frecency / 10).min(MAX_MATCH_BONUS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighs_recent_use_higher() {
+        let now = 1_000 * DAY;
+
+        assert_eq!(frecency(3, now - DAY, now), 300);
+        assert_eq!(frecency(3, now - 20 * DAY, now), 150);
+        assert_eq!(frecency(3, now - 200 * DAY, now), 30);
+    }
+
+    #[test]
+    fn limits_bonus_for_matches() {
+        assert_eq!(match_score(120, 0), 120);
+        assert_eq!(match_score(120, 300), 150);
+        assert_eq!(match_score(120, 10_000), 170);
+    }
+
+    #[test]
+    fn records_use_of_requests() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-frecency-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+
+        let login = root.join("api/login.http");
+        record_use(&root, &login).unwrap();
+        record_use(&root, &login).unwrap();
+        record_use(&root, &root.join("health.http")).unwrap();
+
+        let scores = frecency_scores(&root);
+        assert_eq!(scores.get("api/login.http"), Some(&200));
+        assert_eq!(scores.get("health.http"), Some(&100));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
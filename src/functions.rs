@@ -0,0 +1,180 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Utc,
+};
+use uuid::Builder;
+
+use crate::{
+    clock::{self, parse_offset},
+    random::{next_u64, random_bytes},
+};
+
+/// The format of `{{now()}}` without a format
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Evaluate a function placeholder, like `uuid()` or `now(+2h, %Y-%m-%d)`.
+/// Gives `None` for anything that isn't a call to a known function, so that
+/// it is looked up as a variable.
+pub fn call_function(placeholder: &str) -> Option<Result<String>> {
+    let (name, rest) = placeholder.trim().split_once('(')?;
+    let args = rest.strip_suffix(')')?.trim();
+
+    let result = match name.trim() {
+        "uuid" => no_args(args).map(|_| uuid()),
+        "timestamp" => timestamp(args),
+        "now" => now(args),
+        "random_int" => random_int(args),
+        "base64" => Ok(STANDARD.encode(args)),
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+fn no_args(args: &str) -> Result<()> {
+    if !args.is_empty() {
+        bail!("Takes no arguments");
+    }
+
+    Ok(())
+}
+
+/// A random version 4 UUID
+fn uuid() -> String {
+    Builder::from_random_bytes(random_bytes())
+        .into_uuid()
+        .to_string()
+}
+
+/// Seconds since the unix epoch, or milliseconds with `timestamp(ms)`
+fn timestamp(args: &str) -> Result<String> {
+    let since_epoch = clock::now().duration_since(UNIX_EPOCH)?;
+
+    match args {
+        "" | "s" => Ok(since_epoch.as_secs().to_string()),
+        "ms" => Ok(since_epoch.as_millis().to_string()),
+        _ => bail!("Unknown unit {args}, use s or ms"),
+    }
+}
+
+/// The current time in UTC, optionally moved by an offset like `+2h` or
+/// `-30m`, and formatted like `%Y-%m-%d`. The format may contain commas.
+fn now(args: &str) -> Result<String> {
+    let (offset, format) = if args.starts_with(['+', '-']) {
+        args.split_once(',')
+            .map_or((args, ""), |(offset, format)| (offset, format.trim()))
+    } else {
+        ("", args)
+    };
+
+    let mut time = DateTime::<Utc>::from(clock::now());
+    if !offset.is_empty() {
+        let ms = parse_offset(offset)
+            .ok_or_else(|| anyhow!("Invalid offset {offset}"))?;
+        let magnitude = Duration::from_millis(ms.unsigned_abs());
+        time = if ms < 0 {
+            time - magnitude
+        } else {
+            time + magnitude
+        };
+    }
+
+    let format = if format.is_empty() {
+        DEFAULT_TIME_FORMAT
+    } else {
+        format
+    };
+    // Formatting panics on invalid formats, so they are checked first
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        bail!("Invalid time format {format}");
+    }
+
+    Ok(time.format_with_items(items.into_iter()).to_string())
+}
+
+/// A random integer between two integers, both included
+fn random_int(args: &str) -> Result<String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| anyhow!("Invalid integer {}", s.trim()))
+    };
+
+    let (min, max) = match args.split_once(',') {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => bail!("Takes a minimum and a maximum, like random_int(1, 100)"),
+    };
+    if min > max {
+        bail!("The minimum {min} is larger than the maximum {max}");
+    }
+
+    let span = max.abs_diff(min).saturating_add(1);
+    let n = match span {
+        0 => next_u64(),
+        span => next_u64() % span,
+    };
+
+    Ok(min.wrapping_add(n as i64).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(placeholder: &str) -> String {
+        call_function(placeholder).unwrap().unwrap()
+    }
+
+    #[test]
+    fn generates_uuids() {
+        let uuid = call("uuid()");
+
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+        assert_ne!(uuid, call("uuid()"));
+        assert!(call_function("uuid(1)").unwrap().is_err());
+    }
+
+    #[test]
+    fn formats_times() {
+        let now = DateTime::<Utc>::from(clock::now());
+        let today = now.format("%Y-%m-%d").to_string();
+
+        assert_eq!(call("now(%Y-%m-%d)"), today);
+        assert_eq!(call("now(+0s, %Y-%m-%d)"), today);
+        assert_eq!(call("now(%a, %d)"), now.format("%a, %d").to_string());
+        assert_eq!(call("now()").len(), "2024-05-02T12:00:00Z".len());
+        assert!(call_function("now(%Q)").unwrap().is_err());
+        assert!(call_function("now(+2 weeks, %Y)").unwrap().is_err());
+        assert!(call("timestamp()").parse::<u64>().is_ok());
+        assert_eq!(call("timestamp(ms)").len(), call("timestamp()").len() + 3);
+    }
+
+    #[test]
+    fn generates_integers_in_range() {
+        for _ in 0..100 {
+            let n: i64 = call("random_int(1, 3)").parse().unwrap();
+            assert!((1..=3).contains(&n));
+        }
+        assert_eq!(call("random_int(-5, -5)"), "-5");
+        assert!(call_function("random_int(3, 1)").unwrap().is_err());
+        assert!(call_function("random_int(1)").unwrap().is_err());
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(call("base64(user:pass)"), "dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn leaves_other_placeholders() {
+        assert!(call_function("user_id").is_none());
+        assert!(call_function("lookup(id)").is_none());
+    }
+}
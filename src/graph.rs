@@ -0,0 +1,294 @@
+use std::{
+    fmt::Write,
+    fs::read_to_string,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use toml::{Table, Value};
+
+use crate::{
+    depends::dependencies,
+    directive::{Directives, OUTPUT, SCRIPT},
+    discovery::find_project_requests,
+    include::include_body,
+    script::RequestScripts,
+    substitute::placeholder_keys,
+};
+
+/// How a graph is written
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz, like `hitman graph | dot -Tsvg > graph.svg`
+    #[default]
+    Dot,
+    /// Mermaid, which renders in Markdown on GitHub and GitLab
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            _ => bail!("Unknown graph format `{s}`, expected dot or mermaid"),
+        }
+    }
+}
+
+/// What a request takes from other requests, and what it gives them
+#[derive(Debug, Default, PartialEq)]
+pub struct RequestNode {
+    /// Relative to the root directory
+    pub file: PathBuf,
+    /// Values extracted from the response, or saved by a script
+    pub produces: Vec<String>,
+    /// Values used in placeholders
+    pub consumes: Vec<String>,
+    /// Requests given with `# @depends on:`, relative to the root directory
+    pub depends_on: Vec<PathBuf>,
+}
+
+/// Find which values each request in a project produces and consumes
+pub fn request_graph(root_dir: &Path) -> Result<Vec<RequestNode>> {
+    let mut nodes = Vec::new();
+    for file in find_project_requests(root_dir)? {
+        let Ok(buf) = read_to_string(root_dir.join(&file)) else {
+            continue;
+        };
+        let config =
+            read_to_string(root_dir.join(&file).with_extension("http.toml"))
+                .ok()
+                .and_then(|content| toml::from_str::<Table>(&content).ok())
+                .unwrap_or_default();
+
+        let buf = Directives::parse(&buf)?.without(&[OUTPUT]);
+        let dir = root_dir.join(&file);
+        let buf =
+            include_body(&buf, dir.parent().unwrap_or(root_dir)).unwrap_or(buf);
+
+        nodes.push(request_node(file, &buf, &config)?);
+    }
+
+    Ok(nodes)
+}
+
+fn request_node(
+    file: PathBuf,
+    buf: &str,
+    config: &Table,
+) -> Result<RequestNode> {
+    let directives = Directives::parse(buf)?;
+    let scripts = RequestScripts::from_directives(&directives)?;
+
+    let mut produces: Vec<String> = match config.get("_extract") {
+        Some(Value::Table(rules)) => rules.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    for var in scripts.saved_vars() {
+        if !produces.contains(&var) {
+            produces.push(var);
+        }
+    }
+
+    let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+    let depends_on = dependencies(&directives)
+        .iter()
+        .map(|dep| normalize(&dir.join(dep)))
+        .collect();
+
+    Ok(RequestNode {
+        consumes: placeholder_keys(&directives.without(&[SCRIPT])),
+        file,
+        produces,
+        depends_on,
+    })
+}
+
+/// Resolve `..` in a relative path, without looking at the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => (),
+            part => out.push(part),
+        }
+    }
+
+    out
+}
+
+/// Write a graph of the requests, the values they produce, and the requests
+/// that consume them. Values that no request produces, like the values of
+/// the targets, are left out, and so are the requests that are not linked to
+/// any other.
+pub fn write_graph(nodes: &[RequestNode], format: GraphFormat) -> String {
+    let mut values: Vec<&str> = Vec::new();
+    for node in nodes {
+        for value in &node.produces {
+            if !values.contains(&value.as_str()) {
+                values.push(value);
+            }
+        }
+    }
+
+    let request_id = |file: &Path| {
+        let i = nodes.iter().position(|n| n.file == file);
+        i.map(|i| format!("r{i}"))
+    };
+    let value_id = |value: &str| {
+        let i = values.iter().position(|v| *v == value);
+        i.map(|i| format!("v{i}"))
+    };
+
+    let mut out = String::new();
+    match format {
+        GraphFormat::Dot => {
+            out.push_str("digraph hitman {\n    rankdir=LR;\n");
+        }
+        GraphFormat::Mermaid => out.push_str("flowchart LR\n"),
+    }
+
+    let mut edges = Vec::new();
+    for node in nodes {
+        let Some(from) = request_id(&node.file) else {
+            continue;
+        };
+        for value in &node.produces {
+            edges.push((from.clone(), value_id(value).unwrap(), false));
+        }
+        for value in &node.consumes {
+            if let Some(id) = value_id(value) {
+                edges.push((id, from.clone(), false));
+            }
+        }
+        for dep in &node.depends_on {
+            if let Some(id) = request_id(dep) {
+                edges.push((id, from.clone(), true));
+            }
+        }
+    }
+
+    // Requests without a part in any chain are left out
+    let linked = |id: &str| edges.iter().any(|(a, b, _)| a == id || b == id);
+    for (i, node) in nodes.iter().enumerate() {
+        if !linked(&format!("r{i}")) {
+            continue;
+        }
+        let label = node.file.display().to_string();
+        let _ = match format {
+            GraphFormat::Dot => {
+                writeln!(out, "    r{i} [label={}, shape=box];", quoted(&label))
+            }
+            GraphFormat::Mermaid => {
+                writeln!(out, "    r{i}[{}]", quoted(&label))
+            }
+        };
+    }
+    for (i, value) in values.iter().enumerate() {
+        let _ = match format {
+            GraphFormat::Dot => writeln!(
+                out,
+                "    v{i} [label={}, shape=ellipse];",
+                quoted(value)
+            ),
+            GraphFormat::Mermaid => {
+                writeln!(out, "    v{i}([{}])", quoted(value))
+            }
+        };
+    }
+
+    for (from, to, dependency) in edges {
+        let _ = match (format, dependency) {
+            (GraphFormat::Dot, false) => writeln!(out, "    {from} -> {to};"),
+            (GraphFormat::Dot, true) => {
+                writeln!(out, "    {from} -> {to} [style=dashed];")
+            }
+            (GraphFormat::Mermaid, false) => {
+                writeln!(out, "    {from} --> {to}")
+            }
+            (GraphFormat::Mermaid, true) => {
+                writeln!(out, "    {from} -.-> {to}")
+            }
+        };
+    }
+
+    if format == GraphFormat::Dot {
+        out.push_str("}\n");
+    }
+
+    out
+}
+
+fn quoted(label: &str) -> String {
+    format!("\"{}\"", label.replace('"', "'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> Vec<RequestNode> {
+        let config: Table =
+            toml::from_str("[_extract]\ntoken = \"$.token\"").unwrap();
+        vec![
+            request_node(
+                PathBuf::from("login.http"),
+                "POST {{url}}/login\n",
+                &config,
+            )
+            .unwrap(),
+            request_node(
+                PathBuf::from("orders/list.http"),
+                "# @depends on: ../login.http\nGET {{url}}/orders\nAuthorization: Bearer {{token}}\n",
+                &Table::new(),
+            )
+            .unwrap(),
+        ]
+    }
+
+    #[test]
+    fn finds_produced_and_consumed_values() {
+        let nodes = nodes();
+
+        assert_eq!(nodes[0].produces, vec!["token"]);
+        assert_eq!(nodes[1].consumes, vec!["url", "token"]);
+        assert_eq!(nodes[1].depends_on, vec![PathBuf::from("login.http")]);
+    }
+
+    #[test]
+    fn writes_dot_and_mermaid_graphs() {
+        let nodes = nodes();
+
+        assert_eq!(
+            write_graph(&nodes, GraphFormat::Dot),
+            "digraph hitman {
+    rankdir=LR;
+    r0 [label=\"login.http\", shape=box];
+    r1 [label=\"orders/list.http\", shape=box];
+    v0 [label=\"token\", shape=ellipse];
+    r0 -> v0;
+    v0 -> r1;
+    r0 -> r1 [style=dashed];
+}
+"
+        );
+        assert_eq!(
+            write_graph(&nodes, GraphFormat::Mermaid),
+            "flowchart LR
+    r0[\"login.http\"]
+    r1[\"orders/list.http\"]
+    v0([\"token\"])
+    r0 --> v0
+    v0 --> r1
+    r0 -.-> r1
+"
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use h2::client::SendRequest;
+use http::HeaderMap;
+use prost::Message;
+use prost_reflect::DescriptorPool;
+use reqwest::{Response, ResponseBuilderExt, Url};
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use toml::{Table, Value};
+
+use crate::{
+    directive::Directives,
+    env::{find_root_dir, read_and_merge_config},
+    proto::{decode, encode, find_method, load_protos, ReflectedFiles},
+    request::USER_AGENT,
+    websocket::request_line,
+};
+
+const GRPC_KEY: &str = "_grpc";
+const GRPC_METHOD: &str = "GRPC";
+
+/// Tried in order, since older servers only have the alpha version
+const REFLECTION_SERVICES: &[&str] = &[
+    "grpc.reflection.v1.ServerReflection",
+    "grpc.reflection.v1alpha.ServerReflection",
+];
+
+/// The names of the gRPC status codes, by number
+const STATUS_NAMES: &[&str] = &[
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+const UNIMPLEMENTED: u32 = 12;
+
+/// A gRPC call, given as `GRPC host:port package.Service/Method`, followed
+/// by metadata like headers, and a JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrpcRequest {
+    /// The server, as `http` or `https`
+    pub url: Url,
+    pub service: String,
+    pub method: String,
+    pub metadata: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Where to find the protos, from the `[_grpc]` table in the config file.
+/// Without protos, the types are fetched with server reflection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GrpcConfig {
+    pub protos: Vec<PathBuf>,
+    pub import_paths: Vec<PathBuf>,
+}
+
+impl GrpcConfig {
+    pub fn load(root_dir: &Path) -> Result<Self> {
+        let config = read_and_merge_config(root_dir)?;
+
+        Ok(match config.get(GRPC_KEY) {
+            Some(Value::Table(grpc)) => Self::from_table(root_dir, grpc),
+            _ => Self::default(),
+        })
+    }
+
+    fn from_table(root_dir: &Path, grpc: &Table) -> Self {
+        let paths = |key: &str| -> Vec<PathBuf> {
+            match grpc.get(key) {
+                Some(Value::String(path)) => vec![root_dir.join(path)],
+                Some(Value::Array(paths)) => paths
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|path| root_dir.join(path))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+
+        let mut import_paths = paths("import_paths");
+        import_paths.push(root_dir.to_path_buf());
+
+        Self {
+            protos: paths("protos"),
+            import_paths,
+        }
+    }
+}
+
+/// Whether a request is a gRPC call, which is the case for the `GRPC` method
+pub fn is_grpc_request(request: &str) -> bool {
+    request_line(request)
+        .split_whitespace()
+        .next()
+        .is_some_and(|method| method.eq_ignore_ascii_case(GRPC_METHOD))
+}
+
+pub fn parse_grpc_request(buf: &str) -> Result<GrpcRequest> {
+    let buf = Directives::parse(buf)?.request();
+    let mut lines = buf
+        .lines()
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'));
+
+    let line = lines.next().context("Invalid input: server not found")?;
+    let (target, path) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [_, target, path] => (target, path),
+        _ => bail!("Invalid input: expected GRPC host:port Service/Method"),
+    };
+
+    let url = if target.contains("://") {
+        Url::parse(target)?
+    } else {
+        Url::parse(&format!("http://{target}"))?
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Unsupported gRPC scheme: {}", url.scheme());
+    }
+
+    let (service, method) = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .context("Invalid input: expected Service/Method")?;
+
+    let mut metadata = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("Invalid header: {line}"))?;
+        metadata.push((name.trim().to_lowercase(), value.trim().to_string()));
+    }
+
+    let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+    Ok(GrpcRequest {
+        url,
+        service: service.to_string(),
+        method: method.to_string(),
+        metadata,
+        body,
+    })
+}
+
+/// Make a unary or server streaming call, and give the reply as a JSON
+/// response, with the gRPC status mapped to an HTTP status. Streamed replies
+/// are given as an array.
+pub async fn grpc_request(buf: &str) -> Result<(Response, Duration)> {
+    let request = parse_grpc_request(buf)?;
+    let mut channel = connect(&request.url).await?;
+
+    let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+    let config = GrpcConfig::load(&root_dir)?;
+    let pool = if config.protos.is_empty() {
+        reflect(&mut channel, &request).await.context(
+            "Server reflection failed, configure protos in [_grpc] instead",
+        )?
+    } else {
+        load_protos(&config.protos, &config.import_paths)?
+    };
+
+    let method = find_method(&pool, &request.service, &request.method)?;
+    if method.is_client_streaming() {
+        bail!("Client streaming calls are not supported");
+    }
+
+    let body: JsonValue = if request.body.is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(&request.body).context("Invalid JSON body")?
+    };
+    let message = encode(method.input(), &body)?;
+
+    let path = format!("/{}/{}", request.service, request.method);
+    let start = Instant::now();
+    let reply = call(&mut channel, &request, &path, &message).await?;
+    let elapsed = start.elapsed();
+
+    let (code, status_message) = reply.status();
+    let body = if code != 0 {
+        json!({
+            "code": status_name(code),
+            "message": status_message,
+        })
+    } else {
+        let messages = reply
+            .messages
+            .iter()
+            .map(|m| decode(method.output(), m))
+            .collect::<Result<Vec<_>>>()?;
+        match messages.len() {
+            1 if !method.is_server_streaming() => messages[0].clone(),
+            _ => JsonValue::Array(messages),
+        }
+    };
+
+    let mut url = request.url.clone();
+    url.set_path(&path);
+    let mut builder =
+        http::Response::builder().status(http_status(code)).url(url);
+    for (name, value) in reply.headers.iter().chain(reply.trailers.iter()) {
+        if name != http::header::CONTENT_TYPE {
+            builder = builder.header(name, value);
+        }
+    }
+    let response = builder
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&body)?)?;
+
+    Ok((response.into(), elapsed))
+}
+
+/// The HTTP status closest to a gRPC status, as grpc-gateway maps them
+fn http_status(code: u32) -> u16 {
+    match code {
+        0 => 200,
+        1 => 499,
+        3 | 9 | 11 => 400,
+        4 => 504,
+        5 => 404,
+        6 | 10 => 409,
+        7 => 403,
+        8 => 429,
+        12 => 501,
+        14 => 503,
+        16 => 401,
+        _ => 500,
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Stream for T {}
+
+/// Open an HTTP/2 connection, with TLS for `https`
+async fn connect(url: &Url) -> Result<SendRequest<Bytes>> {
+    let host = url.host_str().context("Invalid input: URL has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let tcp = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("Failed to connect to {host}:{port}"))?;
+
+    let stream: Box<dyn Stream> = if url.scheme() == "https" {
+        let connector = tokio_native_tls::TlsConnector::from(
+            native_tls::TlsConnector::builder()
+                .request_alpns(&["h2"])
+                .build()?,
+        );
+        Box::new(connector.connect(host, tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+
+    let (client, connection) = h2::client::handshake(stream).await?;
+    tokio::spawn(async move {
+        // Fails when the server goes away, which the call notices
+        let _ = connection.await;
+    });
+
+    Ok(client)
+}
+
+#[derive(Debug, Default)]
+struct Reply {
+    headers: HeaderMap,
+    messages: Vec<Vec<u8>>,
+    trailers: HeaderMap,
+}
+
+impl Reply {
+    /// The status code and message, from the trailers, or the headers when
+    /// the server had nothing else to send
+    fn status(&self) -> (u32, String) {
+        let get = |name: &str| {
+            self.trailers
+                .get(name)
+                .or_else(|| self.headers.get(name))
+                .and_then(|v| v.to_str().ok())
+        };
+
+        let code = get("grpc-status").and_then(|s| s.parse().ok()).unwrap_or(2);
+        let message = percent_decode(get("grpc-message").unwrap_or_default());
+
+        (code, message)
+    }
+}
+
+/// Send one message, and read the reply until the server ends the stream
+async fn call(
+    channel: &mut SendRequest<Bytes>,
+    request: &GrpcRequest,
+    path: &str,
+    message: &[u8],
+) -> Result<Reply> {
+    let url = &request.url;
+    let authority = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut builder = http::Request::builder()
+        .method("POST")
+        .uri(format!("{}://{authority}{path}", url.scheme()))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .header("user-agent", USER_AGENT);
+    for (name, value) in &request.metadata {
+        builder = builder.header(name, value);
+    }
+
+    let mut sender = channel.clone().ready().await?;
+    let (response, mut send) = sender.send_request(builder.body(())?, false)?;
+    send.send_data(Bytes::from(frame(message)), true)?;
+
+    let (head, mut body) = response.await?.into_parts();
+    if head.status != http::StatusCode::OK {
+        bail!("Not a gRPC server, got HTTP status {}", head.status);
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+    let trailers = body.trailers().await?.unwrap_or_default();
+
+    Ok(Reply {
+        headers: head.headers,
+        messages: unframe(&data)?,
+        trailers,
+    })
+}
+
+/// Prefix a message with the compression flag and its length
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut out = vec![0];
+    out.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    out.extend_from_slice(message);
+    out
+}
+
+fn unframe(mut data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut messages = Vec::new();
+    while !data.is_empty() {
+        let [compressed, a, b, c, d, ..] = *data else {
+            bail!("Truncated gRPC message");
+        };
+        if compressed != 0 {
+            bail!("Compressed gRPC messages are not supported");
+        }
+        let len = u32::from_be_bytes([a, b, c, d]) as usize;
+        let message = data.get(5..5 + len).context("Truncated gRPC message")?;
+        messages.push(message.to_vec());
+        data = &data[5 + len..];
+    }
+
+    Ok(messages)
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+            if let Some(n) = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                out.push(n);
+                continue;
+            }
+            out.push(b);
+            out.extend(hex);
+        } else {
+            out.push(b);
+        }
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// The fields of `ServerReflectionRequest` that are used, which are from a
+/// oneof in `grpc/reflection/v1/reflection.proto`
+#[derive(Clone, PartialEq, Message)]
+struct ReflectionRequest {
+    #[prost(string, optional, tag = "3")]
+    file_by_filename: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    file_containing_symbol: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ReflectionResponse {
+    #[prost(message, optional, tag = "4")]
+    file_descriptor_response: Option<FileDescriptorResponse>,
+    #[prost(message, optional, tag = "7")]
+    error_response: Option<ErrorResponse>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FileDescriptorResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    file_descriptor_proto: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ErrorResponse {
+    #[prost(int32, tag = "1")]
+    error_code: i32,
+    #[prost(string, tag = "2")]
+    error_message: String,
+}
+
+/// Fetch the types of a service with server reflection, including the files
+/// the service depends on
+async fn reflect(
+    channel: &mut SendRequest<Bytes>,
+    request: &GrpcRequest,
+) -> Result<DescriptorPool> {
+    let mut files = ReflectedFiles::default();
+
+    let by_symbol = ReflectionRequest {
+        file_containing_symbol: Some(request.service.clone()),
+        ..Default::default()
+    };
+    let mut found = None;
+    for (i, service) in REFLECTION_SERVICES.iter().enumerate() {
+        match reflection_call(channel, request, service, &by_symbol).await? {
+            Reflected::Files(reflected) => {
+                found = Some((*service, reflected));
+                break;
+            }
+            Reflected::Failed(UNIMPLEMENTED, _)
+                if i + 1 < REFLECTION_SERVICES.len() => {}
+            failed => {
+                failed.files()?;
+            }
+        };
+    }
+    let (service, reflected) = found.context("Server reflection not found")?;
+    for file in reflected {
+        files.add(&file)?;
+    }
+
+    // By file name, for the dependencies that were not sent along
+    loop {
+        let missing = files.missing_dependencies();
+        if missing.is_empty() {
+            break;
+        }
+        for name in missing {
+            let by_filename = ReflectionRequest {
+                file_by_filename: Some(name.clone()),
+                ..Default::default()
+            };
+            let reflected =
+                reflection_call(channel, request, service, &by_filename)
+                    .await?
+                    .files()?;
+            if reflected.is_empty() {
+                bail!("Server reflection did not find {name}");
+            }
+            for file in reflected {
+                files.add(&file)?;
+            }
+        }
+    }
+
+    files.into_pool()
+}
+
+/// The answer to a reflection request, which fails with a status when the
+/// server doesn't have reflection or the symbol
+enum Reflected {
+    Files(Vec<Vec<u8>>),
+    Failed(u32, String),
+}
+
+impl Reflected {
+    fn files(self) -> Result<Vec<Vec<u8>>> {
+        match self {
+            Self::Files(files) => Ok(files),
+            Self::Failed(code, message) => {
+                bail!("Reflection failed with {}: {message}", status_name(code))
+            }
+        }
+    }
+}
+
+fn status_name(code: u32) -> &'static str {
+    STATUS_NAMES.get(code as usize).unwrap_or(&"UNKNOWN")
+}
+
+async fn reflection_call(
+    channel: &mut SendRequest<Bytes>,
+    request: &GrpcRequest,
+    service: &str,
+    reflection: &ReflectionRequest,
+) -> Result<Reflected> {
+    let path = format!("/{service}/ServerReflectionInfo");
+    let reply =
+        call(channel, request, &path, &reflection.encode_to_vec()).await?;
+    let (code, status_message) = reply.status();
+    if code != 0 {
+        return Ok(Reflected::Failed(code, status_message));
+    }
+
+    let mut files = Vec::new();
+    for message in &reply.messages {
+        let response = ReflectionResponse::decode(message.as_slice())
+            .context("Invalid reflection response")?;
+        if let Some(error) = response.error_response {
+            return Ok(Reflected::Failed(
+                error.error_code as u32,
+                error.error_message,
+            ));
+        }
+        if let Some(found) = response.file_descriptor_response {
+            files.extend(found.file_descriptor_proto);
+        }
+    }
+
+    Ok(Reflected::Files(files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_grpc_requests() {
+        assert!(is_grpc_request("# Get\nGRPC localhost:50051 a.B/C\n"));
+        assert!(!is_grpc_request("GET http://localhost/grpc\n"));
+    }
+
+    #[test]
+    fn parses_grpc_requests() {
+        let request = parse_grpc_request(
+            "GRPC localhost:50051 shop.v1.Orders/Get\n\
+             Authorization: Bearer abc\n\
+             \n\
+             {\"id\": 1}\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.url.as_str(), "http://localhost:50051/");
+        assert_eq!(request.service, "shop.v1.Orders");
+        assert_eq!(request.method, "Get");
+        assert_eq!(
+            request.metadata,
+            vec![("authorization".into(), "Bearer abc".into())]
+        );
+        assert_eq!(request.body, "{\"id\": 1}");
+
+        let request =
+            parse_grpc_request("GRPC https://api.example.com a.B/C").unwrap();
+        assert_eq!(request.url.port_or_known_default(), Some(443));
+
+        assert!(parse_grpc_request("GRPC localhost:50051").is_err());
+    }
+
+    #[test]
+    fn frames_messages() {
+        let data = [frame(b"abc"), frame(b"")].concat();
+
+        assert_eq!(&data[..5], [0, 0, 0, 0, 3]);
+        assert_eq!(unframe(&data).unwrap(), vec![b"abc".to_vec(), vec![]]);
+        assert!(unframe(&data[..6]).is_err());
+    }
+
+    #[test]
+    fn reads_the_status() {
+        let mut reply = Reply::default();
+        reply.trailers.insert("grpc-status", "5".parse().unwrap());
+        reply
+            .trailers
+            .insert("grpc-message", "No order 12%25".parse().unwrap());
+
+        assert_eq!(reply.status(), (5, "No order 12%".to_string()));
+        assert_eq!(http_status(5), 404);
+    }
+
+    #[test]
+    fn reads_grpc_config() {
+        let root = Path::new("/project");
+        let grpc: Table =
+            toml::from_str("protos = [\"proto/shop.proto\"]").unwrap();
+
+        let config = GrpcConfig::from_table(root, &grpc);
+
+        assert_eq!(config.protos, vec![root.join("proto/shop.proto")]);
+        assert_eq!(config.import_paths, vec![root.to_path_buf()]);
+    }
+
+    #[test]
+    fn encodes_reflection_requests() {
+        let request = ReflectionRequest {
+            file_containing_symbol: Some("a.B".into()),
+            ..Default::default()
+        };
+
+        assert_eq!(request.encode_to_vec(), [0x22, 3, b'a', b'.', b'B']);
+
+        let response = ReflectionResponse {
+            error_response: Some(ErrorResponse {
+                error_code: 5,
+                error_message: "Not found".into(),
+            }),
+            ..Default::default()
+        };
+        let decoded =
+            ReflectionResponse::decode(response.encode_to_vec().as_slice())
+                .unwrap();
+        assert_eq!(decoded, response);
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    Request, Response, ResponseBuilderExt,
+};
+use serde_json::{json, Value as JsonValue};
+
+use crate::scrub::{hide_secrets, ScrubRules};
+
+const HAR_VERSION: &str = "1.2";
+
+/// The requests of a run, while `--har` is given
+struct Capture {
+    rules: ScrubRules,
+    entries: Vec<JsonValue>,
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+/// Keep every request sent from now on, with the values given by the scrub
+/// rules hidden, to be written with [write_har]
+pub fn start_capture(rules: ScrubRules) {
+    if let Ok(mut capture) = CAPTURE.lock() {
+        *capture = Some(Capture {
+            rules,
+            entries: Vec::new(),
+        });
+    }
+}
+
+pub fn is_capturing() -> bool {
+    CAPTURE.lock().is_ok_and(|capture| capture.is_some())
+}
+
+/// Keep a request and its response, and give the response back to be used
+/// as usual
+pub async fn capture(
+    request: &Request,
+    response: Response,
+    started: SystemTime,
+    elapsed: Duration,
+) -> Result<Response> {
+    let status = response.status();
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?.to_vec();
+
+    if let Ok(mut capture) = CAPTURE.lock() {
+        if let Some(capture) = capture.as_mut() {
+            let entry = har_entry(
+                &capture.rules,
+                request,
+                (status.as_u16(), &headers, &body),
+                started,
+                elapsed,
+            );
+            capture.entries.push(entry);
+        }
+    }
+
+    let mut builder = http::Response::builder().status(status).url(url);
+    for (name, value) in &headers {
+        builder = builder.header(name, value);
+    }
+
+    Ok(builder.body(body)?.into())
+}
+
+/// Write the captured requests as an HTTP Archive. Gives the number of
+/// requests written.
+pub fn write_har(path: &Path) -> Result<usize> {
+    let entries = match CAPTURE.lock() {
+        Ok(capture) => capture
+            .as_ref()
+            .map(|c| c.entries.clone())
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    let count = entries.len();
+
+    let har = json!({
+        "log": {
+            "version": HAR_VERSION,
+            "creator": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "entries": entries,
+        }
+    });
+    fs::write(path, format!("{}\n", serde_json::to_string_pretty(&har)?))?;
+
+    Ok(count)
+}
+
+fn har_entry(
+    rules: &ScrubRules,
+    request: &Request,
+    (status, headers, body): (u16, &HeaderMap, &[u8]),
+    started: SystemTime,
+    elapsed: Duration,
+) -> JsonValue {
+    let request_body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .unwrap_or_default();
+    let request_body = rules.scrub_body(request_body);
+    let body = rules.scrub_body(body);
+
+    let mut har_request = json!({
+        "method": request.method().as_str(),
+        "url": hide_secrets(request.url().as_str()),
+        "httpVersion": "HTTP/1.1",
+        "cookies": [],
+        "headers": har_headers(rules, request.headers()),
+        "queryString": request
+            .url()
+            .query_pairs()
+            .map(|(name, value)| {
+                json!({"name": name, "value": hide_secrets(&value)})
+            })
+            .collect::<Vec<_>>(),
+        "headersSize": -1,
+        "bodySize": request_body.len(),
+    });
+    if !request_body.is_empty() {
+        har_request["postData"] = json!({
+            "mimeType": content_type(request.headers()),
+            "text": String::from_utf8_lossy(&request_body),
+        });
+    }
+
+    let mut content = json!({
+        "size": body.len(),
+        "mimeType": content_type(headers),
+    });
+    match String::from_utf8(body.clone()) {
+        Ok(text) => content["text"] = json!(text),
+        Err(_) => {
+            content["text"] = json!(STANDARD.encode(&body));
+            content["encoding"] = json!("base64");
+        }
+    }
+
+    let status_text = reqwest::StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or_default();
+    let time = elapsed.as_secs_f64() * 1000.0;
+
+    json!({
+        "startedDateTime": DateTime::<Utc>::from(started)
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        "time": time,
+        "request": har_request,
+        "response": {
+            "status": status,
+            "statusText": status_text,
+            "httpVersion": "HTTP/1.1",
+            "cookies": [],
+            "headers": har_headers(rules, headers),
+            "content": content,
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": body.len(),
+        },
+        "cache": {},
+        "timings": {"send": 0, "wait": time, "receive": 0},
+    })
+}
+
+fn har_headers(rules: &ScrubRules, headers: &HeaderMap) -> JsonValue {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    rules.scrub_headers(&mut headers);
+
+    headers
+        .into_iter()
+        .map(|(name, value)| json!({"name": name, "value": value}))
+        .collect()
+}
+
+fn content_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use toml::Table;
+
+    use super::*;
+
+    #[test]
+    fn writes_har_entries() {
+        let request = Client::new()
+            .post("http://example.com/login?lang=en")
+            .header("Content-Type", "application/json")
+            .header("Authorization", "Bearer abc")
+            .body(r#"{"user": "a"}"#)
+            .build()
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(1714651200);
+
+        let rules: Table =
+            toml::from_str(r#"headers = ["authorization"]"#).unwrap();
+        let mut config = Table::new();
+        config.insert("_scrub".into(), rules.into());
+        let rules = ScrubRules::from_config(&config).unwrap();
+
+        let entry = har_entry(
+            &rules,
+            &request,
+            (201, &headers, b"ok"),
+            started,
+            Duration::from_millis(85),
+        );
+
+        assert_eq!(entry["startedDateTime"], "2024-05-02T12:00:00.000Z");
+        assert_eq!(entry["time"], 85.0);
+        assert_eq!(
+            entry["request"]["headers"],
+            json!([
+                {"name": "content-type", "value": "application/json"},
+                {"name": "authorization", "value": "********"},
+            ])
+        );
+        assert_eq!(
+            entry["request"]["queryString"],
+            json!([{"name": "lang", "value": "en"}])
+        );
+        assert_eq!(entry["request"]["postData"]["text"], r#"{"user": "a"}"#);
+        assert_eq!(entry["response"]["statusText"], "Created");
+        assert_eq!(
+            entry["response"]["content"],
+            json!({"size": 2, "mimeType": "text/plain", "text": "ok"})
+        );
+    }
+}
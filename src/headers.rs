@@ -0,0 +1,315 @@
+use toml::{Table, Value};
+
+pub(crate) const HEADERS_KEY: &str = "_headers";
+
+/// Headers added to every request, from `[_headers]` in the config, and
+/// `[<target>._headers]` for each target
+pub fn default_headers(env: &Table) -> Vec<(String, String)> {
+    match env.get(HEADERS_KEY) {
+        Some(Value::Table(headers)) => headers
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (name.clone(), value)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Header value that removes a header, such as a default header from the
+/// config, like `Authorization: !unset`
+const UNSET: &str = "!unset";
+
+/// Merge the default headers of the target into a request. Headers written
+/// in the request itself take precedence, and headers marked with `!unset`
+/// are removed.
+pub fn apply_default_headers(buf: &str, env: &Table) -> String {
+    let lines: Vec<&str> = buf.lines().collect();
+    let Some((start, end)) = header_section(&lines) else {
+        return buf.to_string();
+    };
+
+    let present: Vec<String> = lines[start..end]
+        .iter()
+        .filter_map(|l| l.split_once(':'))
+        .map(|(name, _)| name.trim().to_lowercase())
+        .collect();
+
+    let mut headers: Vec<String> = lines[start..end]
+        .iter()
+        .filter(|l| !is_unset(l))
+        .map(|l| l.to_string())
+        .collect();
+    for (name, value) in default_headers(env) {
+        if !present.contains(&name.to_lowercase()) {
+            headers.push(format!("{name}: {value}"));
+        }
+    }
+
+    join_lines(&lines[..start], &headers, &lines[end..])
+}
+
+/// Remove headers from a request, such as with `--unset-header`
+pub fn remove_headers(buf: &str, names: &[String]) -> String {
+    let lines: Vec<&str> = buf.lines().collect();
+    let Some((start, end)) = header_section(&lines) else {
+        return buf.to_string();
+    };
+
+    let headers: Vec<String> = lines[start..end]
+        .iter()
+        .filter(|l| {
+            let name = l.split_once(':').map(|(n, _)| n.trim()).unwrap_or("");
+            !names.iter().any(|n| n.eq_ignore_ascii_case(name))
+        })
+        .map(|l| l.to_string())
+        .collect();
+
+    join_lines(&lines[..start], &headers, &lines[end..])
+}
+
+/// Add headers to a request, replacing headers with the same name
+pub fn add_headers(buf: &str, headers: &[(String, String)]) -> String {
+    let names: Vec<String> = headers.iter().map(|(n, _)| n.clone()).collect();
+    let buf = remove_headers(buf, &names);
+
+    let lines: Vec<&str> = buf.lines().collect();
+    let Some((start, end)) = header_section(&lines) else {
+        return buf;
+    };
+
+    let mut section: Vec<String> =
+        lines[start..end].iter().map(|l| l.to_string()).collect();
+    for (name, value) in headers {
+        section.push(format!("{name}: {value}"));
+    }
+
+    join_lines(&lines[..start], &section, &lines[end..])
+}
+
+fn is_unset(line: &str) -> bool {
+    line.split_once(':')
+        .is_some_and(|(_, value)| value.trim() == UNSET)
+}
+
+/// The range of header lines in a request, from after the request line to
+/// the first empty line. Comments before the request line are skipped.
+fn header_section(lines: &[&str]) -> Option<(usize, usize)> {
+    let request_line = lines.iter().position(|l| {
+        !l.trim().is_empty() && !l.trim_start().starts_with('#')
+    })?;
+    let start = request_line + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|l| l.trim().is_empty())
+        .map(|i| start + i)
+        .unwrap_or(lines.len());
+
+    Some((start, end))
+}
+
+fn join_lines(before: &[&str], headers: &[String], after: &[&str]) -> String {
+    let mut output = String::new();
+    for line in before {
+        output.push_str(line);
+        output.push('\n');
+    }
+    for line in headers {
+        output.push_str(line);
+        output.push('\n');
+    }
+    for line in after {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Which response headers are shown. Noisy headers are hidden with the
+/// `hide_headers` setting, such as `hide_headers = ["x-azure-*", "cf-*"]`,
+/// and a pattern shows only the headers matching it.
+#[derive(Debug, Default, Clone)]
+pub struct HeaderFilter {
+    hidden: Vec<String>,
+    /// Show the headers in the hide-list anyway
+    pub show_hidden: bool,
+    /// Only show headers matching this pattern
+    pub only: Option<String>,
+}
+
+impl HeaderFilter {
+    pub fn from_settings(settings: &Table) -> Self {
+        let hidden = match settings.get("hide_headers") {
+            Some(Value::Array(names)) => names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            hidden,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a response header is shown
+    pub fn shows(&self, name: &str) -> bool {
+        if let Some(only) = &self.only {
+            if !matches_pattern(only, name) {
+                return false;
+            }
+        }
+
+        // Headers asked for by a pattern are shown even when in the hide-list
+        self.show_hidden
+            || self.only.is_some()
+            || !self.hidden.iter().any(|p| matches_pattern(p, name))
+    }
+
+    /// Whether any headers can be hidden by this filter
+    pub fn is_active(&self) -> bool {
+        self.only.is_some() || (!self.show_hidden && !self.hidden.is_empty())
+    }
+}
+
+/// Match a header name with a pattern, ignoring case. `*` matches any
+/// characters, and a pattern without `*` matches a part of the name.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if !pattern.contains('*') {
+        return name.contains(&pattern);
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, rest) = parts.split_first().unwrap();
+    let Some(mut remaining) = name.strip_prefix(first) else {
+        return false;
+    };
+    let (last, middle) = rest.split_last().unwrap();
+    for part in middle {
+        match remaining.find(part) {
+            Some(i) => remaining = &remaining[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    remaining.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Table {
+        toml::from_str(
+            r#"
+            [_headers]
+            Accept = "application/json"
+            User-Agent = "hitman-tests"
+            X-Api-Key = "{{api_key}}"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn adds_default_headers_after_request_headers() {
+        let buf =
+            "# Get apples\nGET http://example.com HTTP/1.1\nX-Trace: 1\n\n{}\n";
+
+        let res = apply_default_headers(buf, &env());
+
+        assert_eq!(
+            res,
+            "# Get apples\nGET http://example.com HTTP/1.1\nX-Trace: 1\nAccept: application/json\nUser-Agent: hitman-tests\nX-Api-Key: {{api_key}}\n\n{}\n"
+        );
+    }
+
+    #[test]
+    fn keeps_headers_from_request() {
+        let buf = "GET http://example.com HTTP/1.1\naccept: text/csv\n";
+
+        let res = apply_default_headers(buf, &env());
+
+        assert!(res.contains("accept: text/csv\n"));
+        assert!(!res.contains("application/json"));
+        assert!(res.contains("User-Agent: hitman-tests\n"));
+    }
+
+    #[test]
+    fn removes_headers_marked_unset() {
+        let buf =
+            "GET http://example.com HTTP/1.1\nX-Api-Key: !unset\nX-Trace: 1\n";
+
+        let res = apply_default_headers(buf, &env());
+
+        assert!(!res.contains("X-Api-Key"));
+        assert!(res.contains("X-Trace: 1\n"));
+        assert!(res.contains("Accept: application/json\n"));
+    }
+
+    #[test]
+    fn removes_named_headers() {
+        let buf = "GET http://example.com HTTP/1.1\nAuthorization: Bearer abc\nAccept: */*\n\nAuthorization: body\n";
+
+        let res = remove_headers(buf, &["authorization".into()]);
+
+        assert_eq!(
+            res,
+            "GET http://example.com HTTP/1.1\nAccept: */*\n\nAuthorization: body\n"
+        );
+    }
+
+    #[test]
+    fn adds_and_replaces_headers() {
+        let buf = "GET http://example.com HTTP/1.1\nX-Run: old\n\n{}\n";
+
+        let res = add_headers(buf, &[("X-Run".into(), "new".into())]);
+
+        assert_eq!(res, "GET http://example.com HTTP/1.1\nX-Run: new\n\n{}\n");
+    }
+
+    #[test]
+    fn leaves_request_without_defaults() {
+        let buf = "GET http://example.com HTTP/1.1\n";
+
+        assert_eq!(apply_default_headers(buf, &Table::new()), buf);
+    }
+
+    #[test]
+    fn matches_header_patterns() {
+        assert!(matches_pattern("x-azure-*", "X-Azure-Ref"));
+        assert!(matches_pattern("*-id", "x-request-id"));
+        assert!(matches_pattern("x-*-id", "x-request-id"));
+        assert!(matches_pattern("cache", "Cache-Control"));
+        assert!(!matches_pattern("cf-*", "x-cf-ray"));
+        assert!(!matches_pattern("x-*-id", "x-id"));
+    }
+
+    #[test]
+    fn hides_and_filters_response_headers() {
+        let settings: Table =
+            toml::from_str("hide_headers = [\"x-azure-*\", \"cf-*\"]").unwrap();
+        let mut filter = HeaderFilter::from_settings(&settings);
+
+        assert!(filter.shows("content-type"));
+        assert!(!filter.shows("cf-ray"));
+        assert!(filter.is_active());
+
+        filter.show_hidden = true;
+        assert!(filter.shows("cf-ray"));
+        assert!(!filter.is_active());
+
+        filter.only = Some("cf-*".to_string());
+        assert!(filter.shows("cf-ray"));
+        assert!(!filter.shows("content-type"));
+    }
+}
@@ -0,0 +1,815 @@
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Days, Local, NaiveDate, TimeZone};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use reqwest::{header::HeaderMap, Url};
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::{
+    clock,
+    env::{get_target, pinned_target, request_key},
+    hook::ResponseMeta,
+    scrub::ScrubRules,
+    state::{prune_store, store_dir, Retention, HISTORY_STORE, PINNED_DIR},
+};
+
+const ENTRY_EXTENSION: &str = ".toml.gz";
+/// Entries were kept uncompressed before
+const LEGACY_ENTRY_EXTENSION: &str = ".toml";
+
+/// A request that was sent, and the response to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub id: String,
+    /// Milliseconds since the unix epoch
+    pub time: i64,
+    /// The request file, relative to the project root
+    pub file: String,
+    pub target: String,
+    pub method: String,
+    pub url: String,
+    pub request: String,
+    pub status: u16,
+    pub elapsed: Duration,
+    pub headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub body: Vec<u8>,
+    pub note: Option<String>,
+    /// Bookmarked entries are kept in the pinned directory of the store, and
+    /// are never pruned
+    pub bookmarked: bool,
+}
+
+impl HistoryEntry {
+    /// A line describing the entry, for lists of entries
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} {} {} {}ms {} {} [{}]",
+            self.local_time(),
+            self.status,
+            self.method,
+            self.elapsed.as_millis(),
+            self.file,
+            self.url,
+            self.target,
+        );
+        if self.bookmarked {
+            summary.push_str(" (bookmarked)");
+        }
+        if let Some(note) = &self.note {
+            summary.push_str(&format!(" - {note}"));
+        }
+
+        summary
+    }
+
+    pub fn local_time(&self) -> String {
+        Local
+            .timestamp_millis_opt(self.time)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        let mut insert = |key: &str, value: Value| {
+            table.insert(key.to_string(), value);
+        };
+
+        insert("time", Value::Integer(self.time));
+        insert("file", Value::String(self.file.clone()));
+        insert("target", Value::String(self.target.clone()));
+        insert("method", Value::String(self.method.clone()));
+        insert("url", Value::String(self.url.clone()));
+        insert("request", Value::String(self.request.clone()));
+        insert("status", Value::Integer(self.status.into()));
+        insert(
+            "elapsed_ms",
+            Value::Integer(self.elapsed.as_millis() as i64),
+        );
+        insert(
+            "headers",
+            Value::Array(
+                self.headers
+                    .iter()
+                    .map(|(name, value)| {
+                        Value::Array(vec![
+                            Value::String(name.clone()),
+                            Value::String(value.clone()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        );
+        insert("content_type", Value::String(self.content_type.clone()));
+        if let Some(note) = &self.note {
+            insert("note", Value::String(note.clone()));
+        }
+        // Binary bodies can't be kept in a TOML string
+        match String::from_utf8(self.body.clone()) {
+            Ok(body) => insert("body", Value::String(body)),
+            Err(_) => insert(
+                "body_base64",
+                Value::String(STANDARD.encode(&self.body)),
+            ),
+        }
+
+        table
+    }
+
+    fn from_table(id: &str, table: &Table) -> Option<Self> {
+        let str_value = |key: &str| -> Option<String> {
+            Some(table.get(key)?.as_str()?.to_string())
+        };
+
+        let body = match (table.get("body"), table.get("body_base64")) {
+            (Some(Value::String(body)), _) => body.as_bytes().to_vec(),
+            (_, Some(Value::String(data))) => STANDARD.decode(data).ok()?,
+            _ => Vec::new(),
+        };
+
+        let headers = table
+            .get("headers")?
+            .as_array()?
+            .iter()
+            .filter_map(|header| match header.as_array()?.as_slice() {
+                [Value::String(name), Value::String(value)] => {
+                    Some((name.clone(), value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Some(Self {
+            id: id.to_string(),
+            time: table.get("time")?.as_integer()?,
+            file: str_value("file")?,
+            target: str_value("target")?,
+            method: str_value("method")?,
+            url: str_value("url")?,
+            request: str_value("request")?,
+            status: table.get("status")?.as_integer()?.try_into().ok()?,
+            elapsed: Duration::from_millis(
+                table.get("elapsed_ms")?.as_integer()?.max(0) as u64,
+            ),
+            headers,
+            content_type: str_value("content_type").unwrap_or_default(),
+            body,
+            note: str_value("note"),
+            bookmarked: false,
+        })
+    }
+}
+
+/// Keep a response in the history of the project, with the values given by
+/// the scrub rules, and the values of sensitive keys, hidden
+pub fn record_response(
+    root_dir: &Path,
+    meta: &ResponseMeta,
+    request: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<HistoryEntry> {
+    let file = Path::new(&meta.file);
+    let target = match pinned_target(root_dir, file)? {
+        Some(target) => target,
+        None => get_target(root_dir),
+    };
+
+    let time = clock::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+
+    let mut entry = HistoryEntry {
+        id: String::new(),
+        time,
+        file: request_key(root_dir, file),
+        target,
+        method: meta.method.clone(),
+        url: meta.url.clone(),
+        request: request.to_string(),
+        status: meta.status,
+        elapsed: meta.elapsed,
+        headers: headers
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (name.to_string(), value.to_string())
+            })
+            .collect(),
+        content_type: meta.content_type.clone(),
+        body: body.to_vec(),
+        note: None,
+        bookmarked: false,
+    };
+    ScrubRules::load(root_dir)?.scrub_entry(&mut entry);
+
+    let (id, file) = create_entry_file(root_dir, time)?;
+    entry.id = id;
+    write_entry_to(file, &entry)?;
+    prune_store(root_dir, HISTORY_STORE, &Retention::load(root_dir)?)?;
+
+    Ok(entry)
+}
+
+/// The file of a new entry, and its id. The id is the time of the response,
+/// with a suffix like `-1` when another response was kept in the same
+/// millisecond.
+fn create_entry_file(root_dir: &Path, time: i64) -> Result<(String, File)> {
+    let dir = store_dir(root_dir, HISTORY_STORE)?;
+
+    for n in 0.. {
+        let id = match n {
+            0 => time.to_string(),
+            n => format!("{time}-{n}"),
+        };
+        // A bookmarked entry may have the id
+        if find_entry(root_dir, &id).is_ok() {
+            continue;
+        }
+
+        let path = dir.join(format!("{id}{ENTRY_EXTENSION}"));
+        match File::options().write(true).create_new(true).open(path) {
+            Ok(file) => return Ok((id, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    unreachable!()
+}
+
+fn write_entry(dir: &Path, entry: &HistoryEntry) -> Result<()> {
+    let path = dir.join(format!("{}{ENTRY_EXTENSION}", entry.id));
+
+    write_entry_to(File::create(path)?, entry)
+}
+
+fn write_entry_to(file: File, entry: &HistoryEntry) -> Result<()> {
+    let mut file = GzEncoder::new(file, Compression::default());
+    file.write_all(toml::to_string_pretty(&entry.to_table())?.as_bytes())?;
+    file.finish()?;
+
+    Ok(())
+}
+
+fn read_entry(path: &Path) -> Option<HistoryEntry> {
+    let name = path.file_name()?.to_str()?;
+
+    let (id, content) = if let Some(id) = name.strip_suffix(ENTRY_EXTENSION) {
+        let mut content = String::new();
+        GzDecoder::new(File::open(path).ok()?)
+            .read_to_string(&mut content)
+            .ok()?;
+        (id, content)
+    } else {
+        let id = name.strip_suffix(LEGACY_ENTRY_EXTENSION)?;
+        (id, fs::read_to_string(path).ok()?)
+    };
+
+    let mut entry =
+        HistoryEntry::from_table(id, &toml::from_str(&content).ok()?)?;
+    entry.bookmarked =
+        path.parent().is_some_and(|dir| dir.ends_with(PINNED_DIR));
+
+    Some(entry)
+}
+
+/// All responses in the history of the project, newest first
+pub fn load_history(root_dir: &Path) -> Result<Vec<HistoryEntry>> {
+    let mut entries: Vec<HistoryEntry> = entry_paths(root_dir)?
+        .iter()
+        .filter_map(|path| read_entry(path))
+        .collect();
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.time));
+
+    Ok(entries)
+}
+
+/// The files of all entries, including the bookmarked ones
+fn entry_paths(root_dir: &Path) -> Result<Vec<PathBuf>> {
+    let dir = store_dir(root_dir, HISTORY_STORE)?;
+
+    let mut paths = Vec::new();
+    for dir in [dir.join(PINNED_DIR), dir] {
+        if let Ok(entries) = fs::read_dir(dir) {
+            paths.extend(
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file()),
+            );
+        }
+    }
+
+    Ok(paths)
+}
+
+/// The file of an entry, by its id, or `last` for the newest entry
+fn find_entry(root_dir: &Path, id: &str) -> Result<PathBuf> {
+    let id = match id {
+        "last" => match load_history(root_dir)?.first() {
+            Some(entry) => entry.id.clone(),
+            None => bail!("No responses in the history yet"),
+        },
+        id => id.to_string(),
+    };
+
+    entry_paths(root_dir)?
+        .into_iter()
+        .find(|path| {
+            path.file_name().and_then(|name| name.to_str()).is_some_and(
+                |name| {
+                    name.strip_suffix(ENTRY_EXTENSION)
+                        .or_else(|| name.strip_suffix(LEGACY_ENTRY_EXTENSION))
+                        == Some(id.as_str())
+                },
+            )
+        })
+        .ok_or_else(|| anyhow!("No response {id} in the history"))
+}
+
+/// An entry by its id, or `last` for the newest entry
+pub fn history_entry(root_dir: &Path, id: &str) -> Result<HistoryEntry> {
+    let path = find_entry(root_dir, id)?;
+
+    read_entry(&path)
+        .ok_or_else(|| anyhow!("Could not read {}", path.display()))
+}
+
+/// Attach a note to an entry, or remove it with `None`
+pub fn set_note(
+    root_dir: &Path,
+    id: &str,
+    note: Option<&str>,
+) -> Result<HistoryEntry> {
+    let path = find_entry(root_dir, id)?;
+    let mut entry = read_entry(&path)
+        .ok_or_else(|| anyhow!("Could not read {}", path.display()))?;
+    entry.note = note.map(str::to_string);
+
+    // Keep the time the entry was written, which the pruning goes by
+    let modified = fs::metadata(&path)?.modified()?;
+    let dir = path.parent().unwrap_or(&path);
+    write_entry(dir, &entry)?;
+    if path.to_string_lossy().ends_with(LEGACY_ENTRY_EXTENSION) {
+        fs::remove_file(&path)?;
+    }
+    let new_path = dir.join(format!("{}{ENTRY_EXTENSION}", entry.id));
+    File::options()
+        .write(true)
+        .open(new_path)?
+        .set_modified(modified)?;
+
+    Ok(entry)
+}
+
+/// Bookmark an entry, to keep it when the history is pruned, or remove the
+/// bookmark
+pub fn set_bookmark(
+    root_dir: &Path,
+    id: &str,
+    bookmarked: bool,
+) -> Result<HistoryEntry> {
+    let path = find_entry(root_dir, id)?;
+    let store = store_dir(root_dir, HISTORY_STORE)?;
+    let dir = match bookmarked {
+        true => store.join(PINNED_DIR),
+        false => store,
+    };
+    fs::create_dir_all(&dir)?;
+
+    let new_path = dir.join(path.file_name().unwrap_or_default());
+    fs::rename(&path, &new_path)?;
+
+    read_entry(&new_path)
+        .ok_or_else(|| anyhow!("Could not read {}", new_path.display()))
+}
+
+/// A search in the history, like `order_id:12345 status:2xx since:2024-05-01`.
+///
+/// The filters `status`, `target`, `method`, `file`, `since`, `until`, `on`
+/// and `is:bookmarked` match the metadata of a response. Any other `key:value` matches a JSON
+/// field with that name at any depth of the request or response body, or a
+/// query parameter. The remaining words, or "quoted phrases", are looked for
+/// in the request, the response and the note.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryQuery {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    /// Lower case
+    Text(String),
+    Field(String, String),
+    /// Like `404`, or `4xx` for a class of statuses
+    Status(String),
+    Target(String),
+    Method(String),
+    Bookmarked,
+    File(String),
+    /// Milliseconds since the unix epoch, included
+    Since(i64),
+    /// Milliseconds since the unix epoch, excluded
+    Until(i64),
+}
+
+impl HistoryQuery {
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+
+        for word in split_words(query) {
+            let Some((key, value)) = word.split_once(':') else {
+                terms.push(Term::Text(word.to_lowercase()));
+                continue;
+            };
+
+            match key {
+                "status" => {
+                    let valid = value.len() == 3
+                        && value
+                            .chars()
+                            .all(|c| c.is_ascii_digit() || c == 'x');
+                    if !valid {
+                        bail!("Invalid status {value}, use like 404 or 4xx");
+                    }
+                    terms.push(Term::Status(value.to_string()));
+                }
+                "target" => terms.push(Term::Target(value.to_string())),
+                "is" if value == "bookmarked" => terms.push(Term::Bookmarked),
+                "method" => terms.push(Term::Method(value.to_uppercase())),
+                "file" => terms.push(Term::File(value.to_string())),
+                "since" => terms.push(Term::Since(start_of_day(value, 0)?)),
+                "until" => terms.push(Term::Until(start_of_day(value, 1)?)),
+                "on" => {
+                    terms.push(Term::Since(start_of_day(value, 0)?));
+                    terms.push(Term::Until(start_of_day(value, 1)?));
+                }
+                // Like `https://example.com`, which is text and not a field
+                _ if key.is_empty() || value.starts_with("//") => {
+                    terms.push(Term::Text(word.to_lowercase()))
+                }
+                _ => {
+                    terms.push(Term::Field(key.to_string(), value.to_string()))
+                }
+            }
+        }
+
+        Ok(Self { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::Text(text) => entry.contains_text(text),
+            Term::Field(key, value) => entry.has_field(key, value),
+            Term::Status(pattern) => entry
+                .status
+                .to_string()
+                .chars()
+                .zip(pattern.chars())
+                .all(|(digit, p)| p == 'x' || p == digit),
+            Term::Target(target) => &entry.target == target,
+            Term::Method(method) => &entry.method == method,
+            Term::Bookmarked => entry.bookmarked,
+            Term::File(file) => entry.file.contains(file.as_str()),
+            Term::Since(time) => entry.time >= *time,
+            Term::Until(time) => entry.time < *time,
+        })
+    }
+}
+
+/// Words separated by whitespace, where a quoted phrase is one word
+fn split_words(query: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// The start of a local date like `2024-05-02`, moved by a number of days
+fn start_of_day(date: &str, days: u64) -> Result<i64> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.checked_add_days(Days::new(days)))
+        .ok_or_else(|| anyhow!("Invalid date {date}, use like 2024-05-02"))?;
+
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .map(|t| t.timestamp_millis())
+        .ok_or_else(|| anyhow!("Invalid date {date}"))
+}
+
+impl HistoryEntry {
+    fn contains_text(&self, text: &str) -> bool {
+        let body = String::from_utf8_lossy(&self.body);
+
+        let note = self.note.as_deref().unwrap_or_default();
+
+        let found = [self.url.as_str(), &self.request, &body, note]
+            .into_iter()
+            .chain(self.headers.iter().map(|(_, value)| value.as_str()))
+            .any(|s| s.to_lowercase().contains(text));
+
+        found
+    }
+
+    fn has_field(&self, key: &str, value: &str) -> bool {
+        let request_body = self
+            .request
+            .split_once("\n\n")
+            .map(|(_, body)| body)
+            .unwrap_or_default();
+
+        let in_json = [request_body.as_bytes(), &self.body]
+            .into_iter()
+            .filter_map(|body| serde_json::from_slice::<JsonValue>(body).ok())
+            .any(|json| json_has_field(&json, key, value));
+
+        in_json
+            || Url::parse(&self.url).is_ok_and(|url| {
+                url.query_pairs().any(|(k, v)| k == key && v == value)
+            })
+    }
+}
+
+fn json_has_field(json: &JsonValue, key: &str, value: &str) -> bool {
+    match json {
+        JsonValue::Object(object) => object.iter().any(|(k, v)| {
+            let found = k == key
+                && match v {
+                    JsonValue::String(s) => s.eq_ignore_ascii_case(value),
+                    JsonValue::Array(_) | JsonValue::Object(_) => false,
+                    v => serde_json::from_str::<JsonValue>(value)
+                        .is_ok_and(|parsed| &parsed == v),
+                };
+            found || json_has_field(v, key, value)
+        }),
+        JsonValue::Array(items) => {
+            items.iter().any(|v| json_has_field(v, key, value))
+        }
+        _ => false,
+    }
+}
+
+/// The responses matching a search, newest first
+pub fn search_history(
+    root_dir: &Path,
+    query: &HistoryQuery,
+) -> Result<Vec<HistoryEntry>> {
+    Ok(load_history(root_dir)?
+        .into_iter()
+        .filter(|e| query.matches(e))
+        .collect())
+}
+
+/// The responses to a request file, newest first
+pub fn responses_for(
+    root_dir: &Path,
+    file_path: &Path,
+) -> Result<Vec<HistoryEntry>> {
+    let key = request_key(root_dir, file_path);
+
+    Ok(load_history(root_dir)?
+        .into_iter()
+        .filter(|e| e.file == key)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(body: &[u8]) -> HistoryEntry {
+        HistoryEntry {
+            id: "1714651200000".into(),
+            time: 1714651200000,
+            file: "api/login.http".into(),
+            target: "default".into(),
+            method: "POST".into(),
+            url: "http://example.com/login".into(),
+            request: "POST http://example.com/login HTTP/1.1\n".into(),
+            status: 200,
+            elapsed: Duration::from_millis(85),
+            headers: vec![
+                ("set-cookie".into(), "a=1".into()),
+                ("set-cookie".into(), "b=2".into()),
+            ],
+            content_type: "application/json".into(),
+            body: body.to_vec(),
+            note: None,
+            bookmarked: false,
+        }
+    }
+
+    #[test]
+    fn keeps_entries_as_toml() {
+        let text = entry(b"{\"token\": \"abc\"}");
+        let table = text.to_table();
+        assert_eq!(
+            HistoryEntry::from_table(&text.id, &table),
+            Some(text.clone())
+        );
+        assert!(table.contains_key("body"));
+
+        let binary = entry(&[0xff, 0xd8, 0xff, 0x00]);
+        let table = binary.to_table();
+        assert_eq!(HistoryEntry::from_table(&binary.id, &table), Some(binary));
+        assert!(table.contains_key("body_base64"));
+    }
+
+    #[test]
+    fn reads_compressed_and_legacy_entries() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let compressed = entry(b"{}");
+        write_entry(&dir, &compressed).unwrap();
+        let mut legacy = entry(b"[]");
+        legacy.id = "1714651100000".into();
+        let legacy_path = dir.join(format!("{}.toml", legacy.id));
+        fs::write(&legacy_path, toml::to_string(&legacy.to_table()).unwrap())
+            .unwrap();
+
+        let compressed_path = dir.join("1714651200000.toml.gz");
+        assert!(fs::read(&compressed_path)
+            .unwrap()
+            .starts_with(&[0x1f, 0x8b]));
+        assert_eq!(read_entry(&compressed_path), Some(compressed));
+        assert_eq!(read_entry(&legacy_path), Some(legacy));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn parses_queries() {
+        let query = HistoryQuery::parse(
+            r#"order_id:12345 status:4xx "not found" https://example.com"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.terms,
+            [
+                Term::Field("order_id".into(), "12345".into()),
+                Term::Status("4xx".into()),
+                Term::Text("not found".into()),
+                Term::Text("https://example.com".into()),
+            ]
+        );
+        assert!(HistoryQuery::parse("status:40").is_err());
+        assert!(HistoryQuery::parse("since:tuesday").is_err());
+        assert!(HistoryQuery::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn searches_entries() {
+        let mut entry = entry(br#"{"order": {"order_id": 12345}}"#);
+        entry.request = "POST http://example.com/login HTTP/1.1\n\
+                         \n\
+                         {\"user\": \"Alice\"}"
+            .into();
+        entry.url = "http://example.com/login?lang=en".into();
+        let matches =
+            |query: &str| HistoryQuery::parse(query).unwrap().matches(&entry);
+
+        assert!(matches("order_id:12345"));
+        assert!(!matches("order_id:1234"));
+        assert!(matches("user:alice lang:en"));
+        assert!(matches("status:200 status:2xx method:post target:default"));
+        assert!(!matches("status:201"));
+        assert!(matches("file:api/ LOGIN"));
+        assert!(matches("b=2"));
+        assert!(!matches("logout"));
+
+        let day = Local
+            .timestamp_millis_opt(entry.time)
+            .unwrap()
+            .format("%Y-%m-%d")
+            .to_string();
+        assert!(matches(&format!("on:{day}")));
+        assert!(matches(&format!("since:{day} until:{day}")));
+        assert!(!matches("until:2024-01-01"));
+        assert!(!matches("since:2024-06-01"));
+    }
+
+    #[test]
+    fn gives_responses_in_the_same_millisecond_their_own_ids() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-history-ids-{}", std::process::id()));
+
+        let (first, _) = create_entry_file(&root, 1714651200000).unwrap();
+        let (second, _) = create_entry_file(&root, 1714651200000).unwrap();
+        assert_eq!(first, "1714651200000");
+        assert_eq!(second, "1714651200000-1");
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn keeps_sensitive_values_out_of_the_history() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-history-secrets-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("hitman.toml"),
+            "[_settings]\nsensitive = [\"password\"]\n\n[dev]\n",
+        )
+        .unwrap();
+
+        let env = toml::from_str(
+            "password = \"hunter2-secret\"\n\
+             [_settings]\nsensitive = [\"password\"]",
+        )
+        .unwrap();
+        crate::scrub::remember_secrets(&env);
+
+        let meta = ResponseMeta {
+            file: "login.http".into(),
+            method: "POST".into(),
+            url: "http://example.com/login?password=hunter2-secret".into(),
+            status: 200,
+            content_type: "application/json".into(),
+            elapsed: Duration::from_millis(10),
+        };
+        let entry = record_response(
+            &root,
+            &meta,
+            "POST http://example.com/login HTTP/1.1\n\n\
+             {\"password\": \"hunter2-secret\"}\n",
+            &HeaderMap::new(),
+            b"{\"echo\": \"hunter2-secret\"}",
+        )
+        .unwrap();
+
+        let path = find_entry(&root, &entry.id).unwrap();
+        let mut content = String::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert!(content.contains("login.http"));
+        assert!(!content.contains("hunter2-secret"));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn keeps_notes_and_bookmarks() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-bookmarks-{}", std::process::id()));
+        let store = store_dir(&root, HISTORY_STORE).unwrap();
+        write_entry(&store, &entry(b"{}")).unwrap();
+
+        set_note(&root, "last", Some("repro for BUG-423")).unwrap();
+        let entry = set_bookmark(&root, "1714651200000", true).unwrap();
+        assert_eq!(entry.note.as_deref(), Some("repro for BUG-423"));
+        assert!(entry.bookmarked);
+
+        let retention = Retention {
+            max_entries: Some(0),
+            ..Default::default()
+        };
+        prune_store(&root, HISTORY_STORE, &retention).unwrap();
+        let query = HistoryQuery::parse("is:bookmarked bug-423").unwrap();
+        assert_eq!(search_history(&root, &query).unwrap(), [entry]);
+
+        set_bookmark(&root, "1714651200000", false).unwrap();
+        prune_store(&root, HISTORY_STORE, &retention).unwrap();
+        assert!(load_history(&root).unwrap().is_empty());
+        assert!(set_note(&root, "1714651200000", None).is_err());
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
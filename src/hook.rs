@@ -0,0 +1,169 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use toml::{Table, Value};
+
+use crate::{trust::ensure_trusted, util::shell_command};
+
+/// Shell command that gets every response, given in the config or in the
+/// `.http.toml` file of a request, like `_on_response = "./save-fixture.sh"`
+pub(crate) const HOOK_KEY: &str = "_on_response";
+
+/// Details about a request and its response, given to the hook in
+/// environment variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseMeta {
+    pub file: String,
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub elapsed: Duration,
+}
+
+impl ResponseMeta {
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("HITMAN_REQUEST", self.file.clone()),
+            ("HITMAN_METHOD", self.method.clone()),
+            ("HITMAN_URL", self.url.clone()),
+            ("HITMAN_STATUS", self.status.to_string()),
+            ("HITMAN_CONTENT_TYPE", self.content_type.clone()),
+            ("HITMAN_ELAPSED_MS", self.elapsed.as_millis().to_string()),
+        ]
+    }
+}
+
+/// The method of a request, from its request line
+pub fn request_method(buf: &str) -> String {
+    buf.lines()
+        .find(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+        .and_then(|l| l.split_whitespace().next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The command configured to run for every response, if any
+pub fn response_hook(env: &Table) -> Option<&str> {
+    env.get(HOOK_KEY).and_then(Value::as_str)
+}
+
+/// Pipe a response body to the configured hook, such as a script that
+/// updates fixtures or a local database. Does nothing without a hook, and
+/// fails for a hook that is not trusted.
+pub fn run_response_hook(
+    env: &Table,
+    meta: &ResponseMeta,
+    body: &[u8],
+) -> Result<()> {
+    let Some(hook) = response_hook(env) else {
+        return Ok(());
+    };
+    ensure_trusted("Response hook", hook)?;
+
+    run_hook(shell_command(hook), meta, body)
+}
+
+fn run_hook(
+    mut command: Command,
+    meta: &ResponseMeta,
+    body: &[u8],
+) -> Result<()> {
+    // The output is kept, so that it doesn't mix with the response
+    let mut child = command
+        .envs(meta.env_vars())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Written while the output is read, so that a hook that writes before
+    // it has read everything doesn't block on a full pipe
+    let writer = child.stdin.take().map(|mut stdin| {
+        let body = body.to_vec();
+        thread::spawn(move || {
+            // The hook may not read the body
+            let _ = stdin.write_all(&body);
+        })
+    });
+
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    if !output.status.success() {
+        bail!(
+            "Response hook failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> ResponseMeta {
+        ResponseMeta {
+            file: "apples/get.http".into(),
+            method: "GET".into(),
+            url: "http://example.com/apples".into(),
+            status: 200,
+            content_type: "application/json".into(),
+            elapsed: Duration::from_millis(42),
+        }
+    }
+
+    #[test]
+    fn finds_request_method() {
+        assert_eq!(
+            request_method(
+                "# @assert status == 200\n\nPUT http://x HTTP/1.1\n"
+            ),
+            "PUT"
+        );
+    }
+
+    #[test]
+    fn reads_hook_from_config() {
+        let env: Table = toml::from_str(r#"_on_response = "cat""#).unwrap();
+
+        assert_eq!(response_hook(&env), Some("cat"));
+        assert_eq!(response_hook(&Table::new()), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_body_and_metadata_to_hook() {
+        let check = r#"test "$(cat)" = '{"id":1}' && test "$HITMAN_STATUS" = 200 && test "$HITMAN_ELAPSED_MS" = 42"#;
+
+        assert!(run_hook(shell_command(check), &meta(), br#"{"id":1}"#).is_ok());
+        assert!(run_hook(shell_command("exit 3"), &meta(), b"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_large_bodies_to_hooks_that_echo() {
+        let body = vec![b'x'; 1024 * 1024];
+
+        assert!(run_hook(shell_command("cat"), &meta(), &body).is_ok());
+    }
+
+    #[test]
+    fn refuses_untrusted_hooks() {
+        let env: Table =
+            toml::from_str(r#"_on_response = "./pulled.sh""#).unwrap();
+
+        let err = run_response_hook(&env, &meta(), b"").unwrap_err();
+
+        assert!(err.to_string().contains("not trusted on this machine"));
+    }
+}
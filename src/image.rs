@@ -0,0 +1,217 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::env;
+
+/// Images are scaled down to at most this many terminal columns
+const MAX_COLUMNS: u32 = 60;
+
+/// Rough size of a terminal cell, used to avoid scaling up small images
+const CELL_WIDTH: u32 = 10;
+
+#[derive(Debug, PartialEq)]
+pub struct ImageInfo {
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInfo {
+    pub fn summary(&self, size: usize) -> String {
+        format!(
+            "{} image, {}x{} pixels, {} bytes",
+            self.format, self.width, self.height, size
+        )
+    }
+}
+
+/// Read the format and dimensions from the header of an image
+pub fn image_info(data: &[u8]) -> Option<ImageInfo> {
+    let be16 = |i: usize| {
+        Some(u16::from_be_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32)
+    };
+    let le16 = |i: usize| {
+        Some(u16::from_le_bytes([*data.get(i)?, *data.get(i + 1)?]) as u32)
+    };
+    let be32 = |i: usize| {
+        Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?))
+    };
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(ImageInfo {
+            format: "PNG",
+            width: be32(16)?,
+            height: be32(20)?,
+        });
+    }
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(ImageInfo {
+            format: "GIF",
+            width: le16(6)?,
+            height: le16(8)?,
+        });
+    }
+
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        let (width, height) = match data.get(12..16)? {
+            b"VP8X" => (
+                1 + (le16(24)? | (*data.get(26)? as u32) << 16),
+                1 + (le16(27)? | (*data.get(29)? as u32) << 16),
+            ),
+            b"VP8L" => {
+                let bits =
+                    u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                (1 + (bits & 0x3fff), 1 + ((bits >> 14) & 0x3fff))
+            }
+            _ => (le16(26)? & 0x3fff, le16(28)? & 0x3fff),
+        };
+        return Some(ImageInfo {
+            format: "WebP",
+            width,
+            height,
+        });
+    }
+
+    if data.starts_with(&[0xff, 0xd8]) {
+        // Find the start of frame segment, which holds the dimensions
+        let mut i = 2;
+        while i + 9 < data.len() {
+            if data[i] != 0xff {
+                return None;
+            }
+            let marker = data[i + 1];
+            let len = be16(i + 2)? as usize;
+            if (0xc0..=0xcf).contains(&marker)
+                && ![0xc4, 0xc8, 0xcc].contains(&marker)
+            {
+                return Some(ImageInfo {
+                    format: "JPEG",
+                    width: be16(i + 7)?,
+                    height: be16(i + 5)?,
+                });
+            }
+            i += 2 + len;
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Iterm2,
+}
+
+/// Guess which inline graphics protocol the terminal supports, if any
+pub fn detect_graphics_protocol() -> Option<GraphicsProtocol> {
+    let term = env::var("TERM").unwrap_or_default();
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+    if env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" {
+        Some(GraphicsProtocol::Kitty)
+    } else if ["iTerm.app", "WezTerm"].contains(&term_program.as_str()) {
+        Some(GraphicsProtocol::Iterm2)
+    } else {
+        None
+    }
+}
+
+/// Escape sequence that shows the image inline in the terminal
+pub fn inline_image(
+    data: &[u8],
+    info: &ImageInfo,
+    protocol: &GraphicsProtocol,
+) -> Option<String> {
+    let columns = info.width.div_ceil(CELL_WIDTH).clamp(1, MAX_COLUMNS);
+    let encoded = STANDARD.encode(data);
+
+    match protocol {
+        GraphicsProtocol::Iterm2 => Some(format!(
+            "\x1b]1337;File=inline=1;size={};width={}:{}\x07\n",
+            data.len(),
+            columns,
+            encoded
+        )),
+        // Kitty only accepts PNG directly
+        GraphicsProtocol::Kitty if info.format == "PNG" => {
+            let mut out = String::new();
+            let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = if i + 1 < chunks.len() { 1 } else { 0 };
+                let control = if i == 0 {
+                    format!("f=100,a=T,c={columns},m={more}")
+                } else {
+                    format!("m={more}")
+                };
+                out.push_str(&format!(
+                    "\x1b_G{};{}\x1b\\",
+                    control,
+                    std::str::from_utf8(chunk).ok()?
+                ));
+            }
+            out.push('\n');
+            Some(out)
+        }
+        GraphicsProtocol::Kitty => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_png_dimensions() {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.extend_from_slice(&640u32.to_be_bytes());
+        data.extend_from_slice(&480u32.to_be_bytes());
+
+        assert_eq!(
+            image_info(&data),
+            Some(ImageInfo {
+                format: "PNG",
+                width: 640,
+                height: 480
+            })
+        );
+    }
+
+    #[test]
+    fn reads_gif_dimensions() {
+        let data = b"GIF89a\x20\x00\x10\x00";
+
+        assert_eq!(
+            image_info(data),
+            Some(ImageInfo {
+                format: "GIF",
+                width: 32,
+                height: 16
+            })
+        );
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions() {
+        let data = [
+            0xff, 0xd8, // SOI
+            0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, // APP0
+            0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0x64, 0x00, 0xc8,
+            0x03, // SOF0
+            0x00, 0x00,
+        ];
+
+        assert_eq!(
+            image_info(&data),
+            Some(ImageInfo {
+                format: "JPEG",
+                width: 200,
+                height: 100
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_data() {
+        assert_eq!(image_info(b"hello world"), None);
+    }
+}
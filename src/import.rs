@@ -0,0 +1,136 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use log::warn;
+use toml::Table;
+
+use crate::env::CONFIG_FILE;
+
+/// Request files and config made from another tool's format
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Imported {
+    pub requests: Vec<ImportedRequest>,
+    pub config: Table,
+    /// What could not be converted, to be told to the user
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedRequest {
+    /// Relative to the directory imported into
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl Imported {
+    /// Add a request file, with a name that is not taken in its directory
+    pub fn add_request(&mut self, dir: &Path, name: &str, content: String) {
+        let taken: HashSet<_> =
+            self.requests.iter().map(|r| r.path.clone()).collect();
+        let stem = file_stem(name);
+
+        let mut path = dir.join(format!("{stem}.http"));
+        let mut n = 2;
+        while taken.contains(&path) {
+            path = dir.join(format!("{stem}_{n}.http"));
+            n += 1;
+        }
+
+        self.requests.push(ImportedRequest { path, content });
+    }
+}
+
+/// A file or directory name for a name like `Get all users` or
+/// `getAllUsers`, as `get_all_users`
+pub fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    let mut lower = false;
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() {
+            if lower && c.is_uppercase() {
+                stem.push('_');
+            }
+            lower = c.is_lowercase() || c.is_numeric();
+            stem.extend(c.to_lowercase());
+        } else {
+            lower = false;
+            if !stem.is_empty() && !stem.ends_with('_') {
+                stem.push('_');
+            }
+        }
+    }
+
+    match stem.trim_end_matches('_') {
+        "" => "request".to_string(),
+        stem => stem.to_string(),
+    }
+}
+
+/// Write the imported files to a directory. Existing request files are never
+/// overwritten, and an existing config file is kept as it is.
+pub fn write_imported(dir: &Path, imported: &Imported) -> Result<()> {
+    for request in &imported.requests {
+        let path = dir.join(&request.path);
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+    }
+
+    for request in &imported.requests {
+        let path = dir.join(&request.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &request.content)?;
+    }
+
+    let config_file = dir.join(CONFIG_FILE);
+    if config_file.exists() {
+        warn!("# Kept the existing {}", config_file.display());
+    } else {
+        fs::write(&config_file, toml::to_string_pretty(&imported.config)?)?;
+    }
+
+    for warning in &imported.warnings {
+        warn!("# {warning}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makes_file_names() {
+        assert_eq!(file_stem("Get all users"), "get_all_users");
+        assert_eq!(file_stem(" Orders / {id} (v2) "), "orders_id_v2");
+        assert_eq!(file_stem("listHTTPLogs2"), "list_httplogs2");
+        assert_eq!(file_stem("???"), "request");
+    }
+
+    #[test]
+    fn numbers_taken_names() {
+        let mut imported = Imported::default();
+        let dir = Path::new("users");
+
+        imported.add_request(dir, "Get user", String::new());
+        imported.add_request(dir, "Get user", String::new());
+        imported.add_request(Path::new(""), "Get user", String::new());
+
+        let paths: Vec<_> = imported.requests.iter().map(|r| &r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("users/get_user.http"),
+                Path::new("users/get_user_2.http"),
+                Path::new("get_user.http"),
+            ]
+        );
+    }
+}
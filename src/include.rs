@@ -0,0 +1,96 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{Context, Result};
+
+const INCLUDE_PREFIX: &str = "<";
+
+/// Replace a body given as `< ./payloads/big.json` with the content of the
+/// file, relative to the directory of the request file. The content is part
+/// of the request like an inlined body, so values in it are substituted.
+pub fn include_body(buf: &str, dir: &Path) -> Result<String> {
+    let Some(path) = included_file(buf) else {
+        return Ok(buf.to_string());
+    };
+    let path = dir.join(path);
+    let content = read_to_string(&path).with_context(|| {
+        format!("Failed to read request body from {}", path.display())
+    })?;
+
+    let head: String = buf
+        .split_inclusive('\n')
+        .take(body_start(buf).unwrap_or_default())
+        .collect();
+
+    Ok(format!("{head}{content}"))
+}
+
+/// The file given as the body of a request, when the body is only a line
+/// like `< ./payloads/big.json`
+pub fn included_file(buf: &str) -> Option<&str> {
+    let mut body = buf
+        .lines()
+        .skip(body_start(buf)?)
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+
+    let path = body.next()?.strip_prefix(INCLUDE_PREFIX)?.trim();
+    if path.is_empty() || body.next().is_some() {
+        return None;
+    }
+
+    Some(path)
+}
+
+/// The line the body starts at, after the request line and the headers
+pub(crate) fn body_start(buf: &str) -> Option<usize> {
+    let lines: Vec<&str> = buf.lines().collect();
+    let request_line = lines.iter().position(|line| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#')
+    })?;
+    let blank = lines[request_line..]
+        .iter()
+        .position(|line| line.trim().is_empty())?;
+
+    Some(request_line + blank + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn finds_included_files() {
+        let buf = "# @timeout 2m\nPOST http://example.com HTTP/1.1\n\
+                   Content-Type: application/json\n\n< ./payloads/big.json\n\n";
+        assert_eq!(included_file(buf), Some("./payloads/big.json"));
+
+        let inline = "POST http://example.com HTTP/1.1\n\n<xml>\n</xml>\n";
+        assert_eq!(included_file(inline), None);
+
+        let headers = "GET http://example.com HTTP/1.1\nAccept: */*\n";
+        assert_eq!(included_file(headers), None);
+    }
+
+    #[test]
+    fn includes_bodies() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-include-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("payloads")).unwrap();
+        fs::write(dir.join("payloads/big.json"), "{\"id\": {{id}}}\n").unwrap();
+
+        let buf = "POST http://example.com HTTP/1.1\n\
+                   Content-Type: application/json\n\n< payloads/big.json\n";
+        assert_eq!(
+            include_body(buf, &dir).unwrap(),
+            "POST http://example.com HTTP/1.1\n\
+             Content-Type: application/json\n\n{\"id\": {{id}}}\n"
+        );
+        assert!(include_body(buf, &dir.join("missing")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use toml::{Table, Value};
+
+use crate::substitute::{placeholder_keys, substitute, SubstituteError};
+
+/// How the value of a placeholder is found when a request is made
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputStatus {
+    /// Given by the target, the request config or an extracted value
+    Set,
+    /// Read from an environment variable
+    EnvVar,
+    /// One of these many values, which the user selects
+    Select(usize),
+    /// Not given, so the fallback is used, or offered in the prompt
+    Fallback(String),
+    /// Not given, so the user is asked for it
+    Prompt,
+}
+
+/// A value a request needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestInput {
+    /// The key of the value, or `env:NAME` for an environment variable
+    pub key: String,
+    pub status: InputStatus,
+}
+
+impl RequestInput {
+    /// Whether the user is asked for the value, which fails when running
+    /// non-interactively
+    pub fn prompts(&self) -> bool {
+        matches!(self.status, InputStatus::Select(_) | InputStatus::Prompt)
+    }
+}
+
+/// Find the values a request needs, and how each of them is found, without
+/// making the request. Values in conditional blocks that are left out are
+/// not needed.
+pub fn request_inputs(template: &str, env: &Table) -> Vec<RequestInput> {
+    let mut resolved = env.clone();
+    let mut unresolved: Vec<RequestInput> = Vec::new();
+
+    // Fill in every missing value, to find the ones after it
+    loop {
+        let (key, status) = match substitute(template, &resolved) {
+            Err(SubstituteError::ValueNotFound { key, fallback })
+                if !resolved.contains_key(&key) =>
+            {
+                let status = match fallback {
+                    Some(fallback) => InputStatus::Fallback(fallback),
+                    None => InputStatus::Prompt,
+                };
+                (key, status)
+            }
+            Err(SubstituteError::MultipleValuesFound { key, values }) => {
+                (key, InputStatus::Select(values.len()))
+            }
+            _ => break,
+        };
+        resolved.insert(key.clone(), Value::String(String::new()));
+        unresolved.push(RequestInput { key, status });
+    }
+
+    let mut inputs: Vec<RequestInput> = placeholder_keys(template)
+        .into_iter()
+        .chain(env_var_keys(template))
+        .filter_map(|key| {
+            if let Some(input) = unresolved.iter().find(|i| i.key == key) {
+                return Some(input.clone());
+            }
+            let status = match key.strip_prefix("env:") {
+                Some(_) => InputStatus::EnvVar,
+                // Keys of left out blocks aren't needed
+                None if env.contains_key(&key) => InputStatus::Set,
+                None => return None,
+            };
+            Some(RequestInput { key, status })
+        })
+        .collect();
+
+    for input in unresolved {
+        if !inputs.iter().any(|i| i.key == input.key) {
+            inputs.push(input);
+        }
+    }
+
+    inputs
+}
+
+/// The environment variables used by a template, as `env:NAME`
+fn env_var_keys(template: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut slice = template;
+
+    while let Some(start) = slice.find("{{") {
+        let Some(end) = slice[start..].find("}}") else {
+            break;
+        };
+        let inner = &slice[start + 2..start + end];
+        slice = &slice[start + end + 2..];
+
+        let name = inner.split('|').next().unwrap_or("").trim();
+        if let Some(name) = name.strip_prefix("env:") {
+            let key = format!("env:{}", name.trim());
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    keys
+}
+
+/// A config for the target with the values that would be asked for, to fill
+/// in and put in `hitman.local.toml`, such as on a CI server. Environment
+/// variables are listed in comments, as they are better given as secrets.
+pub fn answers_file(inputs: &[RequestInput], target: &str) -> String {
+    let mut out = String::new();
+    let mut values = Table::new();
+
+    for input in inputs.iter().filter(|i| i.prompts()) {
+        match input.key.strip_prefix("env:") {
+            Some(name) => out.push_str(&format!("# export {name}=\n")),
+            None => {
+                values.insert(input.key.clone(), Value::String(String::new()));
+            }
+        }
+    }
+
+    if !values.is_empty() {
+        let mut config = Table::new();
+        config.insert(target.to_string(), Value::Table(values));
+        out.push_str(&toml::to_string(&config).unwrap_or_default());
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_how_inputs_are_given() {
+        let env: Table = toml::from_str(
+            r#"
+            base_url = "https://example.com"
+            apple_id = [1, 2]
+            "#,
+        )
+        .unwrap();
+        let template =
+            "GET {{base_url}}/apples/{{apple_id}}?limit={{limit | 10}}\n\
+                        Authorization: {{token}}\n\
+                        X-Key: {{env:HITMAN_TEST_UNSET_KEY}}\n\
+                        {{#if debug}}X-Debug: {{debug_level}}{{/if}}";
+
+        let inputs = request_inputs(template, &env);
+        let status = |key: &str| {
+            let input = inputs.iter().find(|i| i.key == key);
+            input.map(|i| i.status.clone())
+        };
+
+        assert_eq!(status("base_url"), Some(InputStatus::Set));
+        assert_eq!(status("apple_id"), Some(InputStatus::Select(2)));
+        assert_eq!(
+            status("limit"),
+            Some(InputStatus::Fallback("10".to_string()))
+        );
+        assert_eq!(status("token"), Some(InputStatus::Prompt));
+        assert_eq!(
+            status("env:HITMAN_TEST_UNSET_KEY"),
+            Some(InputStatus::Prompt)
+        );
+        assert_eq!(status("debug_level"), None);
+    }
+
+    #[test]
+    fn writes_answers_for_prompted_inputs() {
+        let input = |key: &str, status| RequestInput {
+            key: key.to_string(),
+            status,
+        };
+        let inputs = vec![
+            input("base_url", InputStatus::Set),
+            input("token", InputStatus::Prompt),
+            input("apple_id", InputStatus::Select(2)),
+            input("limit", InputStatus::Fallback("10".to_string())),
+            input("env:API_KEY", InputStatus::Prompt),
+        ];
+
+        assert_eq!(
+            answers_file(&inputs, "ci"),
+            "# export API_KEY=\n[ci]\napple_id = \"\"\ntoken = \"\"\n"
+        );
+    }
+}
@@ -1,8 +1,79 @@
+pub mod assertions;
+pub mod batch;
+pub mod cache;
+pub mod clock;
+pub mod cookies;
+pub mod curl;
+pub mod data;
+pub mod dataset;
+pub mod depends;
+pub mod diff;
+pub mod directive;
+pub mod discovery;
+pub mod doctor;
+pub mod download;
 pub mod env;
+pub mod etag;
+pub mod expiry;
+pub mod export;
 pub mod extract;
+pub mod fixtures;
 pub mod flurry;
+pub mod format;
+pub mod frecency;
+pub mod functions;
+pub mod graph;
+pub mod grpc;
+pub mod har;
+pub mod headers;
+pub mod history;
+pub mod hook;
+pub mod image;
+pub mod import;
+pub mod include;
+pub mod inputs;
+pub mod link;
+pub mod lint;
+pub mod load;
+pub mod markup;
+pub mod metrics;
+pub mod monitor;
+pub mod multipart;
+pub mod negotiate;
+pub mod oauth;
+pub mod openapi;
+pub mod output;
+pub mod overwrite;
+pub mod paginate;
+pub mod ping;
+pub mod plugin;
+pub mod postman;
+pub mod proto;
+pub mod proxy;
+pub mod random;
+pub mod recording;
+pub mod redirect;
+pub mod refactor;
+pub mod report;
 pub mod request;
+pub mod retry;
+pub mod scenario;
+pub mod schema;
+pub mod script;
+pub mod scrub;
+pub mod security;
+pub mod share;
+pub mod shared;
+pub mod state;
 pub mod substitute;
+pub mod sync;
+pub mod timeout;
+pub mod tls;
+pub mod totp;
+pub mod trust;
 pub mod util;
+pub mod viewer;
+pub mod websocket;
+pub mod workspace;
 
 pub mod prompt;
@@ -0,0 +1,239 @@
+use regex::Regex;
+use reqwest::{header::HeaderMap, header::LINK, Url};
+
+/// A link from a `Link` response header (RFC 8288)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: String,
+    pub rel: Option<String>,
+    pub title: Option<String>,
+}
+
+impl Link {
+    /// A short description, such as `next: https://example.com/?page=2`
+    pub fn label(&self) -> String {
+        match (&self.rel, &self.title) {
+            (Some(rel), Some(title)) => {
+                format!("{rel} ({title}): {}", self.url)
+            }
+            (Some(rel), None) => format!("{rel}: {}", self.url),
+            (None, Some(title)) => format!("{title}: {}", self.url),
+            (None, None) => self.url.clone(),
+        }
+    }
+}
+
+/// Find all links in the `Link` headers of a response, with URLs resolved
+/// relative to the URL of the request.
+pub fn response_links(headers: &HeaderMap, base: &Url) -> Vec<Link> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(parse_link_header)
+        .map(|link| Link {
+            url: base.join(&link.url).map(String::from).unwrap_or(link.url),
+            ..link
+        })
+        .collect()
+}
+
+/// Parse the value of a `Link` header, such as:
+///
+/// `<https://api.example.com/items?page=2>; rel="next", </items?page=9>; rel=last`
+pub fn parse_link_header(value: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let url = rest[start + 1..start + end].trim().to_string();
+        rest = &rest[start + end + 1..];
+
+        let params_end = find_link_end(rest);
+        let mut link = Link {
+            url,
+            rel: None,
+            title: None,
+        };
+
+        for param in split_params(&rest[..params_end]) {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match name.trim().to_lowercase().as_str() {
+                "rel" => link.rel = Some(value),
+                "title" => link.title = Some(value),
+                _ => (),
+            }
+        }
+
+        links.push(link);
+        rest = &rest[params_end..];
+    }
+
+    links
+}
+
+/// Find the comma that ends the parameters of a link, ignoring commas in
+/// quoted strings.
+fn find_link_end(s: &str) -> usize {
+    let mut quoted = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return i,
+            _ => (),
+        }
+    }
+
+    s.len()
+}
+
+fn split_params(s: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    params.push(&s[start..]);
+
+    params
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Make a GET request for following a link, from a previous request. The
+/// headers of the previous request are kept, so that authorization etc still
+/// applies, but the body is dropped.
+pub fn follow_up_request(buf: &str, url: &str) -> String {
+    let mut request = format!("GET {url} HTTP/1.1\n");
+
+    for line in buf.lines().skip(1) {
+        if line.trim().is_empty() {
+            break;
+        }
+
+        let name = line.split(':').next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("content-type")
+            || name.eq_ignore_ascii_case("content-length")
+        {
+            continue;
+        }
+
+        request.push_str(line);
+        request.push('\n');
+    }
+
+    request.push('\n');
+    request
+}
+
+/// Find the URLs in a text, such as a response body. Trailing punctuation is
+/// not considered part of a URL.
+pub fn find_urls(text: &str) -> Vec<(usize, &str)> {
+    let pattern = Regex::new(r#"https?://[^\s"'<>`]+"#).expect("valid regex");
+
+    pattern
+        .find_iter(text)
+        .map(|m| {
+            let url = m.as_str().trim_end_matches(['.', ',', ';', ':', ')']);
+            (m.start(), url)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pagination_links() {
+        let header = r#"<https://api.example.com/items?page=2>; rel="next", <https://api.example.com/items?page=9>; rel=last"#;
+
+        let links = parse_link_header(header);
+
+        assert_eq!(
+            links,
+            vec![
+                Link {
+                    url: "https://api.example.com/items?page=2".into(),
+                    rel: Some("next".into()),
+                    title: None,
+                },
+                Link {
+                    url: "https://api.example.com/items?page=9".into(),
+                    rel: Some("last".into()),
+                    title: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_separators_in_quoted_params() {
+        let header =
+            r#"</authors/1>; rel="author"; title="Smith, John; Jr", </x>"#;
+
+        let links = parse_link_header(header);
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].title.as_deref(), Some("Smith, John; Jr"));
+        assert_eq!(links[1].url, "/x");
+        assert_eq!(links[1].rel, None);
+    }
+
+    #[test]
+    fn resolves_relative_links() {
+        let mut headers = HeaderMap::new();
+        headers.insert(LINK, r#"</items?page=2>; rel="next""#.parse().unwrap());
+        let base = Url::parse("https://example.com/api/items").unwrap();
+
+        let links = response_links(&headers, &base);
+
+        assert_eq!(links[0].url, "https://example.com/items?page=2");
+    }
+
+    #[test]
+    fn keeps_headers_in_follow_up_request() {
+        let buf = "POST https://example.com/items HTTP/1.1\n\
+                   Authorization: Bearer abc\n\
+                   Content-Type: application/json\n\
+                   \n\
+                   {\"name\": \"apple\"}";
+
+        assert_eq!(
+            follow_up_request(buf, "https://example.com/items/1"),
+            "GET https://example.com/items/1 HTTP/1.1\n\
+             Authorization: Bearer abc\n\n"
+        );
+    }
+
+    #[test]
+    fn finds_urls_in_text() {
+        let text = r#"{"self": "https://example.com/items/1", "docs": "See http://example.com/docs."}"#;
+
+        let urls: Vec<&str> =
+            find_urls(text).into_iter().map(|(_, u)| u).collect();
+
+        assert_eq!(
+            urls,
+            vec!["https://example.com/items/1", "http://example.com/docs"]
+        );
+    }
+}
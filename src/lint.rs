@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::discovery::find_project_requests;
+
+/// A problem found in the request files of a project
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintWarning {
+    pub rule: &'static str,
+    pub file: PathBuf,
+    pub message: String,
+}
+
+/// Check the request files of a project for problems, such as requests that
+/// are copies of each other
+pub fn lint(root_dir: &Path) -> Result<Vec<LintWarning>> {
+    let requests = find_project_requests(root_dir)?;
+
+    let mut warnings = Vec::new();
+    for group in duplicate_requests(root_dir, &requests) {
+        for file in &group {
+            let others: Vec<String> = group
+                .iter()
+                .filter(|other| *other != file)
+                .map(|other| other.display().to_string())
+                .collect();
+            warnings.push(LintWarning {
+                rule: "duplicate-request",
+                file: file.clone(),
+                message: format!(
+                    "Same method and URL as {}",
+                    others.join(", ")
+                ),
+            });
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Groups of requests with the same method and URL template. Paths are
+/// relative to the root directory.
+pub fn duplicate_requests(
+    root_dir: &Path,
+    requests: &[PathBuf],
+) -> Vec<Vec<PathBuf>> {
+    let mut by_template: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in requests {
+        let Ok(buf) = read_to_string(root_dir.join(path)) else {
+            continue;
+        };
+        if let Some(template) = request_template(&buf) {
+            by_template.entry(template).or_default().push(path.clone());
+        }
+    }
+
+    by_template
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect()
+}
+
+/// The method and URL of a request, with the names of placeholders left out,
+/// so that `/orders/{{id}}` and `/orders/{{order_id}}` are the same
+fn request_template(buf: &str) -> Option<String> {
+    let line = buf
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_uppercase();
+    let url = parts.next()?;
+
+    let mut template = String::new();
+    let mut rest = url;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        template.push_str(&rest[..start]);
+        template.push_str("{{}}");
+        rest = &rest[start + end + 2..];
+    }
+    template.push_str(rest);
+
+    Some(format!("{method} {}", template.trim_end_matches('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn normalizes_request_templates() {
+        assert_eq!(
+            request_template("# List\nget {{url}}/orders/ HTTP/1.1\n"),
+            Some("GET {{}}/orders".to_string())
+        );
+        assert_eq!(
+            request_template("GET {{url}}/orders/{{order_id}}\n"),
+            request_template("GET {{base}}/orders/{{id}}\n")
+        );
+        assert_ne!(
+            request_template("GET {{url}}/orders\n"),
+            request_template("POST {{url}}/orders\n")
+        );
+        assert_eq!(request_template("# Nothing here\n"), None);
+    }
+
+    #[test]
+    fn finds_duplicate_requests() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-lint-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.http"), "GET {{url}}/orders\n").unwrap();
+        fs::write(dir.join("b.http"), "GET {{url}}/orders/\n").unwrap();
+        fs::write(dir.join("c.http"), "POST {{url}}/orders\n").unwrap();
+
+        let requests: Vec<PathBuf> =
+            ["a.http", "b.http", "c.http"].map(PathBuf::from).into();
+        let duplicates = duplicate_requests(&dir, &requests);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            duplicates,
+            vec![vec![PathBuf::from("a.http"), PathBuf::from("b.http")]]
+        );
+    }
+}
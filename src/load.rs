@@ -0,0 +1,230 @@
+use anyhow::{bail, Result};
+use futures::future::join_all;
+use log::warn;
+use spinoff::{spinners, Color, Spinner, Streams};
+
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::spawn;
+use toml::Table;
+
+use crate::request::{build_client, do_request, prepare_request};
+use crate::retry::RetryPolicy;
+use crate::util::IterExt;
+
+/// How hard, and how long, a request is sent in a load test
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    pub duration: Duration,
+    /// Requests started per second, across all connections. Without a rate,
+    /// every connection sends its next request as soon as it gets a response.
+    pub rate: Option<u32>,
+    pub concurrency: u32,
+}
+
+/// The outcome of one request in a load test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// The status of the response, or `None` when the request failed
+    pub status: Option<u16>,
+    pub latency: Duration,
+}
+
+impl Sample {
+    /// Failed requests and responses that are not 2xx or 3xx are errors
+    fn is_error(&self) -> bool {
+        !self.status.is_some_and(|s| (200..400).contains(&s))
+    }
+}
+
+/// Send a request over and over, and report latency percentiles, the error
+/// rate and the throughput
+pub async fn load_test(
+    file_path: &Path,
+    env: &Table,
+    options: &LoadOptions,
+) -> Result<()> {
+    if options.concurrency < 1 {
+        bail!("Concurrency must be at least 1");
+    }
+    if options.rate == Some(0) {
+        bail!("Rate must be at least 1");
+    }
+
+    let (buf, env, _) = prepare_request(file_path, env, &[]).await?;
+    let client = build_client(&env)?;
+
+    let rate = match options.rate {
+        Some(rate) => format!(", at {rate} requests per second"),
+        None => String::new(),
+    };
+    warn!(
+        "# Load testing for {:.0?} on {} connections{rate}...",
+        options.duration, options.concurrency
+    );
+
+    let mut spinner = Spinner::new_with_stream(
+        spinners::BouncingBall,
+        "",
+        Color::Yellow,
+        Streams::Stderr,
+    );
+
+    let start = Instant::now();
+    let deadline = start + options.duration;
+    let interval = options.rate.map(|r| Duration::from_secs(1) / r);
+    // The next request to start, when sending at a fixed rate
+    let next_slot = Arc::new(AtomicU64::new(0));
+
+    let handles = (0..options.concurrency).map(|_| {
+        let buf = buf.clone();
+        let client = client.clone();
+        let next_slot = next_slot.clone();
+        spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                if let Some(interval) = interval {
+                    let slot = next_slot.fetch_add(1, Ordering::Relaxed);
+                    let at = start + interval * slot as u32;
+                    if at >= deadline {
+                        break;
+                    }
+                    tokio::time::sleep_until(at.into()).await;
+                } else if Instant::now() >= deadline {
+                    break;
+                }
+
+                // Failed requests are counted, not retried
+                let sent = Instant::now();
+                let retry = RetryPolicy::default();
+                let status = match do_request(&client, &buf, &retry).await {
+                    Ok((res, _)) => {
+                        let status = res.status().as_u16();
+                        res.bytes().await.ok().map(|_| status)
+                    }
+                    Err(_) => None,
+                };
+                samples.push(Sample {
+                    status,
+                    latency: sent.elapsed(),
+                });
+            }
+            samples
+        })
+    });
+
+    let samples: Vec<Sample> = join_all(handles)
+        .await
+        .into_iter()
+        .filter_map(|h| h.ok())
+        .flatten()
+        .collect();
+
+    spinner.stop();
+
+    for line in summary(&samples, start.elapsed()) {
+        warn!("{line}");
+    }
+
+    Ok(())
+}
+
+/// The lines of the report of a load test
+fn summary(samples: &[Sample], elapsed: Duration) -> Vec<String> {
+    if samples.is_empty() {
+        return vec!["# No requests were sent".to_string()];
+    }
+
+    let mut latencies: Vec<Duration> =
+        samples.iter().map(|s| s.latency).collect();
+    latencies.sort();
+
+    let count = samples.len();
+    let errors = samples.iter().filter(|s| s.is_error()).count();
+    let mut results: Vec<(String, u32)> = samples
+        .iter()
+        .map(|s| match s.status {
+            Some(status) => status.to_string(),
+            None => "failed".to_string(),
+        })
+        .counted()
+        .into_iter()
+        .collect();
+    results.sort();
+    let results = results
+        .iter()
+        .map(|(s, c)| format!("{s} ({c})"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    vec![
+        format!(
+            "# Sent {count} requests in {elapsed:.2?} ({:.1} requests per second)",
+            count as f64 / elapsed.as_secs_f64()
+        ),
+        format!("# Results: {results}"),
+        format!(
+            "# Errors: {errors} ({:.1}%)",
+            errors as f64 * 100.0 / count as f64
+        ),
+        format!(
+            "# Latency: p50 {:.2?}, p95 {:.2?}, p99 {:.2?}, max {:.2?}",
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 95.0),
+            percentile(&latencies, 99.0),
+            latencies[count - 1]
+        ),
+    ]
+}
+
+/// The latency that `p` percent of the sorted latencies are at or below
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_percentiles() {
+        let latencies: Vec<Duration> =
+            (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&latencies, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies[..1], 95.0), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn summarizes_samples() {
+        let sample = |status, ms| Sample {
+            status,
+            latency: Duration::from_millis(ms),
+        };
+        let samples = vec![
+            sample(Some(200), 10),
+            sample(Some(200), 20),
+            sample(Some(503), 30),
+            sample(None, 40),
+        ];
+
+        let lines = summary(&samples, Duration::from_secs(2));
+        assert_eq!(
+            lines[0],
+            "# Sent 4 requests in 2.00s (2.0 requests per second)"
+        );
+        assert_eq!(lines[1], "# Results: 200 (2), 503 (1), failed (1)");
+        assert_eq!(lines[2], "# Errors: 2 (50.0%)");
+        assert_eq!(
+            lines[3],
+            "# Latency: p50 20.00ms, p95 40.00ms, p99 40.00ms, max 40.00ms"
+        );
+    }
+}
@@ -1,46 +1,139 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use inquire::{list_option::ListOption, Select};
-use log::{error, info};
+use log::{error, info, warn};
 use notify::EventKind;
-use std::env::current_dir;
-use std::path::Path;
+use std::env::{current_dir, set_current_dir};
+use std::path::{Path, PathBuf};
 use tokio::sync::mpsc;
 
+use hitman::clock;
+use hitman::dataset::load_rows;
+use hitman::depends::run_dependencies;
 use hitman::env::{
-    find_available_requests, find_root_dir, load_env, select_env, watch_list,
+    find_available_requests, find_root_dir, load_env, load_settings,
+    pinned_target, watch_list,
 };
 use hitman::flurry::flurry_attack;
+use hitman::format::align_table;
+use hitman::frecency::{record_use, request_scores};
+use hitman::har::{start_capture, write_har};
+use hitman::load::{load_test, LoadOptions};
+use hitman::negotiate::{accept_values, negotiate};
 use hitman::prompt::{fuzzy_match, set_interactive_mode};
-use hitman::request::make_request;
+use hitman::proxy::set_proxy_override;
+use hitman::random;
+use hitman::recording;
+use hitman::redirect::set_no_follow;
+use hitman::request::{
+    build_client, make_request, prepare_request, print_curl, RequestOptions,
+};
+use hitman::scrub::ScrubRules;
+use hitman::state::migrate_legacy_state;
+use hitman::workspace::find_project;
 
+use cli::{Command, RequestArgs};
 use watcher::Watcher;
 
 mod cli;
+mod commands;
 mod logging;
 mod watcher;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = cli::parse_args();
+    let mut args = cli::parse_args();
+    let command = args.take_command();
+    let request = args.request;
+
+    logging::init(
+        args.verbose,
+        args.quiet,
+        request.flurry.is_some() || request.load,
+    )?;
+
+    set_interactive_mode(!(request.non_interactive || request.watch));
 
-    logging::init(args.verbose, args.quiet, args.flurry.is_some())?;
+    if args.har.is_some() && request.watch {
+        bail!("--har can't be used with --watch");
+    }
 
-    set_interactive_mode(!(args.non_interactive || args.watch));
+    if let Some(project) = &args.project {
+        set_current_dir(find_project(project)?)?;
+    }
+
+    if let Some(Command::Import(command)) = command {
+        return commands::import(command);
+    }
 
     let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+    migrate_legacy_state(&root_dir)?;
+    clock::configure(&load_settings(&root_dir)?)?;
+
+    if args.record {
+        recording::set_mode(recording::Mode::Record);
+    } else if args.offline {
+        recording::set_mode(recording::Mode::Offline);
+    }
+
+    if let Some(proxy) = &args.proxy {
+        set_proxy_override(proxy);
+    }
 
-    if args.select {
-        select_env(&root_dir)?;
-        return Ok(());
+    if let Some(seed) = args.seed {
+        random::seed(seed);
+        clock::freeze();
     }
 
+    if args.har.is_some() {
+        start_capture(ScrubRules::load(&root_dir)?);
+    }
+
+    let result = match command {
+        Some(command) => commands::run(command, &root_dir).await,
+        None => run_requests(&root_dir, request).await,
+    };
+
+    // FIXME Must be a way to make this nicer
+    let result = match &result {
+        Err(e) => {
+            if is_user_cancelation(e) {
+                Ok(())
+            } else {
+                result
+            }
+        }
+        _ => result,
+    };
+
+    finish_har(args.har.as_deref(), result)
+}
+
+/// Send the request given, or pick requests to send
+async fn run_requests(root_dir: &Path, args: RequestArgs) -> Result<()> {
     let cwd = current_dir()?;
 
-    let result = if let Some(file_path) = args.name {
+    if args.no_follow {
+        set_no_follow();
+    }
+
+    let request_options = RequestOptions {
+        explain_cache: args.explain_cache,
+        security_report: args.security_report,
+        open_response: args.open,
+        unset_headers: args.unset_header.clone(),
+        diff: args.diff,
+        all_headers: args.all_headers,
+        header_pattern: args.headers.clone(),
+        // Every row of a data file is shown
+        keep_screen: args.data.is_some(),
+        output: args.output.clone(),
+    };
+
+    if let Some(file_path) = args.name {
         let file_path = cwd.join(file_path);
 
         if let Some(flurry_size) = args.flurry {
-            let env = load_env(&root_dir, &file_path, &args.options)?;
+            let env = load_env(root_dir, &file_path, &args.options)?;
             flurry_attack(
                 &file_path,
                 flurry_size,
@@ -48,18 +141,56 @@ async fn main() -> Result<()> {
                 &env,
             )
             .await
+        } else if args.load {
+            run_dependencies(root_dir, &file_path, &args.options).await?;
+            let env = load_env(root_dir, &file_path, &args.options)?;
+            let options = LoadOptions {
+                duration: args.duration,
+                rate: args.rate,
+                concurrency: args.concurrency,
+            };
+            load_test(&file_path, &env, &options).await
+        } else if args.curl {
+            let env = load_env(root_dir, &file_path, &args.options)?;
+            print_curl(&file_path, &env, &request_options).await
+        } else if let Some(data) = &args.data {
+            run_rows(
+                root_dir,
+                &file_path,
+                &args.options,
+                &cwd.join(data),
+                &request_options,
+            )
+            .await
+        } else if let Some(accept) = &args.accept {
+            compare_representations(
+                root_dir,
+                &file_path,
+                &args.options,
+                &accept_values(accept),
+                &request_options,
+            )
+            .await
         } else {
-            let res = run_once(&root_dir, &file_path, &args.options).await;
+            let res =
+                run_once(root_dir, &file_path, &args.options, &request_options)
+                    .await;
 
             if args.watch {
-                watch_mode(&root_dir, &file_path, &args.options).await
+                watch_mode(
+                    root_dir,
+                    &file_path,
+                    &args.options,
+                    &request_options,
+                )
+                .await
             } else {
                 res
             }
         }
     } else {
         loop {
-            let files = find_available_requests(&cwd)?;
+            let files = ranked_requests(root_dir, &cwd)?;
             let options: Vec<ListOption<String>> = files
                 .iter()
                 .enumerate()
@@ -74,7 +205,9 @@ async fn main() -> Result<()> {
 
             let file_path = &files[selected.index];
 
-            let result = run_once(&root_dir, file_path, &args.options).await;
+            let result =
+                run_once(root_dir, file_path, &args.options, &request_options)
+                    .await;
 
             if !args.repeat {
                 break result;
@@ -89,42 +222,152 @@ async fn main() -> Result<()> {
                 }
             }
         }
-    };
+    }
+}
 
-    // FIXME Must be a way to make this nicer
-    match &result {
-        Err(e) => {
-            if is_user_cancelation(e) {
-                Ok(())
-            } else {
-                result
-            }
-        }
-        _ => result,
+/// Write the HTTP Archive of the run, if one was asked for, whether the run
+/// succeeded or not
+fn finish_har(path: Option<&Path>, result: Result<()>) -> Result<()> {
+    if let Some(path) = path {
+        let count = write_har(path)?;
+        warn!("# Wrote {} requests to {}", count, path.display());
     }
+
+    result
 }
 
 fn is_user_cancelation(err: &anyhow::Error) -> bool {
     use inquire::InquireError::*;
-    false
-        || matches!(err.downcast_ref(), Some(OperationCanceled))
+    matches!(err.downcast_ref(), Some(OperationCanceled))
         || matches!(err.downcast_ref(), Some(OperationInterrupted))
 }
 
+/// The available requests, with the most frequently and recently used first
+fn ranked_requests(root_dir: &Path, cwd: &Path) -> Result<Vec<PathBuf>> {
+    let files = find_available_requests(cwd)?;
+    let paths: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+    let scores = request_scores(root_dir, &paths);
+
+    let mut ranked: Vec<_> = files.into_iter().zip(scores).collect();
+    ranked.sort_by_key(|(_, score)| -score);
+
+    Ok(ranked.into_iter().map(|(file, _)| file).collect())
+}
+
 async fn run_once(
     root_dir: &Path,
     file_path: &Path,
     options: &[(String, String)],
+    request_options: &RequestOptions,
 ) -> Result<()> {
+    if let Some(target) = pinned_target(root_dir, file_path)? {
+        warn!("# Pinned to target {}", target);
+    }
+
+    run_dependencies(root_dir, file_path, options).await?;
+
     let env = load_env(root_dir, file_path, options)?;
 
-    make_request(file_path, &env).await
+    // Only used to rank the requests, so not worth failing for
+    let _ = record_use(root_dir, file_path);
+
+    make_request(file_path, &env, request_options).await
+}
+
+/// Send a request once per row of a data file. Dependencies run once, before
+/// the first row, and a failed row doesn't stop the others.
+async fn run_rows(
+    root_dir: &Path,
+    file_path: &Path,
+    options: &[(String, String)],
+    data: &Path,
+    request_options: &RequestOptions,
+) -> Result<()> {
+    let rows = load_rows(data)?;
+
+    run_dependencies(root_dir, file_path, options).await?;
+    let _ = record_use(root_dir, file_path);
+
+    let mut failed = 0;
+    for (i, row) in rows.iter().enumerate() {
+        warn!("# Row {} of {}", i + 1, rows.len());
+
+        // Values given on the command line win over the columns
+        let row_options: Vec<(String, String)> =
+            row.iter().chain(options).cloned().collect();
+        let result = match load_env(root_dir, file_path, &row_options) {
+            Ok(env) => make_request(file_path, &env, request_options).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            error!("# Row {} failed: {}", i + 1, err);
+            failed += 1;
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => bail!("{failed} of {} rows failed", rows.len()),
+    }
+}
+
+async fn compare_representations(
+    root_dir: &Path,
+    file_path: &Path,
+    options: &[(String, String)],
+    accepts: &[String],
+    request_options: &RequestOptions,
+) -> Result<()> {
+    let (buf, env, _) = prepare_request(
+        file_path,
+        &load_env(root_dir, file_path, options)?,
+        &request_options.unset_headers,
+    )
+    .await?;
+    let client = build_client(&env)?;
+
+    let results = negotiate(&client, &buf, accepts).await?;
+
+    let mut rows = vec![vec![
+        "Accept".to_string(),
+        "Status".to_string(),
+        "Content-Type".to_string(),
+        "Size".to_string(),
+        "Result".to_string(),
+    ]];
+    for res in &results {
+        let result = if res.matches() {
+            "[pass]"
+        } else if res.status == 406 {
+            "[warn] not acceptable"
+        } else {
+            "[fail] other type"
+        };
+        rows.push(vec![
+            res.accept.clone(),
+            res.status.to_string(),
+            res.content_type.clone().unwrap_or_default(),
+            format!("{} bytes", res.size),
+            result.to_string(),
+        ]);
+    }
+    print!("{}", align_table(&rows));
+
+    let first = results.first().map(|r| &r.content_type);
+    if results.len() > 1
+        && results.iter().all(|r| Some(&r.content_type) == first)
+    {
+        warn!("# The server returned the same type for every Accept value");
+    }
+
+    Ok(())
 }
 
 async fn watch_mode(
     root_dir: &Path,
     file_path: &Path,
     options: &[(String, String)],
+    request_options: &RequestOptions,
 ) -> Result<()> {
     let (tx, mut rx) = mpsc::channel(1);
 
@@ -138,7 +381,9 @@ async fn watch_mode(
         if let Some(event) = rx.recv().await {
             if let EventKind::Modify(_) = event.kind {
                 watcher.unwatch_all()?;
-                if let Err(err) = run_once(root_dir, file_path, options).await {
+                let res =
+                    run_once(root_dir, file_path, options, request_options);
+                if let Err(err) = res.await {
                     error!("# {}", err)
                 }
                 watcher.watch_all()?;
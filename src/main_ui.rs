@@ -1,9 +1,11 @@
 use std::{
     io::{self, stdout, Write},
     panic::{set_hook, take_hook},
+    path::PathBuf,
 };
 
 use anyhow::Result;
+use clap::Parser;
 use crossterm::{
     terminal::{
         disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
@@ -17,10 +19,26 @@ use ui::app::{App, Screen};
 
 mod ui;
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// A request file to select on startup
+    file: Option<PathBuf>,
+
+    /// Send the request right away
+    #[arg(long, requires = "file")]
+    send: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
     init_panic_hook();
     let mut app = App::new()?;
+    if let Some(file) = &args.file {
+        app.open_request(file, args.send)?;
+    }
 
     let screen = CrosstermScreen::new(CrosstermBackend::new(stdout()))?;
     app.run(screen).await?;
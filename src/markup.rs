@@ -0,0 +1,597 @@
+use anyhow::{anyhow, bail, Context, Result};
+use quick_xml::{
+    events::{BytesStart, Event},
+    name::QName,
+    Reader,
+};
+use scraper::{Html, Selector};
+
+/// An XML or HTML document, for picking values out of responses with XPath
+/// or CSS selectors.
+///
+/// CSS selectors work on both, with the document read like a browser would.
+/// XPath works on XML only, which has to be well formed.
+pub struct Document {
+    html: Html,
+    /// Unless the document is HTML
+    xml: Option<XmlTree>,
+}
+
+impl Document {
+    /// Parse a document, as HTML if it starts like an HTML page, and as XML
+    /// otherwise
+    pub fn parse(text: &str) -> Result<Self> {
+        let xml = if looks_like_html(text) {
+            None
+        } else {
+            Some(XmlTree::parse(text).context("Could not parse XML")?)
+        };
+
+        Ok(Self {
+            html: Html::parse_document(text),
+            xml,
+        })
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.xml.is_none()
+    }
+
+    /// The values selected by an XPath expression, like
+    /// `//order[@status='paid']/id` or `//link/@href`. Elements give their
+    /// text.
+    ///
+    /// Only a subset of XPath is supported: location paths with `/` and
+    /// `//`, steps with a name, `*`, `@name` or `text()`, and predicates
+    /// with a position or a test of an attribute or child element, like
+    /// `[1]`, `[@id]`, `[@id='1']` or `[name='x']`.
+    pub fn xpath(&self, expr: &str) -> Result<Vec<String>> {
+        let Some(xml) = &self.xml else {
+            bail!("XPath needs an XML document, use a CSS selector for HTML");
+        };
+
+        xml.select(&parse_xpath(expr)?)
+    }
+
+    /// The text of the elements matched by a CSS selector, like
+    /// `table.orders td:first-child`, or the value of one of their
+    /// attributes if the selector ends with `@name`, like `a.next@href`
+    pub fn css(&self, selector: &str) -> Result<Vec<String>> {
+        let (selector, attribute) = split_attribute(selector);
+        let selector = Selector::parse(selector)
+            .map_err(|err| anyhow!("{err}"))
+            .with_context(|| format!("Invalid CSS selector: {selector}"))?;
+
+        let values = self
+            .html
+            .select(&selector)
+            .filter_map(|element| match attribute {
+                Some(name) => element.attr(name).map(str::to_string),
+                None => {
+                    let text: String = element.text().collect();
+                    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+                }
+            })
+            .collect();
+
+        Ok(values)
+    }
+}
+
+/// Split `a.next@href` into the selector and the attribute to take
+fn split_attribute(selector: &str) -> (&str, Option<&str>) {
+    match selector.rsplit_once('@') {
+        Some((selector, attribute))
+            if !attribute.is_empty()
+                && attribute.chars().all(|c| {
+                    c.is_alphanumeric() || matches!(c, '-' | '_' | ':')
+                }) =>
+        {
+            (selector.trim_end(), Some(attribute))
+        }
+        _ => (selector, None),
+    }
+}
+
+/// Whether a document starts with a doctype or an `html` element, after
+/// whitespace and comments
+fn looks_like_html(text: &str) -> bool {
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    while let Some(comment) = rest.strip_prefix("<!--") {
+        rest = match comment.split_once("-->") {
+            Some((_, after)) => after.trim_start(),
+            None => return false,
+        };
+    }
+
+    let start: String = rest.chars().take(14).collect();
+    let start = start.to_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// The elements of an XML document, by their names without a namespace
+/// prefix, so that `//soap:Body` and `//Body` find the same elements
+struct XmlTree {
+    nodes: Vec<XmlNode>,
+}
+
+enum XmlNode {
+    Root {
+        children: Vec<usize>,
+    },
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<usize>,
+    },
+    Text(String),
+}
+
+/// The root node of the document
+const ROOT: usize = 0;
+
+impl XmlTree {
+    fn parse(text: &str) -> Result<Self> {
+        let mut tree = Self {
+            nodes: vec![XmlNode::Root {
+                children: Vec::new(),
+            }],
+        };
+
+        let mut reader = Reader::from_str(text);
+        let mut open = vec![ROOT];
+        loop {
+            let parent = *open.last().unwrap_or(&ROOT);
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let element = tree.push_element(parent, &start)?;
+                    open.push(element);
+                }
+                Event::Empty(start) => {
+                    tree.push_element(parent, &start)?;
+                }
+                Event::End(_) => {
+                    open.pop();
+                }
+                Event::Text(text) => {
+                    let text = text.unescape()?.to_string();
+                    tree.push(parent, XmlNode::Text(text));
+                }
+                Event::CData(data) => {
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    tree.push(parent, XmlNode::Text(text));
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+
+        Ok(tree)
+    }
+
+    fn push(&mut self, parent: usize, node: XmlNode) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(node);
+        if let XmlNode::Root { children } | XmlNode::Element { children, .. } =
+            &mut self.nodes[parent]
+        {
+            children.push(id);
+        }
+        id
+    }
+
+    fn push_element(
+        &mut self,
+        parent: usize,
+        start: &BytesStart,
+    ) -> Result<usize> {
+        let mut attributes = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr?;
+            let value = attr.unescape_value()?.to_string();
+            attributes.push((local_name(attr.key), value));
+        }
+        let element = XmlNode::Element {
+            name: local_name(start.name()),
+            attributes,
+            children: Vec::new(),
+        };
+
+        Ok(self.push(parent, element))
+    }
+
+    fn children(&self, id: usize) -> &[usize] {
+        match &self.nodes[id] {
+            XmlNode::Root { children } | XmlNode::Element { children, .. } => {
+                children
+            }
+            XmlNode::Text(_) => &[],
+        }
+    }
+
+    fn name(&self, id: usize) -> Option<&str> {
+        match &self.nodes[id] {
+            XmlNode::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    fn attribute(&self, id: usize, name: &str) -> Option<&str> {
+        let XmlNode::Element { attributes, .. } = &self.nodes[id] else {
+            return None;
+        };
+        attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// A node and all nodes below it, in document order
+    fn descendants_or_self(&self, id: usize) -> Vec<usize> {
+        let mut out = vec![id];
+        let mut i = 0;
+        while i < out.len() {
+            out.extend(self.children(out[i]).iter().copied());
+            i += 1;
+        }
+        // Node ids are given in document order
+        out.sort_unstable();
+        out
+    }
+
+    /// The text of a node and all nodes below it, trimmed
+    fn text(&self, id: usize) -> String {
+        let text: String = self
+            .descendants_or_self(id)
+            .into_iter()
+            .filter_map(|node| match &self.nodes[node] {
+                XmlNode::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        text.trim().to_string()
+    }
+
+    fn select(&self, steps: &[Step]) -> Result<Vec<String>> {
+        let mut nodes = vec![ROOT];
+        for (i, step) in steps.iter().enumerate() {
+            let context = if step.descendant {
+                let mut all: Vec<_> = nodes
+                    .iter()
+                    .flat_map(|&id| self.descendants_or_self(id))
+                    .collect();
+                all.sort_unstable();
+                all.dedup();
+                all
+            } else {
+                nodes
+            };
+
+            match &step.test {
+                NodeTest::Attribute(name) => {
+                    if i + 1 < steps.len() {
+                        bail!("Attributes can only be selected last");
+                    }
+                    return Ok(context
+                        .into_iter()
+                        .filter_map(|id| self.attribute(id, name))
+                        .map(str::to_string)
+                        .collect());
+                }
+                NodeTest::Text => {
+                    if i + 1 < steps.len() {
+                        bail!("text() can only be selected last");
+                    }
+                    return Ok(context
+                        .into_iter()
+                        .flat_map(|id| self.children(id))
+                        .filter_map(|&id| match &self.nodes[id] {
+                            XmlNode::Text(text) => Some(text.trim()),
+                            _ => None,
+                        })
+                        .filter(|text| !text.is_empty())
+                        .map(str::to_string)
+                        .collect());
+                }
+                NodeTest::Name(_) | NodeTest::Any => (),
+            }
+
+            let mut next = Vec::new();
+            for id in context {
+                let children: Vec<usize> = self
+                    .children(id)
+                    .iter()
+                    .copied()
+                    .filter(|&child| match (&step.test, self.name(child)) {
+                        (NodeTest::Name(name), Some(n)) => n == name,
+                        (NodeTest::Any, Some(_)) => true,
+                        _ => false,
+                    })
+                    .collect();
+                next.extend(self.filter(children, &step.predicates));
+            }
+            next.sort_unstable();
+            next.dedup();
+            nodes = next;
+        }
+
+        Ok(nodes.into_iter().map(|id| self.text(id)).collect())
+    }
+
+    fn filter(
+        &self,
+        mut nodes: Vec<usize>,
+        predicates: &[Predicate],
+    ) -> Vec<usize> {
+        for predicate in predicates {
+            nodes = match predicate {
+                Predicate::Position(n) => {
+                    nodes.get(n - 1).copied().into_iter().collect()
+                }
+                Predicate::Attribute(name, value) => nodes
+                    .into_iter()
+                    .filter(|&id| {
+                        let found = self.attribute(id, name);
+                        found.is_some()
+                            && value.iter().all(|v| found == Some(v))
+                    })
+                    .collect(),
+                Predicate::Child(name, value) => nodes
+                    .into_iter()
+                    .filter(|&id| {
+                        self.children(id).iter().any(|&child| {
+                            self.name(child) == Some(name.as_str())
+                                && self.text(child) == *value
+                        })
+                    })
+                    .collect(),
+            };
+        }
+
+        nodes
+    }
+}
+
+/// A name without its namespace prefix
+fn local_name(name: QName) -> String {
+    String::from_utf8_lossy(name.local_name().into_inner()).to_string()
+}
+
+struct Step {
+    /// After `//`
+    descendant: bool,
+    test: NodeTest,
+    predicates: Vec<Predicate>,
+}
+
+enum NodeTest {
+    Name(String),
+    Any,
+    Attribute(String),
+    Text,
+}
+
+enum Predicate {
+    /// From 1
+    Position(usize),
+    /// `[@name]` or `[@name='value']`
+    Attribute(String, Option<String>),
+    /// `[name='value']`
+    Child(String, String),
+}
+
+fn parse_xpath(expr: &str) -> Result<Vec<Step>> {
+    let expr = expr.trim();
+    let invalid = || format!("Invalid XPath expression: {expr}");
+
+    let mut steps = Vec::new();
+    let mut rest = expr;
+    while !rest.is_empty() {
+        let descendant = rest.starts_with("//");
+        rest = rest.trim_start_matches('/');
+
+        let end = step_end(rest);
+        let step = parse_step(&rest[..end]).with_context(invalid)?;
+        steps.push(Step { descendant, ..step });
+        rest = &rest[end..];
+    }
+
+    if steps.is_empty() {
+        bail!(invalid());
+    }
+
+    Ok(steps)
+}
+
+/// Where a step ends, at the next `/` outside brackets and quotes
+fn step_end(text: &str) -> usize {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (c, quote) {
+            (q, Some(open)) if q == open => quote = None,
+            (_, Some(_)) => (),
+            ('\'' | '"', None) => quote = Some(c),
+            ('[', None) => depth += 1,
+            (']', None) => depth -= 1,
+            ('/', None) if depth == 0 => return i,
+            _ => (),
+        }
+    }
+    text.len()
+}
+
+fn parse_step(part: &str) -> Result<Step> {
+    let (test, mut rest) = match part.find('[') {
+        Some(i) => (&part[..i], &part[i..]),
+        None => (part, ""),
+    };
+
+    let test = match test.trim() {
+        "*" => NodeTest::Any,
+        "text()" => NodeTest::Text,
+        test => match test.strip_prefix('@') {
+            Some(name) => NodeTest::Attribute(valid_name(name)?),
+            None => NodeTest::Name(valid_name(test)?),
+        },
+    };
+
+    let mut predicates = Vec::new();
+    while !rest.is_empty() {
+        let Some(inner) = rest.strip_prefix('[') else {
+            bail!("Unexpected {rest}");
+        };
+        let (predicate, after) = inner.split_once(']').context("Missing ]")?;
+        predicates.push(parse_predicate(predicate.trim())?);
+        rest = after.trim_start();
+    }
+
+    Ok(Step {
+        descendant: false,
+        test,
+        predicates,
+    })
+}
+
+fn parse_predicate(predicate: &str) -> Result<Predicate> {
+    if let Ok(n) = predicate.parse::<usize>() {
+        if n == 0 {
+            bail!("Positions start at 1");
+        }
+        return Ok(Predicate::Position(n));
+    }
+
+    let (operand, value) = match predicate.split_once('=') {
+        Some((operand, value)) => (operand.trim(), Some(literal(value)?)),
+        None => (predicate, None),
+    };
+
+    match (operand.strip_prefix('@'), value) {
+        (Some(name), value) => {
+            Ok(Predicate::Attribute(valid_name(name)?, value))
+        }
+        (None, Some(value)) => {
+            Ok(Predicate::Child(valid_name(operand)?, value))
+        }
+        (None, None) => bail!("Unsupported predicate: {predicate}"),
+    }
+}
+
+/// A quoted string, or a number
+fn literal(value: &str) -> Result<String> {
+    let value = value.trim();
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return Ok(inner.to_string());
+        }
+    }
+    if value.parse::<f64>().is_ok() {
+        return Ok(value.to_string());
+    }
+
+    bail!("Expected a quoted string, got {value}")
+}
+
+/// A name, without its namespace prefix like in the document
+fn valid_name(name: &str) -> Result<String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    if !valid {
+        bail!("Invalid name: {name}");
+    }
+
+    Ok(name.rsplit(':').next().unwrap_or(name).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOAP: &str = r#"<?xml version="1.0"?>
+<soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope">
+  <soap:Body>
+    <m:GetOrdersResponse xmlns:m="http://example.com/orders">
+      <m:Order status="paid"><m:Id>1</m:Id><m:Total>12.50</m:Total></m:Order>
+      <m:Order status="open"><m:Id>2</m:Id><m:Total>3 &amp; 4</m:Total></m:Order>
+      <m:Note><![CDATA[<b>fragile</b>]]></m:Note>
+    </m:GetOrdersResponse>
+  </soap:Body>
+</soap:Envelope>"#;
+
+    const HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <script>if (a < b && c) {}</script>
+</head>
+<body>
+  <form action="/login">
+    <input type="hidden" name="csrf" value="abc&amp;123">
+    <input type=text name=user disabled>
+  </form>
+  <ul class="orders list">
+    <li id="o1">First <b>order</b>
+    <li id="o2">Second&nbsp;order
+    <li id="o3" class="last">Third</li>
+  </ul>
+  <p>Unclosed <a class="next" href="/page/2">Next</a>
+</body>
+</html>"#;
+
+    #[test]
+    fn selects_xml_with_xpath() {
+        let doc = Document::parse(SOAP).unwrap();
+        let xpath = |expr| doc.xpath(expr).unwrap();
+
+        assert_eq!(xpath("//Order/Id"), ["1", "2"]);
+        assert_eq!(xpath("//m:Order[@status='open']/m:Total"), ["3 & 4"]);
+        assert_eq!(xpath("/Envelope/Body/*/Order[2]/@status"), ["open"]);
+        assert_eq!(xpath("//Order[Id='1']/Total/text()"), ["12.50"]);
+        assert_eq!(xpath("//Note"), ["<b>fragile</b>"]);
+        assert!(xpath("//Order[@status='paid'][@missing]").is_empty());
+        assert!(xpath("//order").is_empty());
+
+        assert!(doc.xpath("//Order[").is_err());
+        assert!(doc.xpath("//Order[0]").is_err());
+        assert!(doc.xpath("//Order/@status/Id").is_err());
+        assert!(doc.xpath("//Order[last()]").is_err());
+    }
+
+    #[test]
+    fn selects_html_with_css() {
+        let doc = Document::parse(HTML).unwrap();
+        let css = |selector| doc.css(selector).unwrap();
+
+        assert!(doc.is_html());
+        assert_eq!(css("input[name=csrf]@value"), ["abc&123"]);
+        assert_eq!(css("form > input[disabled]@name"), ["user"]);
+        assert_eq!(
+            css("ul.orders li"),
+            ["First order", "Second order", "Third"]
+        );
+        assert_eq!(css("li:first-child + li@id"), ["o2"]);
+        assert_eq!(css("#o1 ~ li:last-child"), ["Third"]);
+        assert_eq!(css("li:nth-child(2), .last@id"), ["o2", "o3"]);
+        assert_eq!(css("a.next@href"), ["/page/2"]);
+        assert_eq!(css("a[href^='/page']"), ["Next"]);
+        assert_eq!(css("SCRIPT"), ["if (a < b && c) {}"]);
+        assert!(css("ul > a").is_empty());
+
+        assert!(doc.xpath("//li[@id='o3']").is_err());
+        assert!(doc.css("li >").is_err());
+    }
+
+    #[test]
+    fn detects_html() {
+        assert!(looks_like_html("\n<!-- x --><!doctype html><p>"));
+        assert!(looks_like_html("<HTML lang=en>"));
+        assert!(!looks_like_html("<?xml version=\"1.0\"?><html/>"));
+        assert!(!looks_like_html("<rss><channel/></rss>"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use log::{error, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::monitor::CheckResult;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Debug, Default, Clone)]
+struct RequestMetrics {
+    successes: u64,
+    failures: u64,
+    up: bool,
+    /// Number of observations in each bucket, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Results of monitor checks, in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<String, RequestMetrics>>,
+}
+
+impl Metrics {
+    pub fn record(&self, result: &CheckResult) {
+        let mut requests = self.requests.lock().expect("metrics lock");
+        let metrics = requests
+            .entry(result.file.display().to_string())
+            .or_insert_with(|| RequestMetrics {
+                buckets: vec![0; BUCKETS.len()],
+                ..Default::default()
+            });
+
+        metrics.up = result.passed();
+        if result.passed() {
+            metrics.successes += 1;
+        } else {
+            metrics.failures += 1;
+        }
+
+        if let Some(elapsed) = result.elapsed {
+            let secs = elapsed.as_secs_f64();
+            if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) {
+                metrics.buckets[i] += 1;
+            }
+            metrics.sum += secs;
+            metrics.count += 1;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let requests = self.requests.lock().expect("metrics lock");
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP hitman_check_total Number of monitor checks by result\n\
+             # TYPE hitman_check_total counter"
+        );
+        for (request, m) in requests.iter() {
+            let request = escape_label(request);
+            let _ = writeln!(
+                out,
+                "hitman_check_total{{request=\"{request}\",result=\"success\"}} {}",
+                m.successes
+            );
+            let _ = writeln!(
+                out,
+                "hitman_check_total{{request=\"{request}\",result=\"failure\"}} {}",
+                m.failures
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP hitman_check_up Whether the last check passed\n\
+             # TYPE hitman_check_up gauge"
+        );
+        for (request, m) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "hitman_check_up{{request=\"{}\"}} {}",
+                escape_label(request),
+                u8::from(m.up)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP hitman_check_duration_seconds Response time of monitor checks\n\
+             # TYPE hitman_check_duration_seconds histogram"
+        );
+        for (request, m) in requests.iter() {
+            let request = escape_label(request);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(&m.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "hitman_check_duration_seconds_bucket{{request=\"{request}\",le=\"{bound}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "hitman_check_duration_seconds_bucket{{request=\"{request}\",le=\"+Inf\"}} {}",
+                m.count
+            );
+            let _ = writeln!(
+                out,
+                "hitman_check_duration_seconds_sum{{request=\"{request}\"}} {}",
+                m.sum
+            );
+            let _ = writeln!(
+                out,
+                "hitman_check_duration_seconds_count{{request=\"{request}\"}} {}",
+                m.count
+            );
+        }
+
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve the metrics on `/metrics`, until the process exits
+pub async fn serve_metrics(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    warn!(
+        "# Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let metrics = metrics.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = respond(stream, &metrics).await {
+                            error!("# Metrics request failed: {err}");
+                        }
+                    });
+                }
+                Err(err) => error!("# Metrics connection failed: {err}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", metrics.render())
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+
+    fn result(ms: u64, passed: bool) -> CheckResult {
+        CheckResult {
+            file: PathBuf::from("checks/health.http"),
+            status: Some(200),
+            elapsed: Some(Duration::from_millis(ms)),
+            failures: if passed { vec![] } else { vec!["fail".into()] },
+        }
+    }
+
+    #[test]
+    fn renders_counters_and_histogram() {
+        let metrics = Metrics::default();
+        metrics.record(&result(80, true));
+        metrics.record(&result(300, false));
+
+        let text = metrics.render();
+
+        for line in [
+            r#"hitman_check_total{request="checks/health.http",result="success"} 1"#,
+            r#"hitman_check_total{request="checks/health.http",result="failure"} 1"#,
+            r#"hitman_check_up{request="checks/health.http"} 0"#,
+            r#"hitman_check_duration_seconds_bucket{request="checks/health.http",le="0.1"} 1"#,
+            r#"hitman_check_duration_seconds_bucket{request="checks/health.http",le="0.5"} 2"#,
+            r#"hitman_check_duration_seconds_bucket{request="checks/health.http",le="+Inf"} 2"#,
+            r#"hitman_check_duration_seconds_count{request="checks/health.http"} 2"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} in\n{text}");
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{error, warn};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    assertions::{assertions, ResponseInfo},
+    directive::Directives,
+    env::{find_available_requests, load_env, update_data},
+    extract::{
+        extract_header_variables, extract_markup_variables, extract_variables,
+        store_extracted,
+    },
+    headers::add_headers,
+    metrics::{serve_metrics, Metrics},
+    oauth::with_access_token,
+    plugin::{apply_request_hooks, plugins_from},
+    prompt::{substitute_interactive, NoUserInteraction},
+    request::{build_client, do_request, read_request},
+    retry::RetryPolicy,
+    script::RequestScripts,
+    util::shell_command,
+};
+
+pub struct MonitorOptions {
+    pub every: Duration,
+    /// Shell command to run when a check fails
+    pub on_failure: Option<String>,
+    /// File to append results to, in addition to printing them
+    pub log_file: Option<PathBuf>,
+    /// Address to serve Prometheus metrics on
+    pub metrics: Option<SocketAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub file: PathBuf,
+    pub status: Option<u16>,
+    pub elapsed: Option<Duration>,
+    pub failures: Vec<String>,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// One line describing the result, such as
+    /// `FAIL checks/health.http 500 12ms: status == 200 (got 500)`
+    pub fn summary(&self) -> String {
+        let mut line = format!(
+            "{} {}",
+            if self.passed() { "OK  " } else { "FAIL" },
+            self.file.display()
+        );
+        if let Some(status) = self.status {
+            line.push_str(&format!(" {status}"));
+        }
+        if let Some(elapsed) = self.elapsed {
+            line.push_str(&format!(" {}ms", elapsed.as_millis()));
+        }
+        if !self.passed() {
+            line.push_str(&format!(": {}", self.failures.join(", ")));
+        }
+
+        line
+    }
+}
+
+/// Find the request files to run, given a single file or a directory
+pub fn find_checks(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<PathBuf> = find_available_requests(path)?
+        .into_iter()
+        .map(|f| path.join(f))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// Run a request once, and evaluate its assertions. Without assertions, the
+/// check fails on error status codes.
+pub async fn run_check(root_dir: &Path, file: &Path) -> CheckResult {
+    run_check_with(root_dir, file, &[], &[]).await
+}
+
+/// Run a check with extra values to substitute, and extra headers to send
+pub async fn run_check_with(
+    root_dir: &Path,
+    file: &Path,
+    options: &[(String, String)],
+    headers: &[(String, String)],
+) -> CheckResult {
+    check(root_dir, file, options, headers, None).await
+}
+
+/// Run a check that expects a status code, instead of failing on error status
+/// codes
+pub async fn run_check_expecting(
+    root_dir: &Path,
+    file: &Path,
+    options: &[(String, String)],
+    headers: &[(String, String)],
+    status: u16,
+) -> CheckResult {
+    check(root_dir, file, options, headers, Some(status)).await
+}
+
+async fn check(
+    root_dir: &Path,
+    file: &Path,
+    options: &[(String, String)],
+    headers: &[(String, String)],
+    expected_status: Option<u16>,
+) -> CheckResult {
+    let mut result = CheckResult {
+        file: file.to_path_buf(),
+        status: None,
+        elapsed: None,
+        failures: Vec::new(),
+    };
+
+    if let Err(err) = do_run_check(
+        root_dir,
+        file,
+        options,
+        headers,
+        expected_status,
+        &mut result,
+    )
+    .await
+    {
+        result.failures.push(err.to_string());
+    }
+
+    result
+}
+
+async fn do_run_check(
+    root_dir: &Path,
+    file: &Path,
+    options: &[(String, String)],
+    headers: &[(String, String)],
+    expected_status: Option<u16>,
+    result: &mut CheckResult,
+) -> Result<()> {
+    let env = with_access_token(&load_env(root_dir, file, options)?).await?;
+    let client = build_client(&env)?;
+
+    let (buf, scripts) = RequestScripts::extract(&read_request(file, &env)?)?;
+    let env = scripts.before(&env)?;
+    let buf = add_headers(&buf, headers);
+    // Nobody is asked for values, but fallbacks are used
+    let buf = substitute_interactive(&buf, &env, &NoUserInteraction)?;
+    let buf = apply_request_hooks(&env, &buf)?;
+    let directives = Directives::parse(&buf)?;
+    let assertions = assertions(&directives)?;
+
+    let retry = RetryPolicy::for_request(&directives, &env)?;
+    let (response, elapsed) = do_request(&client, &buf, &retry).await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.text().await?;
+
+    result.status = Some(status.as_u16());
+    result.elapsed = Some(elapsed);
+
+    match expected_status {
+        Some(expected) if expected != status.as_u16() => result
+            .failures
+            .push(format!("status == {expected} (got {})", status.as_u16())),
+        Some(_) => (),
+        None if assertions.is_empty()
+            && (status.is_client_error() || status.is_server_error()) =>
+        {
+            result.failures.push(format!("status {status}"))
+        }
+        None => (),
+    }
+
+    let info = ResponseInfo {
+        status: status.as_u16(),
+        headers: &headers,
+        body: &body,
+        elapsed,
+        plugins: &plugins_from(&env)?,
+    };
+    for assertion in &assertions {
+        let res = assertion.evaluate(&info);
+        if !res.passed {
+            let message = res.message.unwrap_or_default();
+            result
+                .failures
+                .push(format!("{} ({})", res.source, message));
+        }
+    }
+
+    // Keep extracted values, such as tokens from a login check
+    update_data(&scripts.after(&env, &info)?, &env)?;
+    store_extracted(&extract_header_variables(&headers, &env)?, &env, None)?;
+    if let Ok(json) = serde_json::from_str(&body) {
+        store_extracted(&extract_variables(&json, &env)?, &env, Some(&json))?;
+    } else {
+        store_extracted(&extract_markup_variables(&body, &env)?, &env, None)?;
+    }
+
+    Ok(())
+}
+
+/// Run the requests in a file or directory on a schedule, until interrupted
+pub async fn monitor(
+    root_dir: &Path,
+    path: &Path,
+    options: &MonitorOptions,
+) -> Result<()> {
+    let files = find_checks(path)?;
+    if files.is_empty() {
+        anyhow::bail!("No requests found in {}", path.display());
+    }
+
+    warn!(
+        "# Monitoring {} requests every {:?}",
+        files.len(),
+        options.every
+    );
+
+    let metrics = Arc::new(Metrics::default());
+    if let Some(addr) = options.metrics {
+        serve_metrics(addr, metrics.clone()).await?;
+    }
+
+    let mut ticker = interval(options.every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let mut failed = Vec::new();
+        for file in &files {
+            let result = run_check(root_dir, file).await;
+            log_result(&result, options)?;
+            metrics.record(&result);
+            if !result.passed() {
+                failed.push(result);
+            }
+        }
+
+        if let (Some(command), false) = (&options.on_failure, failed.is_empty())
+        {
+            if let Err(err) = run_failure_command(command, &failed) {
+                error!("# Failed to run {command}: {err}");
+            }
+        }
+    }
+}
+
+fn log_result(result: &CheckResult, options: &MonitorOptions) -> Result<()> {
+    let line = format!(
+        "{} {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        result.summary()
+    );
+    println!("{line}");
+
+    if let Some(log_file) = &options.log_file {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .with_context(|| {
+                format!("Failed to open {}", log_file.display())
+            })?;
+        writeln!(file, "{line}")?;
+    }
+
+    Ok(())
+}
+
+/// Run a shell command, with the failed request files in `HITMAN_FAILED`
+fn run_failure_command(command: &str, failed: &[CheckResult]) -> Result<()> {
+    let files: Vec<String> = failed
+        .iter()
+        .map(|r| r.file.display().to_string())
+        .collect();
+
+    shell_command(command)
+        .env("HITMAN_FAILED", files.join(" "))
+        .status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn summarizes_results() {
+        let mut result = CheckResult {
+            file: PathBuf::from("checks/health.http"),
+            status: Some(200),
+            elapsed: Some(Duration::from_millis(85)),
+            failures: Vec::new(),
+        };
+
+        assert_eq!(result.summary(), "OK   checks/health.http 200 85ms");
+
+        result.failures.push("status == 201 (got 200)".into());
+
+        assert_eq!(
+            result.summary(),
+            "FAIL checks/health.http 200 85ms: status == 201 (got 200)"
+        );
+    }
+
+    #[tokio::test]
+    async fn uses_fallbacks_of_missing_values() {
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let len = tcp.read(&mut buf).await.unwrap();
+            tcp.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_lowercase()
+        });
+
+        let root = std::env::temp_dir()
+            .join(format!("hitman-check-fallback-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            root.join("hitman.toml"),
+            format!(
+                "[_headers]\nAuthorization = \"Bearer {{{{token | none}}}}\"\n\
+                 [default]\nhost = \"{addr}\"\n"
+            ),
+        )
+        .unwrap();
+        let file = root.join("health.http");
+        fs::write(&file, "GET http://{{host}}/health HTTP/1.1\n").unwrap();
+
+        let result = run_check_with(&root, &file, &[], &[]).await;
+        let request = server.await.unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.passed(), "{:?}", result.failures);
+        assert!(request.contains("authorization: bearer none"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{include::body_start, random::next_u64};
+
+const MULTIPART_TYPE: &str = "multipart/form-data";
+
+/// A field of a `multipart/form-data` body, given as `name=value`, or
+/// `name=@path` for a file, with an optional `;type=image/png`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    Text {
+        name: String,
+        value: String,
+    },
+    File {
+        name: String,
+        path: PathBuf,
+        content_type: Option<String>,
+    },
+}
+
+/// Whether a body should be built from fields. A content type that already
+/// has a boundary is for a body written by hand, which is sent as it is.
+pub fn is_multipart(content_type: &str) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+
+    content_type.trim().starts_with(MULTIPART_TYPE)
+        && !content_type.contains("boundary=")
+}
+
+/// Make the paths of files in a multipart request relative to the directory
+/// of the request file, instead of the working directory
+pub fn resolve_files(buf: &str, dir: &Path) -> String {
+    let Some(body_start) = body_start(buf) else {
+        return buf.to_string();
+    };
+    if !buf.lines().take(body_start).any(is_multipart_header) {
+        return buf.to_string();
+    }
+
+    buf.split_inclusive('\n')
+        .enumerate()
+        .map(|(i, line)| match line.split_once("=@") {
+            Some((name, path)) if i >= body_start => {
+                let path = path.trim_start();
+                if Path::new(path).is_absolute() {
+                    line.to_string()
+                } else {
+                    format!("{name}=@{}", dir.join(path).display())
+                }
+            }
+            _ => line.to_string(),
+        })
+        .collect()
+}
+
+/// The fields of a multipart body, one per line
+pub fn parse_parts(body: &str) -> Result<Vec<Part>> {
+    body.lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("Invalid multipart field, expected name=value: {line}");
+            };
+            let name = name.trim().to_string();
+
+            Ok(match value.strip_prefix('@') {
+                Some(file) => {
+                    let (path, content_type) = match file.split_once(";type=") {
+                        Some((path, t)) => (path, Some(t.trim().to_string())),
+                        None => (file, None),
+                    };
+                    Part::File {
+                        name,
+                        path: PathBuf::from(path.trim()),
+                        content_type,
+                    }
+                }
+                None => Part::Text {
+                    name,
+                    value: value.to_string(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Encode the fields of a body, reading the files they refer to. Returns
+/// the content type, with the boundary, and the body.
+pub fn encode_multipart(parts: &[Part]) -> Result<(String, Vec<u8>)> {
+    let boundary = format!("hitman-{:016x}{:016x}", next_u64(), next_u64());
+
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+        match part {
+            Part::Text { name, value } => {
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                        escape(name)
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(value.as_bytes());
+            }
+            Part::File {
+                name,
+                path,
+                content_type,
+            } => {
+                let data = fs::read(path).with_context(|| {
+                    format!("Failed to read {}", path.display())
+                })?;
+                let filename = path
+                    .file_name()
+                    .map(|f| f.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let content_type = content_type
+                    .clone()
+                    .unwrap_or_else(|| guess_content_type(path).to_string());
+
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; \
+                         filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                        escape(name),
+                        escape(&filename),
+                        content_type
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(&data);
+            }
+        }
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+    Ok((format!("{MULTIPART_TYPE}; boundary={boundary}"), body))
+}
+
+fn is_multipart_header(line: &str) -> bool {
+    match line.split_once(':') {
+        Some((name, value)) => {
+            name.trim().eq_ignore_ascii_case("content-type")
+                && is_multipart(value)
+        }
+        None => false,
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn guess_content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_parts() {
+        let body = "title=Beach\r\n\nphoto=@./img/beach.png;type=image/x-png\n\
+                    notes=@/tmp/notes.txt\n";
+
+        assert_eq!(
+            parse_parts(body).unwrap(),
+            vec![
+                Part::Text {
+                    name: "title".to_string(),
+                    value: "Beach".to_string(),
+                },
+                Part::File {
+                    name: "photo".to_string(),
+                    path: PathBuf::from("./img/beach.png"),
+                    content_type: Some("image/x-png".to_string()),
+                },
+                Part::File {
+                    name: "notes".to_string(),
+                    path: PathBuf::from("/tmp/notes.txt"),
+                    content_type: None,
+                },
+            ]
+        );
+        assert!(parse_parts("no value").is_err());
+    }
+
+    #[test]
+    fn resolves_file_paths() {
+        let buf = "# @retries 2\nPOST http://example.com HTTP/1.1\n\
+                   Content-Type: multipart/form-data\n\n\
+                   title=a=@b\nphoto=@img/a.png\nlog=@/var/log/x.log\n";
+
+        assert_eq!(
+            resolve_files(buf, Path::new("/project/uploads")),
+            "# @retries 2\nPOST http://example.com HTTP/1.1\n\
+             Content-Type: multipart/form-data\n\n\
+             title=a=@/project/uploads/b\n\
+             photo=@/project/uploads/img/a.png\nlog=@/var/log/x.log\n"
+        );
+
+        let json = "POST http://example.com HTTP/1.1\n\n{\"a\": \"x=@y\"}";
+        assert_eq!(resolve_files(json, Path::new("/project")), json);
+    }
+
+    #[test]
+    fn encodes_parts() {
+        let parts = vec![Part::Text {
+            name: "title".to_string(),
+            value: "Beach".to_string(),
+        }];
+
+        let (content_type, body) = encode_multipart(&parts).unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; \
+                 name=\"title\"\r\n\r\nBeach\r\n--{boundary}--\r\n"
+            )
+        );
+        assert!(is_multipart("multipart/form-data"));
+        assert!(!is_multipart(&content_type));
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use reqwest::{header::CONTENT_TYPE, Client};
+
+use crate::{headers::remove_headers, request::do_request, retry::RetryPolicy};
+
+/// Short names for common media types
+const SHORTHANDS: &[(&str, &str)] = &[
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("problem+json", "application/problem+json"),
+    ("problem+xml", "application/problem+xml"),
+    ("html", "text/html"),
+    ("text", "text/plain"),
+    ("csv", "text/csv"),
+    ("yaml", "application/yaml"),
+    ("any", "*/*"),
+];
+
+/// The response to a request with one Accept value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negotiation {
+    pub accept: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub size: usize,
+}
+
+impl Negotiation {
+    /// Check if the server responded with the media type that was asked for
+    pub fn matches(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|ct| media_type_matches(&self.accept, ct))
+    }
+}
+
+/// Parse a comma separated list of media types, where common types can be
+/// given by short names, like `json,xml,application/vnd.api+json`
+pub fn accept_values(spec: &str) -> Vec<String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            SHORTHANDS
+                .iter()
+                .find(|(short, _)| short.eq_ignore_ascii_case(s))
+                .map(|(_, full)| full.to_string())
+                .unwrap_or_else(|| s.to_string())
+        })
+        .collect()
+}
+
+/// Send a request once for each Accept value, to compare the representations
+/// returned by the server
+pub async fn negotiate(
+    client: &Client,
+    buf: &str,
+    accepts: &[String],
+) -> Result<Vec<Negotiation>> {
+    let mut results = Vec::new();
+
+    for accept in accepts {
+        let request = with_accept(buf, accept);
+        let (response, _) =
+            do_request(client, &request, &RetryPolicy::default()).await?;
+
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let size = response.bytes().await?.len();
+
+        results.push(Negotiation {
+            accept: accept.clone(),
+            status,
+            content_type,
+            size,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Replace the Accept header of a request
+fn with_accept(buf: &str, accept: &str) -> String {
+    let buf = remove_headers(buf, &["Accept".to_string()]);
+    let mut lines = buf.lines();
+
+    // Keep comments before the request line in place
+    let mut output = String::new();
+    for line in lines.by_ref() {
+        output.push_str(line);
+        output.push('\n');
+        if !line.trim().is_empty() && !line.trim_start().starts_with('#') {
+            break;
+        }
+    }
+    output.push_str(&format!("Accept: {accept}\n"));
+    for line in lines {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    output
+}
+
+fn media_type_matches(accept: &str, content_type: &str) -> bool {
+    let mime =
+        |s: &str| s.split(';').next().unwrap_or("").trim().to_lowercase();
+    let (accept, content_type) = (mime(accept), mime(content_type));
+
+    match accept.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((kind, "*")) => content_type.starts_with(&format!("{kind}/")),
+        _ => accept == content_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_short_names() {
+        assert_eq!(
+            accept_values("json, problem+json,application/vnd.api+json"),
+            vec![
+                "application/json",
+                "application/problem+json",
+                "application/vnd.api+json"
+            ]
+        );
+    }
+
+    #[test]
+    fn replaces_accept_header() {
+        let buf = "# List\nGET http://example.com HTTP/1.1\naccept: */*\nX-Trace: 1\n";
+
+        assert_eq!(
+            with_accept(buf, "application/xml"),
+            "# List\nGET http://example.com HTTP/1.1\nAccept: application/xml\nX-Trace: 1\n"
+        );
+    }
+
+    #[test]
+    fn matches_media_types() {
+        assert!(media_type_matches(
+            "application/json",
+            "application/json; charset=utf-8"
+        ));
+        assert!(media_type_matches("text/*", "text/csv"));
+        assert!(media_type_matches("*/*", "image/png"));
+        assert!(!media_type_matches("application/xml", "application/json"));
+    }
+}
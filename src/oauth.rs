@@ -0,0 +1,299 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::{
+    clock::unix_now,
+    env::{
+        find_root_dir, get_settings, is_sensitive_key, read_toml,
+        update_toml_file,
+    },
+    request::build_client,
+    state::{ensure_state_dir, tokens_file},
+    substitute::substitute,
+};
+
+/// Authentication for all requests of a target, such as:
+///
+/// ```toml
+/// [_auth.oauth2]
+/// token_url = "https://auth.example.com/oauth/token"
+/// client_id = "hitman"
+/// client_secret = "{{client_secret}}"
+/// scopes = ["orders:read", "orders:write"]
+/// ```
+pub(crate) const AUTH_KEY: &str = "_auth";
+
+/// The token is available in requests as `{{access_token}}`
+const ACCESS_TOKEN_KEY: &str = "access_token";
+
+/// Tokens are refreshed this many seconds before they expire, so that they
+/// don't expire on the way to the server
+const EXPIRY_MARGIN: u64 = 30;
+
+/// Lifetime of tokens when the server doesn't say
+const DEFAULT_EXPIRES_IN: u64 = 3600;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: Vec<String>,
+}
+
+impl OAuth2Config {
+    /// Tokens are cached for each client of each server, and each set of
+    /// scopes, in any order
+    fn cache_key(&self) -> String {
+        let mut scopes = self.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        format!("{} {} {}", self.token_url, self.client_id, scopes.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    access_token: String,
+    expires_at: u64,
+}
+
+/// The OAuth2 client credentials of a target, if any. Values can refer to
+/// other values, like `client_secret = "{{client_secret}}"`.
+pub fn oauth2_config(env: &Table) -> Result<Option<OAuth2Config>> {
+    let Some(conf) = env.get(AUTH_KEY).and_then(|a| a.get("oauth2")) else {
+        return Ok(None);
+    };
+    let Value::Table(conf) = conf else {
+        bail!("Invalid {AUTH_KEY}.oauth2 section");
+    };
+
+    let string = |key: &str| -> Result<String> {
+        let value = conf
+            .get(key)
+            .and_then(Value::as_str)
+            .with_context(|| format!("Missing {key} in {AUTH_KEY}.oauth2"))?;
+
+        // Substitution works on lines, and ends with a newline
+        Ok(substitute(value, env)?.trim_end().to_string())
+    };
+
+    let scopes = match conf.get("scopes") {
+        None => Vec::new(),
+        Some(Value::String(s)) => {
+            s.split_whitespace().map(String::from).collect()
+        }
+        Some(Value::Array(scopes)) => scopes
+            .iter()
+            .filter_map(Value::as_str)
+            .map(String::from)
+            .collect(),
+        Some(_) => bail!("Invalid scopes in {AUTH_KEY}.oauth2"),
+    };
+
+    Ok(Some(OAuth2Config {
+        token_url: string("token_url")?,
+        client_id: string("client_id")?,
+        client_secret: string("client_secret")?,
+        scopes,
+    }))
+}
+
+/// Add `access_token` to the environment, when the target uses OAuth2. A
+/// cached token is used until it expires, and a new one is fetched with the
+/// client credentials grant otherwise.
+pub async fn with_access_token(env: &Table) -> Result<Table> {
+    let Some(config) = oauth2_config(env)? else {
+        return Ok(env.clone());
+    };
+
+    // Sensitive tokens are never written to disk
+    let persist = !is_sensitive_key(&get_settings(env), ACCESS_TOKEN_KEY);
+    let root_dir = find_root_dir()?;
+    let cache = match &root_dir {
+        Some(root_dir) if persist => {
+            read_toml(&tokens_file(root_dir)).unwrap_or_default()
+        }
+        _ => Table::new(),
+    };
+
+    let token = match cached_token(&cache, &config.cache_key(), unix_now()) {
+        Some(token) => token,
+        None => {
+            let token = fetch_token(env, &config).await?;
+            if let (Some(root_dir), true) = (&root_dir, persist) {
+                ensure_state_dir(root_dir)?;
+                update_toml_file(
+                    &tokens_file(root_dir),
+                    &cache_entry(&config.cache_key(), &token),
+                )?;
+            }
+            token
+        }
+    };
+
+    let mut env = env.clone();
+    env.insert(
+        ACCESS_TOKEN_KEY.to_string(),
+        Value::String(token.access_token),
+    );
+
+    Ok(env)
+}
+
+fn cached_token(cache: &Table, key: &str, now: u64) -> Option<Token> {
+    let entry = cache.get(key)?.as_table()?;
+    let access_token = entry.get("access_token")?.as_str()?.to_string();
+    let expires_at = entry.get("expires_at")?.as_integer()? as u64;
+
+    (now + EXPIRY_MARGIN < expires_at).then_some(Token {
+        access_token,
+        expires_at,
+    })
+}
+
+fn cache_entry(key: &str, token: &Token) -> Table {
+    let mut entry = Table::new();
+    entry.insert(
+        "access_token".to_string(),
+        Value::String(token.access_token.clone()),
+    );
+    entry.insert(
+        "expires_at".to_string(),
+        Value::Integer(token.expires_at as i64),
+    );
+
+    let mut out = Table::new();
+    out.insert(key.to_string(), Value::Table(entry));
+    out
+}
+
+async fn fetch_token(env: &Table, config: &OAuth2Config) -> Result<Token> {
+    info!("# Fetching access token from {}", config.token_url);
+
+    let mut form = vec![
+        ("grant_type", "client_credentials".to_string()),
+        ("client_id", config.client_id.clone()),
+        ("client_secret", config.client_secret.clone()),
+    ];
+    if !config.scopes.is_empty() {
+        form.push(("scope", config.scopes.join(" ")));
+    }
+
+    let response = build_client(env)?
+        .post(&config.token_url)
+        .form(&form)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("Token request failed with {status}: {body}");
+    }
+
+    parse_token_response(&body, unix_now())
+}
+
+fn parse_token_response(body: &str, now: u64) -> Result<Token> {
+    let json: JsonValue =
+        serde_json::from_str(body).context("Invalid token response")?;
+
+    let access_token = json
+        .get("access_token")
+        .and_then(JsonValue::as_str)
+        .context("No access_token in token response")?
+        .to_string();
+    let expires_in = json
+        .get("expires_in")
+        .and_then(|v| v.as_u64().or_else(|| v.as_str()?.parse().ok()))
+        .unwrap_or(DEFAULT_EXPIRES_IN);
+
+    Ok(Token {
+        access_token,
+        expires_at: now + expires_in,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_oauth2_config() {
+        let env: Table = toml::from_str(
+            r#"
+            secret = "s3cret"
+
+            [_auth.oauth2]
+            token_url = "https://auth.example.com/token"
+            client_id = "hitman"
+            client_secret = "{{secret}}"
+            scopes = ["read", "write"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            oauth2_config(&env).unwrap(),
+            Some(OAuth2Config {
+                token_url: "https://auth.example.com/token".into(),
+                client_id: "hitman".into(),
+                client_secret: "s3cret".into(),
+                scopes: vec!["read".into(), "write".into()],
+            })
+        );
+        assert_eq!(oauth2_config(&Table::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_token_responses() {
+        let body = r#"{"access_token": "abc", "token_type": "Bearer", "expires_in": 300}"#;
+
+        assert_eq!(
+            parse_token_response(body, 1000).unwrap(),
+            Token {
+                access_token: "abc".into(),
+                expires_at: 1300,
+            }
+        );
+        assert!(
+            parse_token_response(r#"{"error": "invalid_client"}"#, 0).is_err()
+        );
+    }
+
+    #[test]
+    fn uses_cached_tokens_until_they_expire() {
+        let token = Token {
+            access_token: "abc".into(),
+            expires_at: 1300,
+        };
+        let cache = cache_entry("key", &token);
+
+        assert_eq!(cached_token(&cache, "key", 1000), Some(token));
+        assert_eq!(cached_token(&cache, "key", 1280), None);
+        assert_eq!(cached_token(&cache, "other", 1000), None);
+    }
+
+    #[test]
+    fn caches_tokens_for_each_set_of_scopes() {
+        let config = |scopes: &[&str]| OAuth2Config {
+            token_url: "https://auth.example.com/token".into(),
+            client_id: "hitman".into(),
+            client_secret: "s3cret".into(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+
+        assert_eq!(
+            config(&["read", "write"]).cache_key(),
+            config(&["write", "read"]).cache_key()
+        );
+        assert_ne!(
+            config(&["read"]).cache_key(),
+            config(&["read", "write"]).cache_key()
+        );
+    }
+}
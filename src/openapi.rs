@@ -0,0 +1,540 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Number, Value as JsonValue};
+use toml::{Table, Value};
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::import::{file_stem, Imported};
+
+const METHODS: &[&str] =
+    &["get", "put", "post", "delete", "patch", "head", "options"];
+
+/// Schemas are only followed this deep, since they may refer to themselves
+const MAX_EXAMPLE_DEPTH: usize = 8;
+
+/// Convert an OpenAPI 3 or Swagger 2 spec, in YAML or JSON, to a request
+/// file for each operation, in a directory for its first tag. Each server
+/// becomes a target, with its URL as `base_url`.
+pub fn import_openapi(spec: &Path) -> Result<Imported> {
+    let data = fs::read_to_string(spec)
+        .with_context(|| format!("Failed to read {}", spec.display()))?;
+
+    let spec = if spec.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&data)?
+    } else {
+        let docs = YamlLoader::load_from_str(&data)?;
+        yaml_to_json(docs.first().context("Empty spec")?)
+    };
+
+    convert_spec(&spec)
+}
+
+pub fn convert_spec(spec: &JsonValue) -> Result<Imported> {
+    let Some(paths) = spec.get("paths").and_then(JsonValue::as_object) else {
+        bail!("Not an OpenAPI spec, expected paths");
+    };
+
+    let mut imported = Imported {
+        config: servers(spec),
+        ..Default::default()
+    };
+
+    for (path, item) in paths {
+        let shared_parameters = item.get("parameters");
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+
+            let name = operation
+                .get("operationId")
+                .and_then(JsonValue::as_str)
+                .map(String::from)
+                .unwrap_or_else(|| format!("{method} {path}"));
+            let dir = operation
+                .pointer("/tags/0")
+                .and_then(JsonValue::as_str)
+                .map(file_stem)
+                .unwrap_or_default();
+
+            let content = convert_operation(
+                spec,
+                method,
+                path,
+                operation,
+                shared_parameters,
+            );
+            imported.add_request(Path::new(&dir), &name, content);
+        }
+    }
+
+    Ok(imported)
+}
+
+/// A target for each server, or the host of a Swagger 2 spec
+fn servers(spec: &JsonValue) -> Table {
+    let mut urls = Vec::new();
+
+    if let Some(servers) = spec.get("servers").and_then(JsonValue::as_array) {
+        for server in servers {
+            let Some(mut url) = server
+                .get("url")
+                .and_then(JsonValue::as_str)
+                .map(String::from)
+            else {
+                continue;
+            };
+            // Server variables, like `https://{region}.example.com`
+            if let Some(variables) =
+                server.get("variables").and_then(JsonValue::as_object)
+            {
+                for (name, variable) in variables {
+                    let default = variable
+                        .get("default")
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or_default();
+                    url = url.replace(&format!("{{{name}}}"), default);
+                }
+            }
+            let description = server
+                .get("description")
+                .and_then(JsonValue::as_str)
+                .map(file_stem);
+            urls.push((description, url.trim_end_matches('/').to_string()));
+        }
+    } else if let Some(host) = spec.get("host").and_then(JsonValue::as_str) {
+        let scheme = spec
+            .pointer("/schemes/0")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("https");
+        let base_path = spec
+            .get("basePath")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default();
+        let url = format!("{scheme}://{host}{base_path}");
+        urls.push((None, url.trim_end_matches('/').to_string()));
+    }
+
+    let mut config = Table::new();
+    for (i, (description, url)) in urls.into_iter().enumerate() {
+        let name = match description {
+            _ if i == 0 => "default".to_string(),
+            Some(name) if !config.contains_key(&name) => name,
+            _ => format!("server_{}", i + 1),
+        };
+        let mut target = Table::new();
+        target.insert("base_url".into(), Value::String(url));
+        config.insert(name, Value::Table(target));
+    }
+    // The config needs at least one target
+    if config.is_empty() {
+        config.insert("default".into(), Value::Table(Table::new()));
+    }
+
+    config
+}
+
+fn convert_operation(
+    spec: &JsonValue,
+    method: &str,
+    path: &str,
+    operation: &JsonValue,
+    shared_parameters: Option<&JsonValue>,
+) -> String {
+    let parameters: Vec<&JsonValue> =
+        [shared_parameters, operation.get("parameters")]
+            .into_iter()
+            .flatten()
+            .filter_map(JsonValue::as_array)
+            .flatten()
+            .map(|p| resolve(spec, p))
+            .collect();
+    let required = |p: &&JsonValue| {
+        p.get("required").and_then(JsonValue::as_bool) == Some(true)
+    };
+    let is_in = |p: &JsonValue, location: &str| {
+        p.get("in").and_then(JsonValue::as_str) == Some(location)
+    };
+    let name = |p: &JsonValue| {
+        p.get("name")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    // Path parameters are written the same way as variables
+    let mut url = format!(
+        "{{{{base_url}}}}{}",
+        path.replace('{', "{{").replace('}', "}}")
+    );
+
+    let query: Vec<String> = parameters
+        .iter()
+        .copied()
+        .filter(|p| is_in(p, "query"))
+        .filter(required)
+        .map(|p| format!("{0}={{{{{0}}}}}", name(p)))
+        .collect();
+    if !query.is_empty() {
+        url = format!("{url}?{}", query.join("&"));
+    }
+
+    let mut content = format!("{} {url} HTTP/1.1\n", method.to_uppercase());
+    for p in parameters
+        .iter()
+        .copied()
+        .filter(|p| is_in(p, "header"))
+        .filter(required)
+    {
+        content.push_str(&format!("{0}: {{{{{0}}}}}\n", name(p)));
+    }
+
+    if let Some((content_type, body)) =
+        request_body(spec, operation, &parameters)
+    {
+        content.push_str(&format!("Content-Type: {content_type}\n\n{body}\n"));
+    }
+
+    content
+}
+
+/// The content type and an example body, preferring JSON
+fn request_body(
+    spec: &JsonValue,
+    operation: &JsonValue,
+    parameters: &[&JsonValue],
+) -> Option<(String, String)> {
+    let (content_type, media) = match operation.get("requestBody") {
+        Some(body) => {
+            let content = resolve(spec, body).get("content")?.as_object()?;
+            content
+                .iter()
+                .find(|(t, _)| t.contains("json"))
+                .or_else(|| content.iter().next())
+                .map(|(t, media)| (t.clone(), media.clone()))?
+        }
+        // Swagger 2 has the body as a parameter
+        None => {
+            let body = parameters.iter().find(|p| {
+                p.get("in").and_then(JsonValue::as_str) == Some("body")
+            })?;
+            ("application/json".to_string(), (*body).clone())
+        }
+    };
+
+    let example = media
+        .get("example")
+        .cloned()
+        .or_else(|| {
+            let examples = media.get("examples")?.as_object()?;
+            let example = resolve(spec, examples.values().next()?);
+            example.get("value").cloned()
+        })
+        .or_else(|| Some(example_value(spec, media.get("schema")?, 0)))?;
+
+    let body = match example {
+        JsonValue::String(text) if !content_type.contains("json") => text,
+        example => serde_json::to_string_pretty(&example).ok()?,
+    };
+
+    Some((content_type, body))
+}
+
+/// Follow a `$ref` within the spec, like `#/components/schemas/Order`
+fn resolve<'a>(spec: &'a JsonValue, value: &'a JsonValue) -> &'a JsonValue {
+    let mut value = value;
+    // Refs may point to other refs
+    for _ in 0..MAX_EXAMPLE_DEPTH {
+        match value
+            .get("$ref")
+            .and_then(JsonValue::as_str)
+            .and_then(|r| r.strip_prefix('#'))
+            .and_then(|pointer| spec.pointer(pointer))
+        {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+
+    value
+}
+
+/// An example of a value of a schema, from its examples and defaults where
+/// there are any
+fn example_value(
+    spec: &JsonValue,
+    schema: &JsonValue,
+    depth: usize,
+) -> JsonValue {
+    let schema = resolve(spec, schema);
+    if depth > MAX_EXAMPLE_DEPTH {
+        return JsonValue::Null;
+    }
+
+    for key in ["example", "default"] {
+        if let Some(value) = schema.get(key) {
+            return value.clone();
+        }
+    }
+    if let Some(value) = schema.pointer("/examples/0") {
+        return value.clone();
+    }
+    if let Some(value) = schema.pointer("/enum/0") {
+        return value.clone();
+    }
+
+    if let Some(all_of) = schema.get("allOf").and_then(JsonValue::as_array) {
+        let mut object = Map::new();
+        for part in all_of {
+            if let JsonValue::Object(part) =
+                example_value(spec, part, depth + 1)
+            {
+                object.extend(part);
+            }
+        }
+        return JsonValue::Object(object);
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(first) = schema.get(key).and_then(|s| s.get(0)) {
+            return example_value(spec, first, depth + 1);
+        }
+    }
+
+    let kind = match schema.get("type") {
+        Some(JsonValue::String(kind)) => kind.as_str(),
+        // OpenAPI 3.1 allows a list, like ["string", "null"]
+        Some(JsonValue::Array(kinds)) => kinds
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .find(|k| *k != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ => "string",
+    };
+
+    match kind {
+        "object" => {
+            let mut object = Map::new();
+            if let Some(properties) =
+                schema.get("properties").and_then(JsonValue::as_object)
+            {
+                for (name, property) in properties {
+                    object.insert(
+                        name.clone(),
+                        example_value(spec, property, depth + 1),
+                    );
+                }
+            }
+            JsonValue::Object(object)
+        }
+        "array" => match schema.get("items") {
+            Some(items) => json!([example_value(spec, items, depth + 1)]),
+            None => json!([]),
+        },
+        "integer" => json!(0),
+        "number" => json!(0.0),
+        "boolean" => json!(false),
+        "null" => JsonValue::Null,
+        _ => {
+            let format = schema.get("format").and_then(JsonValue::as_str);
+            json!(match format {
+                Some("date-time") => "2024-01-01T00:00:00Z",
+                Some("date") => "2024-01-01",
+                Some("uuid") => "00000000-0000-0000-0000-000000000000",
+                Some("email") => "user@example.com",
+                Some("uri") => "https://example.com",
+                _ => "string",
+            })
+        }
+    }
+}
+
+fn yaml_to_json(yaml: &Yaml) -> JsonValue {
+    match yaml {
+        Yaml::Real(s) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map_or(JsonValue::Null, JsonValue::Number),
+        Yaml::Integer(n) => json!(n),
+        Yaml::String(s) => json!(s),
+        Yaml::Boolean(b) => json!(b),
+        Yaml::Array(values) => {
+            JsonValue::Array(values.iter().map(yaml_to_json).collect())
+        }
+        Yaml::Hash(hash) => JsonValue::Object(
+            hash.iter()
+                .map(|(key, value)| {
+                    // Keys like response codes are numbers in YAML
+                    let key = match key {
+                        Yaml::String(s) => s.clone(),
+                        Yaml::Integer(n) => n.to_string(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        Yaml::Real(s) => s.clone(),
+                        _ => String::new(),
+                    };
+                    (key, yaml_to_json(value))
+                })
+                .collect(),
+        ),
+        Yaml::Null | Yaml::BadValue | Yaml::Alias(_) => JsonValue::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+openapi: 3.0.0
+servers:
+  - url: https://{region}.shop.test/v1/
+    variables:
+      region:
+        default: eu
+  - url: http://localhost:8080/v1
+    description: Local
+paths:
+  /orders/{orderId}:
+    parameters:
+      - name: orderId
+        in: path
+        required: true
+    get:
+      operationId: getOrder
+      tags: [Orders]
+      parameters:
+        - name: expand
+          in: query
+          required: true
+        - name: page
+          in: query
+        - $ref: '#/components/parameters/Tenant'
+      responses:
+        200:
+          description: OK
+  /orders:
+    post:
+      tags: [Orders]
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: '#/components/schemas/Order'
+components:
+  parameters:
+    Tenant:
+      name: X-Tenant
+      in: header
+      required: true
+  schemas:
+    Order:
+      type: object
+      properties:
+        id:
+          type: integer
+        status:
+          type: string
+          enum: [new, shipped]
+        created:
+          type: string
+          format: date-time
+        items:
+          type: array
+          items:
+            type: object
+            properties:
+              sku: { type: string, example: A-1 }
+"#;
+
+    fn spec() -> JsonValue {
+        yaml_to_json(&YamlLoader::load_from_str(SPEC).unwrap()[0])
+    }
+
+    #[test]
+    fn converts_operations() {
+        let imported = convert_spec(&spec()).unwrap();
+
+        let files: Vec<_> = imported
+            .requests
+            .iter()
+            .map(|r| (r.path.display().to_string(), r.content.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (
+                    "orders/post_orders.http".to_string(),
+                    "POST {{base_url}}/orders HTTP/1.1\n\
+                     Content-Type: application/json\n\
+                     \n\
+                     {\n  \
+                       \"created\": \"2024-01-01T00:00:00Z\",\n  \
+                       \"id\": 0,\n  \
+                       \"items\": [\n    {\n      \"sku\": \"A-1\"\n    }\n  ],\n  \
+                       \"status\": \"new\"\n\
+                     }\n"
+                ),
+                (
+                    "orders/get_order.http".to_string(),
+                    "GET {{base_url}}/orders/{{orderId}}?expand={{expand}} HTTP/1.1\n\
+                     X-Tenant: {{X-Tenant}}\n"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn makes_targets_of_servers() {
+        let imported = convert_spec(&spec()).unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            [default]
+            base_url = "https://eu.shop.test/v1"
+
+            [local]
+            base_url = "http://localhost:8080/v1"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(imported.config, expected);
+    }
+
+    #[test]
+    fn reads_swagger_hosts() {
+        let spec = json!({
+            "swagger": "2.0",
+            "host": "api.shop.test",
+            "basePath": "/v2",
+            "schemes": ["http"],
+            "paths": {
+                "/pets": {
+                    "post": {
+                        "operationId": "addPet",
+                        "parameters": [{
+                            "in": "body",
+                            "name": "pet",
+                            "schema": {"properties": {"name": {"type": "string"}}}
+                        }]
+                    }
+                }
+            }
+        });
+
+        let imported = convert_spec(&spec).unwrap();
+
+        assert_eq!(
+            imported.config["default"]["base_url"].as_str(),
+            Some("http://api.shop.test/v2")
+        );
+        assert_eq!(
+            imported.requests[0].content,
+            "POST {{base_url}}/pets HTTP/1.1\n\
+             Content-Type: application/json\n\
+             \n\
+             {\n  \"name\": \"string\"\n}\n"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use toml::{Table, Value};
+
+use crate::{
+    clock::unix_now,
+    directive::{Directives, OUTPUT},
+    substitute::substitute,
+};
+
+/// Where the body of a response is written, given in the request with a line
+/// like `# @output ./responses/{{request_name}}-{{timestamp}}.json`. The path
+/// is relative to the request file.
+pub fn output_directive(
+    file_path: &Path,
+    directives: &Directives,
+) -> Option<PathBuf> {
+    let template = directives.value(OUTPUT)?;
+
+    let dir = file_path.parent().unwrap_or(Path::new(""));
+    Some(dir.join(template))
+}
+
+/// The output path of a request file. It is read from the file as written,
+/// since the path is substituted when the response arrives, and not with the
+/// request.
+pub fn output_file(file_path: &Path) -> Result<Option<PathBuf>> {
+    let directives = Directives::parse(&fs::read_to_string(file_path)?)?;
+
+    Ok(output_directive(file_path, &directives))
+}
+
+/// Write the body of a response to the path of a template, as it was
+/// received. Besides the values of the request, the template can use the
+/// `name` of the request file, the `status` of the response, and the
+/// `timestamp` when it arrived.
+pub fn save_response(
+    template: &Path,
+    file_path: &Path,
+    env: &Table,
+    status: u16,
+    data: &[u8],
+) -> Result<PathBuf> {
+    let path = output_path(template, file_path, env, status)?;
+
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, data)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    warn!("# Saved {} bytes to {}", data.len(), path.display());
+
+    Ok(path)
+}
+
+fn output_path(
+    template: &Path,
+    file_path: &Path,
+    env: &Table,
+    status: u16,
+) -> Result<PathBuf> {
+    let mut scope = env.clone();
+
+    let request_name = file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    scope.insert("request_name".to_string(), Value::String(request_name));
+    scope.insert("status".to_string(), Value::Integer(status.into()));
+    scope.insert("timestamp".to_string(), Value::Integer(unix_now() as i64));
+
+    let path = substitute(&template.to_string_lossy(), &scope)?;
+
+    Ok(PathBuf::from(path.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_output_directives() {
+        let buf =
+            "# @output out/{{request_name}}.json\nGET http://example.com\n";
+        let file = Path::new("api/orders/get.http");
+        let directives = |buf| Directives::parse(buf).unwrap();
+
+        assert_eq!(
+            output_directive(file, &directives(buf)),
+            Some(PathBuf::from("api/orders/out/{{request_name}}.json"))
+        );
+        assert_eq!(
+            output_directive(file, &directives("GET http://example.com")),
+            None
+        );
+    }
+
+    #[test]
+    fn substitutes_output_paths() {
+        let env: Table = toml::from_str("order_id = 42").unwrap();
+        let path = |template| {
+            output_path(Path::new(template), Path::new("get.http"), &env, 200)
+                .unwrap()
+        };
+
+        assert_eq!(
+            path("out/{{request_name}}-{{order_id}}-{{status}}.json"),
+            PathBuf::from("out/get-42-200.json")
+        );
+        assert_ne!(path("{{timestamp}}.json"), PathBuf::from(".json"));
+    }
+
+    #[test]
+    fn uses_name_values_in_output_paths() {
+        let env: Table = toml::from_str("name = \"foo\"").unwrap();
+
+        let path = output_path(
+            Path::new("out-{{name}}-{{timestamp}}.json"),
+            Path::new("get.http"),
+            &env,
+            200,
+        )
+        .unwrap();
+
+        let path = path.to_string_lossy();
+        assert!(path.starts_with("out-foo-"), "{path}");
+        assert_ne!(path, "out-foo-.json");
+    }
+}
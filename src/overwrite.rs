@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use log::warn;
+use toml::{Table, Value};
+
+use crate::{prompt::UserInteraction, util::truncate};
+
+/// What happens when an extracted value would replace a stored value, given
+/// for all keys or per key in `[_settings]`:
+///
+/// ```toml
+/// [_settings.on_overwrite]
+/// _default = "log"
+/// access_token = "ask"
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OverwritePolicy {
+    /// Replace the stored value
+    #[default]
+    Replace,
+    /// Replace the stored value, and show both values
+    Log,
+    /// Ask whether to replace or keep the stored value. When nobody can be
+    /// asked, the value is replaced and both values are shown.
+    Ask,
+    /// Keep the stored value, and only store values that are missing
+    Keep,
+}
+
+impl FromStr for OverwritePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "replace" => Ok(Self::Replace),
+            "log" => Ok(Self::Log),
+            "ask" => Ok(Self::Ask),
+            "keep" => Ok(Self::Keep),
+            _ => bail!(
+                "Unknown on_overwrite `{s}`, expected replace, log, ask or keep"
+            ),
+        }
+    }
+}
+
+/// The policy for a key, from `on_overwrite` in the settings. It's either
+/// one policy for every key, or a table of policies per key, with the
+/// policy for other keys in `_default`.
+pub fn overwrite_policy(
+    settings: &Table,
+    key: &str,
+) -> Result<OverwritePolicy> {
+    let policy = match settings.get("on_overwrite") {
+        Some(Value::String(policy)) => Some(policy.as_str()),
+        Some(Value::Table(policies)) => policies
+            .get(key)
+            .or_else(|| policies.get("_default"))
+            .and_then(Value::as_str),
+        _ => None,
+    };
+
+    policy.map_or(Ok(OverwritePolicy::default()), |p| p.parse())
+}
+
+/// The values to store, after resolving the values that would replace
+/// stored values with different ones
+pub fn resolve_overwrites(
+    settings: &Table,
+    stored: &Table,
+    vars: &Table,
+    interaction: &dyn UserInteraction,
+) -> Result<Table> {
+    let mut out = Table::new();
+
+    for (key, new) in vars {
+        let Some(old) = stored.get(key).filter(|old| *old != new) else {
+            out.insert(key.clone(), new.clone());
+            continue;
+        };
+
+        let replace = match overwrite_policy(settings, key)? {
+            OverwritePolicy::Replace => true,
+            OverwritePolicy::Log => {
+                log_change("Replacing", key, old, new);
+                true
+            }
+            OverwritePolicy::Keep => {
+                log_change("Keeping", key, old, new);
+                false
+            }
+            OverwritePolicy::Ask => {
+                let message = truncate(&format!(
+                    "Replace {key} = {} with {}?",
+                    display_value(old),
+                    display_value(new)
+                ));
+                let options = ["Replace".to_string(), "Keep".to_string()];
+                match interaction.choose(&message, &options)? {
+                    Some(choice) => choice == 0,
+                    None => {
+                        log_change("Replacing", key, old, new);
+                        true
+                    }
+                }
+            }
+        };
+
+        if replace {
+            out.insert(key.clone(), new.clone());
+        }
+    }
+
+    Ok(out)
+}
+
+fn log_change(action: &str, key: &str, old: &Value, new: &Value) {
+    warn!("# {action} '{key}' = '{}'", truncate(&display_value(old)));
+    warn!("#   extracted '{}'", truncate(&display_value(new)));
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::NoUserInteraction;
+
+    use super::*;
+
+    fn settings() -> Table {
+        toml::from_str(
+            r#"
+            [on_overwrite]
+            _default = "keep"
+            token = "ask"
+            session = "replace"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_overwrite_policies() {
+        let settings = settings();
+        let policy = |key| overwrite_policy(&settings, key).unwrap();
+
+        assert_eq!(policy("token"), OverwritePolicy::Ask);
+        assert_eq!(policy("user_id"), OverwritePolicy::Keep);
+        assert_eq!(
+            overwrite_policy(&Table::new(), "token").unwrap(),
+            OverwritePolicy::Replace
+        );
+
+        let settings = toml::from_str("on_overwrite = \"never\"").unwrap();
+        assert!(overwrite_policy(&settings, "token").is_err());
+    }
+
+    #[test]
+    fn keeps_or_replaces_stored_values() {
+        let stored: Table =
+            toml::from_str("token = \"a\"\nuser_id = 1\nsession = \"x\"")
+                .unwrap();
+        let vars: Table = toml::from_str(
+            "token = \"b\"\nuser_id = 2\nsession = \"y\"\norder_id = 3",
+        )
+        .unwrap();
+
+        let out =
+            resolve_overwrites(&settings(), &stored, &vars, &NoUserInteraction)
+                .unwrap();
+
+        // Nobody is asked without a terminal, so the token is replaced
+        assert_eq!(
+            out,
+            toml::from_str("token = \"b\"\nsession = \"y\"\norder_id = 3")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, bail, Result};
+use jsonpath::Selector;
+use reqwest::Url;
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+/// How to get the next and previous page of a list endpoint, usually given
+/// in the `.http.toml` file of the request:
+///
+/// ```toml
+/// [_pagination]
+/// param = "cursor"
+/// next = "$.meta.next_cursor"
+/// prev = "$.meta.prev_cursor"
+/// ```
+///
+/// Without `next` and `prev`, the parameter is a page number, counted up and
+/// down from the one in the request.
+const PAGINATION_KEY: &str = "_pagination";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pagination {
+    /// The query parameter holding the page number or cursor
+    pub param: String,
+    /// JSONPath of the next cursor, or URL, in the response
+    pub next: Option<String>,
+    /// JSONPath of the previous cursor, or URL, in the response
+    pub prev: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Page {
+    Next,
+    Prev,
+}
+
+impl Pagination {
+    pub fn from_env(env: &Table) -> Result<Option<Self>> {
+        let Some(value) = env.get(PAGINATION_KEY) else {
+            return Ok(None);
+        };
+        let Value::Table(table) = value else {
+            bail!("Invalid {PAGINATION_KEY}: {value}");
+        };
+
+        let string = |key: &str| table.get(key).and_then(Value::as_str);
+
+        Ok(Some(Self {
+            param: string("param").unwrap_or("page").to_string(),
+            next: string("next").map(String::from),
+            prev: string("prev").map(String::from),
+        }))
+    }
+
+    /// The request for another page, given the request and response of the
+    /// current page. Fails when there is no such page.
+    pub fn page_request(
+        &self,
+        request: &str,
+        body: &str,
+        page: Page,
+    ) -> Result<String> {
+        let path = match page {
+            Page::Next => &self.next,
+            Page::Prev => &self.prev,
+        };
+
+        let value = match path {
+            Some(path) => {
+                let json: JsonValue = serde_json::from_str(body)
+                    .map_err(|_| anyhow!("The response is not JSON"))?;
+                match find_value(&json, path)? {
+                    Some(value) => value,
+                    None => bail!("No {} page", page_name(page)),
+                }
+            }
+            None => {
+                let current = query_param(request, &self.param)?
+                    .map(|v| v.parse::<i64>())
+                    .transpose()
+                    .map_err(|_| anyhow!("{} is not a number", self.param))?
+                    .unwrap_or(1);
+                match page {
+                    Page::Next => current + 1,
+                    Page::Prev if current > 1 => current - 1,
+                    Page::Prev => bail!("Already on the first page"),
+                }
+                .to_string()
+            }
+        };
+
+        // Some APIs give the whole URL of the next page
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return replace_url(request, &value);
+        }
+
+        set_query_param(request, &self.param, &value)
+    }
+}
+
+fn page_name(page: Page) -> &'static str {
+    match page {
+        Page::Next => "next",
+        Page::Prev => "previous",
+    }
+}
+
+fn find_value(json: &JsonValue, path: &str) -> Result<Option<String>> {
+    let selector = Selector::new(path)
+        .map_err(|err| anyhow!("Invalid jsonpath: {err}"))?;
+
+    Ok(match selector.find(json).next() {
+        Some(JsonValue::String(s)) if !s.is_empty() => Some(s.clone()),
+        Some(JsonValue::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Split the request line into method, URL and version
+fn request_line(request: &str) -> Result<(&str, Url, &str)> {
+    let line = request.lines().next().unwrap_or_default();
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(url)) = (parts.next(), parts.next()) else {
+        bail!("Invalid input: URL not found");
+    };
+
+    Ok((method, Url::parse(url)?, parts.next().unwrap_or("HTTP/1.1")))
+}
+
+fn query_param(request: &str, param: &str) -> Result<Option<String>> {
+    let (_, url, _) = request_line(request)?;
+
+    Ok(url
+        .query_pairs()
+        .find(|(name, _)| name == param)
+        .map(|(_, value)| value.to_string()))
+}
+
+/// The request with a query parameter set to a value
+fn set_query_param(request: &str, param: &str, value: &str) -> Result<String> {
+    let (_, mut url, _) = request_line(request)?;
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != param)
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair(param, value);
+
+    replace_url(request, url.as_str())
+}
+
+/// The request with another URL, keeping the method, headers and body
+fn replace_url(request: &str, url: &str) -> Result<String> {
+    let (method, _, version) = request_line(request)?;
+
+    let mut out = format!("{method} {url} {version}\n");
+    for line in request.lines().skip(1) {
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pagination(config: &str) -> Pagination {
+        let env: Table = toml::from_str(config).unwrap();
+        Pagination::from_env(&env).unwrap().unwrap()
+    }
+
+    #[test]
+    fn counts_page_numbers() {
+        let pages = pagination("[_pagination]\nparam = \"page\"");
+        let request = "GET http://example.com/orders?page=2&size=10 HTTP/1.1\nAccept: application/json\n";
+
+        assert_eq!(
+            pages.page_request(request, "", Page::Next).unwrap(),
+            "GET http://example.com/orders?size=10&page=3 HTTP/1.1\nAccept: application/json\n"
+        );
+        assert_eq!(
+            pages.page_request(request, "", Page::Prev).unwrap(),
+            "GET http://example.com/orders?size=10&page=1 HTTP/1.1\nAccept: application/json\n"
+        );
+
+        let first = "GET http://example.com/orders HTTP/1.1\n";
+        assert!(pages.page_request(first, "", Page::Prev).is_err());
+        assert_eq!(
+            pages.page_request(first, "", Page::Next).unwrap(),
+            "GET http://example.com/orders?page=2 HTTP/1.1\n"
+        );
+    }
+
+    #[test]
+    fn follows_cursors_in_response() {
+        let pages = pagination(
+            "[_pagination]\nparam = \"cursor\"\nnext = \"$.next\"\nprev = \"$.prev\"",
+        );
+        let request = "GET http://example.com/orders HTTP/1.1\n";
+
+        assert_eq!(
+            pages
+                .page_request(
+                    request,
+                    r#"{"next": "abc", "prev": null}"#,
+                    Page::Next
+                )
+                .unwrap(),
+            "GET http://example.com/orders?cursor=abc HTTP/1.1\n"
+        );
+        assert!(pages
+            .page_request(
+                request,
+                r#"{"next": "abc", "prev": null}"#,
+                Page::Prev
+            )
+            .is_err());
+        assert_eq!(
+            pages
+                .page_request(
+                    request,
+                    r#"{"next": "http://example.com/orders?after=9"}"#,
+                    Page::Next
+                )
+                .unwrap(),
+            "GET http://example.com/orders?after=9 HTTP/1.1\n"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures::future::join_all;
+use reqwest::Client;
+use toml::{Table, Value};
+
+use crate::{
+    env::{find_environments, load_target_env, read_and_merge_config},
+    request::USER_AGENT,
+    substitute::substitute,
+    util::duration_value,
+};
+
+const PING_KEY: &str = "_ping";
+const DEFAULT_URL: &str = "{{base_url}}/health";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_VERSION_HEADERS: &[&str] =
+    &["x-version", "x-app-version", "x-api-version"];
+
+/// How to check the health of targets, from the `[_ping]` table in the
+/// config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PingConfig {
+    /// Template for the health endpoint, substituted for each target
+    pub url: String,
+    pub timeout: Duration,
+    /// Response headers that may contain the version of the service
+    pub version_headers: Vec<String>,
+}
+
+impl PingConfig {
+    pub fn load(root_dir: &Path) -> Result<Self> {
+        let config = read_and_merge_config(root_dir)?;
+
+        Ok(match config.get(PING_KEY) {
+            Some(Value::Table(ping)) => Self::from_table(ping),
+            _ => Self::from_table(&Table::new()),
+        })
+    }
+
+    fn from_table(ping: &Table) -> Self {
+        let version_headers = match ping.get("version_header") {
+            Some(Value::String(h)) => vec![h.to_lowercase()],
+            _ => DEFAULT_VERSION_HEADERS
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+        };
+
+        Self {
+            url: ping
+                .get("url")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_URL)
+                .to_string(),
+            timeout: ping
+                .get("timeout")
+                .and_then(duration_value)
+                .unwrap_or(DEFAULT_TIMEOUT),
+            version_headers,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PingStatus {
+    Up(u16),
+    Down(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PingResult {
+    pub target: String,
+    pub status: PingStatus,
+    pub latency: Option<Duration>,
+    pub version: Option<String>,
+}
+
+/// Check the health endpoint of every target concurrently
+pub async fn ping_all(root_dir: &Path) -> Result<Vec<PingResult>> {
+    let config = PingConfig::load(root_dir)?;
+
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(config.timeout)
+        .build()?;
+
+    let targets = find_environments(root_dir)?;
+    let pings = targets.into_iter().map(|target| {
+        let client = &client;
+        let config = &config;
+        async move {
+            match load_target_env(root_dir, &target) {
+                Ok(env) => ping(client, config, target, &env).await,
+                Err(err) => PingResult {
+                    target,
+                    status: PingStatus::Down(err.to_string()),
+                    latency: None,
+                    version: None,
+                },
+            }
+        }
+    });
+
+    Ok(join_all(pings).await)
+}
+
+async fn ping(
+    client: &Client,
+    config: &PingConfig,
+    target: String,
+    env: &Table,
+) -> PingResult {
+    let mut result = PingResult {
+        target,
+        status: PingStatus::Down(String::new()),
+        latency: None,
+        version: None,
+    };
+
+    let url = match substitute(&config.url, env) {
+        Ok(url) => url.trim().to_string(),
+        Err(err) => {
+            result.status = PingStatus::Down(err.to_string());
+            return result;
+        }
+    };
+
+    let t = Instant::now();
+    match client.get(&url).send().await {
+        Ok(response) => {
+            result.latency = Some(t.elapsed());
+            result.version = config.version_headers.iter().find_map(|h| {
+                response.headers().get(h)?.to_str().ok().map(String::from)
+            });
+
+            let status = response.status();
+            result.status = if status.is_success() {
+                PingStatus::Up(status.as_u16())
+            } else {
+                PingStatus::Down(status.to_string())
+            };
+        }
+        Err(err) if err.is_timeout() => {
+            result.status = PingStatus::Down("timeout".into());
+        }
+        Err(err) if err.is_connect() => {
+            result.status = PingStatus::Down("connection failed".into());
+        }
+        Err(err) => {
+            result.status = PingStatus::Down(err.to_string());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_defaults_without_ping_config() {
+        let config = PingConfig::from_table(&Table::new());
+
+        assert_eq!(config.url, "{{base_url}}/health");
+        assert_eq!(config.timeout, Duration::from_secs(5));
+        assert!(config.version_headers.contains(&"x-version".to_string()));
+    }
+
+    #[test]
+    fn reads_ping_config() {
+        let table: Table = toml::from_str(
+            r#"
+            url = "{{api_url}}/status"
+            timeout = "2s"
+            version_header = "X-Build"
+            "#,
+        )
+        .unwrap();
+
+        let config = PingConfig::from_table(&table);
+
+        assert_eq!(
+            config,
+            PingConfig {
+                url: "{{api_url}}/status".into(),
+                timeout: Duration::from_secs(2),
+                version_headers: vec!["x-build".into()],
+            }
+        );
+    }
+}
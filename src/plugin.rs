@@ -0,0 +1,545 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value as JsonValue};
+use toml::{Table, Value};
+use wasmtime::{Config, Engine, Instance, Module, Store};
+
+use crate::{
+    assertions::ResponseInfo,
+    env::{find_root_dir, user_config_dir},
+};
+
+/// Plugins are given in the config, such as:
+///
+/// ```toml
+/// [_plugins]
+/// vault = "plugins/vault.wasm"
+///
+/// [_plugins.sigv4]
+/// module = "plugins/sigv4.wasm"
+/// request_hook = true
+/// ```
+///
+/// An installed plugin is used by its name, without a module, like
+/// `[_plugins] vault = {}`.
+pub(crate) const PLUGINS_KEY: &str = "_plugins";
+
+/// Installed plugins are kept in this directory, in the user config dir
+const PLUGINS_DIR: &str = "plugins";
+
+/// A call is stopped after this many units of work, so that a plugin that
+/// never answers doesn't hang hitman
+const FUEL: u64 = 100_000_000;
+
+/// A WebAssembly module that extends hitman. Plugins import nothing, so they
+/// can only compute answers, and don't need to be trusted like commands.
+///
+/// The module exports its `memory`, and `alloc(len: i32) -> i32`, giving
+/// room for a message of `len` bytes. Each hook is an export taking a JSON
+/// message at `(ptr: i32, len: i32)`, and returning where its JSON answer is
+/// as an `i64` of `ptr << 32 | len`:
+///
+/// - `hitman_value`: `{"args"}` → `{"value"}`, for `{{plugin name args}}`
+/// - `hitman_request`: `{"request"}` → `{"request"}`, to change a request
+///   before it is sent, such as to sign it
+/// - `hitman_assert`: `{"args", "status", "headers", "body", "elapsed_ms"}`
+///   → `{"passed", "message"}`, for `# @assert plugin name args`
+///
+/// An answer with an `error` field fails the call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plugin {
+    pub name: String,
+    pub module: PathBuf,
+    /// Change every request before it is sent
+    pub request_hook: bool,
+    /// Installed with `hitman plugin install`
+    pub installed: bool,
+}
+
+impl Plugin {
+    fn call(&self, hook: &str, input: JsonValue) -> Result<JsonValue> {
+        call_plugin(&self.module, hook, &input)
+            .with_context(|| format!("Plugin {} failed", self.name))
+    }
+
+    /// Resolve a value for `{{plugin name args}}`
+    pub fn value(&self, args: &str) -> Result<String> {
+        let output = self.call("value", json!({ "args": args }))?;
+
+        match output.get("value") {
+            Some(JsonValue::String(s)) => Ok(s.clone()),
+            Some(other) => Ok(other.to_string()),
+            None => bail!("Plugin {} returned no value", self.name),
+        }
+    }
+
+    /// Change a request before it is sent
+    pub fn mutate_request(&self, request: &str) -> Result<String> {
+        let output = self.call("request", json!({ "request": request }))?;
+
+        output
+            .get("request")
+            .and_then(JsonValue::as_str)
+            .map(String::from)
+            .with_context(|| {
+                format!("Plugin {} returned no request", self.name)
+            })
+    }
+
+    /// Evaluate `# @assert plugin name args` against a response
+    pub fn assert(
+        &self,
+        args: &str,
+        response: &ResponseInfo,
+    ) -> Result<(bool, Option<String>)> {
+        let headers: serde_json::Map<String, JsonValue> = response
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((name.to_string(), value.to_str().ok()?.into()))
+            })
+            .collect();
+
+        let output = self.call(
+            "assert",
+            json!({
+                "args": args,
+                "status": response.status,
+                "headers": headers,
+                "body": response.body,
+                "elapsed_ms": response.elapsed.as_millis() as u64,
+            }),
+        )?;
+
+        let passed = output
+            .get("passed")
+            .and_then(JsonValue::as_bool)
+            .with_context(|| {
+                format!("Plugin {} returned no result", self.name)
+            })?;
+        let message = output
+            .get("message")
+            .and_then(JsonValue::as_str)
+            .map(String::from);
+
+        Ok((passed, message))
+    }
+}
+
+/// The plugins given in the config. Modules are found from the project
+/// root.
+pub fn plugins_from(env: &Table) -> Result<Vec<Plugin>> {
+    resolve_plugins(
+        env,
+        find_root_dir()?.as_deref(),
+        &installed_plugins().unwrap_or_default(),
+    )
+}
+
+fn resolve_plugins(
+    env: &Table,
+    root_dir: Option<&Path>,
+    installed: &[(String, PathBuf)],
+) -> Result<Vec<Plugin>> {
+    let plugins = match env.get(PLUGINS_KEY) {
+        None => return Ok(Vec::new()),
+        Some(Value::Table(plugins)) => plugins,
+        Some(_) => bail!("Invalid {PLUGINS_KEY} section"),
+    };
+
+    let installed_module = |name: &str| -> Result<PathBuf> {
+        let (_, path) = installed
+            .iter()
+            .find(|(n, _)| n == name)
+            .with_context(|| format!("Plugin {name} is not installed"))?;
+
+        Ok(path.clone())
+    };
+    let project_module = |module: &str| match root_dir {
+        Some(root_dir) => root_dir.join(module),
+        None => PathBuf::from(module),
+    };
+
+    let mut resolved = Vec::new();
+    for (name, conf) in plugins {
+        let (module, installed) = match conf {
+            Value::String(module) => (project_module(module), false),
+            Value::Table(conf) => match conf.get("module") {
+                Some(Value::String(module)) => (project_module(module), false),
+                Some(_) => bail!("Invalid module for plugin {name}"),
+                None => (installed_module(name)?, true),
+            },
+            _ => bail!("Invalid plugin {name}"),
+        };
+        let request_hook = conf
+            .get("request_hook")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        resolved.push(Plugin {
+            name: name.clone(),
+            module,
+            request_hook,
+            installed,
+        });
+    }
+
+    Ok(resolved)
+}
+
+/// The directory of installed plugins, shared by all projects
+pub fn plugins_dir() -> Result<PathBuf> {
+    Ok(user_config_dir()
+        .context("Could not find the user config directory")?
+        .join(PLUGINS_DIR))
+}
+
+/// The installed plugins, by name
+pub fn installed_plugins() -> Result<Vec<(String, PathBuf)>> {
+    let dir = plugins_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut plugins = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "wasm") {
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                plugins.push((name.to_string(), path.clone()));
+            }
+        }
+    }
+    plugins.sort();
+
+    Ok(plugins)
+}
+
+/// Copy a plugin module into the plugins directory. The name defaults to
+/// the file name, without extension.
+pub fn install_plugin(source: &Path, name: Option<&str>) -> Result<String> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => source
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("Invalid plugin file name")?
+            .to_string(),
+    };
+    if name.contains(['/', '\\']) {
+        bail!("Invalid plugin name: {name}");
+    }
+    // Fail now, rather than in the middle of a request
+    load_module(source)?;
+
+    let dir = plugins_dir()?;
+    fs::create_dir_all(&dir)?;
+    fs::copy(source, module_path(&dir, &name))
+        .with_context(|| format!("Failed to copy {}", source.display()))?;
+
+    Ok(name)
+}
+
+pub fn remove_plugin(name: &str) -> Result<()> {
+    let path = module_path(&plugins_dir()?, name);
+    if !path.is_file() {
+        bail!("Plugin {name} is not installed");
+    }
+
+    Ok(fs::remove_file(path)?)
+}
+
+fn module_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.wasm"))
+}
+
+pub fn find_plugin(env: &Table, name: &str) -> Result<Plugin> {
+    plugins_from(env)?
+        .into_iter()
+        .find(|p| p.name == name)
+        .with_context(|| format!("Unknown plugin {name}"))
+}
+
+/// Pass a request through the request hooks of all plugins, in order
+pub fn apply_request_hooks(env: &Table, request: &str) -> Result<String> {
+    let mut request = request.to_string();
+    for plugin in plugins_from(env)?.iter().filter(|p| p.request_hook) {
+        request = plugin.mutate_request(&request)?;
+    }
+
+    Ok(request)
+}
+
+fn engine() -> Result<&'static Engine> {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config)?;
+
+    Ok(ENGINE.get_or_init(|| engine))
+}
+
+fn load_module(path: &Path) -> Result<Module> {
+    Module::from_file(engine()?, path)
+        .map_err(anyhow::Error::from)
+        .with_context(|| format!("Failed to load {}", path.display()))
+}
+
+/// Call a hook of a plugin, in a new instance, so that calls don't share any
+/// state
+fn call_plugin(
+    module: &Path,
+    hook: &str,
+    input: &JsonValue,
+) -> Result<JsonValue> {
+    let module = load_module(module)?;
+    let mut store = Store::new(engine()?, ());
+    store.set_fuel(FUEL)?;
+    let instance = Instance::new(&mut store, &module, &[])
+        .map_err(anyhow::Error::from)
+        .context("Plugins can't import anything")?;
+
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .context("The plugin exports no memory")?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")
+        .map_err(anyhow::Error::from)
+        .context("The plugin exports no alloc")?;
+    let hook = instance
+        .get_typed_func::<(i32, i32), i64>(
+            &mut store,
+            &format!("hitman_{hook}"),
+        )
+        .map_err(anyhow::Error::from)
+        .with_context(|| format!("The plugin has no {hook} hook"))?;
+
+    let input = input.to_string();
+    let len = i32::try_from(input.len()).context("Too large message")?;
+    let ptr = alloc.call(&mut store, len)?;
+    memory.write(&mut store, ptr as u32 as usize, input.as_bytes())?;
+
+    let answer = hook.call(&mut store, (ptr, len))? as u64;
+    let mut output = vec![0; (answer & 0xffff_ffff) as usize];
+    memory
+        .read(&store, (answer >> 32) as usize, &mut output)
+        .context("The plugin answered out of its memory")?;
+
+    let output: JsonValue =
+        serde_json::from_slice(&output).context("Invalid JSON from plugin")?;
+    if let Some(error) = output.get("error").and_then(JsonValue::as_str) {
+        bail!("{error}");
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::HeaderMap;
+
+    use super::*;
+
+    /// Answers from fixed messages, except for the request hook, which
+    /// answers with its input
+    const MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "{\"value\": \"s3cret\"}")
+          (data (i32.const 64) "{\"passed\": false, \"message\": \"slow\"}")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "hitman_value") (param i32 i32) (result i64)
+            (i64.const 19))
+          (func (export "hitman_request") (param i32 i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32))
+              (i64.extend_i32_u (local.get 1))))
+          (func (export "hitman_assert") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 64) (i64.const 32)) (i64.const 36))))
+    "#;
+
+    fn plugin(name: &str, source: &str) -> Plugin {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-plugin-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let module = dir.join(format!("{name}.wat"));
+        fs::write(&module, source).unwrap();
+
+        Plugin {
+            name: name.into(),
+            module,
+            request_hook: false,
+            installed: false,
+        }
+    }
+
+    #[test]
+    fn reads_plugins_from_config() {
+        let env: Table = toml::from_str(
+            r#"
+            [_plugins]
+            vault = "plugins/vault.wasm"
+
+            [_plugins.sigv4]
+            module = "/plugins/sigv4.wasm"
+            request_hook = true
+            "#,
+        )
+        .unwrap();
+
+        let plugins =
+            resolve_plugins(&env, Some(Path::new("/project")), &[]).unwrap();
+
+        assert_eq!(
+            plugins,
+            vec![
+                Plugin {
+                    name: "sigv4".into(),
+                    module: PathBuf::from("/plugins/sigv4.wasm"),
+                    request_hook: true,
+                    installed: false,
+                },
+                Plugin {
+                    name: "vault".into(),
+                    module: PathBuf::from("/project/plugins/vault.wasm"),
+                    request_hook: false,
+                    installed: false,
+                },
+            ]
+        );
+        assert!(find_plugin(&env, "missing").is_err());
+    }
+
+    #[test]
+    fn uses_installed_plugins_by_name() {
+        let installed = vec![
+            ("sigv4".to_string(), PathBuf::from("/plugins/sigv4.wasm")),
+            ("vault".to_string(), PathBuf::from("/plugins/vault.wasm")),
+        ];
+        let env: Table = toml::from_str(
+            r#"
+            [_plugins]
+            sigv4 = { request_hook = true }
+            vault = {}
+            "#,
+        )
+        .unwrap();
+
+        let plugins = resolve_plugins(&env, None, &installed).unwrap();
+
+        assert_eq!(
+            plugins,
+            vec![
+                Plugin {
+                    name: "sigv4".into(),
+                    module: PathBuf::from("/plugins/sigv4.wasm"),
+                    request_hook: true,
+                    installed: true,
+                },
+                Plugin {
+                    name: "vault".into(),
+                    module: PathBuf::from("/plugins/vault.wasm"),
+                    request_hook: false,
+                    installed: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_plugins_not_installed() {
+        let env: Table = toml::from_str("[_plugins]\nsigv4 = {}").unwrap();
+
+        let err = resolve_plugins(&env, None, &[]).unwrap_err();
+
+        assert_eq!(err.to_string(), "Plugin sigv4 is not installed");
+    }
+
+    #[test]
+    fn calls_the_hooks_of_plugins() {
+        let plugin = plugin("hooks", MODULE);
+        let response = ResponseInfo {
+            status: 200,
+            headers: &HeaderMap::new(),
+            body: "{}",
+            elapsed: Duration::from_millis(10),
+            plugins: &[],
+        };
+
+        assert_eq!(plugin.value("db/password").unwrap(), "s3cret");
+        assert_eq!(plugin.mutate_request("GET /\n").unwrap(), "GET /\n");
+        assert_eq!(
+            plugin.assert("fast", &response).unwrap(),
+            (false, Some("slow".to_string()))
+        );
+    }
+
+    #[test]
+    fn fails_on_errors_from_plugins() {
+        let failing = plugin(
+            "failing",
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 0) "{\"error\": \"no access\"}")
+              (func (export "alloc") (param i32) (result i32) (i32.const 64))
+              (func (export "hitman_value") (param i32 i32) (result i64)
+                (i64.const 22)))
+            "#,
+        );
+        let err = failing.value("db/password").unwrap_err();
+        assert!(format!("{err:#}").contains("no access"));
+
+        let err = failing.mutate_request("GET /").unwrap_err();
+        assert!(format!("{err:#}").contains("no request hook"));
+    }
+
+    #[test]
+    fn stops_plugins_that_never_answer() {
+        let looping = plugin(
+            "looping",
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "hitman_value") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (unreachable)))
+            "#,
+        );
+
+        assert!(looping.value("db/password").is_err());
+    }
+
+    #[test]
+    fn refuses_plugins_that_import() {
+        let importing = plugin(
+            "importing",
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "proc_exit"
+                (func (param i32))))
+            "#,
+        );
+
+        let err = importing.value("db/password").unwrap_err();
+
+        assert!(format!("{err:#}").contains("can't import anything"));
+    }
+}
@@ -0,0 +1,454 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::Regex;
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::import::{file_stem, Imported};
+
+/// Convert a Postman collection, and optionally its environments, to request
+/// files and a config. Folders become directories, the collection variables
+/// become global values, and each environment becomes a target.
+pub fn import_postman(
+    collection: &Path,
+    environments: &[PathBuf],
+) -> Result<Imported> {
+    let read = |path: &Path| -> Result<JsonValue> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&data)
+            .with_context(|| format!("Invalid JSON in {}", path.display()))
+    };
+
+    let environments = environments
+        .iter()
+        .map(|path| read(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    convert_collection(&read(collection)?, &environments)
+}
+
+pub fn convert_collection(
+    collection: &JsonValue,
+    environments: &[JsonValue],
+) -> Result<Imported> {
+    let Some(items) = collection.get("item").and_then(JsonValue::as_array)
+    else {
+        bail!("Not a Postman collection, expected a list of items");
+    };
+
+    let mut imported = Imported::default();
+
+    for (key, value) in variables(collection.get("variable")) {
+        imported.config.insert(key, Value::String(value));
+    }
+
+    for environment in environments {
+        let name = environment
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("default");
+        let values: Table = variables(environment.get("values"))
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+        imported
+            .config
+            .insert(file_stem(name), Value::Table(values));
+    }
+    // The config needs at least one target
+    if environments.is_empty() {
+        imported
+            .config
+            .insert("default".to_string(), Value::Table(Table::new()));
+    }
+
+    convert_items(&mut imported, items, Path::new(""), collection.get("auth"));
+
+    Ok(imported)
+}
+
+/// Enabled `{ key, value }` pairs, as used for variables, headers and auth
+fn variables(list: Option<&JsonValue>) -> Vec<(String, String)> {
+    let Some(list) = list.and_then(JsonValue::as_array) else {
+        return Vec::new();
+    };
+
+    list.iter()
+        .filter(|v| v.get("disabled") != Some(&JsonValue::Bool(true)))
+        .filter(|v| v.get("enabled") != Some(&JsonValue::Bool(false)))
+        .filter_map(|v| {
+            let key = v.get("key")?.as_str()?.to_string();
+            let value = match v.get("value") {
+                Some(JsonValue::String(s)) => s.clone(),
+                Some(JsonValue::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+fn convert_items(
+    imported: &mut Imported,
+    items: &[JsonValue],
+    dir: &Path,
+    auth: Option<&JsonValue>,
+) {
+    for item in items {
+        let name = item
+            .get("name")
+            .and_then(JsonValue::as_str)
+            .unwrap_or("request");
+        // Folders and requests may override the auth of their parents
+        let auth = item.get("auth").or(auth);
+
+        if let Some(children) = item.get("item").and_then(JsonValue::as_array) {
+            convert_items(imported, children, &dir.join(file_stem(name)), auth);
+            continue;
+        }
+
+        if let Some(events) = item.get("event").and_then(JsonValue::as_array) {
+            if !events.is_empty() {
+                imported
+                    .warnings
+                    .push(format!("Skipped the scripts of {name}"));
+            }
+        }
+
+        match item.get("request") {
+            Some(request) => {
+                let content = convert_request(imported, name, request, auth);
+                imported.add_request(dir, name, content);
+            }
+            None => imported.warnings.push(format!("Skipped {name}")),
+        }
+    }
+}
+
+fn convert_request(
+    imported: &mut Imported,
+    name: &str,
+    request: &JsonValue,
+    auth: Option<&JsonValue>,
+) -> String {
+    // A request can be just the URL
+    if let JsonValue::String(url) = request {
+        return format!("GET {} HTTP/1.1\n", path_variables(url));
+    }
+
+    let method = request
+        .get("method")
+        .and_then(JsonValue::as_str)
+        .unwrap_or("GET")
+        .to_uppercase();
+
+    let mut url = match request.get("url") {
+        Some(JsonValue::String(url)) => url.clone(),
+        Some(url) => {
+            // Values of path variables, like `:id`, are global values
+            for (key, value) in variables(url.get("variable")) {
+                if !value.is_empty() && !imported.config.contains_key(&key) {
+                    imported.config.insert(key, Value::String(value));
+                }
+            }
+            url.get("raw")
+                .and_then(JsonValue::as_str)
+                .unwrap_or_default()
+                .to_string()
+        }
+        None => String::new(),
+    };
+    url = path_variables(&url);
+
+    let mut headers = variables(request.get("header"));
+    let has_header = |headers: &[(String, String)], name: &str| {
+        headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+    };
+
+    if let Some(auth) = request.get("auth").or(auth) {
+        convert_auth(imported, name, auth, &mut headers, &mut url);
+    }
+
+    let mut body = String::new();
+    if let Some(request_body) = request.get("body") {
+        let mode = request_body
+            .get("mode")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default();
+        let content_type = match mode {
+            "raw" => {
+                body = request_body
+                    .get("raw")
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or_default()
+                    .to_string();
+                let language = request_body
+                    .pointer("/options/raw/language")
+                    .and_then(JsonValue::as_str);
+                match language {
+                    Some("json") => Some("application/json"),
+                    Some("xml") => Some("application/xml"),
+                    _ => None,
+                }
+            }
+            "urlencoded" => {
+                body = variables(request_body.get("urlencoded"))
+                    .iter()
+                    .map(|(k, v)| format!("{k}={v}"))
+                    .collect::<Vec<_>>()
+                    .join("&");
+                Some("application/x-www-form-urlencoded")
+            }
+            "graphql" => {
+                let graphql = request_body.get("graphql");
+                let field = |key| {
+                    graphql
+                        .and_then(|g| g.get(key))
+                        .and_then(JsonValue::as_str)
+                        .unwrap_or_default()
+                        .trim()
+                };
+                // Written the way hitman sends GraphQL, the query followed
+                // by the variables
+                body = field("query").to_string();
+                if !field("variables").is_empty() {
+                    body = format!("{body}\n\n{}", field("variables"));
+                }
+                Some("application/json")
+            }
+            "" | "none" => None,
+            other => {
+                imported
+                    .warnings
+                    .push(format!("Skipped the {other} body of {name}"));
+                None
+            }
+        };
+
+        if let Some(content_type) = content_type {
+            if !body.is_empty() && !has_header(&headers, "content-type") {
+                headers.push(("Content-Type".into(), content_type.into()));
+            }
+        }
+    }
+
+    let mut content = format!("{method} {url} HTTP/1.1\n");
+    for (key, value) in &headers {
+        content.push_str(&format!("{key}: {value}\n"));
+    }
+    if !body.is_empty() {
+        content.push('\n');
+        content.push_str(body.trim_end());
+        content.push('\n');
+    }
+
+    content
+}
+
+/// Turn the `:name` path variables of Postman into `{{name}}`
+fn path_variables(url: &str) -> String {
+    let pattern = Regex::new(r"/:([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+
+    pattern.replace_all(url, "/{{$1}}").to_string()
+}
+
+fn convert_auth(
+    imported: &mut Imported,
+    name: &str,
+    auth: &JsonValue,
+    headers: &mut Vec<(String, String)>,
+    url: &mut String,
+) {
+    let kind = auth
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .unwrap_or("noauth");
+    let values = variables(auth.get(kind));
+    let value = |key: &str| {
+        values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+
+    match kind {
+        "noauth" => (),
+        "bearer" => headers.push((
+            "Authorization".into(),
+            format!("Bearer {}", value("token")),
+        )),
+        "basic" => {
+            let credentials =
+                format!("{}:{}", value("username"), value("password"));
+            // Variables can't be encoded until the request is sent
+            if credentials.contains("{{") {
+                imported.warnings.push(format!(
+                    "Skipped the basic auth of {name}, since it uses variables"
+                ));
+            } else {
+                headers.push((
+                    "Authorization".into(),
+                    format!("Basic {}", STANDARD.encode(credentials)),
+                ));
+            }
+        }
+        "apikey" if value("in") == "query" => {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            url.push_str(&format!(
+                "{separator}{}={}",
+                value("key"),
+                value("value")
+            ));
+        }
+        "apikey" => headers.push((value("key"), value("value"))),
+        other => imported
+            .warnings
+            .push(format!("Skipped the {other} auth of {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn collection() -> JsonValue {
+        json!({
+            "info": {"name": "Shop"},
+            "variable": [{"key": "base_url", "value": "https://shop.test"}],
+            "auth": {
+                "type": "bearer",
+                "bearer": [{"key": "token", "value": "{{token}}"}]
+            },
+            "item": [
+                {
+                    "name": "Users",
+                    "item": [{
+                        "name": "Get user",
+                        "request": {
+                            "method": "GET",
+                            "header": [
+                                {"key": "Accept", "value": "application/json"},
+                                {"key": "X-Old", "value": "1", "disabled": true}
+                            ],
+                            "url": {
+                                "raw": "{{base_url}}/users/:id?full=true",
+                                "variable": [{"key": "id", "value": "7"}]
+                            }
+                        }
+                    }]
+                },
+                {
+                    "name": "Create order",
+                    "auth": {"type": "noauth"},
+                    "event": [{"listen": "test"}],
+                    "request": {
+                        "method": "post",
+                        "url": "{{base_url}}/orders",
+                        "body": {
+                            "mode": "raw",
+                            "raw": "{\"item\": 1}",
+                            "options": {"raw": {"language": "json"}}
+                        }
+                    }
+                },
+                {
+                    "name": "Login",
+                    "request": {
+                        "method": "POST",
+                        "url": "{{base_url}}/login",
+                        "body": {
+                            "mode": "urlencoded",
+                            "urlencoded": [
+                                {"key": "user", "value": "admin"},
+                                {"key": "pass", "value": "{{password}}"}
+                            ]
+                        }
+                    }
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn converts_collections() {
+        let imported = convert_collection(&collection(), &[]).unwrap();
+
+        let files: Vec<_> = imported
+            .requests
+            .iter()
+            .map(|r| (r.path.display().to_string(), r.content.as_str()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (
+                    "users/get_user.http".to_string(),
+                    "GET {{base_url}}/users/{{id}}?full=true HTTP/1.1\n\
+                     Accept: application/json\n\
+                     Authorization: Bearer {{token}}\n"
+                ),
+                (
+                    "create_order.http".to_string(),
+                    "POST {{base_url}}/orders HTTP/1.1\n\
+                     Content-Type: application/json\n\
+                     \n\
+                     {\"item\": 1}\n"
+                ),
+                (
+                    "login.http".to_string(),
+                    "POST {{base_url}}/login HTTP/1.1\n\
+                     Authorization: Bearer {{token}}\n\
+                     Content-Type: application/x-www-form-urlencoded\n\
+                     \n\
+                     user=admin&pass={{password}}\n"
+                ),
+            ]
+        );
+        assert_eq!(imported.warnings, ["Skipped the scripts of Create order"]);
+    }
+
+    #[test]
+    fn makes_targets_of_environments() {
+        let environments = [
+            json!({
+                "name": "Local Dev",
+                "values": [{"key": "token", "value": "abc", "enabled": true}]
+            }),
+            json!({
+                "name": "Production",
+                "values": [{"key": "token", "value": "xyz", "enabled": false}]
+            }),
+        ];
+
+        let imported =
+            convert_collection(&collection(), &environments).unwrap();
+
+        let expected: Table = toml::from_str(
+            r#"
+            base_url = "https://shop.test"
+            id = "7"
+
+            [local_dev]
+            token = "abc"
+
+            [production]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(imported.config, expected);
+    }
+
+    #[test]
+    fn needs_a_collection() {
+        assert!(convert_collection(&json!({"values": []}), &[]).is_err());
+    }
+}
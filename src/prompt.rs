@@ -1,9 +1,12 @@
 use anyhow::{bail, Result};
-use inquire::{list_option::ListOption, DateSelect, Select, Text};
-use std::env;
+use inquire::{list_option::ListOption, Confirm, DateSelect, Select, Text};
+use std::{env, io::BufRead};
 use toml::{Table, Value};
 
-use crate::substitute::{substitute, SubstituteError};
+use crate::{
+    scrub::remember_secrets,
+    substitute::{is_json_placeholder, substitute, SubstituteError},
+};
 
 fn set_boolean(name: &str, value: bool) {
     env::set_var(name, if value { "y" } else { "n" });
@@ -53,7 +56,15 @@ pub fn get_interaction() -> Box<dyn UserInteraction> {
 
 pub trait UserInteraction {
     fn prompt(&self, key: &str, fallback: Option<&str>) -> Result<String>;
+    /// Prompt for a whole JSON value, such as an object
+    fn prompt_json(&self, key: &str, fallback: Option<&str>) -> Result<String>;
     fn select(&self, key: &str, values: &[Value]) -> Result<String>;
+    fn confirm(&self, message: &str) -> Result<bool>;
+    fn choose(
+        &self,
+        message: &str,
+        options: &[String],
+    ) -> Result<Option<usize>>;
 }
 
 pub fn substitute_interactive<I>(
@@ -65,12 +76,19 @@ where
     I: UserInteraction + ?Sized,
 {
     match substitute(input, env) {
-        Ok(res) => Ok(res),
+        Ok(res) => {
+            remember_secrets(env);
+            Ok(res)
+        }
         Err(err) => {
             let (key, value) = match err {
                 SubstituteError::ValueNotFound { key, fallback } => {
-                    let value =
-                        interaction.prompt(&key, fallback.as_deref())?;
+                    let fallback = fallback.as_deref();
+                    let value = if is_json_placeholder(input, &key, fallback) {
+                        interaction.prompt_json(&key, fallback)?
+                    } else {
+                        interaction.prompt(&key, fallback)?
+                    };
                     (key, value)
                 }
                 SubstituteError::MultipleValuesFound { key, values } => {
@@ -99,6 +117,11 @@ impl UserInteraction for NoUserInteraction {
         bail!("Replacement not found: {key}");
     }
 
+    fn prompt_json(&self, key: &str, fallback: Option<&str>) -> Result<String> {
+        let value = self.prompt(key, fallback)?;
+        validate_json(&value)
+    }
+
     fn select(&self, key: &str, values: &[toml::Value]) -> Result<String> {
         let suggestions: Vec<String> = values
             .iter()
@@ -112,6 +135,18 @@ impl UserInteraction for NoUserInteraction {
 
         bail!("Replacement not selected: {key}\nSuggestions:\n{suggestions}");
     }
+
+    fn confirm(&self, _message: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn choose(
+        &self,
+        _message: &str,
+        _options: &[String],
+    ) -> Result<Option<usize>> {
+        Ok(None)
+    }
 }
 
 pub struct CliUserInteraction;
@@ -121,9 +156,35 @@ impl UserInteraction for CliUserInteraction {
         prompt_user(key, fallback)
     }
 
+    fn prompt_json(&self, key: &str, fallback: Option<&str>) -> Result<String> {
+        prompt_user_json(key, fallback)
+    }
+
     fn select(&self, key: &str, values: &[toml::Value]) -> Result<String> {
         select_replacement(key, values)
     }
+
+    fn confirm(&self, message: &str) -> Result<bool> {
+        Ok(Confirm::new(message).with_default(false).prompt()?)
+    }
+
+    fn choose(
+        &self,
+        message: &str,
+        options: &[String],
+    ) -> Result<Option<usize>> {
+        let options: Vec<ListOption<&String>> = options
+            .iter()
+            .enumerate()
+            .map(|(i, o)| ListOption::new(i, o))
+            .collect();
+
+        let selected = Select::new(message, options)
+            .with_help_message("esc to skip")
+            .prompt_skippable()?;
+
+        Ok(selected.map(|o| o.index))
+    }
 }
 
 fn prompt_user(key: &str, fallback: Option<&str>) -> Result<String> {
@@ -142,6 +203,55 @@ fn prompt_user(key: &str, fallback: Option<&str>) -> Result<String> {
     Ok(input)
 }
 
+/// Validate JSON entered for a placeholder. The text is kept as it was
+/// written, so that the order of keys doesn't change.
+pub fn validate_json(input: &str) -> Result<String> {
+    let input = input.trim();
+    serde_json::from_str::<serde_json::Value>(input)
+        .map_err(|err| anyhow::anyhow!("Invalid JSON: {err}"))?;
+
+    Ok(input.to_string())
+}
+
+/// Read JSON over several lines, until an empty line. Invalid JSON is
+/// reported, and the value is asked for again.
+fn prompt_user_json(key: &str, fallback: Option<&str>) -> Result<String> {
+    let stdin = std::io::stdin();
+
+    loop {
+        match fallback {
+            Some(fb) => eprintln!(
+                "Enter JSON for {key}, and finish with an empty line (default: {fb})"
+            ),
+            None => eprintln!(
+                "Enter JSON for {key}, and finish with an empty line"
+            ),
+        }
+
+        let mut input = String::new();
+        for line in stdin.lock().lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                break;
+            }
+            input.push_str(&line);
+            input.push('\n');
+        }
+
+        if input.is_empty() {
+            match fallback {
+                Some(fb) => input = fb.to_string(),
+                None => bail!("Replacement not found: {key}"),
+            }
+        }
+
+        match validate_json(&input) {
+            Ok(value) => return Ok(value),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
 fn prompt_for_date(key: &str) -> Result<Option<String>> {
     let msg = format!("Select a date for {}", key);
     let formatter =
@@ -194,6 +304,18 @@ fn select_replacement(key: &str, values: &[Value]) -> Result<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn accepts_valid_json() {
+        let value = validate_json("{\n  \"name\": \"apple\"\n}\n");
+
+        assert_eq!(value.unwrap(), "{\n  \"name\": \"apple\"\n}");
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(validate_json("{\"name\": }").is_err());
+    }
+
     #[test]
     fn returns_true_for_identical() {
         assert!(fuzzy_match("a", "a"));
@@ -0,0 +1,260 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use prost::Message;
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor,
+};
+use prost_types::FileDescriptorProto;
+use protox::Compiler;
+use serde_json::Value as JsonValue;
+
+/// Compile `.proto` files, and the files they import. Imports are found in
+/// the import paths or next to the given files, and the well-known types,
+/// like `google/protobuf/empty.proto`, are always available.
+pub fn load_protos(
+    files: &[PathBuf],
+    import_paths: &[PathBuf],
+) -> Result<DescriptorPool> {
+    let mut includes = import_paths.to_vec();
+    for dir in files.iter().filter_map(|file| file.parent()) {
+        if !includes.iter().any(|include| include == dir) {
+            includes.push(dir.to_path_buf());
+        }
+    }
+
+    let mut compiler = Compiler::new(&includes)?;
+    compiler.include_imports(true);
+    for file in files {
+        compiler
+            .open_file(file)
+            .with_context(|| format!("Failed to load {}", file.display()))?;
+    }
+
+    Ok(compiler.descriptor_pool())
+}
+
+/// The files of a service, as sent by server reflection. Files can come in
+/// any order, and the files they import may have to be asked for.
+#[derive(Debug, Default)]
+pub struct ReflectedFiles {
+    files: Vec<FileDescriptorProto>,
+}
+
+impl ReflectedFiles {
+    /// Add a serialized `FileDescriptorProto`, unless it's already here
+    pub fn add(&mut self, data: &[u8]) -> Result<()> {
+        let file = FileDescriptorProto::decode(data)
+            .context("Invalid file descriptor")?;
+        if !self.files.iter().any(|f| f.name == file.name) {
+            self.files.push(file);
+        }
+
+        Ok(())
+    }
+
+    /// Files imported by the files, that are not here themselves
+    pub fn missing_dependencies(&self) -> Vec<String> {
+        let mut missing: Vec<String> = Vec::new();
+        for dependency in self.files.iter().flat_map(|f| &f.dependency) {
+            let found = self.files.iter().any(|f| f.name() == dependency);
+            if !found && !missing.contains(dependency) {
+                missing.push(dependency.clone());
+            }
+        }
+
+        missing
+    }
+
+    pub fn into_pool(self) -> Result<DescriptorPool> {
+        let mut pool = DescriptorPool::new();
+        pool.add_file_descriptor_protos(self.files)?;
+
+        Ok(pool)
+    }
+}
+
+/// Find a method by the full name of its service, like
+/// `orders.v1.OrderService`
+pub fn find_method(
+    pool: &DescriptorPool,
+    service: &str,
+    method: &str,
+) -> Result<MethodDescriptor> {
+    pool.get_service_by_name(service)
+        .with_context(|| format!("Unknown service {service}"))?
+        .methods()
+        .find(|m| m.name() == method)
+        .with_context(|| format!("Unknown method {method} in {service}"))
+}
+
+/// Encode a message given as JSON, with field names as in the `.proto` file
+/// or in camel case
+pub fn encode(message: MessageDescriptor, json: &JsonValue) -> Result<Vec<u8>> {
+    let name = message.full_name().to_string();
+    let message = DynamicMessage::deserialize(message, json)
+        .with_context(|| format!("Invalid {name}"))?;
+
+    Ok(message.encode_to_vec())
+}
+
+/// Decode a message as JSON, as protoc maps it: with camel case field names,
+/// enums by name, and 64-bit integers as strings
+pub fn decode(message: MessageDescriptor, data: &[u8]) -> Result<JsonValue> {
+    let name = message.full_name().to_string();
+    let message = DynamicMessage::decode(message, data)
+        .with_context(|| format!("Invalid {name}"))?;
+
+    Ok(serde_json::to_value(&message)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::*;
+
+    const SHOP: &str = r#"
+        syntax = "proto3";
+        package shop.v1;
+
+        import "google/protobuf/empty.proto";
+        import "item.proto";
+        option go_package = "example.com/shop";
+
+        /* Orders, with
+           their items */
+        message Order {
+            int64 id = 1;
+            repeated Item items = 2 [deprecated = true];
+            Status status = 3;
+            map<string, int32> stock_levels = 4;
+            oneof payment {
+                string card = 5;
+                string invoice = 6;
+            }
+            reserved 7, 8;
+        }
+
+        enum Status {
+            UNKNOWN = 0;
+            SHIPPED = 1;
+        }
+
+        service Orders {
+            rpc Get(Order) returns (Order);
+            rpc List(google.protobuf.Empty) returns (stream Order) {
+                option deprecated = true;
+            }
+        }
+    "#;
+
+    const ITEM: &str = r#"
+        syntax = "proto3";
+        package shop.v1;
+
+        message Item {
+            string name = 1; // The name
+        }
+    "#;
+
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<DescriptorPool> {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-proto-{name}-{}", std::process::id()));
+        let proto_dir = dir.join("proto");
+        fs::create_dir_all(&proto_dir).unwrap();
+        for (file, source) in files {
+            fs::write(proto_dir.join(file), source).unwrap();
+        }
+
+        let pool = load_protos(
+            &[proto_dir.join(files[0].0)],
+            std::slice::from_ref(&dir),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        pool
+    }
+
+    #[test]
+    fn loads_proto_files() {
+        let pool = load("load", &[("shop.proto", SHOP), ("item.proto", ITEM)])
+            .unwrap();
+
+        let order = pool.get_message_by_name("shop.v1.Order").unwrap();
+        let names: Vec<_> =
+            order.fields().map(|f| f.name().to_string()).collect();
+        assert_eq!(
+            names,
+            ["id", "items", "status", "stock_levels", "card", "invoice"]
+        );
+
+        let list = find_method(&pool, "shop.v1.Orders", "List").unwrap();
+        assert_eq!(list.input().full_name(), "google.protobuf.Empty");
+        assert_eq!(list.output().full_name(), "shop.v1.Order");
+        assert!(list.is_server_streaming());
+
+        assert!(find_method(&pool, "shop.v1.Orders", "Delete").is_err());
+    }
+
+    #[test]
+    fn transcodes_messages() {
+        let pool =
+            load("transcode", &[("shop.proto", SHOP), ("item.proto", ITEM)])
+                .unwrap();
+        let order = pool.get_message_by_name("shop.v1.Order").unwrap();
+        let json = json!({
+            "id": "12",
+            "items": [{"name": "apple"}],
+            "status": "SHIPPED",
+            "stockLevels": {"apple": 3},
+            "card": "visa",
+        });
+
+        let data = encode(order.clone(), &json).unwrap();
+
+        assert_eq!(decode(order.clone(), &data).unwrap(), json);
+
+        // Field names from the .proto file work too
+        let data =
+            encode(order.clone(), &json!({"stock_levels": {"a": 1}})).unwrap();
+        assert_eq!(
+            decode(order.clone(), &data).unwrap(),
+            json!({"stockLevels": {"a": 1}})
+        );
+        assert!(encode(order, &json!({"nope": 1})).is_err());
+    }
+
+    #[test]
+    fn fails_on_unknown_types() {
+        let source = "syntax = \"proto3\"; message A { B b = 1; }";
+        let err = load("unknown", &[("a.proto", source)]).unwrap_err();
+
+        assert!(format!("{err:#}").ends_with("name 'B' is not defined"));
+    }
+
+    #[test]
+    fn collects_reflected_files() {
+        let pool =
+            load("reflect", &[("shop.proto", SHOP), ("item.proto", ITEM)])
+                .unwrap();
+        let (empty, others): (Vec<_>, Vec<_>) = pool
+            .file_descriptor_protos()
+            .partition(|f| f.name() == "google/protobuf/empty.proto");
+
+        let mut files = ReflectedFiles::default();
+        for file in others.iter().rev() {
+            files.add(&file.encode_to_vec()).unwrap();
+        }
+        assert_eq!(
+            files.missing_dependencies(),
+            vec!["google/protobuf/empty.proto".to_string()]
+        );
+
+        files.add(&empty[0].encode_to_vec()).unwrap();
+        assert!(files.missing_dependencies().is_empty());
+        let pool = files.into_pool().unwrap();
+        assert!(find_method(&pool, "shop.v1.Orders", "Get").is_ok());
+    }
+}
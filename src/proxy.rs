@@ -0,0 +1,124 @@
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use reqwest::{ClientBuilder, NoProxy, Proxy, Url};
+use toml::{Table, Value};
+
+use crate::{doctor, substitute::substitute};
+
+/// The proxy used for requests, given in the config or for a target, such
+/// as:
+///
+/// ```toml
+/// [_proxy]
+/// url = "http://proxy.corp.example.com:3128"
+/// no_proxy = "localhost,.internal.example.com"
+/// ```
+pub(crate) const PROXY_KEY: &str = "_proxy";
+
+/// Given with `--proxy`, used for every request instead of the config
+static PROXY_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Send every request through a proxy, like one given with `--proxy`
+pub fn set_proxy_override(url: &str) {
+    let _ = PROXY_OVERRIDE.set(url.to_string());
+}
+
+/// Use the proxy given with `--proxy`, or the one configured for the target.
+/// Without either, the proxy environment variables are used as before.
+pub fn configure_proxy(
+    builder: ClientBuilder,
+    env: &Table,
+) -> Result<ClientBuilder> {
+    let Some((url, no_proxy)) = proxy_config(env)? else {
+        return Ok(builder);
+    };
+
+    let proxy = Proxy::all(&url)
+        .with_context(|| format!("Invalid proxy URL: {url}"))?
+        .no_proxy(no_proxy.as_deref().and_then(NoProxy::from_string));
+
+    Ok(builder.proxy(proxy))
+}
+
+/// The proxy used for a URL, the same way as [configure_proxy]
+pub fn target_proxy(url: &Url, env: &Table) -> Result<Option<String>> {
+    let Some((proxy, no_proxy)) = proxy_config(env)? else {
+        return Ok(doctor::proxy_for(url));
+    };
+
+    let excluded = match (url.host_str(), no_proxy) {
+        (Some(host), Some(no_proxy)) => {
+            doctor::no_proxy_matches(host, &no_proxy)
+        }
+        _ => false,
+    };
+
+    Ok(Some(proxy).filter(|_| !excluded))
+}
+
+/// The proxy URL and the hosts that are not proxied, if a proxy is given.
+/// Both can have placeholders, to keep credentials out of the config.
+fn proxy_config(env: &Table) -> Result<Option<(String, Option<String>)>> {
+    if let Some(url) = PROXY_OVERRIDE.get() {
+        return Ok(Some((url.clone(), None)));
+    }
+
+    let Some(Value::Table(config)) = env.get(PROXY_KEY) else {
+        return Ok(None);
+    };
+    let Some(url) = config.get("url").and_then(Value::as_str) else {
+        return Ok(None);
+    };
+
+    let no_proxy = match config.get("no_proxy") {
+        Some(Value::String(hosts)) => Some(hosts.clone()),
+        Some(Value::Array(hosts)) => Some(
+            hosts
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        _ => None,
+    };
+
+    Ok(Some((
+        resolve(url, env)?,
+        no_proxy.map(|hosts| resolve(&hosts, env)).transpose()?,
+    )))
+}
+
+fn resolve(value: &str, env: &Table) -> Result<String> {
+    Ok(substitute(value, env)?.trim_end_matches('\n').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_proxy_of_a_target() {
+        let env: Table = toml::from_str(
+            r#"
+            proxy_host = "proxy.example.com"
+
+            [_proxy]
+            url = "http://{{proxy_host}}:3128"
+            no_proxy = ["localhost", ".internal"]
+            "#,
+        )
+        .unwrap();
+
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(
+            target_proxy(&url("https://api.example.com/"), &env).unwrap(),
+            Some("http://proxy.example.com:3128".to_string())
+        );
+        assert_eq!(
+            target_proxy(&url("http://db.internal/"), &env).unwrap(),
+            None
+        );
+        assert!(configure_proxy(ClientBuilder::new(), &env).is_ok());
+    }
+}
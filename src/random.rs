@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use rand::RngCore;
+
+/// Added to the state for each value (from SplitMix64)
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+static SEEDED: AtomicBool = AtomicBool::new(false);
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Generate the same values on every run from now on, to reproduce a run
+/// exactly. Without a seed, values come from the random number generator of
+/// the system.
+pub fn seed(seed: u64) {
+    STATE.store(seed, Ordering::Relaxed);
+    SEEDED.store(true, Ordering::Relaxed);
+}
+
+/// A random number, as used for generated values. These are the same on
+/// every run with a seed.
+pub fn next_u64() -> u64 {
+    if SEEDED.load(Ordering::Relaxed) {
+        let state = STATE.fetch_add(GOLDEN_GAMMA, Ordering::Relaxed);
+        return mix(state.wrapping_add(GOLDEN_GAMMA));
+    }
+
+    rand::thread_rng().next_u64()
+}
+
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut out = [0u8; N];
+    if !SEEDED.load(Ordering::Relaxed) {
+        rand::thread_rng().fill_bytes(&mut out);
+        return out;
+    }
+
+    for chunk in out.chunks_mut(8) {
+        let value = next_u64().to_be_bytes();
+        chunk.copy_from_slice(&value[..chunk.len()]);
+    }
+
+    out
+}
+
+/// The output function of SplitMix64
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_like_splitmix64() {
+        // The first values of SplitMix64 with seed 0
+        assert_eq!(mix(GOLDEN_GAMMA), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(mix(GOLDEN_GAMMA.wrapping_mul(2)), 0x6E78_9E6A_A1B9_65F4);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Request, Response, ResponseBuilderExt, Url};
+use sha1::{Digest, Sha1};
+use toml::{Table, Value};
+
+use crate::{
+    env::{find_root_dir, read_toml},
+    scrub::hide_secrets,
+    state::{store_dir, RECORDINGS_STORE},
+};
+
+/// Whether responses are recorded, or served from recordings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Send requests as usual
+    Live,
+    /// Send requests, and save the responses
+    Record,
+    /// Serve responses from the recordings, without network access
+    Offline,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Live as u8);
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        m if m == Mode::Record as u8 => Mode::Record,
+        m if m == Mode::Offline as u8 => Mode::Offline,
+        _ => Mode::Live,
+    }
+}
+
+/// A response, as recorded
+#[derive(Debug, Clone, PartialEq)]
+struct Recording {
+    method: String,
+    url: String,
+    status: u16,
+    elapsed: Duration,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// Identifies the same request across runs, from the method, URL and body.
+/// Headers are left out, since they often hold tokens that change.
+fn recording_key(request: &Request) -> String {
+    let mut data = format!("{} {}\n", request.method(), request.url());
+    let body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .unwrap_or_default();
+    data.push_str(&String::from_utf8_lossy(body));
+
+    Sha1::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn recording_file(request: &Request) -> Result<PathBuf> {
+    let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+
+    Ok(store_dir(&root_dir, RECORDINGS_STORE)?
+        .join(format!("{}.toml", recording_key(request))))
+}
+
+/// Save a response for later runs, and give it back to be used as usual
+pub async fn record(
+    request: &Request,
+    response: Response,
+    elapsed: Duration,
+) -> Result<Response> {
+    let url = response.url().clone();
+    let recording = Recording {
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        status: response.status().as_u16(),
+        elapsed,
+        headers: response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (name.to_string(), value.to_string())
+            })
+            .collect(),
+        body: response.bytes().await?.to_vec(),
+    };
+
+    fs::write(
+        recording_file(request)?,
+        toml::to_string_pretty(&recording.without_secrets().to_table())?,
+    )?;
+
+    recording.into_response(url)
+}
+
+/// The recorded response to a request
+pub fn replay(request: &Request) -> Result<(Response, Duration)> {
+    let file = recording_file(request)?;
+    let recording = read_toml(&file)
+        .ok()
+        .and_then(|table| Recording::from_table(&table))
+        .with_context(|| {
+            format!(
+                "No recording of {} {}, run it with --record first",
+                request.method(),
+                request.url()
+            )
+        })?;
+
+    let elapsed = recording.elapsed;
+    Ok((recording.into_response(request.url().clone())?, elapsed))
+}
+
+impl Recording {
+    fn into_response(self, url: Url) -> Result<Response> {
+        let mut builder =
+            http::Response::builder().status(self.status).url(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        Ok(builder.body(self.body)?.into())
+    }
+
+    /// The recording as written to disk, without the values of sensitive keys
+    fn without_secrets(&self) -> Self {
+        Self {
+            url: hide_secrets(&self.url),
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), hide_secrets(value)))
+                .collect(),
+            body: match std::str::from_utf8(&self.body) {
+                Ok(body) => hide_secrets(body).into_bytes(),
+                Err(_) => self.body.clone(),
+            },
+            ..self.clone()
+        }
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        let mut insert = |key: &str, value: Value| {
+            table.insert(key.to_string(), value);
+        };
+
+        insert("method", Value::String(self.method.clone()));
+        insert("url", Value::String(self.url.clone()));
+        insert("status", Value::Integer(self.status.into()));
+        insert(
+            "elapsed_ms",
+            Value::Integer(self.elapsed.as_millis() as i64),
+        );
+        insert(
+            "headers",
+            Value::Array(
+                self.headers
+                    .iter()
+                    .map(|(name, value)| {
+                        Value::Array(vec![
+                            Value::String(name.clone()),
+                            Value::String(value.clone()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        );
+        // Binary bodies can't be kept in a TOML string
+        match String::from_utf8(self.body.clone()) {
+            Ok(body) => insert("body", Value::String(body)),
+            Err(_) => insert(
+                "body_base64",
+                Value::String(STANDARD.encode(&self.body)),
+            ),
+        }
+
+        table
+    }
+
+    fn from_table(table: &Table) -> Option<Self> {
+        let str_value = |key: &str| -> Option<String> {
+            Some(table.get(key)?.as_str()?.to_string())
+        };
+
+        let body = match (table.get("body"), table.get("body_base64")) {
+            (Some(Value::String(body)), _) => body.as_bytes().to_vec(),
+            (_, Some(Value::String(data))) => STANDARD.decode(data).ok()?,
+            _ => Vec::new(),
+        };
+
+        let headers = table
+            .get("headers")?
+            .as_array()?
+            .iter()
+            .filter_map(|header| match header.as_array()?.as_slice() {
+                [Value::String(name), Value::String(value)] => {
+                    Some((name.clone(), value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Some(Self {
+            method: str_value("method")?,
+            url: str_value("url")?,
+            status: table.get("status")?.as_integer()?.try_into().ok()?,
+            elapsed: Duration::from_millis(
+                table.get("elapsed_ms")?.as_integer()?.max(0) as u64,
+            ),
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, url: &str, body: &str) -> Request {
+        reqwest::Client::new()
+            .request(method.parse().unwrap(), url)
+            .body(body.to_string())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn identifies_requests_by_method_url_and_body() {
+        let key = recording_key(&request("GET", "http://example.com/a", ""));
+
+        // The SHA-1 of the method, URL and body, as in earlier recordings
+        assert_eq!(key, "a3adcb4895069a099df65a026d5ab131c3ebc14b");
+        assert_eq!(
+            key,
+            recording_key(&request("GET", "http://example.com/a", ""))
+        );
+        assert_ne!(
+            key,
+            recording_key(&request("POST", "http://example.com/a", ""))
+        );
+        assert_ne!(
+            key,
+            recording_key(&request("GET", "http://example.com/a", "{}"))
+        );
+    }
+
+    #[test]
+    fn replays_recorded_responses() {
+        let recording = Recording {
+            method: "GET".into(),
+            url: "http://example.com/apples".into(),
+            status: 404,
+            elapsed: Duration::from_millis(12),
+            headers: vec![("content-type".into(), "text/plain".into())],
+            body: vec![0xff, 0x00],
+        };
+
+        let table = recording.to_table();
+        assert!(table.contains_key("body_base64"));
+        assert_eq!(Recording::from_table(&table), Some(recording.clone()));
+
+        let url = Url::parse(&recording.url).unwrap();
+        let response = recording.into_response(url.clone()).unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+        assert_eq!(response.url(), &url);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+    }
+}
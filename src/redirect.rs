@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use log::info;
+use reqwest::{
+    header::{
+        HeaderMap, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE,
+        LOCATION, PROXY_AUTHORIZATION,
+    },
+    Client, Method, Request, Response, StatusCode, Url,
+};
+
+use crate::{
+    directive::{Directives, REDIRECTS},
+    retry::{execute, RetryPolicy},
+};
+
+/// Redirects followed when a request doesn't say otherwise
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Set with `--no-follow`, to get the redirect responses themselves
+static NO_FOLLOW: AtomicBool = AtomicBool::new(false);
+
+/// Don't follow redirects for any request, like `--no-follow`
+pub fn set_no_follow() {
+    NO_FOLLOW.store(true, Ordering::Relaxed);
+}
+
+/// How many redirects are followed for a request, given with a line like
+/// `# @redirects 0`
+pub fn max_redirects(directives: &Directives) -> Result<usize> {
+    if NO_FOLLOW.load(Ordering::Relaxed) {
+        return Ok(0);
+    }
+
+    match directives.value(REDIRECTS) {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("Invalid redirects: {value}")),
+        None => Ok(DEFAULT_MAX_REDIRECTS),
+    }
+}
+
+/// Send a request, and follow the redirects of the responses, up to `max`.
+/// Each redirect is shown with its status and location.
+pub async fn follow_redirects(
+    client: &Client,
+    request: Request,
+    retry: &RetryPolicy,
+    max: usize,
+) -> Result<Response> {
+    let mut sent = request.try_clone();
+    let mut response = execute(client, request, retry).await?;
+
+    for count in 1.. {
+        let Some(next) = sent
+            .as_ref()
+            .and_then(|sent| redirect_request(sent, &response))
+        else {
+            break;
+        };
+
+        let status = response.status();
+        if count > max {
+            if max > 0 {
+                info!("# Stopped after {max} redirects");
+            }
+            break;
+        }
+        info!("# {} -> {}", status, next.url());
+
+        sent = next.try_clone();
+        response = execute(client, next, retry).await?;
+    }
+
+    Ok(response)
+}
+
+/// The request to send for a redirect response. Unless the status is 307 or
+/// 308, it is sent as a GET without the body, and credentials are never sent
+/// to other hosts.
+fn redirect_request(sent: &Request, response: &Response) -> Option<Request> {
+    let status = response.status();
+    if !status.is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    let url = response.url().join(location).ok()?;
+
+    let keep_body = matches!(
+        status,
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+    );
+    let method = match sent.method() {
+        method if keep_body || method == Method::HEAD => method.clone(),
+        _ => Method::GET,
+    };
+
+    let mut next = Request::new(method, url.clone());
+    *next.headers_mut() =
+        redirect_headers(sent.headers(), sent.url(), &url, keep_body);
+    *next.timeout_mut() = sent.timeout().copied();
+    if keep_body {
+        if let Some(body) = sent.body().and_then(|b| b.as_bytes()) {
+            *next.body_mut() = Some(body.to_vec().into());
+        }
+    }
+
+    Some(next)
+}
+
+fn redirect_headers(
+    headers: &HeaderMap,
+    from: &Url,
+    to: &Url,
+    keep_body: bool,
+) -> HeaderMap {
+    let mut headers = headers.clone();
+    if !keep_body {
+        headers.remove(CONTENT_TYPE);
+        headers.remove(CONTENT_LENGTH);
+    }
+    if from.host_str() != to.host_str() || from.port() != to.port() {
+        headers.remove(AUTHORIZATION);
+        headers.remove(COOKIE);
+        headers.remove(PROXY_AUTHORIZATION);
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn reads_redirect_directives() {
+        let max = |buf| max_redirects(&Directives::parse(buf).unwrap());
+
+        assert_eq!(max("GET /\n").unwrap(), DEFAULT_MAX_REDIRECTS);
+        assert_eq!(max("# @redirects 0\nGET /\n").unwrap(), 0);
+        assert!(max("# @redirects many\nGET /\n").is_err());
+    }
+
+    #[test]
+    fn keeps_credentials_on_the_same_host() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        let same = redirect_headers(
+            &headers,
+            &url("https://a.example.com/old"),
+            &url("https://a.example.com/new"),
+            false,
+        );
+        assert!(same.contains_key(AUTHORIZATION));
+        assert!(!same.contains_key(CONTENT_TYPE));
+
+        let other = redirect_headers(
+            &headers,
+            &url("https://a.example.com/old"),
+            &url("https://b.example.com/new"),
+            true,
+        );
+        assert!(!other.contains_key(AUTHORIZATION));
+        assert!(other.contains_key(CONTENT_TYPE));
+    }
+}
@@ -0,0 +1,506 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use toml::{Table, Value};
+
+use crate::{
+    discovery::find_project_requests,
+    env::{CONFIG_FILE, LOCAL_CONFIG_FILE},
+    expiry::EXPIRES_KEY,
+    extract::APPEND_KEY,
+};
+
+/// A file with its content before and after a change. The path is relative
+/// to the root directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub old: String,
+    pub new: String,
+}
+
+/// Rename a value in every request, request config and the config files of
+/// a project. The changes are returned without writing them, so they can be
+/// shown first.
+pub fn rename_variable(
+    root_dir: &Path,
+    old: &str,
+    new: &str,
+) -> Result<Vec<FileChange>> {
+    for name in [old, new] {
+        if name.is_empty() || !name.chars().all(is_key_char) {
+            bail!("Invalid name `{name}`, expected letters, digits and _");
+        }
+    }
+    if old == new {
+        bail!("`{old}` already has that name");
+    }
+
+    let mut files =
+        vec![PathBuf::from(CONFIG_FILE), PathBuf::from(LOCAL_CONFIG_FILE)];
+    for request in find_project_requests(root_dir)? {
+        files.push(request.with_extension("http.toml"));
+        files.push(request);
+    }
+
+    let mut changes = Vec::new();
+    for path in files {
+        let Ok(content) = fs::read_to_string(root_dir.join(&path)) else {
+            continue;
+        };
+        let renamed = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => rename_in_config(&content, old, new),
+            _ => rename_in_request(&content, old, new),
+        };
+        if renamed != content {
+            changes.push(FileChange {
+                path,
+                old: content,
+                new: renamed,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Move values that are the same in every target of `hitman.toml` to the
+/// global defaults. Without a key, every such value is moved.
+pub fn hoist_values(
+    root_dir: &Path,
+    key: Option<&str>,
+) -> Result<Vec<FileChange>> {
+    let path = PathBuf::from(CONFIG_FILE);
+    let content = fs::read_to_string(root_dir.join(&path))?;
+
+    let shared = shared_values(&content)?;
+    let keys = match key {
+        Some(key) if shared.iter().any(|k| k == key) => vec![key.to_string()],
+        Some(key) => {
+            bail!("`{key}` doesn't have the same value in every target")
+        }
+        None => shared,
+    };
+
+    let mut new = content.clone();
+    for key in &keys {
+        new = hoist(&new, key)?;
+    }
+
+    Ok(changed(path, content, new))
+}
+
+/// Move a global default of `hitman.toml` to every target that doesn't have
+/// its own value
+pub fn push_down_value(root_dir: &Path, key: &str) -> Result<Vec<FileChange>> {
+    let path = PathBuf::from(CONFIG_FILE);
+    let content = fs::read_to_string(root_dir.join(&path))?;
+    let new = push_down(&content, key)?;
+
+    Ok(changed(path, content, new))
+}
+
+fn changed(path: PathBuf, old: String, new: String) -> Vec<FileChange> {
+    if old == new {
+        return Vec::new();
+    }
+
+    vec![FileChange { path, old, new }]
+}
+
+/// The targets of a config
+fn targets(config: &Table) -> Vec<(&String, &Table)> {
+    config
+        .iter()
+        .filter(|(name, _)| !name.starts_with('_'))
+        .filter_map(|(name, value)| Some((name, value.as_table()?)))
+        .collect()
+}
+
+/// The keys that have the same value in every target, that the global
+/// defaults don't give another value
+fn shared_values(content: &str) -> Result<Vec<String>> {
+    let config: Table = toml::from_str(content)?;
+    let targets = targets(&config);
+    let Some((_, first)) = targets.first() else {
+        return Ok(Vec::new());
+    };
+    if targets.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    Ok(first
+        .iter()
+        .filter(|(key, value)| {
+            !key.starts_with('_')
+                && !value.is_table()
+                && config.get(*key).is_none_or(|global| global == *value)
+                && targets.iter().all(|(_, t)| t.get(*key) == Some(value))
+        })
+        .map(|(key, _)| key.clone())
+        .collect())
+}
+
+/// The lines of a config, each with the table it's in, or an empty string at
+/// the top level. Every line ends with a line break.
+fn config_lines(content: &str) -> Vec<(String, String)> {
+    let mut table = String::new();
+
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let header = line.split('#').next().unwrap_or("").trim();
+            if header.starts_with('[') {
+                table = header.trim_matches(['[', ']']).trim().to_string();
+            }
+            let line = match line.ends_with('\n') {
+                true => line.to_string(),
+                false => format!("{line}\n"),
+            };
+            (table.clone(), line)
+        })
+        .collect()
+}
+
+fn is_header(line: &str) -> bool {
+    line.trim_start().starts_with('[')
+}
+
+/// The line setting a key, made to stand on its own
+fn key_line(line: &str, key: &str) -> Result<String> {
+    let line = line.trim_start().to_string();
+    toml::from_str::<Table>(&line).ok().with_context(|| {
+        format!("`{key}` spans several lines, and has to be moved by hand")
+    })?;
+
+    Ok(line)
+}
+
+fn hoist(content: &str, key: &str) -> Result<String> {
+    let config: Table = toml::from_str(content)?;
+    let target_names: Vec<&String> =
+        targets(&config).into_iter().map(|(name, _)| name).collect();
+
+    let mut lines = config_lines(content);
+    let is_target = |table: &str| target_names.iter().any(|t| *t == table);
+    let sets_key = |line: &str| key_of(line).is_some_and(|(_, k, _)| k == key);
+
+    let Some((_, line)) = lines
+        .iter()
+        .find(|(table, line)| is_target(table) && sets_key(line))
+    else {
+        return Ok(content.to_string());
+    };
+    let entry = key_line(line, key)?;
+
+    let has_global = config.contains_key(key);
+    lines.retain(|(table, line)| !(is_target(table) && sets_key(line)));
+    if !has_global {
+        // After the last global value, or before the first table
+        let at = match lines.iter().rposition(|(table, line)| {
+            table.is_empty() && key_of(line).is_some()
+        }) {
+            Some(i) => i + 1,
+            None => {
+                let i = lines.iter().position(|(_, l)| is_header(l));
+                let i = i.unwrap_or(lines.len());
+                lines.insert(i, (String::new(), "\n".to_string()));
+                i
+            }
+        };
+        lines.insert(at, (String::new(), entry));
+    }
+
+    Ok(lines.into_iter().map(|(_, line)| line).collect())
+}
+
+fn push_down(content: &str, key: &str) -> Result<String> {
+    let config: Table = toml::from_str(content)?;
+    match config.get(key) {
+        Some(Value::Table(_)) => bail!("`{key}` is a table, not a value"),
+        Some(_) => (),
+        None => bail!("`{key}` is not a global value"),
+    }
+
+    let mut lines = config_lines(content);
+    let Some(i) = lines.iter().position(|(table, line)| {
+        table.is_empty() && key_of(line).is_some_and(|(_, k, _)| k == key)
+    }) else {
+        bail!("`{key}` is not a global value");
+    };
+    let entry = key_line(&lines.remove(i).1, key)?;
+
+    for (name, values) in targets(&config) {
+        if values.contains_key(key) {
+            continue;
+        }
+        // After the last line of the target that isn't empty
+        let Some(at) = lines.iter().rposition(|(table, line)| {
+            table == name && !line.trim().is_empty()
+        }) else {
+            bail!("`{name}` has no table of its own, so `{key}` has to be moved by hand");
+        };
+        lines.insert(at + 1, (name.clone(), entry.clone()));
+    }
+
+    Ok(lines.into_iter().map(|(_, line)| line).collect())
+}
+
+/// Write the changes made by [rename_variable] and the other refactorings
+pub fn apply_changes(root_dir: &Path, changes: &[FileChange]) -> Result<()> {
+    for change in changes {
+        fs::write(root_dir.join(&change.path), &change.new)?;
+    }
+
+    Ok(())
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Rename the placeholders of a request, and the values its scripts use
+fn rename_in_request(content: &str, old: &str, new: &str) -> String {
+    let renamed = rename_placeholders(content, old, new);
+
+    rename_words(&renamed, &format!("vars.{old}"), &format!("vars.{new}"))
+}
+
+/// Rename a key in the placeholders of a template, like `{{old}}`,
+/// `{{old | 1}}` and `{{#each old}}`. Environment variables and plugins are
+/// left alone.
+fn rename_placeholders(template: &str, old: &str, new: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + end];
+        out.push_str(&rest[..start + 2]);
+
+        let (key, fallback) = match inner.find('|') {
+            Some(i) => inner.split_at(i),
+            None => (inner, ""),
+        };
+        let trimmed = key.trim_start();
+        if trimmed.starts_with("env:") || trimmed.starts_with("plugin ") {
+            out.push_str(inner);
+        } else {
+            out.push_str(&rename_words(key, old, new));
+            out.push_str(fallback);
+        }
+
+        out.push_str("}}");
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Replace whole words, that are not part of a longer key
+fn rename_words(text: &str, old: &str, new: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(i) = rest.find(old) {
+        let before = match i {
+            0 => out.chars().last(),
+            _ => rest[..i].chars().last(),
+        };
+        let after = rest[i + old.len()..].chars().next();
+        let whole = !before.is_some_and(|c| is_key_char(c) || c == '.')
+            && !after.is_some_and(is_key_char);
+
+        out.push_str(&rest[..i]);
+        out.push_str(if whole { new } else { old });
+        rest = &rest[i + old.len()..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Rename a key in a config file, keeping its formatting and comments. Keys
+/// are renamed at the top level, in targets, in extraction and expiry rules
+/// and in `_append`, while special tables, like `[_headers]`, are left alone.
+/// Placeholders in values are renamed everywhere.
+fn rename_in_config(content: &str, old: &str, new: &str) -> String {
+    let mut table = String::new();
+    let mut out = String::new();
+
+    for line in content.split_inclusive('\n') {
+        let header = line.split('#').next().unwrap_or("").trim();
+        if header.starts_with('[') {
+            table = header.trim_matches(['[', ']']).trim().to_string();
+        }
+
+        let holds_values = table.is_empty()
+            || (!table.contains('.') && !table.starts_with('_'))
+            || matches!(
+                table.split('.').next_back(),
+                Some("_extract" | EXPIRES_KEY)
+            );
+        let line = match key_of(line) {
+            Some((indent, key, rest)) if key == old && holds_values => {
+                if rest.starts_with('"') {
+                    format!("{indent}\"{new}\"{}", &rest[old.len() + 2..])
+                } else {
+                    format!("{indent}{new}{}", &rest[old.len()..])
+                }
+            }
+            Some((_, "sensitive", _)) if table == "_settings" => {
+                line.replace(&format!("\"{old}\""), &format!("\"{new}\""))
+            }
+            Some((_, APPEND_KEY, _)) if table.is_empty() => {
+                line.replace(&format!("\"{old}\""), &format!("\"{new}\""))
+            }
+            _ => line.to_string(),
+        };
+
+        out.push_str(&rename_placeholders(&line, old, new));
+    }
+
+    out
+}
+
+/// The indentation and key of a `key = value` line, and the line from the key
+fn key_of(line: &str) -> Option<(&str, &str, &str)> {
+    let rest = line.trim_start();
+    let indent = &line[..line.len() - rest.len()];
+    let (key, _) = rest.split_once('=')?;
+    let key = key.trim();
+    let key = key
+        .strip_prefix('"')
+        .and_then(|k| k.strip_suffix('"'))
+        .unwrap_or(key);
+
+    Some((indent, key, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_placeholders_and_script_values() {
+        let request =
+            "GET {{url}}/orders/{{ order_id | 1 }}?ids={{order_ids}}\n\
+             X-Home: {{env:order_id}}\n\
+             {{#each order_id}}{{this}}{{/each}}\n\
+             # @script after\n\
+             # vars.order_id = response.json.id;\n\
+             # vars.order_ids = [];\n\
+             # @end\n";
+
+        assert_eq!(
+            rename_in_request(request, "order_id", "id"),
+            "GET {{url}}/orders/{{ id | 1 }}?ids={{order_ids}}\n\
+             X-Home: {{env:order_id}}\n\
+             {{#each id}}{{this}}{{/each}}\n\
+             # @script after\n\
+             # vars.id = response.json.id;\n\
+             # vars.order_ids = [];\n\
+             # @end\n"
+        );
+    }
+
+    const TARGETS: &str = r#"api_username = "admin"
+
+[dev]
+url = "http://localhost:8080"
+timeout_ms = 500
+region = "eu"
+
+[prod]
+url = "https://example.com"
+region = "eu"
+timeout_ms = 500 # the same
+"#;
+
+    #[test]
+    fn hoists_values_shared_by_all_targets() {
+        assert_eq!(
+            shared_values(TARGETS).unwrap(),
+            vec!["region", "timeout_ms"]
+        );
+        assert_eq!(
+            hoist(TARGETS, "timeout_ms").unwrap(),
+            r#"api_username = "admin"
+timeout_ms = 500
+
+[dev]
+url = "http://localhost:8080"
+region = "eu"
+
+[prod]
+url = "https://example.com"
+region = "eu"
+"#
+        );
+    }
+
+    #[test]
+    fn pushes_global_values_down_to_targets() {
+        let config = "api_username = \"admin\"\n\n[dev]\nurl = \"a\"\n\n[prod]\napi_username = \"root\"\n";
+
+        assert_eq!(
+            push_down(config, "api_username").unwrap(),
+            "\n[dev]\nurl = \"a\"\napi_username = \"admin\"\n\n[prod]\napi_username = \"root\"\n"
+        );
+        assert!(push_down(config, "url").is_err());
+    }
+
+    #[test]
+    fn renames_keys_in_config() {
+        let config = r#"# Shared values
+token = "{{token_prefix}}-1"
+token_prefix = "x"
+_append = ["token"]
+
+[dev]
+url = "http://localhost/{{token}}"
+"token" = "dev" # local
+
+[_headers]
+token = "header values are not renamed"
+
+[_settings]
+sensitive = ["token", "otp"]
+
+[_extract]
+token = "$.access_token"
+
+[_expires]
+token = "1h""#;
+
+        assert_eq!(
+            rename_in_config(config, "token", "access_token"),
+            r#"# Shared values
+access_token = "{{token_prefix}}-1"
+token_prefix = "x"
+_append = ["access_token"]
+
+[dev]
+url = "http://localhost/{{access_token}}"
+"access_token" = "dev" # local
+
+[_headers]
+token = "header values are not renamed"
+
+[_settings]
+sensitive = ["access_token", "otp"]
+
+[_extract]
+access_token = "$.access_token"
+
+[_expires]
+access_token = "1h""#
+        );
+    }
+}
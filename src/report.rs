@@ -0,0 +1,159 @@
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{batch::BatchResult, monitor::CheckResult};
+
+/// A summary of a batch run for other tools, given as `format:path`, like
+/// `junit:results.xml`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Report {
+    Junit(PathBuf),
+}
+
+impl FromStr for Report {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((format, path)) = s.split_once(':') else {
+            bail!("Expected `format:path`, like `junit:results.xml`");
+        };
+        if path.is_empty() {
+            bail!("Missing path in `{s}`");
+        }
+
+        match format {
+            "junit" => Ok(Report::Junit(PathBuf::from(path))),
+            _ => bail!("Unknown report format `{format}`"),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Report::Junit(path) => write!(f, "JUnit report {}", path.display()),
+        }
+    }
+}
+
+pub fn write_report(report: &Report, result: &BatchResult) -> Result<()> {
+    match report {
+        Report::Junit(path) => {
+            fs::write(path, junit_xml(&result.results, result.elapsed))
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+    }
+}
+
+/// The results as a JUnit XML document, with one test case per request
+pub fn junit_xml(results: &[CheckResult], elapsed: Duration) -> String {
+    let failures = results.iter().filter(|r| !r.passed()).count();
+    let counts = format!(
+        r#"tests="{}" failures="{}" errors="0" time="{:.3}""#,
+        results.len(),
+        failures,
+        elapsed.as_secs_f64()
+    );
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<testsuites name=\"hitman\" {counts}>\n"));
+    xml.push_str(&format!("  <testsuite name=\"hitman\" {counts}>\n"));
+
+    for result in results {
+        let file = result.file.to_string_lossy().replace('\\', "/");
+        let (classname, name) = match file.rsplit_once('/') {
+            Some((dir, name)) => (dir.replace('/', "."), name.to_string()),
+            None => ("hitman".to_string(), file.clone()),
+        };
+        let time = result.elapsed.unwrap_or_default().as_secs_f64();
+
+        xml.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&classname),
+            escape_xml(&name),
+            time
+        ));
+
+        if result.passed() {
+            xml.push_str("/>\n");
+            continue;
+        }
+
+        let message = result.failures.join(", ");
+        xml.push_str(">\n");
+        xml.push_str(&format!(
+            "      <failure message=\"{}\">{}</failure>\n",
+            escape_xml(&message),
+            escape_xml(&result.failures.join("\n"))
+        ));
+        xml.push_str("    </testcase>\n");
+    }
+
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+
+    xml
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_report_argument() {
+        assert_eq!(
+            "junit:out/results.xml".parse::<Report>().unwrap(),
+            Report::Junit(PathBuf::from("out/results.xml"))
+        );
+        assert!("html:out.html".parse::<Report>().is_err());
+        assert!("results.xml".parse::<Report>().is_err());
+    }
+
+    #[test]
+    fn writes_junit_xml() {
+        let results = vec![
+            CheckResult {
+                file: PathBuf::from("smoke/login.http"),
+                status: Some(200),
+                elapsed: Some(Duration::from_millis(12)),
+                failures: Vec::new(),
+            },
+            CheckResult {
+                file: PathBuf::from("orders.http"),
+                status: Some(500),
+                elapsed: Some(Duration::from_millis(30)),
+                failures: vec!["status == 200 (got 500)".into()],
+            },
+        ];
+
+        let xml = junit_xml(&results, Duration::from_millis(42));
+
+        assert!(xml.contains(
+            r#"<testsuites name="hitman" tests="2" failures="1" errors="0" time="0.042">"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase classname="smoke" name="login.http" time="0.012"/>"#
+        ));
+        assert!(xml.contains(
+            r#"<failure message="status == 200 (got 500)">status == 200 (got 500)</failure>"#
+        ));
+        assert_eq!(
+            escape_xml(r#"<a href="x">&"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;"
+        );
+    }
+}
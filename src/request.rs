@@ -1,69 +1,651 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use httparse::Status::*;
 use log::{info, log_enabled, warn, Level};
 use regex::Regex;
-use reqwest::{Client, Method, Response, Url};
+use reqwest::{
+    header::CONTENT_TYPE, redirect::Policy, Client, Method, Request, Response,
+    Url,
+};
 use serde_json::{json, Value};
 use spinoff::{spinners, Color, Spinner, Streams};
 use std::{
     fs::read_to_string,
-    path::Path,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     str::{self, FromStr},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc::unbounded_channel,
 };
 use toml::Table;
 
 use crate::{
-    env::{update_data, HitmanCookieJar},
-    extract::extract_variables,
-    prompt::{get_interaction, substitute_interactive},
-    util::truncate,
+    assertions::{assertions, Assertion, ResponseInfo},
+    cache::explain_caching,
+    clock,
+    cookies::HitmanCookieJar,
+    curl::curl_command,
+    diff::{diff_responses, format_diff, previous_response},
+    directive::{Directives, OUTPUT},
+    download::{download, download_summary, is_download, progress_bar},
+    env::{find_root_dir, get_settings, update_data},
+    etag::{apply_etag, load_etags, save_etag},
+    extract::{
+        extract_header_variables, extract_markup_variables, extract_variables,
+        store_extracted,
+    },
+    format::{
+        describe_binary, format_body, highlight_for_terminal, magic_type,
+        BodyFormat,
+    },
+    grpc::{grpc_request, is_grpc_request},
+    har::{capture, is_capturing},
+    headers::{apply_default_headers, remove_headers, HeaderFilter},
+    history::{record_response, HistoryEntry},
+    hook::{request_method, run_response_hook, ResponseMeta},
+    image::{detect_graphics_protocol, image_info, inline_image},
+    include::include_body,
+    link::{follow_up_request, response_links, Link},
+    multipart::{encode_multipart, is_multipart, parse_parts, resolve_files},
+    oauth::with_access_token,
+    output::{output_file, save_response},
+    plugin::{apply_request_hooks, plugins_from},
+    prompt::{get_interaction, substitute_interactive, UserInteraction},
+    proxy::configure_proxy,
+    recording::{self, record, replay, Mode},
+    redirect::{follow_redirects, max_redirects},
+    retry::RetryPolicy,
+    schema::schema_changes,
+    script::RequestScripts,
+    security::{security_report, Grade},
+    timeout::{target_timeout, timeout_directive},
+    tls::configure_tls,
+    trust::ensure_trusted,
+    util::{open_with_system_viewer, truncate},
+    viewer::open_response,
+    websocket::{
+        is_websocket_request, parse_ws_request, run_session, WsEvent, WsMessage,
+    },
 };
 
-static USER_AGENT: &str =
+pub(crate) static USER_AGENT: &str =
     concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
-pub fn build_client() -> Result<Client> {
-    let client = Client::builder()
+pub fn build_client(env: &Table) -> Result<Client> {
+    // Redirects are followed by do_request, to show them and to limit them
+    // per request
+    let mut builder = Client::builder()
         .user_agent(USER_AGENT)
-        .cookie_provider(Arc::new(HitmanCookieJar))
-        .build()?;
-    Ok(client)
+        .redirect(Policy::none())
+        .cookie_provider(Arc::new(HitmanCookieJar::for_env(env)));
+
+    if let Some(timeout) = target_timeout(env) {
+        builder = builder.timeout(timeout);
+    }
+
+    builder = configure_proxy(builder, env)?;
+
+    Ok(configure_tls(builder, env)?.build()?)
 }
 
-pub async fn make_request(file_path: &Path, env: &Table) -> Result<()> {
-    let client = build_client()?;
+/// Read a request file, with the default headers of the target
+pub fn read_request(file_path: &Path, env: &Table) -> Result<String> {
+    let buf =
+        Directives::parse(&read_to_string(file_path)?)?.without(&[OUTPUT]);
+    let buf = match file_path.parent() {
+        Some(dir) => resolve_files(&include_body(&buf, dir)?, dir),
+        None => buf,
+    };
 
-    let interaction = get_interaction();
+    Ok(apply_default_headers(&buf, env))
+}
+
+/// Options for how a request is made, and how the response is shown
+#[derive(Debug, Default, Clone)]
+pub struct RequestOptions {
+    /// Explain how HTTP caches will treat the response
+    pub explain_cache: bool,
+
+    /// Grade the security headers of the response
+    pub security_report: bool,
+
+    /// Open the response body in an editor or viewer
+    pub open_response: bool,
+
+    /// Headers to remove from the request
+    pub unset_headers: Vec<String>,
+
+    /// Compare the response with the previous response for the request
+    pub diff: bool,
+
+    /// Show the response headers in the hide-list
+    pub all_headers: bool,
+
+    /// Only show the response headers matching this pattern
+    pub header_pattern: Option<String>,
+
+    /// Keep the output of earlier requests on the screen
+    pub keep_screen: bool,
+
+    /// Write the response body to this path, which can have placeholders,
+    /// instead of the path in the `# @output` line of the request
+    pub output: Option<PathBuf>,
+}
+
+/// A request as it is sent: with the access token and the values of its
+/// before script, substituted, and changed by the request hooks of plugins.
+/// The values are given back too, with the scripts to run on the response.
+pub async fn prepare_request(
+    file_path: &Path,
+    env: &Table,
+    unset_headers: &[String],
+) -> Result<(String, Table, RequestScripts)> {
+    let env = with_access_token(env).await?;
+
+    let (request, scripts) =
+        RequestScripts::extract(&read_request(file_path, &env)?)?;
+    let env = scripts.before(&env)?;
 
     let buf = substitute_interactive(
-        &read_to_string(file_path)?,
+        &remove_headers(&request, unset_headers),
+        &env,
+        get_interaction().as_ref(),
+    )?;
+    let buf = apply_request_hooks(&env, &buf)?;
+
+    Ok((buf, env, scripts))
+}
+
+pub async fn make_request(
+    file_path: &Path,
+    env: &Table,
+    options: &RequestOptions,
+) -> Result<()> {
+    let (mut buf, env, scripts) =
+        prepare_request(file_path, env, &options.unset_headers).await?;
+    let env = &env;
+    let client = build_client(env)?;
+
+    let interaction = get_interaction();
+
+    let output = match &options.output {
+        Some(output) => Some(output.clone()),
+        None => output_file(file_path)?,
+    };
+
+    let mut header_filter = HeaderFilter::from_settings(&get_settings(env));
+    header_filter.show_hidden = options.all_headers;
+    header_filter.only.clone_from(&options.header_pattern);
+
+    if is_websocket_request(file_path, &buf) {
+        if !options.keep_screen {
+            clear_screen();
+        }
+        print_request(&buf);
+        return make_websocket_request(&buf).await;
+    }
+
+    loop {
+        // Invalid assertions fail before the request is sent
+        let directives = Directives::parse(&buf)?;
+        let assertions = assertions(&directives)?;
+
+        if !options.keep_screen {
+            clear_screen();
+        }
+        print_request(&buf);
+
+        let mut spinner = Spinner::new_with_stream(
+            spinners::BouncingBar,
+            "",
+            Color::Yellow,
+            Streams::Stderr,
+        );
+        let retry = RetryPolicy::for_request(&directives, env)?;
+        let (response, elapsed) = do_request(&client, &buf, &retry).await?;
+        spinner.stop();
+
+        print_response(&response, &header_filter)?;
+
+        let links = response_links(response.headers(), response.url());
+
+        if options.explain_cache {
+            warn!("# Caching:");
+            for note in explain_caching(response.headers()) {
+                warn!("#  - {}", note);
+            }
+        }
+
+        if options.security_report {
+            print_security_report(&response);
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+
+        let meta = ResponseMeta {
+            file: file_path.display().to_string(),
+            method: request_method(&buf),
+            url: response.url().to_string(),
+            status: response.status().as_u16(),
+            content_type: content_type.clone(),
+            elapsed,
+        };
+
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+
+        let data = handle_response(response, env, interaction.as_ref()).await?;
+
+        if let Some(output) = &output {
+            save_response(output, file_path, env, status, &data)?;
+        }
+
+        if let Some(root_dir) = find_root_dir()? {
+            match record_response(&root_dir, &meta, &buf, &headers, &data) {
+                Ok(entry) => {
+                    if options.diff {
+                        print_diff(&root_dir, &entry)?;
+                    }
+                    print_schema_changes(&root_dir, &entry)?;
+                }
+                Err(err) => warn!("# Failed to save history: {}", err),
+            }
+        }
+
+        if let Err(err) = run_response_hook(env, &meta, &data) {
+            warn!("# {}", err);
+        }
+
+        if options.open_response {
+            open_response(&get_settings(env), &content_type, &data)?;
+        }
+
+        warn!("# Request completed in {:.2?}", elapsed);
+
+        let info = ResponseInfo {
+            status,
+            headers: &headers,
+            body: &String::from_utf8_lossy(&data),
+            elapsed,
+            plugins: &plugins_from(env)?,
+        };
+        update_data(&scripts.after(env, &info)?, env)?;
+
+        let failed = report_assertions(&assertions, &info);
+        if failed > 0 {
+            bail!("{} of {} assertions failed", failed, assertions.len());
+        }
+
+        match choose_link(&links, interaction.as_ref())? {
+            Some(link) => {
+                buf = apply_request_hooks(
+                    env,
+                    &follow_up_request(&buf, &link.url),
+                )?
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Stream the messages of a WebSocket connection to stdout, and send each
+/// line of stdin as a message, until the server closes the connection
+async fn make_websocket_request(buf: &str) -> Result<()> {
+    let request = parse_ws_request(buf)?;
+
+    let (outgoing, messages) = unbounded_channel();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if !line.is_empty() && outgoing.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (sender, mut events) = unbounded_channel();
+    let session = tokio::spawn(run_session(request, messages, sender));
+
+    while let Some(event) = events.recv().await {
+        match event {
+            WsEvent::Connected(head) => {
+                for line in head {
+                    info!("< {}", truncate(&line));
+                }
+                info!("");
+            }
+            WsEvent::Sent(text) => info!("> {}", truncate(&text)),
+            WsEvent::Received(WsMessage::Text(text)) => println!("{text}"),
+            WsEvent::Received(WsMessage::Binary(data)) => {
+                println!(
+                    "{}",
+                    describe_binary(BodyFormat::Binary, &data)
+                        .unwrap_or_default()
+                );
+            }
+            WsEvent::Closed(Some(reason)) => {
+                warn!("# Connection closed: {}", reason)
+            }
+            WsEvent::Closed(None) => warn!("# Connection closed"),
+        }
+    }
+
+    session.await?
+}
+
+/// Print the result of each assertion, and return how many failed
+fn report_assertions(
+    assertions: &[Assertion],
+    response: &ResponseInfo,
+) -> usize {
+    let mut failed = 0;
+    for assertion in assertions {
+        let res = assertion.evaluate(response);
+        if res.passed {
+            warn!("# PASS {}", res.source);
+        } else {
+            failed += 1;
+            // Messages can contain a whole body
+            let message = res.message.unwrap_or_default();
+            let message = message.lines().next().unwrap_or_default();
+            warn!("# FAIL {} ({})", res.source, truncate(message));
+        }
+    }
+
+    failed
+}
+
+async fn handle_response(
+    response: Response,
+    env: &Table,
+    interaction: &dyn UserInteraction,
+) -> Result<Vec<u8>> {
+    let format = response_format(&response);
+    store_extracted(
+        &extract_header_variables(response.headers(), env)?,
         env,
-        interaction.as_ref(),
+        None,
     )?;
+    let data = if is_download(format) {
+        download_with_progress(response).await?
+    } else {
+        response.bytes().await?.into()
+    };
+
+    if let Some(description) = describe_binary(format, &data) {
+        print_binary(&data, &description, interaction)?;
+        return Ok(data);
+    }
+
+    let body = String::from_utf8_lossy(&data);
+
+    if let Ok(json) = serde_json::from_str::<Value>(&body) {
+        print_body(&serde_json::to_string_pretty(&json)?, env)?;
+        let vars = extract_variables(&json, env)?;
+        store_extracted(&vars, env, Some(&json))?;
+    } else if !body.is_empty() {
+        store_extracted(&extract_markup_variables(&body, env)?, env, None)?;
+        let text = format_body(format, &body);
+        if io::stdout().is_terminal() {
+            print_body(&highlight_for_terminal(&text, format), env)?;
+        } else {
+            print_body(&text, env)?;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Print a request, with everything substituted, as a `curl` command that
+/// sends the same request
+pub async fn print_curl(
+    file_path: &Path,
+    env: &Table,
+    options: &RequestOptions,
+) -> Result<()> {
+    let (buf, _, _) =
+        prepare_request(file_path, env, &options.unset_headers).await?;
+
+    if is_websocket_request(file_path, &buf) || is_grpc_request(&buf) {
+        bail!("Only HTTP requests can be written as curl commands");
+    }
+
+    println!("{}", curl_command(&buf, &load_etags())?);
+
+    Ok(())
+}
+
+/// Print the changes since the previous response for the same request
+fn print_diff(root_dir: &Path, entry: &HistoryEntry) -> Result<()> {
+    let Some(previous) = previous_response(root_dir, entry)? else {
+        warn!("# No earlier response to compare with");
+        return Ok(());
+    };
+
+    let diff = diff_responses(&previous, entry);
+    if !diff.iter().any(|line| line.is_change()) {
+        warn!("# Same as the response from {}", previous.local_time());
+        return Ok(());
+    }
+
+    warn!(
+        "# Changes since the response from {}:",
+        previous.local_time()
+    );
+    print!("{}", format_diff(&diff, io::stdout().is_terminal()));
+
+    Ok(())
+}
+
+/// Warn about fields that were added, removed or changed type since the
+/// previous response for the same request
+fn print_schema_changes(root_dir: &Path, entry: &HistoryEntry) -> Result<()> {
+    let Some((previous, changes)) = schema_changes(root_dir, entry)? else {
+        return Ok(());
+    };
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    warn!(
+        "# Schema changes since the response from {}:",
+        previous.local_time()
+    );
+    for change in changes {
+        warn!("#   {change}");
+    }
+
+    Ok(())
+}
+
+/// Print a response from the history, as it was shown when it was received
+pub fn print_history_entry(entry: &HistoryEntry) -> Result<()> {
+    warn!(
+        "# {} {} {} ({})",
+        entry.local_time(),
+        entry.method,
+        entry.url,
+        entry.target
+    );
+
+    print_request(&entry.request);
+
+    if log_enabled!(Level::Info) {
+        let status = reqwest::StatusCode::from_u16(entry.status)?;
+        info!(
+            "< HTTP/1.1 {} {}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("")
+        );
+        for (name, value) in &entry.headers {
+            info!("< {}: {}", name, truncate(value));
+        }
+        info!("");
+    }
+
+    let format = BodyFormat::from_content_type(&entry.content_type);
+    if let Some(description) = describe_binary(format, &entry.body) {
+        warn!("# {}", description);
+        return Ok(());
+    }
+
+    let body = String::from_utf8_lossy(&entry.body);
+    let (text, format) = match serde_json::from_str::<Value>(&body) {
+        Ok(json) => (serde_json::to_string_pretty(&json)?, BodyFormat::Json),
+        Err(_) => (format_body(format, &body), format),
+    };
+    if io::stdout().is_terminal() {
+        println!("{}", highlight_for_terminal(&text, format));
+    } else {
+        println!("{}", text);
+    }
+
+    warn!("# Received in {:.2?}", entry.elapsed);
+
+    Ok(())
+}
 
-    clear_screen();
-    print_request(&buf);
+/// List the links of a response as numbered follow-up requests, and let the
+/// user pick one to execute.
+fn choose_link<'a>(
+    links: &'a [Link],
+    interaction: &dyn UserInteraction,
+) -> Result<Option<&'a Link>> {
+    if links.is_empty() {
+        return Ok(None);
+    }
+
+    let labels: Vec<String> = links.iter().map(Link::label).collect();
+
+    warn!("# Links:");
+    for (i, label) in labels.iter().enumerate() {
+        warn!("#  [{}] {}", i + 1, label);
+    }
+
+    let selected = interaction.choose("Follow link", &labels)?;
+
+    Ok(selected.map(|i| &links[i]))
+}
+
+pub fn response_format(res: &Response) -> BodyFormat {
+    res.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(BodyFormat::from_content_type)
+        .unwrap_or_default()
+}
+
+fn print_security_report(response: &Response) {
+    let notes = security_report(response.headers(), response.url());
+
+    warn!("# Security headers:");
+    for note in &notes {
+        warn!("#  [{}] {}: {}", note.grade, note.header, note.message);
+    }
 
+    let count = |grade| notes.iter().filter(|n| n.grade == grade).count();
+    warn!(
+        "# {} passed, {} warnings, {} failed",
+        count(Grade::Pass),
+        count(Grade::Warn),
+        count(Grade::Fail)
+    );
+}
+
+/// Download a binary body, with a progress bar while it arrives, and its size
+/// and checksum at the end
+async fn download_with_progress(response: Response) -> Result<Vec<u8>> {
+    let started = Instant::now();
     let mut spinner = Spinner::new_with_stream(
-        spinners::BouncingBar,
+        spinners::Dots,
         "",
         Color::Yellow,
         Streams::Stderr,
     );
-    let (response, elapsed) = do_request(&client, &buf).await?;
-    spinner.stop();
 
-    print_response(&response)?;
+    let data = download(response, |received, total| {
+        spinner.update_text(progress_bar(received, total))
+    })
+    .await;
+    spinner.clear();
+    let data = data?;
 
-    if let Ok(json) = response.json::<Value>().await {
-        println!("{}", serde_json::to_string_pretty(&json)?);
-        let vars = extract_variables(&json, env)?;
-        update_data(&vars)?;
+    warn!("# {}", download_summary(&data, started));
+
+    Ok(data)
+}
+
+/// Print a binary response. Unless output is redirected to a file, the raw
+/// data is replaced by a description, or by an inline preview of images when
+/// the terminal supports it.
+fn print_binary(
+    data: &[u8],
+    description: &str,
+    interaction: &dyn UserInteraction,
+) -> Result<()> {
+    let mut stdout = io::stdout();
+
+    if !stdout.is_terminal() {
+        stdout.write_all(data)?;
+        return Ok(());
+    }
+
+    warn!("# {}", description);
+
+    let preview = image_info(data).and_then(|info| {
+        inline_image(data, &info, &detect_graphics_protocol()?)
+    });
+    if let Some(preview) = preview {
+        stdout.write_all(preview.as_bytes())?;
+    } else if interaction.confirm("Open with system viewer?")? {
+        let ext = magic_type(data).map(|(_, ext)| ext).unwrap_or("bin");
+        let path = std::env::temp_dir().join(format!(
+            "hitman-{}.{}",
+            std::process::id(),
+            ext
+        ));
+        std::fs::write(&path, data)?;
+        open_with_system_viewer(&path)?;
     }
 
-    warn!("# Request completed in {:.2?}", elapsed);
+    Ok(())
+}
+
+/// Print the response body, through the configured pager if any
+fn print_body(body: &str, env: &Table) -> Result<()> {
+    let settings = get_settings(env);
+    let pager = settings.get("pager").and_then(|p| p.as_str());
+
+    match pager {
+        Some(pager) if io::stdout().is_terminal() => {
+            ensure_trusted("Pager", pager)?;
+            let mut args = pager.split_whitespace();
+            let program = args.next().context("Invalid pager setting")?;
+
+            let mut child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::piped())
+                .spawn()
+                .with_context(|| format!("Failed to start pager {pager}"))?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                writeln!(stdin, "{}", body)?;
+            }
+            child.wait()?;
+        }
+        _ => println!("{}", body),
+    }
 
     Ok(())
 }
@@ -85,7 +667,73 @@ fn clear_screen() {
 pub async fn do_request(
     client: &Client,
     buf: &str,
+    retry: &RetryPolicy,
 ) -> Result<(Response, Duration)> {
+    if is_grpc_request(buf) {
+        return grpc_request(buf).await;
+    }
+
+    let directives = Directives::parse(buf)?;
+    let request = build_request(client, &directives, &load_etags())?;
+    let url = request.url().clone();
+
+    // Kept for the HTTP Archive as it was sent
+    let captured = if is_capturing() {
+        request.try_clone()
+    } else {
+        None
+    };
+    let started = clock::now();
+
+    let mode = recording::mode();
+    let (response, elapsed) = if mode == Mode::Offline {
+        replay(&request)?
+    } else {
+        // The response is recorded for the request as it was sent
+        let recorded = match mode {
+            Mode::Record => request.try_clone(),
+            _ => None,
+        };
+
+        let t = Instant::now();
+        let response = follow_redirects(
+            client,
+            request,
+            retry,
+            max_redirects(&directives)?,
+        )
+        .await?;
+
+        let elapsed = t.elapsed();
+
+        let response = match recorded {
+            Some(request) => record(&request, response, elapsed).await?,
+            None => response,
+        };
+
+        save_etag(&url, response.status().as_u16(), response.headers());
+
+        (response, elapsed)
+    };
+
+    let response = match captured {
+        Some(request) => capture(&request, response, started, elapsed).await?,
+        None => response,
+    };
+
+    Ok((response, elapsed))
+}
+
+/// The HTTP request described by a prepared request, without sending it.
+/// Requests that change a resource get the ETag captured for it.
+pub fn build_request(
+    client: &Client,
+    directives: &Directives,
+    etags: &Table,
+) -> Result<Request> {
+    let timeout = timeout_directive(directives)?;
+    let buf = &apply_etag(directives, etags);
+
     let mut headers = [httparse::EMPTY_HEADER; 64];
     let mut req = httparse::Request::new(&mut headers);
 
@@ -101,10 +749,19 @@ pub async fn do_request(
 
     let mut builder = client.request(method, url);
 
+    // Fields of a form, encoded here so the boundary is never written by hand
+    let multipart = req.headers.iter().any(|header| {
+        header.name.eq_ignore_ascii_case(CONTENT_TYPE.as_str())
+            && str::from_utf8(header.value).is_ok_and(is_multipart)
+    });
+
     if let Complete(offset) = parse_result {
         let body = &buf[offset..];
 
-        if body.starts_with("query") || body.starts_with("mutation") {
+        if multipart {
+            let (content_type, body) = encode_multipart(&parse_parts(body)?)?;
+            builder = builder.header(CONTENT_TYPE, content_type).body(body);
+        } else if body.starts_with("query") || body.starts_with("mutation") {
             // Split when we find a closing bracket, followed by one or more
             // newlines, and then a starting bracket. This should be
             // the space between the body and the potential
@@ -141,16 +798,20 @@ pub async fn do_request(
             break;
         }
         let value = str::from_utf8(header.value)?;
+        if multipart && header.name.eq_ignore_ascii_case(CONTENT_TYPE.as_str())
+        {
+            continue;
+        }
 
         builder = builder.header(String::from(header.name), value);
     }
 
-    let t = std::time::Instant::now();
-    let response = builder.send().await?;
-
-    let elapsed = t.elapsed();
+    // Takes precedence over the timeout of the client
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
 
-    Ok((response, elapsed))
+    Ok(builder.build()?)
 }
 
 fn print_request(buf: &str) {
@@ -163,7 +824,7 @@ fn print_request(buf: &str) {
     }
 }
 
-fn print_response(res: &Response) -> Result<()> {
+fn print_response(res: &Response, filter: &HeaderFilter) -> Result<()> {
     if log_enabled!(Level::Info) {
         let status = res.status();
         info!(
@@ -173,13 +834,21 @@ fn print_response(res: &Response) -> Result<()> {
         );
 
         let mut head = String::new();
+        let mut hidden = 0;
         for (name, value) in res.headers() {
+            if !filter.shows(name.as_str()) {
+                hidden += 1;
+                continue;
+            }
             head.push_str(&format!("{}: {}\n", name, value.to_str()?));
         }
 
         for line in head.lines() {
             info!("< {}", truncate(line));
         }
+        if hidden > 0 {
+            info!("< ({hidden} headers hidden)");
+        }
 
         info!("");
     }
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use reqwest::{Client, Request, Response, StatusCode};
+use toml::{Table, Value};
+
+use crate::{
+    directive::{Directives, RETRIES},
+    timeout::http_setting,
+    util::{duration_value, parse_duration},
+};
+
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How often a request is sent again when the connection fails or it times
+/// out, or when the server responds with a 5xx status if `retry_5xx` is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// The time to wait before the first retry, doubled for each retry
+    pub backoff: Duration,
+    pub retry_5xx: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: DEFAULT_BACKOFF,
+            retry_5xx: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The retries of a request, from its `# @retries` line, like
+    /// `# @retries 3 2s`, or the `retries` and `retry_backoff` settings of
+    /// the target
+    pub fn for_request(directives: &Directives, env: &Table) -> Result<Self> {
+        let mut policy = Self::for_target(env)?;
+
+        if let Some(value) = directives.value(RETRIES) {
+            let mut parts = value.split_whitespace();
+            policy.retries = parts
+                .next()
+                .and_then(|n| n.parse().ok())
+                .with_context(|| format!("Invalid retries: {value}"))?;
+            if let Some(backoff) = parts.next() {
+                policy.backoff = parse_duration(backoff)
+                    .with_context(|| format!("Invalid retries: {value}"))?;
+            }
+        }
+
+        Ok(policy)
+    }
+
+    fn for_target(env: &Table) -> Result<Self> {
+        let mut policy = Self::default();
+
+        match http_setting(env, "retries") {
+            Some(Value::Integer(n)) if n >= 0 => policy.retries = n as u32,
+            Some(value) => bail!("Invalid retries: {value}"),
+            None => (),
+        }
+        if let Some(value) = http_setting(env, "retry_backoff") {
+            policy.backoff = duration_value(&value)
+                .with_context(|| format!("Invalid retry_backoff: {value}"))?;
+        }
+        policy.retry_5xx = http_setting(env, "retry_5xx")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        Ok(policy)
+    }
+
+    /// The time to wait before a retry, counted from 1
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+
+    fn retries_status(&self, status: StatusCode) -> bool {
+        self.retry_5xx && status.is_server_error()
+    }
+}
+
+/// Send a request, and send it again as given by the retry policy
+pub async fn execute(
+    client: &Client,
+    mut request: Request,
+    policy: &RetryPolicy,
+) -> Result<Response> {
+    let mut retry = 0;
+    loop {
+        let next = if retry < policy.retries {
+            request.try_clone()
+        } else {
+            None
+        };
+        let result = client.execute(request).await;
+        let Some(next) = next else {
+            return Ok(result?);
+        };
+
+        let reason = match &result {
+            Err(err) if err.is_connect() => "Connection failed".to_string(),
+            Err(err) if err.is_timeout() => "Timed out".to_string(),
+            Ok(res) if policy.retries_status(res.status()) => {
+                format!("Got {}", res.status())
+            }
+            _ => return Ok(result?),
+        };
+
+        retry += 1;
+        let delay = policy.delay(retry);
+        warn!(
+            "# {reason}, retrying in {delay:.1?} ({retry}/{})",
+            policy.retries
+        );
+        tokio::time::sleep(delay).await;
+        request = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn for_request(buf: &str, env: &Table) -> Result<RetryPolicy> {
+        RetryPolicy::for_request(&Directives::parse(buf).unwrap(), env)
+    }
+
+    #[test]
+    fn reads_retry_policies() {
+        let env: Table = toml::from_str(
+            r#"
+            [_http]
+            retries = 2
+            retry_backoff = "1s"
+            retry_5xx = true
+            "#,
+        )
+        .unwrap();
+
+        let policy = for_request("GET /\n", &env).unwrap();
+        assert_eq!(policy.retries, 2);
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert!(policy.retries_status(StatusCode::BAD_GATEWAY));
+        assert!(!policy.retries_status(StatusCode::NOT_FOUND));
+
+        let buf = "# @retries 5 200ms\nGET /\n";
+        let policy = for_request(buf, &env).unwrap();
+        assert_eq!(policy.retries, 5);
+        assert_eq!(policy.backoff, Duration::from_millis(200));
+
+        assert!(for_request("# @retries x\n", &env).is_err());
+        assert_eq!(
+            for_request("GET /\n", &Table::new()).unwrap(),
+            RetryPolicy::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_requests_that_time_out() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            // The first request never gets an answer
+            let (mut slow, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let len = slow.read(&mut buf).await.unwrap();
+            assert!(len > 0);
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read(&mut buf).await.unwrap();
+            assert!(len > 0);
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            drop(slow);
+        });
+
+        let client = Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let request = client.get(format!("http://{addr}/")).build().unwrap();
+        let policy = RetryPolicy {
+            retries: 1,
+            backoff: Duration::from_millis(10),
+            retry_5xx: false,
+        };
+
+        let res = execute(&client, request, &policy).await.unwrap();
+        server.await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
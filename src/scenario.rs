@@ -0,0 +1,542 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{error, warn};
+use toml::{Table, Value};
+
+use crate::{
+    directive::Directives,
+    env::{get_settings, is_sensitive_key, load_env},
+    monitor::{find_checks, run_check_with, CheckResult},
+    oauth::with_access_token,
+    prompt::{substitute_interactive, NoUserInteraction},
+    random::next_u64,
+    request::{build_client, do_request, read_request},
+    retry::RetryPolicy,
+    state::{ensure_state_dir, runs_dir},
+    substitute::placeholder_keys,
+};
+
+/// Compensating requests for a step, given in its `.http.toml` file, such as
+/// `_rollback = "delete_apple.http"`
+const ROLLBACK_KEY: &str = "_rollback";
+
+/// Header sent with every step of a scenario, so that the data it creates
+/// can be traced back to the run
+const RUN_HEADER: &str = "X-Hitman-Run";
+
+/// The id of a run, which can also be used in requests as `{{run_id}}`, for
+/// example to give created resources unique names
+const RUN_ID_KEY: &str = "run_id";
+
+/// A compensating request, to be sent when the scenario ends. The request
+/// is substituted when it is sent, with the values it used when the step
+/// ran, so that sensitive values, like tokens, are never kept on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollback {
+    pub file: PathBuf,
+    /// Values of the run, and the values the request uses, except the
+    /// sensitive ones
+    pub values: Vec<(String, String)>,
+}
+
+impl Rollback {
+    fn to_value(&self) -> Value {
+        let values = self
+            .values
+            .iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+
+        let mut table = Table::new();
+        table.insert(
+            "file".to_string(),
+            Value::String(self.file.display().to_string()),
+        );
+        table.insert("values".to_string(), Value::Table(values));
+
+        Value::Table(table)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        let table = value.as_table()?;
+        let values = table
+            .get("values")?
+            .as_table()?
+            .iter()
+            .filter_map(|(key, value)| {
+                Some((key.clone(), value.as_str()?.to_string()))
+            })
+            .collect();
+
+        Some(Self {
+            file: PathBuf::from(table.get("file")?.as_str()?),
+            values,
+        })
+    }
+}
+
+/// A run of several requests, such as a scenario or a batch. Each request
+/// is tagged with the id of the run, and the compensating requests of the
+/// steps are registered, so that the data created by the run can be removed.
+pub struct Run {
+    pub id: String,
+    rollbacks: Vec<Rollback>,
+}
+
+impl Run {
+    pub fn start() -> Self {
+        let id = new_run_id();
+        warn!("# Starting run {id}");
+
+        Self {
+            id,
+            rollbacks: Vec::new(),
+        }
+    }
+
+    /// The values of the run, to substitute in its requests
+    pub fn values(&self) -> Vec<(String, String)> {
+        vec![(RUN_ID_KEY.to_string(), self.id.clone())]
+    }
+
+    /// The headers sent with every request of the run
+    pub fn headers(&self) -> Vec<(String, String)> {
+        vec![(RUN_HEADER.to_string(), self.id.clone())]
+    }
+
+    /// Register the compensating requests of a step. A step that got a
+    /// successful response may have created something, even when its
+    /// assertions failed.
+    pub async fn register_rollbacks(
+        &mut self,
+        root_dir: &Path,
+        step: &Path,
+        result: &CheckResult,
+    ) -> Result<()> {
+        if !result.status.is_some_and(|s| (200..300).contains(&s)) {
+            return Ok(());
+        }
+
+        let env = load_env(root_dir, step, &[])?;
+        for file in rollback_files(step, &env)? {
+            // Prepared now, while the values captured by the step are still
+            // current
+            match prepare_rollback(root_dir, &file, &self.values()).await {
+                Ok(rollback) => {
+                    self.rollbacks.push(rollback);
+                    save_rollbacks(root_dir, &self.id, &self.rollbacks)?;
+                }
+                Err(err) => {
+                    error!("# Failed to prepare {}: {err}", file.display())
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send the compensating requests, or keep them for `hitman cleanup`
+    pub async fn finish(self, root_dir: &Path, keep: bool) -> Result<()> {
+        if !keep {
+            return roll_back(root_dir, &self.id, self.rollbacks).await;
+        }
+
+        if !self.rollbacks.is_empty() {
+            warn!(
+                "# Run `hitman cleanup {}` to remove the created data",
+                self.id
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Options for a scenario run
+#[derive(Debug, Default, Clone)]
+pub struct ScenarioOptions {
+    /// Keep the created data, to be removed later with `hitman cleanup`
+    pub keep: bool,
+}
+
+/// The compensating request files registered for a step, relative to the
+/// step itself
+pub fn rollback_files(file: &Path, env: &Table) -> Result<Vec<PathBuf>> {
+    let names = match env.get(ROLLBACK_KEY) {
+        None => Vec::new(),
+        Some(Value::String(name)) => vec![name.clone()],
+        Some(Value::Array(names)) => names
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(String::from)
+                    .with_context(|| format!("Invalid {ROLLBACK_KEY}: {v}"))
+            })
+            .collect::<Result<_>>()?,
+        Some(other) => bail!("Invalid {ROLLBACK_KEY}: {other}"),
+    };
+
+    let dir = file.parent().unwrap_or(Path::new(""));
+
+    Ok(names.iter().map(|name| dir.join(name)).collect())
+}
+
+/// Run the requests in a directory once, in alphabetical order, stopping at
+/// the first failed step. The compensating requests registered by the steps
+/// that succeeded are sent afterwards, in reverse order, whether the
+/// scenario passed or not.
+pub async fn run_scenario(
+    root_dir: &Path,
+    path: &Path,
+    options: &ScenarioOptions,
+) -> Result<()> {
+    let files = find_checks(path)?;
+
+    // Compensating requests are only sent as rollbacks, not as steps
+    let mut compensating = Vec::new();
+    for file in &files {
+        let env = load_env(root_dir, file, &[])?;
+        compensating.extend(rollback_files(file, &env)?);
+    }
+    let steps: Vec<&PathBuf> =
+        files.iter().filter(|f| !compensating.contains(f)).collect();
+    if steps.is_empty() {
+        bail!("No requests found in {}", path.display());
+    }
+
+    let mut run = Run::start();
+
+    let mut passed = true;
+    for step in steps {
+        let result =
+            run_check_with(root_dir, step, &run.values(), &run.headers()).await;
+        println!("{}", result.summary());
+        run.register_rollbacks(root_dir, step, &result).await?;
+
+        if !result.passed() {
+            passed = false;
+            break;
+        }
+    }
+
+    run.finish(root_dir, options.keep).await?;
+
+    if !passed {
+        bail!("Scenario failed");
+    }
+
+    Ok(())
+}
+
+/// Send the compensating requests registered by an earlier run, such as one
+/// made with `hitman run --keep`
+pub async fn cleanup(root_dir: &Path, run_id: &str) -> Result<()> {
+    let file = run_file(root_dir, run_id);
+    if !file.exists() {
+        bail!("No rollbacks registered for run {run_id}");
+    }
+
+    let content: Table = toml::from_str(&fs::read_to_string(&file)?)
+        .with_context(|| format!("Invalid run file {}", file.display()))?;
+    let rollbacks = match content.get("rollback") {
+        Some(Value::Array(items)) => {
+            items.iter().filter_map(Rollback::from_value).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    roll_back(root_dir, run_id, rollbacks).await
+}
+
+/// The runs that still have compensating requests to send, with the number
+/// of requests for each
+pub fn pending_runs(root_dir: &Path) -> Result<Vec<(String, usize)>> {
+    let dir = runs_dir(root_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(run_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let count = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| toml::from_str::<Table>(&c).ok())
+            .and_then(|t| t.get("rollback")?.as_array().map(Vec::len))
+            .unwrap_or(0);
+
+        runs.push((run_id.to_string(), count));
+    }
+    runs.sort();
+
+    Ok(runs)
+}
+
+/// Send compensating requests in reverse order. Requests that fail are kept
+/// for the next `hitman cleanup`.
+async fn roll_back(
+    root_dir: &Path,
+    run_id: &str,
+    rollbacks: Vec<Rollback>,
+) -> Result<()> {
+    if rollbacks.is_empty() {
+        return Ok(());
+    }
+
+    warn!("# Rolling back {} steps", rollbacks.len());
+
+    let mut failed = Vec::new();
+    for rollback in rollbacks.into_iter().rev() {
+        let result = send_rollback(root_dir, &rollback).await;
+        println!("{}", result.summary());
+        if !result.passed() {
+            failed.insert(0, rollback);
+        }
+    }
+
+    if failed.is_empty() {
+        let file = run_file(root_dir, run_id);
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    } else {
+        save_rollbacks(root_dir, run_id, &failed)?;
+        warn!("# Run `hitman cleanup {run_id}` to retry the failed requests");
+    }
+
+    Ok(())
+}
+
+async fn prepare_rollback(
+    root_dir: &Path,
+    file: &Path,
+    values: &[(String, String)],
+) -> Result<Rollback> {
+    let env = load_env(root_dir, file, values)?;
+    let settings = get_settings(&env);
+
+    let mut captured = values.to_vec();
+    for key in placeholder_keys(&read_request(file, &env)?) {
+        if is_sensitive_key(&settings, &key)
+            || captured.iter().any(|(k, _)| *k == key)
+        {
+            continue;
+        }
+        let value = match env.get(&key) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Array(_) | Value::Table(_)) | None => continue,
+            Some(other) => other.to_string(),
+        };
+        captured.push((key, value));
+    }
+
+    Ok(Rollback {
+        // Absolute, so that the cleanup command works from any directory
+        file: fs::canonicalize(file)?,
+        values: captured,
+    })
+}
+
+/// The compensating request to send, with the sensitive values and access
+/// token of now
+async fn rollback_request(
+    root_dir: &Path,
+    rollback: &Rollback,
+) -> Result<(Table, String)> {
+    let env = load_env(root_dir, &rollback.file, &rollback.values)?;
+    let env = with_access_token(&env).await?;
+    let request = substitute_interactive(
+        &read_request(&rollback.file, &env)?,
+        &env,
+        &NoUserInteraction,
+    )?;
+
+    Ok((env, request))
+}
+
+fn run_file(root_dir: &Path, run_id: &str) -> PathBuf {
+    runs_dir(root_dir).join(format!("{run_id}.toml"))
+}
+
+/// Keep the compensating requests of a run on disk, so that they can be sent
+/// later if the run is interrupted or kept
+fn save_rollbacks(
+    root_dir: &Path,
+    run_id: &str,
+    rollbacks: &[Rollback],
+) -> Result<()> {
+    ensure_state_dir(root_dir)?;
+    fs::create_dir_all(runs_dir(root_dir))?;
+
+    let mut content = Table::new();
+    content.insert(
+        "rollback".to_string(),
+        Value::Array(rollbacks.iter().map(Rollback::to_value).collect()),
+    );
+    fs::write(
+        run_file(root_dir, run_id),
+        toml::to_string_pretty(&content)?,
+    )?;
+
+    Ok(())
+}
+
+/// An id like `20241015-093012-481-7f3a`, from the time with milliseconds
+/// and a random suffix, so that runs started at the same time don't share a
+/// run file
+fn new_run_id() -> String {
+    format!(
+        "{}-{:04x}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S-%3f"),
+        next_u64() & 0xffff
+    )
+}
+
+async fn send_rollback(root_dir: &Path, rollback: &Rollback) -> CheckResult {
+    let mut result = CheckResult {
+        file: rollback.file.clone(),
+        status: None,
+        elapsed: None,
+        failures: Vec::new(),
+    };
+
+    let response = async {
+        let (env, request) = rollback_request(root_dir, rollback).await?;
+        let directives = Directives::parse(&request)?;
+        let retry = RetryPolicy::for_request(&directives, &env)?;
+        do_request(&build_client(&env)?, &request, &retry).await
+    };
+    match response.await {
+        Ok((response, elapsed)) => {
+            let status = response.status();
+            result.status = Some(status.as_u16());
+            result.elapsed = Some(elapsed);
+            if status.is_client_error() || status.is_server_error() {
+                result.failures.push(format!("status {status}"));
+            }
+        }
+        Err(err) => result.failures.push(err.to_string()),
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn finds_rollback_files_next_to_step() {
+        let env: Table = toml::from_str(
+            r#"
+            _rollback = ["delete_apple.http", "../cleanup/reset.http"]
+            "#,
+        )
+        .unwrap();
+
+        let res =
+            rollback_files(Path::new("orders/01_create.http"), &env).unwrap();
+
+        assert_eq!(
+            res,
+            vec![
+                PathBuf::from("orders/delete_apple.http"),
+                PathBuf::from("orders/../cleanup/reset.http"),
+            ]
+        );
+        assert!(rollback_files(Path::new("a.http"), &Table::new())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn gives_runs_unique_ids() {
+        let (first, second) = (new_run_id(), new_run_id());
+
+        assert_ne!(first, second);
+        assert_eq!(first.len(), "20241015-093012-481-7f3a".len());
+    }
+
+    #[test]
+    fn keeps_rollbacks_as_toml() {
+        let rollback = Rollback {
+            file: PathBuf::from("/project/orders/delete_apple.http"),
+            values: vec![
+                ("id".to_string(), "12".to_string()),
+                ("run_id".to_string(), "20241015-093012-481-7f3a".to_string()),
+            ],
+        };
+
+        assert_eq!(Rollback::from_value(&rollback.to_value()), Some(rollback));
+    }
+
+    #[test]
+    fn rejects_invalid_rollbacks() {
+        let env: Table = toml::from_str("_rollback = 1").unwrap();
+
+        assert!(rollback_files(Path::new("a.http"), &env).is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_rollbacks_with_secrets() {
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let len = tcp.read(&mut buf).await.unwrap();
+            tcp.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..len]).to_string()
+        });
+
+        let root = std::env::temp_dir()
+            .join(format!("hitman-rollback-{}", std::process::id()));
+        fs::create_dir_all(root.join(".hitman/data")).unwrap();
+        fs::write(
+            root.join("hitman.toml"),
+            format!(
+                "[default]\napi_key = \"s3cret-key\"\nhost = \"{addr}\"\n\
+                 [_settings]\nsensitive = [\"api_key\"]\n"
+            ),
+        )
+        .unwrap();
+        fs::write(root.join(".hitman/data/default.toml"), "id = \"12\"\n")
+            .unwrap();
+        let file = root.join("delete_apple.http");
+        fs::write(
+            &file,
+            "DELETE http://{{host}}/apples/{{id}} HTTP/1.1\n\
+             X-Api-Key: {{api_key}}\n",
+        )
+        .unwrap();
+
+        let values = vec![("run_id".to_string(), "r1".to_string())];
+        let rollback = prepare_rollback(&root, &file, &values).await.unwrap();
+        let keys: Vec<&str> =
+            rollback.values.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["run_id", "host", "id"]);
+
+        // Captured values are used, even when they have changed since
+        fs::write(root.join(".hitman/data/default.toml"), "id = \"13\"\n")
+            .unwrap();
+        let result = send_rollback(&root, &rollback).await;
+        let request = server.await.unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(result.passed(), "{:?}", result.failures);
+        assert!(request.starts_with("DELETE /apples/12 "));
+        assert!(request.to_lowercase().contains("x-api-key: s3cret-key"));
+    }
+}
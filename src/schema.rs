@@ -0,0 +1,236 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+
+use anyhow::Result;
+use serde_json::Value as JsonValue;
+
+use crate::history::{load_history, HistoryEntry};
+
+/// The types found at each path of a JSON value, like `$.items[].id`.
+/// Integers and decimals are both numbers, since a value like a price can be
+/// either from one response to the next.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    types: BTreeMap<String, BTreeSet<&'static str>>,
+    /// Arrays that were empty every time, so their items are unknown
+    empty_arrays: BTreeSet<String>,
+}
+
+pub fn infer_schema(value: &JsonValue) -> Schema {
+    let mut schema = Schema::default();
+    schema.add("$".to_string(), value);
+
+    // An array is only empty if no instance of it had items
+    let empty: Vec<_> = schema
+        .empty_arrays
+        .iter()
+        .filter(|path| {
+            let items = format!("{path}[]");
+            schema.types.contains_key(&items)
+        })
+        .cloned()
+        .collect();
+    for path in empty {
+        schema.empty_arrays.remove(&path);
+    }
+
+    schema
+}
+
+impl Schema {
+    fn add(&mut self, path: String, value: &JsonValue) {
+        let kind = match value {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "boolean",
+            JsonValue::Number(_) => "number",
+            JsonValue::String(_) => "string",
+            JsonValue::Array(_) => "array",
+            JsonValue::Object(_) => "object",
+        };
+        self.types.entry(path.clone()).or_default().insert(kind);
+
+        match value {
+            JsonValue::Array(items) if items.is_empty() => {
+                self.empty_arrays.insert(path);
+            }
+            JsonValue::Array(items) => {
+                let items_path = format!("{path}[]");
+                for item in items {
+                    self.add(items_path.clone(), item);
+                }
+            }
+            JsonValue::Object(object) => {
+                for (key, value) in object {
+                    self.add(format!("{path}.{key}"), value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn types_at(&self, path: &str) -> Option<String> {
+        let types = self.types.get(path)?;
+        Some(types.iter().copied().collect::<Vec<_>>().join(" | "))
+    }
+
+    /// Whether the path is within an array that had no items, so nothing
+    /// is known about it
+    fn is_unknown(&self, path: &str) -> bool {
+        self.empty_arrays
+            .iter()
+            .any(|array| path.starts_with(&format!("{array}[]")))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    Added {
+        path: String,
+        types: String,
+    },
+    Removed {
+        path: String,
+        types: String,
+    },
+    Retyped {
+        path: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added { path, types } => write!(f, "+ {path}: {types}"),
+            Self::Removed { path, types } => write!(f, "- {path}: {types}"),
+            Self::Retyped { path, from, to } => {
+                write!(f, "~ {path}: {from} -> {to}")
+            }
+        }
+    }
+}
+
+/// Fields that were added, removed or changed type. Fields within an added
+/// or removed field are left out.
+pub fn compare_schemas(old: &Schema, new: &Schema) -> Vec<SchemaChange> {
+    let paths: BTreeSet<&String> =
+        old.types.keys().chain(new.types.keys()).collect();
+
+    let mut changes = Vec::new();
+    let mut gone: Vec<&str> = Vec::new();
+    for path in paths {
+        let within = |p: &str| {
+            path.strip_prefix(p)
+                .is_some_and(|rest| rest.starts_with(['.', '[']))
+        };
+        if gone.iter().any(|p| within(p)) {
+            continue;
+        }
+
+        match (old.types_at(path), new.types_at(path)) {
+            (Some(_), None) if new.is_unknown(path) => (),
+            (None, Some(_)) if old.is_unknown(path) => (),
+            (Some(types), None) => {
+                gone.push(path);
+                changes.push(SchemaChange::Removed {
+                    path: path.clone(),
+                    types,
+                });
+            }
+            (None, Some(types)) => {
+                gone.push(path);
+                changes.push(SchemaChange::Added {
+                    path: path.clone(),
+                    types,
+                });
+            }
+            (Some(from), Some(to)) if from != to => {
+                changes.push(SchemaChange::Retyped {
+                    path: path.clone(),
+                    from,
+                    to,
+                })
+            }
+            _ => (),
+        }
+    }
+
+    changes
+}
+
+/// How the shape of a JSON response differs from the newest earlier
+/// response to the same request with the same status, if there is one
+pub fn schema_changes(
+    root_dir: &Path,
+    entry: &HistoryEntry,
+) -> Result<Option<(HistoryEntry, Vec<SchemaChange>)>> {
+    let Ok(current) = serde_json::from_slice::<JsonValue>(&entry.body) else {
+        return Ok(None);
+    };
+
+    let previous = load_history(root_dir)?.into_iter().find(|e| {
+        e.file == entry.file && e.time < entry.time && e.status == entry.status
+    });
+    let Some(previous) = previous else {
+        return Ok(None);
+    };
+    let Ok(old) = serde_json::from_slice::<JsonValue>(&previous.body) else {
+        return Ok(None);
+    };
+
+    let changes = compare_schemas(&infer_schema(&old), &infer_schema(&current));
+
+    Ok(Some((previous, changes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn changes(old: JsonValue, new: JsonValue) -> Vec<String> {
+        compare_schemas(&infer_schema(&old), &infer_schema(&new))
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn infers_types_by_path() {
+        let schema = infer_schema(&json!({
+            "items": [{"id": 1, "tag": "a"}, {"id": 2.5, "tag": null}],
+        }));
+
+        assert_eq!(schema.types_at("$.items[].id").unwrap(), "number");
+        assert_eq!(schema.types_at("$.items[].tag").unwrap(), "null | string");
+        assert_eq!(schema.types_at("$.items").unwrap(), "array");
+    }
+
+    #[test]
+    fn finds_changed_fields() {
+        assert_eq!(
+            changes(
+                json!({"id": 1, "total": "12.50", "old": {"a": 1}}),
+                json!({"id": 2, "total": 12.5, "new": {"b": true}}),
+            ),
+            [
+                "+ $.new: object",
+                "- $.old: object",
+                "~ $.total: string -> number",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_empty_arrays() {
+        assert!(changes(json!({"items": [{"id": 1}]}), json!({"items": []}))
+            .is_empty());
+        assert!(changes(json!([]), json!([{"id": 1}])).is_empty());
+        assert!(changes(json!({"a": 1}), json!({"a": 2})).is_empty());
+    }
+}
@@ -0,0 +1,411 @@
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use rhai::{Array, Dynamic, Engine, Map, Scope};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use crate::{
+    assertions::ResponseInfo,
+    clock::unix_now,
+    directive::{Directives, SCRIPT},
+    env::find_root_dir,
+};
+
+/// Scripts are enabled per project in the config, such as:
+///
+/// ```toml
+/// [_scripts]
+/// enabled = true
+/// include = ["scripts/sign.rhai"]
+/// ```
+pub(crate) const SCRIPTS_KEY: &str = "_scripts";
+
+/// Scripts never run longer than this many operations, unless configured
+/// with `max_operations`
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Rhai scripts in a request file, in blocks of comments like:
+///
+/// ```text
+/// # @script before
+/// # vars.signature = hmac_sha256(vars.secret, vars.body);
+/// # @end
+/// ```
+///
+/// The script `before` runs before the request is substituted, and values set
+/// in `vars` are used for the substitution. The script `after` runs when the
+/// response is received, with the `response`, and values set in `vars` are
+/// saved like extracted values.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RequestScripts {
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl RequestScripts {
+    /// Split the scripts from a request, so that the remaining request can
+    /// be substituted and sent
+    pub fn extract(buf: &str) -> Result<(String, Self)> {
+        let directives = Directives::parse(buf)?;
+        let scripts = Self::from_directives(&directives)?;
+
+        Ok((directives.without(&[SCRIPT]), scripts))
+    }
+
+    pub fn from_directives(directives: &Directives) -> Result<Self> {
+        let mut scripts = Self::default();
+        for directive in directives.all(SCRIPT) {
+            let script = match directive.value.as_str() {
+                "before" => &mut scripts.before,
+                "after" => &mut scripts.after,
+                other => {
+                    bail!("Unknown script: {other}, expected before or after")
+                }
+            };
+            script
+                .get_or_insert_with(String::new)
+                .push_str(directive.block.as_deref().unwrap_or_default());
+        }
+
+        Ok(scripts)
+    }
+
+    /// Run the script before the request, and give the environment with the
+    /// values it set
+    pub fn before(&self, env: &Table) -> Result<Table> {
+        let Some(script) = &self.before else {
+            return Ok(env.clone());
+        };
+
+        let mut env = env.clone();
+        env.extend(run_script(script, env.clone(), Scope::new())?);
+
+        Ok(env)
+    }
+
+    /// Run the script after the response, and give the values it set, to be
+    /// saved
+    pub fn after(&self, env: &Table, response: &ResponseInfo) -> Result<Table> {
+        let Some(script) = &self.after else {
+            return Ok(Table::new());
+        };
+
+        let mut scope = Scope::new();
+        scope.push("response", response_map(response));
+
+        run_script(script, env.clone(), scope)
+    }
+
+    /// The values the script after the response sets, like `vars.token = …`,
+    /// found without running it
+    pub fn saved_vars(&self) -> Vec<String> {
+        let Some(script) = &self.after else {
+            return Vec::new();
+        };
+
+        let mut vars: Vec<String> = Vec::new();
+        for (i, _) in script.match_indices("vars.") {
+            let rest = &script[i + "vars.".len()..];
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            let assigned = rest[name.len()..].trim_start();
+            if !name.is_empty()
+                && assigned.starts_with('=')
+                && !assigned.starts_with("==")
+                && !vars.contains(&name)
+            {
+                vars.push(name);
+            }
+        }
+
+        vars
+    }
+}
+
+/// Run a script with the values of the environment in `vars`, and give the
+/// values that it changed
+fn run_script(script: &str, env: Table, mut scope: Scope) -> Result<Table> {
+    let config = match env.get(SCRIPTS_KEY) {
+        Some(Value::Table(config)) => config.clone(),
+        _ => Table::new(),
+    };
+    if !config
+        .get("enabled")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+    {
+        bail!(
+            "Scripts are not enabled, set `enabled = true` in [{SCRIPTS_KEY}]"
+        );
+    }
+
+    let vars = vars_map(&env);
+    scope.push("vars", vars.clone());
+
+    let source = format!("{}{}", included_source(&config)?, script);
+    engine(&config)
+        .run_with_scope(&mut scope, &source)
+        .map_err(|err| anyhow!("Script failed: {err}"))?;
+
+    let changed = scope
+        .get_value::<Map>("vars")
+        .context("Script replaced vars")?
+        .into_iter()
+        .filter(|(key, value)| {
+            vars.get(key).map(Dynamic::to_string) != Some(value.to_string())
+        })
+        .filter_map(|(key, value)| Some((key.to_string(), toml_value(value)?)))
+        .collect();
+
+    Ok(changed)
+}
+
+/// Scripts shared by the requests of a project, such as functions to sign
+/// requests, with paths relative to the root directory
+fn included_source(config: &Table) -> Result<String> {
+    let Some(Value::Array(paths)) = config.get("include") else {
+        return Ok(String::new());
+    };
+    let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+
+    let mut source = String::new();
+    for path in paths.iter().filter_map(Value::as_str) {
+        let path = root_dir.join(path);
+        source.push_str(&read_script(&path)?);
+        source.push('\n');
+    }
+
+    Ok(source)
+}
+
+fn read_script(path: &Path) -> Result<String> {
+    read_to_string(path)
+        .with_context(|| format!("Failed to read script {}", path.display()))
+}
+
+fn engine(config: &Table) -> Engine {
+    let max_operations = config
+        .get("max_operations")
+        .and_then(Value::as_integer)
+        .map(|n| n.max(0) as u64)
+        .unwrap_or(MAX_OPERATIONS);
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    engine.on_print(|text| warn!("# {text}"));
+    engine.on_debug(|text, _, _| info!("# {text}"));
+
+    engine.register_fn("sha256", |text: &str| hex(&Sha256::digest(text)));
+    engine.register_fn("hmac_sha256", |key: &str, text: &str| {
+        hex(&hmac_sha256(key.as_bytes(), text.as_bytes()))
+    });
+    engine.register_fn("base64", |text: &str| STANDARD.encode(text));
+    engine.register_fn("timestamp", || unix_now() as i64);
+
+    engine
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .expect("HMAC takes keys of any length");
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The values of the environment, without the special tables
+fn vars_map(env: &Table) -> Map {
+    env.iter()
+        .filter(|(key, _)| !key.starts_with('_'))
+        .map(|(key, value)| (key.into(), toml_dynamic(value)))
+        .collect()
+}
+
+fn response_map(response: &ResponseInfo) -> Map {
+    let headers: Map = response
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+            (name.as_str().into(), value.into())
+        })
+        .collect();
+    let json = serde_json::from_str(response.body)
+        .map(|json| json_dynamic(&json))
+        .unwrap_or(Dynamic::UNIT);
+
+    let mut map = Map::new();
+    map.insert("status".into(), (response.status as i64).into());
+    map.insert("headers".into(), headers.into());
+    map.insert("body".into(), response.body.to_string().into());
+    map.insert("json".into(), json);
+    map.insert(
+        "elapsed_ms".into(),
+        (response.elapsed.as_millis() as i64).into(),
+    );
+
+    map
+}
+
+fn toml_dynamic(value: &Value) -> Dynamic {
+    match value {
+        Value::String(s) => s.clone().into(),
+        Value::Integer(i) => (*i).into(),
+        Value::Float(f) => (*f).into(),
+        Value::Boolean(b) => (*b).into(),
+        Value::Datetime(d) => d.to_string().into(),
+        Value::Array(values) => {
+            values.iter().map(toml_dynamic).collect::<Array>().into()
+        }
+        Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.into(), toml_dynamic(value)))
+            .collect::<Map>()
+            .into(),
+    }
+}
+
+fn json_dynamic(value: &JsonValue) -> Dynamic {
+    match value {
+        JsonValue::Null => Dynamic::UNIT,
+        JsonValue::Bool(b) => (*b).into(),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or_default().into(),
+        },
+        JsonValue::String(s) => s.clone().into(),
+        JsonValue::Array(values) => {
+            values.iter().map(json_dynamic).collect::<Array>().into()
+        }
+        JsonValue::Object(object) => object
+            .iter()
+            .map(|(key, value)| (key.into(), json_dynamic(value)))
+            .collect::<Map>()
+            .into(),
+    }
+}
+
+/// Values that can be substituted and saved, other values are left out
+fn toml_value(value: Dynamic) -> Option<Value> {
+    if value.is_string() {
+        return value.into_string().ok().map(Value::String);
+    }
+    if let Some(i) = value.clone().try_cast::<i64>() {
+        return Some(Value::Integer(i));
+    }
+    if let Some(f) = value.clone().try_cast::<f64>() {
+        return Some(Value::Float(f));
+    }
+    if let Some(b) = value.clone().try_cast::<bool>() {
+        return Some(Value::Boolean(b));
+    }
+    if let Some(values) = value.clone().try_cast::<Array>() {
+        return Some(Value::Array(
+            values.into_iter().filter_map(toml_value).collect(),
+        ));
+    }
+    if let Some(map) = value.try_cast::<Map>() {
+        return Some(Value::Table(
+            map.into_iter()
+                .filter_map(|(key, value)| {
+                    Some((key.to_string(), toml_value(value)?))
+                })
+                .collect(),
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::*;
+
+    fn enabled_env() -> Table {
+        toml::from_str(
+            r#"
+            secret = "key"
+            count = 1
+            [_scripts]
+            enabled = true
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn extracts_scripts_from_requests() {
+        let buf = "# @script before\n# vars.a = 1;\n#vars.b = 2;\n# @end\nGET http://example.com HTTP/1.1\n# @script after\n#   let x = 1;\n# @end\n";
+        let (request, scripts) = RequestScripts::extract(buf).unwrap();
+
+        assert_eq!(request, "GET http://example.com HTTP/1.1\n");
+        assert_eq!(
+            scripts.before.as_deref(),
+            Some("vars.a = 1;\nvars.b = 2;\n")
+        );
+        assert_eq!(scripts.after.as_deref(), Some("  let x = 1;\n"));
+
+        assert!(RequestScripts::extract("# @script before\n# 1\n").is_err());
+        assert!(RequestScripts::extract("# @script during\n# @end\n").is_err());
+        assert!(
+            RequestScripts::extract("# @script after\nx\n# @end\n").is_err()
+        );
+    }
+
+    #[test]
+    fn computes_values_before_requests() {
+        let (_, scripts) = RequestScripts::extract(
+            "# @script before\n# vars.signature = hmac_sha256(vars.secret, \"abc\");\n# vars.count += 1;\n# @end\n",
+        )
+        .unwrap();
+
+        let env = scripts.before(&enabled_env()).unwrap();
+        assert_eq!(
+            env.get("signature").and_then(Value::as_str),
+            Some("9c196e32dc0175f86f4b1cb89289d6619de6bee699e4c378e68309ed97a1a6ab")
+        );
+        assert_eq!(env.get("count"), Some(&Value::Integer(2)));
+        assert_eq!(env.get("secret"), Some(&Value::String("key".into())));
+
+        let mut disabled = enabled_env();
+        disabled.remove(SCRIPTS_KEY);
+        assert!(scripts.before(&disabled).is_err());
+    }
+
+    #[test]
+    fn extracts_values_after_responses() {
+        let (_, scripts) = RequestScripts::extract(
+            "# @script after\n# if response.status == 200 && response.headers[\"x-page\"] == \"2\" {\n#   vars.first = response.json.items[0].id;\n# }\n# @end\n",
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-page", HeaderValue::from_static("2"));
+        let response = ResponseInfo {
+            status: 200,
+            headers: &headers,
+            body: r#"{"items": [{"id": "a1"}]}"#,
+            elapsed: Duration::from_millis(5),
+            plugins: &[],
+        };
+
+        let values = scripts.after(&enabled_env(), &response).unwrap();
+        assert_eq!(values, toml::from_str("first = \"a1\"").unwrap());
+        assert_eq!(scripts.saved_vars(), vec!["first"]);
+    }
+}
@@ -0,0 +1,408 @@
+use std::{path::Path, sync::Mutex};
+
+use anyhow::{bail, Result};
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::{
+    env::{get_settings, is_sensitive_key, read_and_merge_config},
+    history::HistoryEntry,
+};
+
+const SCRUB_KEY: &str = "_scrub";
+
+/// What scrubbed values are replaced with
+pub const REDACTED: &str = "********";
+
+/// Shorter values are not hidden, since they would hide unrelated text
+const MIN_SECRET_LEN: usize = 4;
+
+/// The values of sensitive keys used in this run, longest first
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Remember the values of the keys listed in `sensitive` in `[_settings]`,
+/// including values given at prompts, so that they are hidden wherever
+/// requests and responses are written to disk
+pub fn remember_secrets(env: &Table) {
+    let settings = get_settings(env);
+    let values = env
+        .iter()
+        .filter(|(key, _)| is_sensitive_key(&settings, key))
+        .filter_map(|(_, value)| match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(_) | Value::Table(_) => None,
+            other => Some(other.to_string()),
+        })
+        .filter(|value| value.len() >= MIN_SECRET_LEN);
+
+    if let Ok(mut secrets) = SECRETS.lock() {
+        for value in values {
+            if !secrets.contains(&value) {
+                secrets.push(value);
+            }
+        }
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+/// Text with the remembered values of sensitive keys hidden
+pub fn hide_secrets(text: &str) -> String {
+    let Ok(secrets) = SECRETS.lock() else {
+        return text.to_string();
+    };
+
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret, REDACTED)
+    })
+}
+
+/// Values to hide whenever responses are written to disk, given in the config
+/// as header names and JSONPath masks:
+///
+/// ```toml
+/// [_scrub]
+/// headers = ["x-api-key"]
+/// json = ["$.user.email", "$..ssn", "$.cards[*].number"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrubRules {
+    /// Lower case header names
+    headers: Vec<String>,
+    masks: Vec<Vec<Step>>,
+}
+
+/// A step of a JSONPath mask
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(usize),
+    /// `*` or `[*]`, every member or item
+    Any,
+    /// `..key`, the key at any depth
+    Descendant(String),
+}
+
+impl ScrubRules {
+    pub fn load(root_dir: &Path) -> Result<Self> {
+        Self::from_config(&read_and_merge_config(root_dir)?)
+    }
+
+    pub fn from_config(config: &Table) -> Result<Self> {
+        match config.get(SCRUB_KEY) {
+            Some(Value::Table(scrub)) => Self::from_table(scrub),
+            Some(_) => bail!("{SCRUB_KEY} must be a table"),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_table(scrub: &Table) -> Result<Self> {
+        let strings = |key: &str| -> Result<Vec<String>> {
+            match scrub.get(key) {
+                None => Ok(Vec::new()),
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => Ok(s.clone()),
+                        _ => bail!("{SCRUB_KEY}.{key} must be strings"),
+                    })
+                    .collect(),
+                Some(_) => bail!("{SCRUB_KEY}.{key} must be a list"),
+            }
+        };
+
+        Ok(Self {
+            headers: strings("headers")?
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            masks: strings("json")?
+                .iter()
+                .map(|path| parse_mask(path))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.masks.is_empty()
+    }
+
+    pub fn is_scrubbed_header(&self, name: &str) -> bool {
+        self.headers.contains(&name.to_lowercase())
+    }
+
+    /// Hide the values matched by the masks. Gives whether any were found.
+    pub fn scrub_json(&self, value: &mut JsonValue) -> bool {
+        let mut found = false;
+        for mask in &self.masks {
+            found |= apply_mask(value, mask);
+        }
+
+        found
+    }
+
+    /// A body with the masked values hidden, if it is JSON. Other bodies are
+    /// kept as they are.
+    pub fn scrub_body(&self, body: &[u8]) -> Vec<u8> {
+        let hidden = match std::str::from_utf8(body) {
+            Ok(text) => hide_secrets(text).into_bytes(),
+            Err(_) => body.to_vec(),
+        };
+        let body = hidden.as_slice();
+
+        let Ok(mut json) = serde_json::from_slice::<JsonValue>(body) else {
+            return body.to_vec();
+        };
+        if !self.scrub_json(&mut json) {
+            return body.to_vec();
+        }
+
+        serde_json::to_vec_pretty(&json).unwrap_or_else(|_| body.to_vec())
+    }
+
+    pub fn scrub_headers(&self, headers: &mut [(String, String)]) {
+        for (name, value) in headers {
+            if self.is_scrubbed_header(name) {
+                *value = REDACTED.to_string();
+            } else {
+                *value = hide_secrets(value);
+            }
+        }
+    }
+
+    /// A request as written in a request file, with the headers and body
+    /// scrubbed
+    pub fn scrub_request(&self, request: &str) -> String {
+        let hidden = hide_secrets(request);
+        let request = hidden.as_str();
+        let mut lines = request.lines();
+        let mut out = Vec::new();
+        let mut changed = false;
+
+        // The request line, and what comes before it
+        for line in lines.by_ref() {
+            out.push(line.to_string());
+            if !line.trim().is_empty() && !line.starts_with('#') {
+                break;
+            }
+        }
+
+        for line in lines.by_ref() {
+            if line.trim().is_empty() {
+                out.push(line.to_string());
+                break;
+            }
+            match line.split_once(':') {
+                Some((name, _)) if self.is_scrubbed_header(name.trim()) => {
+                    out.push(format!("{name}: {REDACTED}"));
+                    changed = true;
+                }
+                _ => out.push(line.to_string()),
+            }
+        }
+
+        let body = lines.collect::<Vec<_>>().join("\n");
+        let scrubbed = self.scrub_body(body.as_bytes());
+        if scrubbed != body.as_bytes() {
+            out.push(String::from_utf8_lossy(&scrubbed).to_string());
+            changed = true;
+        } else {
+            out.push(body);
+        }
+
+        if !changed {
+            return request.to_string();
+        }
+
+        format!("{}\n", out.join("\n"))
+    }
+
+    /// Scrub everything in a history entry that is written to disk
+    pub fn scrub_entry(&self, entry: &mut HistoryEntry) {
+        entry.url = hide_secrets(&entry.url);
+        entry.request = self.scrub_request(&entry.request);
+        self.scrub_headers(&mut entry.headers);
+        entry.body = self.scrub_body(&entry.body);
+    }
+}
+
+/// Parse the JSONPath subset used for masks: `$`, `.key`, `['key']`, `[0]`,
+/// `.*`, `[*]` and `..key`
+fn parse_mask(path: &str) -> Result<Vec<Step>> {
+    let Some(mut rest) = path.trim().strip_prefix('$') else {
+        bail!("Invalid jsonpath in {SCRUB_KEY}: {path} must start with $");
+    };
+
+    let name_end = |s: &str| s.find(['.', '[']).unwrap_or(s.len());
+
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let end = name_end(after);
+            if end == 0 {
+                bail!("Invalid jsonpath in {SCRUB_KEY}: {path}");
+            }
+            steps.push(Step::Descendant(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = name_end(after);
+            steps.push(match &after[..end] {
+                "" => bail!("Invalid jsonpath in {SCRUB_KEY}: {path}"),
+                "*" => Step::Any,
+                key => Step::Key(key.to_string()),
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let Some(end) = after.find(']') else {
+                bail!("Invalid jsonpath in {SCRUB_KEY}: {path}");
+            };
+            let inner = after[..end].trim();
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|s| s.strip_suffix('\''))
+                .or_else(|| {
+                    inner.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+                });
+            steps.push(match (inner, quoted) {
+                (_, Some(key)) => Step::Key(key.to_string()),
+                ("*", _) => Step::Any,
+                (index, _) => match index.parse() {
+                    Ok(i) => Step::Index(i),
+                    Err(_) => {
+                        bail!("Invalid jsonpath in {SCRUB_KEY}: {path}")
+                    }
+                },
+            });
+            rest = &after[end + 1..];
+        } else {
+            bail!("Invalid jsonpath in {SCRUB_KEY}: {path}");
+        }
+    }
+
+    Ok(steps)
+}
+
+fn apply_mask(value: &mut JsonValue, steps: &[Step]) -> bool {
+    let Some((step, rest)) = steps.split_first() else {
+        if value.is_null() {
+            return false;
+        }
+        *value = JsonValue::String(REDACTED.to_string());
+        return true;
+    };
+
+    match (step, value) {
+        (Step::Key(key), JsonValue::Object(object)) => object
+            .get_mut(key)
+            .is_some_and(|value| apply_mask(value, rest)),
+        (Step::Index(i), JsonValue::Array(items)) => items
+            .get_mut(*i)
+            .is_some_and(|value| apply_mask(value, rest)),
+        (Step::Any, JsonValue::Object(object)) => object
+            .values_mut()
+            .fold(false, |found, value| apply_mask(value, rest) | found),
+        (Step::Any, JsonValue::Array(items)) => items
+            .iter_mut()
+            .fold(false, |found, value| apply_mask(value, rest) | found),
+        (Step::Descendant(key), JsonValue::Object(object)) => {
+            object.iter_mut().fold(false, |found, (k, value)| {
+                let found = if k == key {
+                    apply_mask(value, rest) | found
+                } else {
+                    found
+                };
+                apply_mask(value, steps) | found
+            })
+        }
+        (Step::Descendant(_), JsonValue::Array(items)) => items
+            .iter_mut()
+            .fold(false, |found, value| apply_mask(value, steps) | found),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rules(headers: &[&str], json: &[&str]) -> ScrubRules {
+        let mut table = Table::new();
+        let list = |values: &[&str]| {
+            Value::Array(values.iter().map(|v| Value::from(*v)).collect())
+        };
+        table.insert("headers".into(), list(headers));
+        table.insert("json".into(), list(json));
+
+        ScrubRules::from_table(&table).unwrap()
+    }
+
+    #[test]
+    fn parses_masks() {
+        assert_eq!(
+            parse_mask("$.cards[*].number").unwrap(),
+            [
+                Step::Key("cards".into()),
+                Step::Any,
+                Step::Key("number".into())
+            ]
+        );
+        assert_eq!(
+            parse_mask("$..ssn['full name'][0]").unwrap(),
+            [
+                Step::Descendant("ssn".into()),
+                Step::Key("full name".into()),
+                Step::Index(0)
+            ]
+        );
+        assert!(parse_mask("user.email").is_err());
+        assert!(parse_mask("$.user[").is_err());
+        assert!(parse_mask("$.").is_err());
+    }
+
+    #[test]
+    fn masks_json_values() {
+        let rules = rules(&[], &["$.user.email", "$..ssn", "$.cards[*].cvc"]);
+        let mut value = json!({
+            "user": {"email": "a@example.com", "name": "A"},
+            "family": [{"ssn": "123"}, {"ssn": null}],
+            "cards": [{"cvc": 123, "last4": "4242"}],
+        });
+
+        assert!(rules.scrub_json(&mut value));
+        assert_eq!(
+            value,
+            json!({
+                "user": {"email": REDACTED, "name": "A"},
+                "family": [{"ssn": REDACTED}, {"ssn": null}],
+                "cards": [{"cvc": REDACTED, "last4": "4242"}],
+            })
+        );
+        assert!(!rules.scrub_json(&mut json!({"name": "A"})));
+    }
+
+    #[test]
+    fn scrubs_requests() {
+        let rules = rules(&["X-Api-Key"], &["$.password"]);
+        let request = "# @assert status == 200\n\
+                       POST http://example.com/login HTTP/1.1\n\
+                       x-api-key: abc\n\
+                       Content-Type: application/json\n\
+                       \n\
+                       {\"user\": \"a\", \"password\": \"hunter2\"}\n";
+
+        assert_eq!(
+            rules.scrub_request(request),
+            "# @assert status == 200\n\
+             POST http://example.com/login HTTP/1.1\n\
+             x-api-key: ********\n\
+             Content-Type: application/json\n\
+             \n\
+             {\n  \"password\": \"********\",\n  \"user\": \"a\"\n}\n"
+        );
+        assert_eq!(
+            rules.scrub_request("GET http://example.com/ HTTP/1.1\n"),
+            "GET http://example.com/ HTTP/1.1\n"
+        );
+    }
+}
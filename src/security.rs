@@ -0,0 +1,298 @@
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+        REFERRER_POLICY, SERVER, SET_COOKIE, STRICT_TRANSPORT_SECURITY,
+        X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    Url,
+};
+use std::fmt;
+
+/// The shortest HSTS max-age that is considered good enough (180 days)
+const HSTS_MIN_AGE: u64 = 180 * 24 * 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Grade::Pass => write!(f, "pass"),
+            Grade::Warn => write!(f, "warn"),
+            Grade::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityNote {
+    pub grade: Grade,
+    pub header: &'static str,
+    pub message: String,
+}
+
+impl SecurityNote {
+    fn new(grade: Grade, header: &'static str, message: &str) -> Self {
+        Self {
+            grade,
+            header,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Grade the security headers of a response to a request for the given URL
+pub fn security_report(headers: &HeaderMap, url: &Url) -> Vec<SecurityNote> {
+    use Grade::*;
+
+    let get = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+    let is_html = get(CONTENT_TYPE).is_some_and(|ct| ct.contains("html"));
+    let csp = get(CONTENT_SECURITY_POLICY);
+
+    let mut notes = Vec::new();
+    let mut note = |grade, header, message: &str| {
+        notes.push(SecurityNote::new(grade, header, message))
+    };
+
+    // Strict-Transport-Security
+    let hsts = "Strict-Transport-Security";
+    if url.scheme() != "https" {
+        note(Fail, hsts, "Served over plain HTTP");
+    } else {
+        match get(STRICT_TRANSPORT_SECURITY) {
+            None => note(Fail, hsts, "Missing"),
+            Some(value) => {
+                let value = value.to_lowercase();
+                let max_age = value
+                    .split(';')
+                    .filter_map(|d| d.trim().strip_prefix("max-age="))
+                    .find_map(|v| v.trim_matches('"').parse::<u64>().ok());
+                match max_age {
+                    None => note(Fail, hsts, "No valid max-age"),
+                    Some(age) if age < HSTS_MIN_AGE => {
+                        note(Warn, hsts, "max-age is less than 180 days")
+                    }
+                    Some(_) if !value.contains("includesubdomains") => {
+                        note(Warn, hsts, "Does not include subdomains")
+                    }
+                    Some(_) => note(Pass, hsts, "Enabled"),
+                }
+            }
+        }
+    }
+
+    // Content-Security-Policy
+    let name = "Content-Security-Policy";
+    match &csp {
+        None if is_html => note(Fail, name, "Missing on HTML content"),
+        None => note(Warn, name, "Missing"),
+        Some(policy) if policy.contains("'unsafe-inline'") => {
+            note(Warn, name, "Allows 'unsafe-inline'")
+        }
+        Some(policy) if policy.contains("'unsafe-eval'") => {
+            note(Warn, name, "Allows 'unsafe-eval'")
+        }
+        Some(_) => note(Pass, name, "Present"),
+    }
+
+    // X-Content-Type-Options
+    let name = "X-Content-Type-Options";
+    match get(X_CONTENT_TYPE_OPTIONS) {
+        Some(v) if v.eq_ignore_ascii_case("nosniff") => {
+            note(Pass, name, "nosniff")
+        }
+        Some(_) => note(Fail, name, "Should be nosniff"),
+        None => note(Fail, name, "Missing"),
+    }
+
+    // X-Frame-Options, or the CSP replacement frame-ancestors
+    let name = "X-Frame-Options";
+    let frame_ancestors =
+        csp.as_ref().is_some_and(|p| p.contains("frame-ancestors"));
+    match get(X_FRAME_OPTIONS).map(|v| v.to_uppercase()) {
+        Some(v) if v == "DENY" || v == "SAMEORIGIN" => note(Pass, name, &v),
+        _ if frame_ancestors => {
+            note(Pass, name, "Covered by CSP frame-ancestors")
+        }
+        Some(_) => note(Fail, name, "Should be DENY or SAMEORIGIN"),
+        None => note(Warn, name, "Missing"),
+    }
+
+    // Referrer-Policy
+    let name = "Referrer-Policy";
+    match get(REFERRER_POLICY) {
+        Some(v) if v.contains("unsafe-url") => {
+            note(Fail, name, "unsafe-url leaks full URLs to other sites")
+        }
+        Some(v) => note(Pass, name, &v),
+        None => note(Warn, name, "Missing"),
+    }
+
+    // Permissions-Policy
+    let name = "Permissions-Policy";
+    if headers.contains_key("permissions-policy") {
+        note(Pass, name, "Present");
+    } else {
+        note(Warn, name, "Missing");
+    }
+
+    // Cross-origin resource sharing
+    let name = "Access-Control-Allow-Origin";
+    if get(ACCESS_CONTROL_ALLOW_ORIGIN).as_deref() == Some("*") {
+        if get(ACCESS_CONTROL_ALLOW_CREDENTIALS).as_deref() == Some("true") {
+            note(Fail, name, "Any origin, with credentials");
+        } else {
+            note(Warn, name, "Any origin");
+        }
+    }
+
+    // Headers revealing software versions
+    for (header, name) in [
+        (SERVER, "Server"),
+        (HeaderName::from_static("x-powered-by"), "X-Powered-By"),
+    ] {
+        if let Some(value) = get(header) {
+            if value.chars().any(|c| c.is_ascii_digit()) {
+                note(Warn, name, &format!("Reveals version: {value}"));
+            }
+        }
+    }
+
+    // Cookies
+    for cookie in headers.get_all(SET_COOKIE) {
+        let Ok(cookie) = cookie.to_str() else {
+            continue;
+        };
+        let cookie_name = cookie.split('=').next().unwrap_or("").trim();
+        let attrs = cookie.to_lowercase();
+        let missing: Vec<&str> = [
+            ("secure", "Secure"),
+            ("httponly", "HttpOnly"),
+            ("samesite", "SameSite"),
+        ]
+        .into_iter()
+        .filter(|(attr, _)| {
+            !attrs.split(';').any(|a| a.trim().starts_with(attr))
+        })
+        .map(|(_, name)| name)
+        .collect();
+        if missing.is_empty() {
+            note(Pass, "Set-Cookie", cookie_name);
+        } else {
+            note(
+                Warn,
+                "Set-Cookie",
+                &format!("{cookie_name} is missing {}", missing.join(", ")),
+            );
+        }
+    }
+
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(pairs: &[(&'static str, &'static str)]) -> Vec<SecurityNote> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        let url = Url::parse("https://example.com/api").unwrap();
+
+        security_report(&headers, &url)
+    }
+
+    fn grade_of(notes: &[SecurityNote], header: &str) -> Option<Grade> {
+        notes.iter().find(|n| n.header == header).map(|n| n.grade)
+    }
+
+    #[test]
+    fn passes_secure_headers() {
+        let notes = report(&[
+            (
+                "strict-transport-security",
+                "max-age=31536000; includeSubDomains",
+            ),
+            ("content-security-policy", "default-src 'self'"),
+            ("x-content-type-options", "nosniff"),
+            ("x-frame-options", "DENY"),
+            ("referrer-policy", "no-referrer"),
+            ("permissions-policy", "camera=()"),
+        ]);
+
+        assert!(notes.iter().all(|n| n.grade == Grade::Pass), "{notes:?}");
+    }
+
+    #[test]
+    fn fails_missing_headers() {
+        let notes = report(&[("content-type", "text/html")]);
+
+        assert_eq!(
+            grade_of(&notes, "Strict-Transport-Security"),
+            Some(Grade::Fail)
+        );
+        assert_eq!(
+            grade_of(&notes, "Content-Security-Policy"),
+            Some(Grade::Fail)
+        );
+        assert_eq!(
+            grade_of(&notes, "X-Content-Type-Options"),
+            Some(Grade::Fail)
+        );
+        assert_eq!(grade_of(&notes, "Referrer-Policy"), Some(Grade::Warn));
+    }
+
+    #[test]
+    fn warns_about_short_hsts_and_weak_csp() {
+        let notes = report(&[
+            ("strict-transport-security", "max-age=3600"),
+            (
+                "content-security-policy",
+                "script-src 'self' 'unsafe-inline'",
+            ),
+        ]);
+
+        assert_eq!(
+            grade_of(&notes, "Strict-Transport-Security"),
+            Some(Grade::Warn)
+        );
+        assert_eq!(
+            grade_of(&notes, "Content-Security-Policy"),
+            Some(Grade::Warn)
+        );
+    }
+
+    #[test]
+    fn fails_wildcard_cors_with_credentials() {
+        let notes = report(&[
+            ("access-control-allow-origin", "*"),
+            ("access-control-allow-credentials", "true"),
+        ]);
+
+        assert_eq!(
+            grade_of(&notes, "Access-Control-Allow-Origin"),
+            Some(Grade::Fail)
+        );
+    }
+
+    #[test]
+    fn warns_about_insecure_cookies() {
+        let notes = report(&[("set-cookie", "session=abc; Path=/; HttpOnly")]);
+
+        let cookie = notes.iter().find(|n| n.header == "Set-Cookie").unwrap();
+        assert_eq!(cookie.grade, Grade::Warn);
+        assert_eq!(cookie.message, "session is missing Secure, SameSite");
+    }
+}
@@ -0,0 +1,238 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::{bail, Context, Result};
+use reqwest::{
+    header::{CONTENT_TYPE, LOCATION},
+    StatusCode,
+};
+use toml::{Table, Value};
+
+use crate::{
+    env::{get_target, load_target_env, read_and_merge_config},
+    fixtures::{redact_header, redact_json, redact_url, split_request},
+    history::HistoryEntry,
+    request::build_client,
+    scrub::ScrubRules,
+    substitute::substitute,
+};
+
+const SHARE_KEY: &str = "_share";
+
+/// A request and its response as markdown, to paste in a chat or an issue.
+/// Credentials and the values given by the scrub rules are redacted.
+pub fn share_markdown(entry: &HistoryEntry, rules: &ScrubRules) -> String {
+    let mut entry = entry.clone();
+    // The rules may have changed since the response was kept
+    rules.scrub_entry(&mut entry);
+
+    let url = redact_url(&entry.url);
+    let status = status_line(entry.status);
+    let (headers, body) = split_request(&entry.request);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "**{}** `{}` → **{}** in {}ms",
+        entry.method,
+        url,
+        status,
+        entry.elapsed.as_millis()
+    );
+    let _ = writeln!(
+        out,
+        "\n`{}` on `{}`, {}",
+        entry.file,
+        entry.target,
+        entry.local_time()
+    );
+    if let Some(note) = &entry.note {
+        let _ = writeln!(out, "\n> {note}");
+    }
+
+    let mut request = format!("{} {url} HTTP/1.1\n", entry.method);
+    push_headers(&mut request, &headers);
+    push_body(&mut request, body.as_bytes());
+    let _ = write!(out, "\n**Request**\n\n{}", code_block(&request));
+
+    let mut response = format!("HTTP/1.1 {status}\n");
+    push_headers(&mut response, &entry.headers);
+    push_body(&mut response, &entry.body);
+    let _ = write!(out, "\n**Response**\n\n{}", code_block(&response));
+
+    out
+}
+
+fn status_line(status: u16) -> String {
+    let reason = StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason());
+
+    match reason {
+        Some(reason) => format!("{status} {reason}"),
+        None => status.to_string(),
+    }
+}
+
+fn push_headers(out: &mut String, headers: &[(String, String)]) {
+    for (name, value) in headers {
+        let _ = writeln!(out, "{name}: {}", redact_header(name, value));
+    }
+}
+
+fn push_body(out: &mut String, body: &[u8]) {
+    let body = match std::str::from_utf8(body) {
+        Ok(text) if text.trim().is_empty() => return,
+        Ok(text) => match serde_json::from_str(text) {
+            Ok(json) => serde_json::to_string_pretty(&redact_json(json))
+                .unwrap_or_else(|_| text.to_string()),
+            Err(_) => text.trim_end().to_string(),
+        },
+        Err(_) => format!("({} bytes of binary data)", body.len()),
+    };
+
+    let _ = writeln!(out, "\n{body}");
+}
+
+/// A fenced code block, with a fence longer than any run of backticks in the
+/// content
+fn code_block(content: &str) -> String {
+    let longest = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest.max(2) + 1);
+
+    format!("{fence}http\n{content}{fence}\n")
+}
+
+/// Upload a snippet to the paste endpoint given in the config, and give the
+/// link to it:
+///
+/// ```toml
+/// [_share]
+/// url = "https://paste.example.com/api/pastes"
+/// headers = { Authorization = "Bearer {{env:PASTE_TOKEN}}" }
+/// ```
+///
+/// The link is taken from the `Location` header of the response, or else
+/// from its body.
+pub async fn upload(root_dir: &Path, markdown: &str) -> Result<String> {
+    let config = read_and_merge_config(root_dir)?;
+    let share = match config.get(SHARE_KEY) {
+        Some(Value::Table(share)) => share,
+        Some(_) => bail!("{SHARE_KEY} must be a table"),
+        None => bail!("No paste endpoint configured in [{SHARE_KEY}]"),
+    };
+    let Some(Value::String(url)) = share.get("url") else {
+        bail!("{SHARE_KEY}.url must be the url of a paste endpoint");
+    };
+
+    let env = load_target_env(root_dir, &get_target(root_dir))?;
+    let mut request = build_client(&env)?
+        .post(url)
+        .header(CONTENT_TYPE, "text/markdown; charset=utf-8")
+        .body(markdown.to_string());
+
+    match share.get("headers") {
+        Some(Value::Table(headers)) => {
+            for (name, value) in headers {
+                request = request.header(name, header_value(value, &env)?);
+            }
+        }
+        Some(_) => bail!("{SHARE_KEY}.headers must be a table"),
+        None => (),
+    }
+
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Could not upload to {url}"))?;
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = response.text().await?;
+
+    // A redirect to the paste is a link too
+    let redirected = status.is_redirection() && location.is_some();
+    if !status.is_success() && !redirected {
+        bail!("Upload to {url} failed with {status}: {}", body.trim());
+    }
+    if status == StatusCode::NO_CONTENT && location.is_none() {
+        bail!("The paste endpoint gave no link");
+    }
+
+    Ok(location.unwrap_or_else(|| body.trim().to_string()))
+}
+
+fn header_value(value: &Value, env: &Table) -> Result<String> {
+    let Value::String(value) = value else {
+        bail!("{SHARE_KEY}.headers must be strings");
+    };
+
+    Ok(substitute(value, env)?.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry() -> HistoryEntry {
+        HistoryEntry {
+            id: "1714651200000".into(),
+            time: 1714651200000,
+            file: "api/login.http".into(),
+            target: "default".into(),
+            method: "POST".into(),
+            url: "http://example.com/login?api_key=abc".into(),
+            request: "# @assert status == 201\n\
+                      POST http://example.com/login HTTP/1.1\n\
+                      Authorization: Bearer abc\n\
+                      \n\
+                      {\"user\": \"admin\", \"password\": \"hunter2\"}\n"
+                .into(),
+            status: 201,
+            elapsed: Duration::from_millis(85),
+            headers: vec![("content-type".into(), "text/plain".into())],
+            content_type: "text/plain".into(),
+            body: b"created ```here```".to_vec(),
+            note: Some("Repro for BUG-423".into()),
+            bookmarked: false,
+        }
+    }
+
+    #[test]
+    fn renders_redacted_markdown() {
+        let markdown = share_markdown(&entry(), &ScrubRules::default());
+        let (header, rest) = markdown.split_once("\n\n").unwrap();
+
+        assert_eq!(
+            header,
+            "**POST** `http://example.com/login?api_key=********` → \
+             **201 Created** in 85ms"
+        );
+        assert!(rest.contains("\n> Repro for BUG-423\n"));
+        assert!(rest.ends_with(
+            "**Request**\n\n\
+             ```http\n\
+             POST http://example.com/login?api_key=******** HTTP/1.1\n\
+             Authorization: ********\n\
+             \n\
+             {\n  \"password\": \"********\",\n  \"user\": \"admin\"\n}\n\
+             ```\n\
+             \n\
+             **Response**\n\n\
+             ````http\n\
+             HTTP/1.1 201 Created\n\
+             content-type: text/plain\n\
+             \n\
+             created ```here```\n\
+             ````\n"
+        ));
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use redis::Commands;
+use reqwest::Url;
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use toml::{Table, Value};
+
+use crate::{
+    clock::unix_now,
+    env::is_secret_key,
+    request::USER_AGENT,
+    state::{ensure_state_dir, shared_file},
+    util::duration_value,
+};
+
+/// A store of variables shared by a team, such as the tenant used in the
+/// current sprint, given in the config as:
+///
+/// ```toml
+/// [_shared]
+/// url = "https://config.example.com/hitman.json"
+/// refresh = "5m"
+/// ```
+///
+/// The url is either an HTTP location that returns a JSON object or a TOML
+/// document, an S3 object with such a document, like
+/// `s3://team-config/hitman.json` with `region = "eu-north-1"`, or a redis
+/// server with the values in a hash, like `redis://localhost:6379/0` with
+/// `key = "hitman:shared"`. S3 credentials are taken from the
+/// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` environment variables, and
+/// `endpoint` can point to another S3 compatible store.
+pub(crate) const SHARED_KEY: &str = "_shared";
+
+/// When the values were fetched, in the cache file
+const FETCHED_AT_KEY: &str = "_fetched_at";
+
+const DEFAULT_REFRESH: Duration = Duration::from_secs(300);
+const DEFAULT_REDIS_KEY: &str = "hitman";
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedStore {
+    pub url: String,
+    /// The redis hash with the values
+    pub key: String,
+    /// The region of an S3 bucket
+    pub region: Option<String>,
+    /// Where an S3 compatible store is, other than AWS
+    pub endpoint: Option<String>,
+    /// How long fetched values are used before fetching them again
+    pub refresh: Duration,
+}
+
+pub fn shared_store(config: &Table) -> Result<Option<SharedStore>> {
+    let Some(conf) = config.get(SHARED_KEY) else {
+        return Ok(None);
+    };
+    let Value::Table(conf) = conf else {
+        bail!("Invalid {SHARED_KEY} section");
+    };
+
+    let url = conf
+        .get("url")
+        .and_then(Value::as_str)
+        .with_context(|| format!("Missing url in {SHARED_KEY}"))?;
+    let key = conf
+        .get("key")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_REDIS_KEY);
+    let refresh = conf
+        .get("refresh")
+        .and_then(duration_value)
+        .unwrap_or(DEFAULT_REFRESH);
+    let string = |key| conf.get(key).and_then(Value::as_str).map(String::from);
+
+    Ok(Some(SharedStore {
+        url: url.to_string(),
+        key: key.to_string(),
+        region: string("region"),
+        endpoint: string("endpoint"),
+        refresh,
+    }))
+}
+
+/// The shared values for a target. Values in a table named after the target
+/// take precedence over the others. Secrets are never taken from the shared
+/// store.
+pub fn shared_values(
+    root_dir: &Path,
+    config: &Table,
+    target: &str,
+) -> Result<Table> {
+    let Some(store) = shared_store(config)? else {
+        return Ok(Table::new());
+    };
+
+    let values = cached_values(root_dir, &store);
+
+    let mut out: Table = values
+        .iter()
+        .filter(|(_, v)| !v.is_table())
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if let Some(Value::Table(target_values)) = values.get(target) {
+        out.extend(target_values.clone());
+    }
+
+    out.retain(|key, _| {
+        let secret = is_secret_key(key);
+        if secret {
+            warn!("# Ignoring secret value '{}' from the shared store", key);
+        }
+        !secret && key != FETCHED_AT_KEY
+    });
+
+    Ok(out)
+}
+
+/// The values from the cache, fetched again when they are too old. If the
+/// store can't be reached, the old values are used.
+fn cached_values(root_dir: &Path, store: &SharedStore) -> Table {
+    let file = shared_file(root_dir);
+    let cached: Option<Table> = fs::read_to_string(&file)
+        .ok()
+        .and_then(|c| toml::from_str(&c).ok());
+
+    let fetched_at = cached
+        .as_ref()
+        .and_then(|c| c.get(FETCHED_AT_KEY))
+        .and_then(Value::as_integer)
+        .unwrap_or(0) as u64;
+    if let Some(cached) = &cached {
+        if unix_now() < fetched_at + store.refresh.as_secs() {
+            return cached.clone();
+        }
+    }
+
+    match fetch(store) {
+        Ok(mut values) => {
+            info!("# Fetched shared values from {}", store.url);
+            values.insert(
+                FETCHED_AT_KEY.to_string(),
+                Value::Integer(unix_now() as i64),
+            );
+            let saved = ensure_state_dir(root_dir)
+                .and_then(|_| Ok(toml::to_string_pretty(&values)?))
+                .and_then(|content| Ok(fs::write(&file, content)?));
+            if let Err(err) = saved {
+                warn!("# Failed to cache shared values: {err}");
+            }
+            values
+        }
+        Err(err) => {
+            warn!("# Failed to fetch shared values: {err:#}");
+            cached.unwrap_or_default()
+        }
+    }
+}
+
+fn fetch(store: &SharedStore) -> Result<Table> {
+    if store.url.starts_with("redis://") {
+        fetch_redis(&store.url, &store.key, TIMEOUT)
+    } else if store.url.starts_with("s3://") {
+        fetch_s3(store)
+    } else {
+        fetch_http(&store.url)
+    }
+}
+
+/// Values are loaded while loading the environment, which is not async, so
+/// the request is made on its own thread and runtime
+fn fetch_http(url: &str) -> Result<Table> {
+    let url = url.to_string();
+    let body = std::thread::spawn(move || -> Result<String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let response = reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .timeout(TIMEOUT)
+                .build()?
+                .get(&url)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(response.text().await?)
+        })
+    })
+    .join()
+    .map_err(|_| anyhow!("Shared store request panicked"))??;
+
+    parse_values(&body)
+}
+
+/// A JSON object or a TOML document
+fn parse_values(body: &str) -> Result<Table> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+        return match Value::try_from(json)? {
+            Value::Table(table) => Ok(table),
+            _ => bail!("Shared values must be a JSON object"),
+        };
+    }
+
+    toml::from_str(body).context("Shared values must be JSON or TOML")
+}
+
+/// Get the fields of a redis hash, like `HGETALL hitman`
+fn fetch_redis(url: &str, key: &str, timeout: Duration) -> Result<Table> {
+    let client = redis::Client::open(url)
+        .with_context(|| format!("Invalid redis url: {url}"))?;
+    let mut connection = client.get_connection_with_timeout(timeout)?;
+    connection.set_read_timeout(Some(timeout))?;
+    connection.set_write_timeout(Some(timeout))?;
+
+    let fields: HashMap<String, String> = connection.hgetall(key)?;
+
+    Ok(fields
+        .into_iter()
+        .map(|(field, value)| (field, Value::String(value)))
+        .collect())
+}
+
+/// A presigned url for an object, like `s3://bucket/path/values.json`.
+/// Without credentials, the object must be public.
+fn s3_url(
+    url: &str,
+    region: &str,
+    endpoint: Option<&str>,
+    credentials: Option<&Credentials>,
+) -> Result<Url> {
+    let (name, object) = url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+        .with_context(|| format!("Invalid S3 url: {url}"))?;
+
+    // Other stores, like MinIO, usually don't have a host per bucket
+    let (endpoint, style) = match endpoint {
+        Some(endpoint) => (endpoint.to_string(), UrlStyle::Path),
+        None => (
+            format!("https://s3.{region}.amazonaws.com"),
+            UrlStyle::VirtualHost,
+        ),
+    };
+    let bucket = Bucket::new(
+        endpoint.parse()?,
+        style,
+        name.to_string(),
+        region.to_string(),
+    )?;
+
+    Ok(bucket.get_object(credentials, object).sign(TIMEOUT))
+}
+
+fn fetch_s3(store: &SharedStore) -> Result<Table> {
+    let region = store
+        .region
+        .clone()
+        .or_else(|| std::env::var("AWS_REGION").ok())
+        .unwrap_or_else(|| DEFAULT_S3_REGION.to_string());
+    let url = s3_url(
+        &store.url,
+        &region,
+        store.endpoint.as_deref(),
+        Credentials::from_env().as_ref(),
+    )?;
+
+    fetch_http(url.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_store_from_config() {
+        let config: Table = toml::from_str(
+            r#"
+            [_shared]
+            url = "redis://localhost/2"
+            refresh = "1m"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            shared_store(&config).unwrap(),
+            Some(SharedStore {
+                url: "redis://localhost/2".into(),
+                key: "hitman".into(),
+                region: None,
+                endpoint: None,
+                refresh: Duration::from_secs(60),
+            })
+        );
+        assert_eq!(shared_store(&Table::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_json_and_toml_values() {
+        let json = parse_values(r#"{"tenant": "acme", "dev": {"cohort": 3}}"#)
+            .unwrap();
+        let toml = parse_values("tenant = \"acme\"\n").unwrap();
+
+        assert_eq!(json["tenant"].as_str(), Some("acme"));
+        assert_eq!(json["dev"]["cohort"].as_integer(), Some(3));
+        assert_eq!(toml["tenant"].as_str(), Some("acme"));
+        assert!(parse_values("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn presigns_s3_urls() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret");
+
+        let url = s3_url(
+            "s3://team-config/hitman/values.json",
+            "eu-north-1",
+            None,
+            Some(&credentials),
+        )
+        .unwrap();
+
+        assert_eq!(
+            url.host_str(),
+            Some("team-config.s3.eu-north-1.amazonaws.com")
+        );
+        assert_eq!(url.path(), "/hitman/values.json");
+        assert!(url.query().unwrap().contains("X-Amz-Signature="));
+
+        let url = s3_url(
+            "s3://team-config/values.json",
+            "us-east-1",
+            Some("http://localhost:9000"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(url.path(), "/team-config/values.json");
+        assert!(s3_url("s3://team-config", "us-east-1", None, None).is_err());
+    }
+
+    #[test]
+    fn gives_up_on_redis_servers_that_never_answer() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+
+        let started = std::time::Instant::now();
+        let res = fetch_redis(&url, "hitman", Duration::from_millis(200));
+
+        assert!(res.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
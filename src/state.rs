@@ -0,0 +1,568 @@
+use anyhow::Result;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use toml::{Table, Value};
+use walkdir::WalkDir;
+
+use crate::cookies::import_legacy_cookies;
+use crate::env::{get_target, read_and_merge_config};
+use crate::util::{duration_value, parse_size};
+
+/// All mutable state for a project is kept in this directory, next to
+/// hitman.toml
+const STATE_DIR: &str = ".hitman";
+const DATA_DIR: &str = "data";
+const TARGET_FILE: &str = "target";
+const COOKIE_JARS_DIR: &str = "cookies";
+const ETAGS_FILE: &str = "etags.toml";
+const EXPIRY_DIR: &str = "expiry";
+const RUNS_DIR: &str = "runs";
+const TOKENS_FILE: &str = "tokens.toml";
+const SHARED_FILE: &str = "shared.toml";
+const USAGE_FILE: &str = "usage.toml";
+const SYNC_BASE_FILE: &str = "sync.toml";
+const SESSIONS_DIR: &str = "sessions";
+
+const RETENTION_KEY: &str = "_state";
+
+/// Entries in this directory of a store are never pruned
+pub const PINNED_DIR: &str = "pinned";
+
+/// Every response is kept in this store, as one gzip'd file per response
+pub const HISTORY_STORE: &str = "history";
+/// Responses recorded with `--record`, as one file per request
+pub const RECORDINGS_STORE: &str = "recordings";
+
+/// The stores that are pruned by their retention limits. Other directories
+/// in the state directory, such as captured values, pending rollbacks, cookie
+/// jars and session targets, are never pruned.
+const PRUNED_STORES: [&str; 2] = [HISTORY_STORE, RECORDINGS_STORE];
+
+const LEGACY_TARGET_FILE: &str = ".hitman-target";
+const LEGACY_DATA_FILE: &str = ".hitman-data.toml";
+const LEGACY_COOKIE_KEY: &str = "Cookies";
+/// The cookies of all targets, in the state directory
+const LEGACY_COOKIES_FILE: &str = "cookies.toml";
+/// The values captured with all targets, and when they expire, in the state
+/// directory
+const LEGACY_STATE_DATA_FILE: &str = "data.toml";
+const LEGACY_EXPIRY_FILE: &str = "expiry.toml";
+
+pub fn state_dir(root_dir: &Path) -> PathBuf {
+    root_dir.join(STATE_DIR)
+}
+
+/// The values captured from responses with a target
+pub fn data_file(root_dir: &Path, target: &str) -> PathBuf {
+    state_dir(root_dir)
+        .join(DATA_DIR)
+        .join(format!("{target}.toml"))
+}
+
+pub fn target_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(TARGET_FILE)
+}
+
+pub fn session_target_file(root_dir: &Path, session_id: &str) -> PathBuf {
+    state_dir(root_dir).join(SESSIONS_DIR).join(session_id)
+}
+
+/// The cookies of a target
+pub fn cookie_jar_file(root_dir: &Path, target: &str) -> PathBuf {
+    state_dir(root_dir)
+        .join(COOKIE_JARS_DIR)
+        .join(format!("{target}.toml"))
+}
+
+pub fn etags_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(ETAGS_FILE)
+}
+
+/// When the values captured with a target expire, as unix timestamps
+pub fn expiry_file(root_dir: &Path, target: &str) -> PathBuf {
+    state_dir(root_dir)
+        .join(EXPIRY_DIR)
+        .join(format!("{target}.toml"))
+}
+
+pub fn runs_dir(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(RUNS_DIR)
+}
+
+pub fn tokens_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(TOKENS_FILE)
+}
+
+pub fn shared_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(SHARED_FILE)
+}
+
+pub fn usage_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(USAGE_FILE)
+}
+
+/// The bundle last pulled or pushed over HTTP, to tell local changes from
+/// changes made by others
+pub fn sync_base_file(root_dir: &Path) -> PathBuf {
+    state_dir(root_dir).join(SYNC_BASE_FILE)
+}
+
+/// Create the state directory if needed, and return its path.
+///
+/// The directory ignores itself, so that state never ends up in git, even if
+/// the project doesn't ignore it.
+pub fn ensure_state_dir(root_dir: &Path) -> Result<PathBuf> {
+    let dir = state_dir(root_dir);
+    fs::create_dir_all(&dir)?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(gitignore, "# Created by hitman\n*\n")?;
+    }
+
+    Ok(dir)
+}
+
+/// Move state from the files used by earlier versions, which were written
+/// directly into the project root. Cookies and captured values go to the
+/// selected target.
+pub fn migrate_legacy_state(root_dir: &Path) -> Result<()> {
+    let legacy_target = root_dir.join(LEGACY_TARGET_FILE);
+    if legacy_target.exists() {
+        if !target_file(root_dir).exists() {
+            ensure_state_dir(root_dir)?;
+            fs::copy(&legacy_target, target_file(root_dir))?;
+        }
+        fs::remove_file(&legacy_target)?;
+        warn!("# Moved {} to {}", LEGACY_TARGET_FILE, STATE_DIR);
+    }
+
+    let legacy_data = root_dir.join(LEGACY_DATA_FILE);
+    if legacy_data.exists() {
+        ensure_state_dir(root_dir)?;
+
+        let mut data: Table =
+            toml::from_str(&fs::read_to_string(&legacy_data)?)?;
+
+        if let Some(Value::Array(cookies)) = data.remove(LEGACY_COOKIE_KEY) {
+            let jar = cookie_jar_file(root_dir, &get_target(root_dir));
+            import_legacy_cookies(&jar, cookies)?;
+        }
+        merge_into_file(&data_file(root_dir, &get_target(root_dir)), data)?;
+
+        fs::remove_file(&legacy_data)?;
+        warn!("# Moved {} to {}", LEGACY_DATA_FILE, STATE_DIR);
+    }
+
+    let legacy_cookies = state_dir(root_dir).join(LEGACY_COOKIES_FILE);
+    if legacy_cookies.exists() {
+        let mut content: Table =
+            toml::from_str(&fs::read_to_string(&legacy_cookies)?)?;
+        if let Some(Value::Array(cookies)) = content.remove(LEGACY_COOKIE_KEY) {
+            let jar = cookie_jar_file(root_dir, &get_target(root_dir));
+            import_legacy_cookies(&jar, cookies)?;
+        }
+
+        fs::remove_file(&legacy_cookies)?;
+        warn!("# Moved {} to {}", LEGACY_COOKIES_FILE, COOKIE_JARS_DIR);
+    }
+
+    let target = get_target(root_dir);
+    for (legacy_file, dir, file) in [
+        (
+            LEGACY_STATE_DATA_FILE,
+            DATA_DIR,
+            data_file(root_dir, &target),
+        ),
+        (
+            LEGACY_EXPIRY_FILE,
+            EXPIRY_DIR,
+            expiry_file(root_dir, &target),
+        ),
+    ] {
+        let legacy = state_dir(root_dir).join(legacy_file);
+        if legacy.exists() {
+            let content: Table = toml::from_str(&fs::read_to_string(&legacy)?)?;
+            merge_into_file(&file, content)?;
+
+            fs::remove_file(&legacy)?;
+            warn!("# Moved {} to {}", legacy_file, dir);
+        }
+    }
+
+    Ok(())
+}
+
+/// Add values to a file, without overwriting values that are already there
+fn merge_into_file(file_path: &Path, mut values: Table) -> Result<()> {
+    if let Ok(content) = fs::read_to_string(file_path) {
+        values.extend(toml::from_str::<Table>(&content)?);
+    }
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(file_path, toml::to_string_pretty(&values)?)?;
+
+    Ok(())
+}
+
+/// Get a directory for a store of entries, such as history or recordings.
+/// The stores in [PRUNED_STORES] are subject to pruning, except for entries
+/// moved to the [PINNED_DIR] of the store.
+pub fn store_dir(root_dir: &Path, name: &str) -> Result<PathBuf> {
+    let dir = ensure_state_dir(root_dir)?.join(name);
+    fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+/// Limits for how much is kept in each store, configured in hitman.toml.
+/// A table named after a store has limits for that store only:
+///
+/// ```toml
+/// [_state]
+/// max_entries = 500
+/// max_age = "30d"
+/// max_size = "100MB"
+///
+/// [_state.history]
+/// max_size = "20MB"
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct Retention {
+    pub max_entries: Option<usize>,
+    pub max_age: Option<Duration>,
+    pub max_size: Option<u64>,
+    pub stores: BTreeMap<String, Retention>,
+}
+
+impl Retention {
+    pub fn load(root_dir: &Path) -> Result<Self> {
+        let config = read_and_merge_config(root_dir)?;
+
+        Ok(match config.get(RETENTION_KEY) {
+            Some(Value::Table(t)) => Self::from_table(t),
+            _ => Self::default(),
+        })
+    }
+
+    fn from_table(table: &Table) -> Self {
+        Self {
+            max_entries: table
+                .get("max_entries")
+                .and_then(|v| v.as_integer())
+                .map(|n| n.max(0) as usize),
+            max_age: table.get("max_age").and_then(duration_value),
+            max_size: table.get("max_size").and_then(|v| match v {
+                Value::Integer(n) => Some((*n).max(0) as u64),
+                Value::String(s) => parse_size(s),
+                _ => None,
+            }),
+            stores: table
+                .iter()
+                .filter_map(|(name, value)| match value {
+                    Value::Table(t) => {
+                        Some((name.clone(), Self::from_table(t)))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// The limits for a store, where those given for the store win over
+    /// those given for all stores
+    pub fn for_store(&self, name: &str) -> Self {
+        let store = self.stores.get(name);
+
+        Self {
+            max_entries: store.and_then(|s| s.max_entries).or(self.max_entries),
+            max_age: store.and_then(|s| s.max_age).or(self.max_age),
+            max_size: store.and_then(|s| s.max_size).or(self.max_size),
+            stores: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PruneStats {
+    pub removed: usize,
+    pub bytes: u64,
+}
+
+/// Remove the oldest entries in each of the [PRUNED_STORES], that are
+/// outside the retention limits.
+pub fn prune(root_dir: &Path, retention: &Retention) -> Result<PruneStats> {
+    let mut stats = PruneStats::default();
+
+    for name in PRUNED_STORES {
+        let pruned = prune_store(root_dir, name, retention)?;
+        stats.removed += pruned.removed;
+        stats.bytes += pruned.bytes;
+    }
+
+    Ok(stats)
+}
+
+/// Remove the oldest entries in one store, that are outside its retention
+/// limits
+pub fn prune_store(
+    root_dir: &Path,
+    name: &str,
+    retention: &Retention,
+) -> Result<PruneStats> {
+    let mut stats = PruneStats::default();
+
+    let store = state_dir(root_dir).join(name);
+    if !store.is_dir() {
+        return Ok(stats);
+    }
+
+    for (path, size) in expired_entries(&store, &retention.for_store(name))? {
+        fs::remove_file(path)?;
+        stats.removed += 1;
+        stats.bytes += size;
+    }
+
+    Ok(stats)
+}
+
+fn expired_entries(
+    store: &Path,
+    retention: &Retention,
+) -> Result<Vec<(PathBuf, u64)>> {
+    let pinned = store.join(PINNED_DIR);
+    let mut entries: Vec<(PathBuf, SystemTime, u64)> = WalkDir::new(store)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && !e.path().starts_with(&pinned))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            Some((e.into_path(), meta.modified().ok()?, meta.len()))
+        })
+        .collect();
+
+    // Newest first
+    entries.sort_by_key(|e| std::cmp::Reverse(e.1));
+
+    let now = SystemTime::now();
+    let mut total_size = 0;
+
+    let expired = entries
+        .into_iter()
+        .enumerate()
+        .filter(|(i, (_, modified, size))| {
+            total_size += size;
+
+            let too_many = retention.max_entries.is_some_and(|max| *i >= max);
+            let too_old = retention.max_age.is_some_and(|max| {
+                now.duration_since(*modified).unwrap_or_default() > max
+            });
+            let too_big =
+                retention.max_size.is_some_and(|max| total_size > max);
+
+            too_many || too_old || too_big
+        })
+        .map(|(_, (path, _, size))| (path, size))
+        .collect();
+
+    Ok(expired)
+}
+
+/// Remove all state for the project
+pub fn clean_all(root_dir: &Path) -> Result<()> {
+    let dir = state_dir(root_dir);
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_retention_from_config() {
+        let table = toml::from_str(
+            r#"
+            max_entries = 10
+            max_age = "7d"
+            max_size = "1MB"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            Retention::from_table(&table),
+            Retention {
+                max_entries: Some(10),
+                max_age: Some(Duration::from_secs(7 * 24 * 3600)),
+                max_size: Some(1024 * 1024),
+                stores: BTreeMap::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn store_limits_win() {
+        let table = toml::from_str(
+            r#"
+            max_entries = 10
+            max_age = "7d"
+
+            [history]
+            max_age = "1d"
+            max_size = "1MB"
+            "#,
+        )
+        .unwrap();
+        let retention = Retention::from_table(&table);
+
+        assert_eq!(
+            retention.for_store("history"),
+            Retention {
+                max_entries: Some(10),
+                max_age: Some(Duration::from_secs(24 * 3600)),
+                max_size: Some(1024 * 1024),
+                stores: BTreeMap::new(),
+            }
+        );
+        assert_eq!(
+            retention.for_store("cache").max_age,
+            Some(Duration::from_secs(7 * 24 * 3600))
+        );
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "hitman-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn state_dir_ignores_itself() {
+        let root = temp_root("gitignore");
+        let dir = ensure_state_dir(&root).unwrap();
+
+        let gitignore = fs::read_to_string(dir.join(".gitignore")).unwrap();
+        assert!(gitignore.lines().any(|l| l == "*"));
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn migrates_legacy_state_files() {
+        let root = temp_root("migrate");
+        fs::write(root.join(LEGACY_TARGET_FILE), "prod").unwrap();
+        fs::write(
+            root.join(LEGACY_DATA_FILE),
+            "token = \"abc\"\nCookies = [\"a=b\"]\n",
+        )
+        .unwrap();
+
+        migrate_legacy_state(&root).unwrap();
+
+        assert!(!root.join(LEGACY_TARGET_FILE).exists());
+        assert!(!root.join(LEGACY_DATA_FILE).exists());
+        assert_eq!(fs::read_to_string(target_file(&root)).unwrap(), "prod");
+
+        let data: Table = toml::from_str(
+            &fs::read_to_string(data_file(&root, "prod")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(data.get("token"), Some(&Value::String("abc".into())));
+        assert!(data.get(LEGACY_COOKIE_KEY).is_none());
+        let jar = fs::read_to_string(cookie_jar_file(&root, "prod")).unwrap();
+        assert!(jar.contains("a=b"));
+
+        fs::write(
+            state_dir(&root).join(LEGACY_COOKIES_FILE),
+            "Cookies = [\"c=d\"]\n",
+        )
+        .unwrap();
+        migrate_legacy_state(&root).unwrap();
+
+        assert!(!state_dir(&root).join(LEGACY_COOKIES_FILE).exists());
+        let jar = fs::read_to_string(cookie_jar_file(&root, "prod")).unwrap();
+        assert!(jar.contains("a=b") && jar.contains("c=d"));
+
+        // Values captured before they were kept per target
+        fs::write(
+            state_dir(&root).join(LEGACY_STATE_DATA_FILE),
+            "token = \"def\"\nid = 1\n",
+        )
+        .unwrap();
+        fs::write(state_dir(&root).join(LEGACY_EXPIRY_FILE), "id = 100\n")
+            .unwrap();
+        migrate_legacy_state(&root).unwrap();
+
+        assert!(!state_dir(&root).join(LEGACY_STATE_DATA_FILE).exists());
+        assert!(!state_dir(&root).join(LEGACY_EXPIRY_FILE).exists());
+        let data: Table = toml::from_str(
+            &fs::read_to_string(data_file(&root, "prod")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(data.get("token"), Some(&Value::String("abc".into())));
+        assert_eq!(data.get("id"), Some(&Value::Integer(1)));
+        let expiry = fs::read_to_string(expiry_file(&root, "prod")).unwrap();
+        assert_eq!(expiry, "id = 100\n");
+
+        fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn prunes_entries_beyond_max_entries() {
+        let root = std::env::temp_dir()
+            .join(format!("hitman-prune-{}", std::process::id()));
+        let store = store_dir(&root, "history").unwrap();
+        for i in 0..5 {
+            fs::write(store.join(format!("{i}.json")), "{}").unwrap();
+        }
+
+        let retention = Retention {
+            max_entries: Some(2),
+            ..Default::default()
+        };
+        let stats = prune(&root, &retention).unwrap();
+
+        assert_eq!(stats.removed, 3);
+        assert_eq!(fs::read_dir(&store).unwrap().count(), 2);
+
+        let pinned = store.join(PINNED_DIR);
+        fs::create_dir_all(&pinned).unwrap();
+        for i in 5..8 {
+            fs::write(pinned.join(format!("{i}.json")), "{}").unwrap();
+        }
+        let stats = prune(&root, &retention).unwrap();
+
+        assert_eq!(stats.removed, 0);
+        assert_eq!(fs::read_dir(&pinned).unwrap().count(), 3);
+
+        // Rollbacks and cookie jars are not stores
+        let runs = runs_dir(&root);
+        fs::create_dir_all(&runs).unwrap();
+        for i in 0..3 {
+            fs::write(runs.join(format!("{i}.toml")), "").unwrap();
+        }
+        fs::create_dir_all(cookie_jar_file(&root, "dev").parent().unwrap())
+            .unwrap();
+        fs::write(cookie_jar_file(&root, "dev"), "").unwrap();
+        prune(&root, &retention).unwrap();
+
+        assert_eq!(fs::read_dir(&runs).unwrap().count(), 3);
+        assert!(cookie_jar_file(&root, "dev").exists());
+
+        clean_all(&root).unwrap();
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -2,6 +2,8 @@ use std::str;
 use thiserror::Error;
 use toml::{Table, Value};
 
+use crate::{functions::call_function, plugin::find_plugin, totp::totp};
+
 #[derive(Error, Debug, Clone)]
 pub enum SubstituteError {
     #[error("Missing substitution value for {key}")]
@@ -21,11 +23,16 @@ pub enum SubstituteError {
 
     #[error("Type not supported")]
     TypeNotSupported,
+
+    #[error("Invalid value for {key}: {message}")]
+    InvalidValue { key: String, message: String },
 }
 
 type SubstituteResult<T> = std::result::Result<T, SubstituteError>;
 
 pub fn substitute(input: &str, env: &Table) -> SubstituteResult<String> {
+    let input = expand_blocks(&strip_standalone_tags(input), env)?;
+
     let mut output = String::new();
 
     for line in input.lines() {
@@ -36,6 +43,226 @@ pub fn substitute(input: &str, env: &Table) -> SubstituteResult<String> {
     Ok(output)
 }
 
+/// Remove the line break after block tags that are alone on a line, so that
+/// a block spanning several lines doesn't leave empty lines behind. An empty
+/// line would otherwise end the header section of the request.
+fn strip_standalone_tags(input: &str) -> String {
+    let mut output = String::new();
+
+    for line in input.lines() {
+        let trimmed = line.trim();
+        if is_block_tag(trimmed) {
+            output.push_str(trimmed);
+        } else {
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+fn is_block_tag(s: &str) -> bool {
+    let Some(inner) = s.strip_prefix("{{").and_then(|s| s.strip_suffix("}}"))
+    else {
+        return false;
+    };
+
+    !inner.contains("{{") && !matches!(block_tag(inner), BlockTag::Placeholder)
+}
+
+enum BlockTag<'a> {
+    Open(&'a str, &'a str),
+    Else,
+    Close(&'a str),
+    Placeholder,
+}
+
+fn block_tag(inner: &str) -> BlockTag<'_> {
+    let inner = inner.trim();
+    if let Some(tag) = inner.strip_prefix('#') {
+        let (kind, arg) = tag.split_once(' ').unwrap_or((tag, ""));
+        BlockTag::Open(kind, arg.trim())
+    } else if let Some(kind) = inner.strip_prefix('/') {
+        BlockTag::Close(kind.trim())
+    } else if inner == "else" {
+        BlockTag::Else
+    } else {
+        BlockTag::Placeholder
+    }
+}
+
+/// Expand block helpers, such as `{{#if key}}...{{else}}...{{/if}}` and
+/// `{{#each key}}...{{/each}}`
+fn expand_blocks(input: &str, env: &Table) -> SubstituteResult<String> {
+    let mut output = String::new();
+    let mut slice = input;
+
+    while let Some(pos) = slice.find("{{") {
+        output.push_str(&slice[..pos]);
+        slice = &slice[pos..];
+
+        let Some(end) = slice.find("}}").map(|i| i + 2) else {
+            return Err(SubstituteError::SyntaxError);
+        };
+
+        match block_tag(&slice[2..end - 2]) {
+            BlockTag::Placeholder => {
+                output.push_str(&slice[..end]);
+                slice = &slice[end..];
+            }
+            BlockTag::Open(kind, arg) => {
+                let block = find_block_end(&slice[end..], kind)?;
+
+                match kind {
+                    "if" | "unless" => {
+                        let body = if is_truthy(env.get(arg)) == (kind == "if")
+                        {
+                            block.body
+                        } else {
+                            block.alternative.unwrap_or("")
+                        };
+                        output.push_str(&expand_blocks(body, env)?);
+                    }
+                    "each" => {
+                        let items = list_items(arg, env)?;
+                        let count = items.len();
+                        for (i, item) in items.into_iter().enumerate() {
+                            let scope = item_scope(env, item, i, count);
+                            let body = expand_blocks(block.body, &scope)?;
+                            output.push_str(&substitute_lines(&body, &scope)?);
+                        }
+                    }
+                    _ => return Err(SubstituteError::SyntaxError),
+                };
+
+                slice = block.rest;
+            }
+            BlockTag::Else | BlockTag::Close(_) => {
+                return Err(SubstituteError::SyntaxError);
+            }
+        }
+    }
+
+    output.push_str(slice);
+
+    Ok(output)
+}
+
+struct Block<'a> {
+    body: &'a str,
+    alternative: Option<&'a str>,
+    rest: &'a str,
+}
+
+/// Find the closing tag matching an opening tag of the given kind, skipping
+/// any nested blocks
+fn find_block_end<'a>(
+    input: &'a str,
+    kind: &str,
+) -> SubstituteResult<Block<'a>> {
+    let mut depth = 0;
+    let mut else_pos = None;
+    let mut offset = 0;
+
+    while let Some(pos) = input[offset..].find("{{") {
+        let start = offset + pos;
+        let Some(end) = input[start..].find("}}").map(|i| start + i + 2) else {
+            return Err(SubstituteError::SyntaxError);
+        };
+
+        match block_tag(&input[start + 2..end - 2]) {
+            BlockTag::Open(..) => depth += 1,
+            BlockTag::Else if depth == 0 => else_pos = Some((start, end)),
+            BlockTag::Close(k) if depth == 0 => {
+                if k != kind {
+                    return Err(SubstituteError::SyntaxError);
+                }
+
+                let (body, alternative) = match else_pos {
+                    Some((s, e)) => (&input[..s], Some(&input[e..start])),
+                    None => (&input[..start], None),
+                };
+
+                return Ok(Block {
+                    body,
+                    alternative,
+                    rest: &input[end..],
+                });
+            }
+            BlockTag::Close(_) => depth -= 1,
+            _ => (),
+        }
+
+        offset = end;
+    }
+
+    Err(SubstituteError::SyntaxError)
+}
+
+/// Get the items to loop over in an `{{#each key}}` block. A string is
+/// treated as a comma separated list, so that lists can be given on the
+/// command line or in a prompt.
+fn list_items(key: &str, env: &Table) -> SubstituteResult<Vec<Value>> {
+    match env.get(key) {
+        Some(Value::Array(arr)) => Ok(arr.clone()),
+        Some(Value::String(s)) if s.trim().is_empty() => Ok(Vec::new()),
+        Some(Value::String(s)) => Ok(s
+            .split(',')
+            .map(|it| Value::String(it.trim().to_string()))
+            .collect()),
+        Some(Value::Table(_)) => Err(SubstituteError::TypeNotSupported),
+        Some(other) => Ok(vec![other.clone()]),
+        None => Err(SubstituteError::ValueNotFound {
+            key: key.to_string(),
+            fallback: None,
+        }),
+    }
+}
+
+/// The scope of one iteration in an `{{#each}}` block. The item itself is
+/// available as `this`, and the fields of a table item are available by name.
+fn item_scope(env: &Table, item: Value, index: usize, count: usize) -> Table {
+    let mut scope = env.clone();
+
+    let this = match item {
+        Value::Table(t) => {
+            let this = t.get("value").cloned();
+            scope.extend(t.clone());
+            this.unwrap_or(Value::Table(t))
+        }
+        other => other,
+    };
+
+    scope.insert("this".into(), this);
+    scope.insert("@first".into(), Value::Boolean(index == 0));
+    scope.insert("@last".into(), Value::Boolean(index + 1 == count));
+
+    scope
+}
+
+/// Substitute each line of the input, keeping the line breaks as they are
+fn substitute_lines(input: &str, env: &Table) -> SubstituteResult<String> {
+    let lines = input
+        .split('\n')
+        .map(|line| substitute_line(line, env))
+        .collect::<SubstituteResult<Vec<_>>>()?;
+
+    Ok(lines.join("\n"))
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        None => false,
+        Some(Value::String(s)) => !s.is_empty() && s != "false" && s != "0",
+        Some(Value::Integer(i)) => *i != 0,
+        Some(Value::Float(f)) => *f != 0.0,
+        Some(Value::Boolean(b)) => *b,
+        Some(Value::Array(arr)) => !arr.is_empty(),
+        Some(_) => true,
+    }
+}
+
 fn substitute_line(line: &str, env: &Table) -> SubstituteResult<String> {
     let mut output = String::new();
     let mut slice = line;
@@ -58,8 +285,13 @@ fn substitute_line(line: &str, env: &Table) -> SubstituteResult<String> {
 
                 let rep = find_replacement(&slice[2..end - 2], env)?;
 
-                // Nested substitution
-                let rep = substitute_line(&rep, env)?;
+                // Nested substitution. Values without placeholders are kept
+                // as they are, since they may contain braces, like JSON.
+                let rep = if rep.contains("{{") {
+                    substitute_line(&rep, env)?
+                } else {
+                    rep
+                };
                 output.push_str(&rep);
 
                 slice = &slice[end..];
@@ -70,6 +302,73 @@ fn substitute_line(line: &str, env: &Table) -> SubstituteResult<String> {
     Ok(output)
 }
 
+/// Check if a placeholder stands for a whole JSON value, such as an object
+/// in the request body. This is the case when the placeholder is alone on a
+/// line in the body, or when its fallback is a JSON object or array.
+pub fn is_json_placeholder(
+    input: &str,
+    key: &str,
+    fallback: Option<&str>,
+) -> bool {
+    let json_fallback = fallback
+        .and_then(|fb| serde_json::from_str::<serde_json::Value>(fb).ok())
+        .is_some_and(|v| v.is_object() || v.is_array());
+    if json_fallback {
+        return true;
+    }
+
+    input
+        .lines()
+        .skip_while(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let line = line.trim();
+            let line = line.strip_suffix(',').unwrap_or(line).trim_end();
+            line.strip_prefix("{{")?.strip_suffix("}}")
+        })
+        .any(|inner| {
+            let name = inner.split('|').next().unwrap_or("").trim();
+            name == key
+        })
+}
+
+/// The keys of the values a template refers to, in the order they are first
+/// used. Keys of blocks, like `{{#each items}}`, are included, while
+/// functions, plugins and environment variables are not.
+pub fn placeholder_keys(template: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut slice = template;
+
+    while let Some(start) = slice.find("{{") {
+        let Some(end) = slice[start..].find("}}") else {
+            break;
+        };
+        let inner = &slice[start + 2..start + end];
+        slice = &slice[start + end + 2..];
+
+        let key = match block_tag(inner) {
+            BlockTag::Open(_, arg) => arg,
+            BlockTag::Placeholder => {
+                let key = inner.split('|').next().unwrap_or("").trim();
+                if key.starts_with("plugin ")
+                    || key.starts_with("env:")
+                    || call_function(key).is_some()
+                {
+                    continue;
+                }
+                key.strip_prefix("totp ").unwrap_or(key)
+            }
+            BlockTag::Else | BlockTag::Close(_) => continue,
+        };
+
+        let key: String = key.chars().filter(valid_character).collect();
+        if !key.is_empty() && key != "this" && !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    keys
+}
+
 // Only valid with ascii_alphabetic, ascii_digit or underscores in key name
 fn valid_character(c: &char) -> bool {
     c.is_ascii_alphabetic() || c.is_ascii_digit() || *c == '_'
@@ -82,6 +381,27 @@ fn find_replacement(
     let mut parts = placeholder.split('|');
 
     let key = parts.next().unwrap_or("").trim();
+
+    if let Some(secret_key) = key.strip_prefix("totp ") {
+        return totp_replacement(secret_key.trim(), env);
+    }
+
+    if let Some(call) = key.strip_prefix("plugin ") {
+        return plugin_replacement(call.trim(), env);
+    }
+
+    if let Some(name) = key.strip_prefix("env:") {
+        let fallback = parts.next().map(|fb| fb.trim().to_string());
+        return env_var_replacement(name.trim(), env, fallback);
+    }
+
+    if let Some(value) = call_function(key) {
+        return value.map_err(|err| SubstituteError::InvalidValue {
+            key: key.to_string(),
+            message: err.to_string(),
+        });
+    }
+
     let parsed_key = key.chars().filter(valid_character).collect::<String>();
 
     let parse = |v: &str| key.replace(&parsed_key, v);
@@ -107,6 +427,56 @@ fn find_replacement(
     }
 }
 
+/// `{{totp secret_key}}` gives the current TOTP code for the base32 encoded
+/// secret in `secret_key`
+fn totp_replacement(key: &str, env: &Table) -> SubstituteResult<String> {
+    match env.get(key) {
+        Some(Value::String(secret)) => {
+            totp(secret).map_err(|err| SubstituteError::InvalidValue {
+                key: key.to_string(),
+                message: err.to_string(),
+            })
+        }
+        Some(_) => Err(SubstituteError::TypeNotSupported),
+        None => Err(SubstituteError::ValueNotFound {
+            key: key.to_string(),
+            fallback: None,
+        }),
+    }
+}
+
+/// `{{env:NAME}}` gives the value of an environment variable of the process.
+/// A value given in a prompt is kept in the env as `env:NAME`.
+fn env_var_replacement(
+    name: &str,
+    env: &Table,
+    fallback: Option<String>,
+) -> SubstituteResult<String> {
+    let key = format!("env:{name}");
+
+    if let Ok(value) = std::env::var(name) {
+        return Ok(value);
+    }
+
+    match env.get(&key) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(_) => Err(SubstituteError::TypeNotSupported),
+        None => Err(SubstituteError::ValueNotFound { key, fallback }),
+    }
+}
+
+/// `{{plugin name args}}` gives the value resolved by a plugin
+fn plugin_replacement(call: &str, env: &Table) -> SubstituteResult<String> {
+    let (name, args) = call.split_once(' ').unwrap_or((call, ""));
+
+    find_plugin(env, name)
+        .and_then(|plugin| plugin.value(args.trim()))
+        .map_err(|err| SubstituteError::InvalidValue {
+            key: format!("plugin {name}"),
+            message: format!("{err:#}"),
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,11 +546,15 @@ mod tests {
     }
 
     #[test]
-    fn substitutes_default_value() {
+    fn reports_default_value_for_missing_key() {
         let env = create_env();
-        let res = substitute("foo: {{href | fallback.com }}\n", &env).unwrap();
+        let res = substitute("foo: {{href | fallback.com }}\n", &env);
 
-        assert_eq!(&res, "foo: fallback.com\n");
+        assert!(matches!(
+            res,
+            Err(SubstituteError::ValueNotFound { key, fallback })
+                if key == "href" && fallback.as_deref() == Some("fallback.com")
+        ));
     }
 
     #[test]
@@ -264,6 +638,107 @@ mod tests {
         assert_eq!(&res, "foo: foo.com\n");
     }
 
+    #[test]
+    fn includes_conditional_block_when_value_is_set() {
+        let env = create_env();
+        let res = substitute(
+            "GET /foo?a=1{{#if boolean}}&b={{integer}}{{/if}}",
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(&res, "GET /foo?a=1&b=42\n");
+    }
+
+    #[test]
+    fn skips_conditional_block_when_value_is_missing() {
+        let env = create_env();
+        let res =
+            substitute("GET /foo?a=1{{#if filter}}&b={{filter}}{{/if}}", &env)
+                .unwrap();
+
+        assert_eq!(&res, "GET /foo?a=1\n");
+    }
+
+    #[test]
+    fn uses_else_branch_when_value_is_falsy() {
+        let mut env = create_env();
+        env.insert("flag".into(), Value::String("false".into()));
+        let res = substitute("{{#if flag}}yes{{else}}no{{/if}}", &env).unwrap();
+
+        assert_eq!(&res, "no\n");
+    }
+
+    #[test]
+    fn removes_lines_with_standalone_block_tags() {
+        let env = create_env();
+        let input = "GET /foo\n{{#if token}}\nAuthorization: {{token}}\n{{/if}}\n{{#if missing}}\nX-Missing: yes\n{{/if}}\nAccept: */*\n";
+        let res = substitute(input, &env).unwrap();
+
+        assert_eq!(&res, "GET /foo\nAuthorization: abc123\nAccept: */*\n");
+    }
+
+    #[test]
+    fn supports_nested_conditional_blocks() {
+        let env = create_env();
+        let input = "{{#if url}}a{{#if missing}}b{{/if}}c{{/if}}";
+        let res = substitute(input, &env).unwrap();
+
+        assert_eq!(&res, "ac\n");
+    }
+
+    #[test]
+    fn repeats_each_block_for_list_items() {
+        let env: Table = toml::from_str("ids = [1, 2, 3]").unwrap();
+        let input = r#"[{{#each ids}}{ "id": {{this}} }{{#unless @last}},{{/unless}}{{/each}}]"#;
+        let res = substitute(input, &env).unwrap();
+
+        assert_eq!(&res, "[{ \"id\": 1 },{ \"id\": 2 },{ \"id\": 3 }]\n");
+    }
+
+    #[test]
+    fn exposes_table_fields_in_each_block() {
+        let env: Table = toml::from_str(
+            r#"
+            prefix = "item"
+            items = [{ name = "a", value = 1 }, { name = "b", value = 2 }]
+            "#,
+        )
+        .unwrap();
+        let input = "{{#each items}}\n{{prefix}} {{name}}={{this}}\n{{/each}}";
+        let res = substitute(input, &env).unwrap();
+
+        assert_eq!(&res, "item a=1\nitem b=2\n");
+    }
+
+    #[test]
+    fn splits_string_values_in_each_block() {
+        let mut env = create_env();
+        env.insert("ids".into(), Value::String("4, 5".into()));
+        let res = substitute("{{#each ids}}<{{this}}>{{/each}}", &env).unwrap();
+
+        assert_eq!(&res, "<4><5>\n");
+    }
+
+    #[test]
+    fn reports_missing_list_in_each_block() {
+        let env = create_env();
+        let res = substitute("{{#each ids}}{{this}}{{/each}}", &env);
+
+        assert!(matches!(
+            res,
+            Err(SubstituteError::ValueNotFound { key, .. }) if key == "ids"
+        ));
+    }
+
+    #[test]
+    fn fails_for_unclosed_conditional_block() {
+        let env = create_env();
+        let res = substitute("{{#if url}}foo", &env);
+
+        assert!(res.is_err())
+    }
+
     #[test]
     fn fails_for_unmatched_open() {
         let env = create_env();
@@ -279,4 +754,92 @@ mod tests {
 
         assert!(res.is_err())
     }
+
+    #[test]
+    fn substitutes_totp_codes() {
+        let mut env = create_env();
+        env.insert("mfa_secret".into(), "GEZDGNBVGY3TQOJQ".into());
+
+        let res = substitute("code={{ totp mfa_secret }}\n", &env).unwrap();
+
+        let code = res.trim().strip_prefix("code=").unwrap();
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn asks_for_missing_totp_secret() {
+        let env = create_env();
+        let res = substitute("{{totp mfa_secret}}\n", &env);
+
+        assert!(matches!(
+            res,
+            Err(SubstituteError::ValueNotFound { key, .. }) if key == "mfa_secret"
+        ));
+    }
+
+    #[test]
+    fn substitutes_environment_variables() {
+        let mut env = create_env();
+        std::env::set_var("HITMAN_TEST_API_KEY", "secret");
+
+        let res = substitute("key={{ env:HITMAN_TEST_API_KEY }}\n", &env);
+        assert_eq!(res.unwrap(), "key=secret\n");
+
+        let res = substitute("{{env:HITMAN_TEST_UNSET | fallback}}\n", &env);
+        assert!(matches!(
+            res,
+            Err(SubstituteError::ValueNotFound { key, fallback })
+                if key == "env:HITMAN_TEST_UNSET"
+                    && fallback.as_deref() == Some("fallback")
+        ));
+
+        env.insert("env:HITMAN_TEST_UNSET".into(), "given".into());
+        let res = substitute("{{env:HITMAN_TEST_UNSET}}\n", &env);
+        assert_eq!(res.unwrap(), "given\n");
+    }
+
+    #[test]
+    fn keeps_nested_json_values() {
+        let mut env = create_env();
+        env.insert("payload".into(), r#"{"a": {"b": 1}}"#.into());
+
+        let res = substitute("{{payload}}\n", &env).unwrap();
+
+        assert_eq!(res, "{\"a\": {\"b\": 1}}\n");
+    }
+
+    #[test]
+    fn detects_json_placeholders() {
+        let input =
+            "POST /apples HTTP/1.1\nX-Payload: {{header}}\n\n{{ payload }}\n";
+
+        assert!(is_json_placeholder(input, "payload", None));
+        assert!(!is_json_placeholder(input, "header", None));
+    }
+
+    #[test]
+    fn detects_json_placeholders_in_arrays_and_by_fallback() {
+        let input =
+            "POST /apples HTTP/1.1\n\n[\n  {{first}},\n  {\"id\": {{id}}}\n]\n";
+
+        assert!(is_json_placeholder(input, "first", None));
+        assert!(!is_json_placeholder(input, "id", None));
+        assert!(is_json_placeholder(input, "id", Some(r#"{"a": 1}"#)));
+        assert!(!is_json_placeholder(input, "id", Some("1")));
+    }
+
+    #[test]
+    fn lists_placeholder_keys() {
+        let template = "GET {{url}}/orders/{{ id | 1 }}?at={{now()}}\n\
+                        Authorization: Bearer {{token}}\n\
+                        X-Code: {{totp otp_secret}}\n\
+                        X-Home: {{env:HOME}}\n\n\
+                        {{#each items}}{{this}} {{id}}{{/each}}";
+
+        assert_eq!(
+            placeholder_keys(template),
+            vec!["url", "id", "token", "otp_secret", "items"]
+        );
+    }
 }
@@ -0,0 +1,582 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use reqwest::Client;
+use toml::{Table, Value};
+use walkdir::WalkDir;
+
+use crate::{
+    discovery::is_request_file,
+    env::{is_secret_key, read_and_merge_config, CONFIG_FILE},
+    prompt::UserInteraction,
+    request::USER_AGENT,
+    state::{ensure_state_dir, sync_base_file},
+};
+
+/// Where the collection is shared, given in hitman.toml as a git remote:
+///
+/// ```toml
+/// [_sync]
+/// git = "origin"
+/// branch = "main"
+/// ```
+///
+/// or as an HTTP location that accepts `GET` and `PUT` of a bundle of the
+/// files, with optional headers:
+///
+/// ```toml
+/// [_sync]
+/// url = "https://files.example.com/api-requests.toml"
+/// headers = { Authorization = "Bearer ..." }
+/// ```
+///
+/// Secret values, such as the `Authorization` header, are never shared, so
+/// they are best kept in hitman.local.toml.
+const SYNC_KEY: &str = "_sync";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncSource {
+    Git {
+        remote: String,
+        branch: Option<String>,
+    },
+    Http {
+        url: String,
+        headers: Vec<(String, String)>,
+    },
+}
+
+pub fn sync_source(config: &Table) -> Result<SyncSource> {
+    let Some(Value::Table(sync)) = config.get(SYNC_KEY) else {
+        bail!("No [{SYNC_KEY}] section in {CONFIG_FILE}");
+    };
+    let string = |key| sync.get(key).and_then(Value::as_str).map(String::from);
+
+    match (string("git"), string("url")) {
+        (Some(remote), None) => Ok(SyncSource::Git {
+            remote,
+            branch: string("branch"),
+        }),
+        (None, Some(url)) => {
+            let headers = match sync.get("headers") {
+                Some(Value::Table(headers)) => headers
+                    .iter()
+                    .map(|(k, v)| {
+                        let v = v.as_str().unwrap_or_default().to_string();
+                        (k.clone(), v)
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Ok(SyncSource::Http { url, headers })
+        }
+        _ => bail!("[{SYNC_KEY}] needs either `git` or `url`"),
+    }
+}
+
+/// The files that make up the collection: the requests, their configs, and
+/// hitman.toml. Local config and state are never shared.
+pub fn collection_files(root_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    let walker = WalkDir::new(root_dir).into_iter().filter_entry(|e| {
+        e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.')
+    });
+    for entry in walker {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy();
+        let shared = is_request_file(&name)
+            || name.ends_with(".http.toml")
+            || (entry.depth() == 1 && name == CONFIG_FILE);
+        if shared {
+            files.push(entry.path().strip_prefix(root_dir)?.to_path_buf());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Get the latest version of the collection. Files changed locally since
+/// the last pull or push are only overwritten when forced, or confirmed.
+pub async fn pull(
+    root_dir: &Path,
+    force: bool,
+    interaction: &dyn UserInteraction,
+) -> Result<()> {
+    match sync_source(&read_and_merge_config(root_dir)?)? {
+        SyncSource::Git { remote, branch } => {
+            // Git refuses itself to overwrite local changes
+            let mut args = vec!["pull", "--ff-only", &remote];
+            args.extend(branch.as_deref());
+            git(root_dir, &args)
+        }
+        SyncSource::Http { url, headers } => {
+            let mut request =
+                Client::builder().user_agent(USER_AGENT).build()?.get(&url);
+            for (name, value) in &headers {
+                request = request.header(name, value);
+            }
+            let response = request.send().await?.error_for_status()?;
+            let content = response.text().await?;
+
+            let changed = local_changes(root_dir, &content)?;
+            if !changed.is_empty() && !force {
+                warn!("# Changed locally since the last sync:");
+                for path in &changed {
+                    warn!("{}", path.display());
+                }
+                if !interaction.confirm("Overwrite the local changes?")? {
+                    bail!(
+                        "Local changes would be overwritten. Use --force to \
+                         overwrite them."
+                    );
+                }
+            }
+
+            let files = unbundle(root_dir, &content)?;
+            save_sync_base(root_dir, &content)?;
+            warn!("# Updated {} files from {url}", files.len());
+
+            Ok(())
+        }
+    }
+}
+
+/// Share the local version of the collection, without secret values
+pub async fn push(root_dir: &Path) -> Result<()> {
+    match sync_source(&read_and_merge_config(root_dir)?)? {
+        SyncSource::Git { remote, branch } => {
+            let mut add = vec![];
+            for path in collection_files(root_dir)? {
+                let content = fs::read_to_string(root_dir.join(&path))?;
+                let shared = shared_content(&path, &content)?;
+                if shared == content {
+                    add.push(path);
+                } else {
+                    // Only the index gets the content without secrets, so
+                    // that they stay in the local file
+                    stage_content(root_dir, &path, &shared)?;
+                }
+            }
+            if !add.is_empty() {
+                let mut args = vec!["add", "--"];
+                args.extend(add.iter().filter_map(|f| f.to_str()));
+                git(root_dir, &args)?;
+            }
+
+            // Nothing to commit is fine, there may still be commits to push
+            let staged = Command::new("git")
+                .current_dir(root_dir)
+                .args(["diff", "--cached", "--quiet"])
+                .status()?;
+            if !staged.success() {
+                git(root_dir, &["commit", "-m", "Update requests"])?;
+            }
+
+            let mut args = vec!["push", &remote];
+            args.extend(branch.as_deref());
+            git(root_dir, &args)
+        }
+        SyncSource::Http { url, headers } => {
+            let content = bundle(root_dir)?;
+
+            let mut request = Client::builder()
+                .user_agent(USER_AGENT)
+                .build()?
+                .put(&url)
+                .header("Content-Type", "application/toml")
+                .body(content.clone());
+            for (name, value) in &headers {
+                request = request.header(name, value);
+            }
+            request.send().await?.error_for_status()?;
+            save_sync_base(root_dir, &content)?;
+
+            warn!("# Pushed the collection to {url}");
+
+            Ok(())
+        }
+    }
+}
+
+/// Add content to the git index for a path, without touching the file
+fn stage_content(root_dir: &Path, path: &Path, content: &str) -> Result<()> {
+    let mut child = Command::new("git")
+        .current_dir(root_dir)
+        .args(["hash-object", "-w", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run git")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // git reads all of it before it writes the hash
+        stdin.write_all(content.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("git hash-object failed");
+    }
+
+    let hash = String::from_utf8(output.stdout)?;
+    let path = path.to_string_lossy().replace('\\', "/");
+    let info = format!("100644,{},{path}", hash.trim());
+    git(root_dir, &["update-index", "--add", "--cacheinfo", &info])
+}
+
+fn git(root_dir: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .current_dir(root_dir)
+        .args(args)
+        .status()
+        .context("Failed to run git")?;
+    if !status.success() {
+        bail!("git {} failed", args.first().unwrap_or(&""));
+    }
+
+    Ok(())
+}
+
+/// All files of the collection in one TOML document, with secret values
+/// removed
+pub fn bundle(root_dir: &Path) -> Result<String> {
+    let mut files = Vec::new();
+    for path in collection_files(root_dir)? {
+        let content = fs::read_to_string(root_dir.join(&path))?;
+
+        let mut file = Table::new();
+        file.insert(
+            "path".to_string(),
+            Value::String(path.to_string_lossy().replace('\\', "/")),
+        );
+        file.insert(
+            "content".to_string(),
+            Value::String(shared_content(&path, &content)?),
+        );
+        files.push(Value::Table(file));
+    }
+
+    let mut bundle = Table::new();
+    bundle.insert("file".to_string(), Value::Array(files));
+
+    Ok(toml::to_string_pretty(&bundle)?)
+}
+
+/// Write the files of a bundle into the project. Secret values in the local
+/// config files are kept.
+pub fn unbundle(root_dir: &Path, bundle: &str) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for (path, content) in bundle_files(bundle)? {
+        let target = root_dir.join(&path);
+        let mut content = content;
+        if is_config_file(&path) {
+            if let Ok(local) = fs::read_to_string(&target) {
+                let mut config: Table = toml::from_str(&content)?;
+                keep_secrets(&mut config, &toml::from_str(&local)?);
+                content = toml::to_string_pretty(&config)?;
+            }
+        }
+
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&target, content)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// The files of a bundle that would overwrite changes made locally since the
+/// last sync. Without a last sync, every file that differs is a change.
+pub fn local_changes(root_dir: &Path, bundle: &str) -> Result<Vec<PathBuf>> {
+    let base: HashMap<PathBuf, String> =
+        match fs::read_to_string(sync_base_file(root_dir)) {
+            Ok(base) => bundle_files(&base)?.into_iter().collect(),
+            Err(_) => HashMap::new(),
+        };
+
+    let mut changed = Vec::new();
+    for (path, content) in bundle_files(bundle)? {
+        let Ok(local) = fs::read_to_string(root_dir.join(&path)) else {
+            continue;
+        };
+        let local = without_secrets(&path, &local)?;
+        if local != content && base.get(&path) != Some(&local) {
+            changed.push(path);
+        }
+    }
+
+    Ok(changed)
+}
+
+fn save_sync_base(root_dir: &Path, bundle: &str) -> Result<()> {
+    ensure_state_dir(root_dir)?;
+    Ok(fs::write(sync_base_file(root_dir), bundle)?)
+}
+
+fn bundle_files(bundle: &str) -> Result<Vec<(PathBuf, String)>> {
+    let bundle: Table = toml::from_str(bundle).context("Invalid bundle")?;
+    let Some(Value::Array(files)) = bundle.get("file") else {
+        bail!("Invalid bundle: no files");
+    };
+
+    let mut result = Vec::new();
+    for file in files {
+        let path = file.get("path").and_then(Value::as_str);
+        let content = file.get("content").and_then(Value::as_str);
+        let (Some(path), Some(content)) = (path, content) else {
+            bail!("Invalid bundle: file without path or content");
+        };
+
+        let path = PathBuf::from(path);
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!("Invalid path in bundle: {}", path.display());
+        }
+        result.push((path, content.to_string()));
+    }
+
+    Ok(result)
+}
+
+fn is_config_file(path: &Path) -> bool {
+    path == Path::new(CONFIG_FILE)
+        || path.to_string_lossy().ends_with(".http.toml")
+}
+
+/// The content of a file as it is shared. Secret values are removed from
+/// config files, and request files must not have secret headers with literal
+/// values, as they can't be told from the rest of the request.
+fn shared_content(path: &Path, content: &str) -> Result<String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if is_request_file(&name) {
+        if let Some(header) = literal_secret_header(content) {
+            bail!(
+                "{} has a literal value for the {header} header. Use a \
+                 {{{{value}}}} from hitman.local.toml instead, so that it is \
+                 not shared.",
+                path.display()
+            );
+        }
+    }
+
+    without_secrets(path, content)
+}
+
+fn without_secrets(path: &Path, content: &str) -> Result<String> {
+    if !is_config_file(path) {
+        return Ok(content.to_string());
+    }
+
+    let mut config: Table = toml::from_str(content)
+        .with_context(|| format!("Invalid {}", path.display()))?;
+    if !strip_secrets(&mut config) {
+        // Keep the file as written, with its comments
+        return Ok(content.to_string());
+    }
+
+    Ok(toml::to_string_pretty(&config)?)
+}
+
+/// The first header of a request with a secret name and a value that is not
+/// substituted, such as `Authorization: Bearer abc`
+fn literal_secret_header(request: &str) -> Option<String> {
+    request
+        .lines()
+        .skip_while(|l| l.trim().is_empty() || l.trim_start().starts_with('#'))
+        .skip(1)
+        .take_while(|l| !l.trim().is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, value)| {
+            is_secret_key(name.trim()) && !value.contains("{{")
+        })
+        .map(|(name, _)| name.trim().to_string())
+}
+
+/// Remove the secret values of a config, and tell if there were any
+fn strip_secrets(config: &mut Table) -> bool {
+    let len = config.len();
+    config.retain(|key, _| !is_secret_key(key));
+
+    let mut stripped = config.len() != len;
+    for (_, value) in config.iter_mut() {
+        if let Value::Table(table) = value {
+            stripped |= strip_secrets(table);
+        }
+    }
+
+    stripped
+}
+
+/// Copy secret values that only exist locally into a pulled config
+fn keep_secrets(config: &mut Table, local: &Table) {
+    for (key, value) in local {
+        match (config.get_mut(key), value) {
+            (Some(Value::Table(table)), Value::Table(local)) => {
+                keep_secrets(table, local)
+            }
+            (None, _) if is_secret_key(key) => {
+                config.insert(key.clone(), value.clone());
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_sync_source() {
+        let config: Table = toml::from_str(
+            r#"
+            [_sync]
+            url = "https://files.example.com/requests.toml"
+            headers = { Authorization = "Bearer abc" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sync_source(&config).unwrap(),
+            SyncSource::Http {
+                url: "https://files.example.com/requests.toml".into(),
+                headers: vec![("Authorization".into(), "Bearer abc".into())],
+            }
+        );
+        assert!(sync_source(&Table::new()).is_err());
+    }
+
+    #[test]
+    fn never_shares_sync_credentials() {
+        let content = "[_sync]\nurl = \"https://x\"\n\n[_sync.headers]\nAuthorization = \"Bearer abc\"\n";
+
+        let shared = shared_content(Path::new(CONFIG_FILE), content).unwrap();
+
+        assert!(!shared.contains("Bearer abc"));
+        assert!(shared.contains("https://x"));
+    }
+
+    #[test]
+    fn stages_configs_without_secrets() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-sync-git-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]).unwrap();
+        let content = "[dev]\nurl = \"https://x\"\ntoken = \"t\"\n";
+        fs::write(dir.join(CONFIG_FILE), content).unwrap();
+
+        let path = Path::new(CONFIG_FILE);
+        stage_content(&dir, path, &shared_content(path, content).unwrap())
+            .unwrap();
+
+        let staged = Command::new("git")
+            .current_dir(&dir)
+            .args(["show", ":hitman.toml"])
+            .output()
+            .unwrap();
+        let staged = String::from_utf8(staged.stdout).unwrap();
+        assert!(staged.contains("https://x"));
+        assert!(!staged.contains("token"));
+        assert_eq!(fs::read_to_string(dir.join(CONFIG_FILE)).unwrap(), content);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_literal_secret_headers() {
+        let path = Path::new("apples/get.http");
+        let request = "# @name apples\nGET {{url}}/apples\nAuthorization: Bearer abc\n\n{\"token\": \"t\"}\n";
+
+        assert!(shared_content(path, request).is_err());
+
+        let request = request.replace("Bearer abc", "Bearer {{token}}");
+        assert_eq!(shared_content(path, &request).unwrap(), request);
+    }
+
+    #[test]
+    fn strips_and_keeps_secrets() {
+        let mut config: Table = toml::from_str(
+            r#"
+            [dev]
+            url = "https://dev.example.com"
+            api_key = "abc"
+            "#,
+        )
+        .unwrap();
+        let local = config.clone();
+
+        strip_secrets(&mut config);
+        assert!(config["dev"].get("api_key").is_none());
+
+        keep_secrets(&mut config, &local);
+        assert_eq!(config, local);
+    }
+
+    #[test]
+    fn bundles_collection_files() {
+        let dir = std::env::temp_dir()
+            .join(format!("hitman-sync-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("apples")).unwrap();
+        fs::create_dir_all(dir.join(".hitman/data")).unwrap();
+        fs::write(dir.join("hitman.toml"), "[dev]\ntoken = \"t\"\n").unwrap();
+        fs::write(dir.join("hitman.local.toml"), "").unwrap();
+        fs::write(dir.join("apples/get.http"), "GET /\n").unwrap();
+        fs::write(dir.join("apples/get.http.toml"), "cookie = \"c=1\"\n")
+            .unwrap();
+        fs::write(dir.join(".hitman/data/dev.toml"), "").unwrap();
+
+        assert_eq!(
+            collection_files(&dir).unwrap(),
+            vec![
+                PathBuf::from("apples/get.http"),
+                PathBuf::from("apples/get.http.toml"),
+                PathBuf::from("hitman.toml")
+            ]
+        );
+
+        let bundle = bundle(&dir).unwrap();
+        assert!(!bundle.contains("c=1"));
+        assert!(!bundle.contains("token"));
+        save_sync_base(&dir, &bundle).unwrap();
+        assert!(local_changes(&dir, &bundle).unwrap().is_empty());
+
+        fs::write(dir.join("apples/get.http"), "changed").unwrap();
+        assert_eq!(
+            local_changes(&dir, &bundle).unwrap(),
+            vec![PathBuf::from("apples/get.http")]
+        );
+
+        unbundle(&dir, &bundle).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("apples/get.http")).unwrap(),
+            "GET /\n"
+        );
+        assert!(fs::read_to_string(dir.join("hitman.toml"))
+            .unwrap()
+            .contains("token"));
+        assert!(fs::read_to_string(dir.join("apples/get.http.toml"))
+            .unwrap()
+            .contains("c=1"));
+
+        let evil = "[[file]]\npath = \"../evil.http\"\ncontent = \"\"\n";
+        assert!(unbundle(&dir, evil).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
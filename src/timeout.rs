@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+use crate::{
+    directive::{Directives, TIMEOUT},
+    env::get_settings,
+    util::{duration_value, parse_duration},
+};
+
+/// Timeouts and retries of requests, given in the config or for a target,
+/// such as:
+///
+/// ```toml
+/// [staging._http]
+/// timeout = "10s"
+/// retries = 2
+/// retry_backoff = "500ms"
+/// retry_5xx = true
+/// ```
+pub(crate) const HTTP_KEY: &str = "_http";
+
+/// A setting of the target from `[_http]`, or from `[_settings]` when not
+/// given there
+pub fn http_setting(env: &Table, key: &str) -> Option<Value> {
+    match env.get(HTTP_KEY) {
+        Some(Value::Table(http)) => http.get(key).cloned(),
+        _ => None,
+    }
+    .or_else(|| get_settings(env).get(key).cloned())
+}
+
+/// The timeout of a single request, given with a line like `# @timeout 2m`
+pub fn timeout_directive(directives: &Directives) -> Result<Option<Duration>> {
+    directives
+        .value(TIMEOUT)
+        .map(|value| {
+            parse_duration(value)
+                .with_context(|| format!("Invalid timeout: {value}"))
+        })
+        .transpose()
+}
+
+/// The timeout of a request, from its `# @timeout` line or the `timeout`
+/// setting of the target
+pub fn request_timeout(
+    directives: &Directives,
+    env: &Table,
+) -> Result<Option<Duration>> {
+    Ok(timeout_directive(directives)?.or_else(|| target_timeout(env)))
+}
+
+/// The `timeout` setting of a target
+pub fn target_timeout(env: &Table) -> Option<Duration> {
+    http_setting(env, "timeout")
+        .as_ref()
+        .and_then(duration_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directives(buf: &str) -> Directives {
+        Directives::parse(buf).unwrap()
+    }
+
+    #[test]
+    fn reads_timeout_directives() {
+        let buf = "# @timeout 90s\nGET http://example.com HTTP/1.1\n";
+        let env: Table = toml::from_str("_settings.timeout = \"30s\"").unwrap();
+
+        assert_eq!(
+            request_timeout(&directives(buf), &env).unwrap(),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            request_timeout(&directives("GET http://example.com\n"), &env)
+                .unwrap(),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            request_timeout(&directives("GET /\n"), &Table::new()).unwrap(),
+            None
+        );
+        let soon = directives("# @timeout soon\nGET /\n");
+        assert!(timeout_directive(&soon).is_err());
+    }
+
+    #[test]
+    fn prefers_the_timeout_of_the_target() {
+        let env: Table =
+            toml::from_str("_settings.timeout = \"30s\"\n_http.timeout = 5")
+                .unwrap();
+
+        assert_eq!(target_timeout(&env), Some(Duration::from_secs(5)));
+        assert_eq!(target_timeout(&Table::new()), None);
+    }
+}
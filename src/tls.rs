@@ -0,0 +1,128 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use reqwest::{Certificate, ClientBuilder, Identity};
+use toml::{Table, Value};
+
+use crate::{env::find_root_dir, substitute::substitute};
+
+/// Client certificates and certificate authorities, given in the config or
+/// for a target, such as:
+///
+/// ```toml
+/// [internal._tls]
+/// client_cert = "certs/client.pem"
+/// client_key = "certs/client.key"
+/// ca_bundle = "certs/internal-ca.pem"
+/// ```
+pub(crate) const TLS_KEY: &str = "_tls";
+
+/// Use the client certificate and certificate authorities configured for
+/// the target, if any. Paths are relative to the root directory.
+pub fn configure_tls(
+    mut builder: ClientBuilder,
+    env: &Table,
+) -> Result<ClientBuilder> {
+    let Some(Value::Table(config)) = env.get(TLS_KEY) else {
+        return Ok(builder);
+    };
+    let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+
+    if let Some(identity) = client_identity(&root_dir, config, env)? {
+        builder = builder.identity(identity);
+    }
+
+    if let Some(path) = config.get("ca_bundle").and_then(Value::as_str) {
+        let pem = read_file(&root_dir.join(path))?;
+        let certs = Certificate::from_pem_bundle(&pem).with_context(|| {
+            format!("Invalid certificate authorities in {path}")
+        })?;
+        if certs.is_empty() {
+            bail!("No certificates found in {path}");
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder)
+}
+
+/// The client certificate, either a PEM certificate with its PKCS#8 key, or
+/// a PKCS#12 archive with an optional `password`
+fn client_identity(
+    root_dir: &Path,
+    config: &Table,
+    env: &Table,
+) -> Result<Option<Identity>> {
+    let Some(cert) = config.get("client_cert").and_then(Value::as_str) else {
+        if config.contains_key("client_key") {
+            bail!("client_key is given in [{TLS_KEY}] without client_cert");
+        }
+        return Ok(None);
+    };
+    let data = read_file(&root_dir.join(cert))?;
+
+    if is_pkcs12(cert) {
+        // Can be a placeholder, to keep the password out of the config
+        let password = match config.get("password").and_then(Value::as_str) {
+            Some(password) => substitute(password, env)?
+                .trim_end_matches('\n')
+                .to_string(),
+            None => String::new(),
+        };
+        let identity = Identity::from_pkcs12_der(&data, &password)
+            .with_context(|| format!("Invalid client certificate {cert}"))?;
+        return Ok(Some(identity));
+    }
+
+    let key = config
+        .get("client_key")
+        .and_then(Value::as_str)
+        .with_context(|| format!("client_key is missing for {cert}"))?;
+    let identity =
+        Identity::from_pkcs8_pem(&data, &read_file(&root_dir.join(key))?)
+            .with_context(|| {
+                format!(
+                    "Invalid client certificate {cert} with key {key}, the \
+                     key must be PKCS#8 (BEGIN PRIVATE KEY)"
+                )
+            })?;
+
+    Ok(Some(identity))
+}
+
+fn is_pkcs12(path: &str) -> bool {
+    let path = path.to_lowercase();
+    path.ends_with(".p12") || path.ends_with(".pfx")
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_pkcs12_archives() {
+        assert!(is_pkcs12("certs/client.p12"));
+        assert!(is_pkcs12("certs/CLIENT.PFX"));
+        assert!(!is_pkcs12("certs/client.pem"));
+    }
+
+    #[test]
+    fn requires_a_certificate_for_the_key() {
+        let config: Table = toml::from_str("client_key = \"k.pem\"").unwrap();
+        let err = client_identity(Path::new("."), &config, &Table::new())
+            .unwrap_err();
+
+        assert!(err.to_string().contains("without client_cert"));
+        assert!(
+            client_identity(Path::new("."), &Table::new(), &Table::new())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{bail, Result};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use crate::clock::unix_now;
+
+/// Length of each time step, in seconds
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+
+/// The current TOTP code for a base32 encoded secret, as shown by
+/// authenticator apps
+pub fn totp(secret: &str) -> Result<String> {
+    totp_at(secret, unix_now())
+}
+
+/// The TOTP code for a base32 encoded secret at the given unix time
+/// (RFC 6238, with SHA-1, 30 second steps and 6 digits)
+pub fn totp_at(secret: &str, time: u64) -> Result<String> {
+    let key = decode_base32(secret)?;
+    let counter = (time / STEP).to_be_bytes();
+
+    let mac = hmac_sha1(&key, &counter);
+
+    // Dynamic truncation (RFC 4226)
+    let offset = (mac[19] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        code % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Decode base32 (RFC 4648), ignoring case, spaces, dashes and padding, as
+/// secrets are often written in groups
+fn decode_base32(input: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in input.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            ' ' | '-' | '=' => continue,
+            _ => bail!("Invalid base32 character: {c}"),
+        };
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if out.is_empty() {
+        bail!("Empty secret");
+    }
+
+    Ok(out)
+}
+
+fn hmac_sha1(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key)
+        .expect("HMAC takes keys of any length");
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn computes_hmac() {
+        assert_eq!(
+            hex(&hmac_sha1(
+                b"key",
+                b"The quick brown fox jumps over the lazy dog"
+            )),
+            "de7c9b85b8b78aa6bc8a7a36f70a90701c9db4d9"
+        );
+    }
+
+    #[test]
+    fn decodes_base32_secrets() {
+        assert_eq!(decode_base32("MZXW6YTB").unwrap(), b"fooba");
+        assert_eq!(decode_base32("mzxw 6ytb-oi==").unwrap(), b"foobar");
+        assert!(decode_base32("not base32!").is_err());
+    }
+
+    #[test]
+    fn generates_codes_from_rfc_test_vectors() {
+        // The secret "12345678901234567890" from RFC 6238
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+        assert_eq!(totp_at(secret, 59).unwrap(), "287082");
+        assert_eq!(totp_at(secret, 1111111109).unwrap(), "081804");
+        assert_eq!(totp_at(secret, 2000000000).unwrap(), "279037");
+    }
+}
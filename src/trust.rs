@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use toml::{Table, Value};
+
+use crate::{
+    discovery::find_project_requests,
+    env::{
+        find_root_dir, read_toml, read_user_config, user_config_dir,
+        CONFIG_FILE, LOCAL_CONFIG_FILE, SETTINGS_KEY,
+    },
+    hook::HOOK_KEY,
+};
+
+/// The commands trusted in each project, kept in the user config directory
+/// as lists of commands by project directory
+const TRUST_FILE: &str = "trusted.toml";
+
+/// Commands given in project files, such as for response hooks and viewers,
+/// only run once they are trusted on this machine. A project may come from
+/// anywhere, such as from `hitman sync pull`, and a changed command has to
+/// be trusted again. Commands in the user config are always trusted.
+pub fn ensure_trusted(what: &str, command: &str) -> Result<()> {
+    if is_trusted(find_root_dir()?.as_deref(), command)? {
+        return Ok(());
+    }
+
+    bail!(
+        "{what} runs `{command}`, which is not trusted on this machine. \
+         Run `hitman trust` to review and trust the commands of the project."
+    )
+}
+
+fn is_trusted(root_dir: Option<&Path>, command: &str) -> Result<bool> {
+    if commands_in(&read_user_config()?)
+        .iter()
+        .any(|(_, c)| c == command)
+    {
+        return Ok(true);
+    }
+
+    Ok(match root_dir {
+        Some(root_dir) => {
+            trusted_commands(root_dir)?.iter().any(|c| c == command)
+        }
+        None => false,
+    })
+}
+
+/// The commands in the project files that are not trusted yet, with what
+/// runs them
+pub fn untrusted_commands(root_dir: &Path) -> Result<Vec<(String, String)>> {
+    let mut files =
+        vec![root_dir.join(CONFIG_FILE), root_dir.join(LOCAL_CONFIG_FILE)];
+    files.extend(
+        find_project_requests(root_dir)?
+            .iter()
+            .map(|file| root_dir.join(file).with_extension("http.toml")),
+    );
+
+    let mut commands: Vec<(String, String)> = Vec::new();
+    for file in files.iter().filter(|file| file.exists()) {
+        for (what, command) in commands_in(&read_toml(file)?) {
+            let known = commands.iter().any(|(_, c)| *c == command);
+            if !known && !is_trusted(Some(root_dir), &command)? {
+                commands.push((what, command));
+            }
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Trust commands in a project, in addition to those trusted before
+pub fn trust_commands(root_dir: &Path, commands: &[String]) -> Result<()> {
+    let file = trust_file()?;
+    let mut trusted = if file.exists() {
+        read_toml(&file)?
+    } else {
+        Table::new()
+    };
+
+    let key = project_key(root_dir);
+    let mut list = trusted_commands(root_dir)?;
+    for command in commands {
+        if !list.contains(command) {
+            list.push(command.clone());
+        }
+    }
+    trusted.insert(
+        key,
+        Value::Array(list.into_iter().map(Value::String).collect()),
+    );
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&file, toml::to_string_pretty(&trusted)?)?;
+
+    Ok(())
+}
+
+fn trusted_commands(root_dir: &Path) -> Result<Vec<String>> {
+    let file = trust_file()?;
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(read_toml(&file)?
+        .get(&project_key(root_dir))
+        .and_then(Value::as_array)
+        .map(|commands| {
+            commands
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+fn trust_file() -> Result<PathBuf> {
+    Ok(user_config_dir()
+        .context("Could not find the user config directory")?
+        .join(TRUST_FILE))
+}
+
+/// The editor, pager and viewers given in settings
+fn setting_commands(settings: &Table) -> Vec<(String, String)> {
+    let mut commands = Vec::new();
+    for (key, what) in [("editor", "Editor"), ("pager", "Pager")] {
+        if let Some(Value::String(command)) = settings.get(key) {
+            commands.push((what.to_string(), command.clone()));
+        }
+    }
+    if let Some(Value::Table(viewers)) = settings.get("viewers") {
+        for (mime, viewer) in viewers {
+            if let Value::String(viewer) = viewer {
+                commands.push((format!("Viewer for {mime}"), viewer.clone()));
+            }
+        }
+    }
+
+    commands
+}
+
+fn project_key(root_dir: &Path) -> String {
+    fs::canonicalize(root_dir)
+        .unwrap_or_else(|_| root_dir.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// The commands of the response hook and settings in a config, also those
+/// given for a target
+fn commands_in(config: &Table) -> Vec<(String, String)> {
+    let mut commands = Vec::new();
+    if let Some(Value::String(hook)) = config.get(HOOK_KEY) {
+        commands.push(("Response hook".to_string(), hook.clone()));
+    }
+    if let Some(Value::Table(settings)) = config.get(SETTINGS_KEY) {
+        commands.extend(setting_commands(settings));
+    }
+
+    for (key, value) in config {
+        if let (false, Value::Table(table)) = (key.starts_with('_'), value) {
+            commands.extend(commands_in(table));
+        }
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_commands_in_config() {
+        let config: Table = toml::from_str(
+            r#"
+            _on_response = "./save.sh"
+
+            [_settings]
+            pager = "less -R"
+
+            [_settings.viewers]
+            "text/html" = "firefox"
+
+            [_plugins]
+            vault = "plugins/vault.wasm"
+
+            [dev]
+            _on_response = "./notify.sh"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            commands_in(&config),
+            vec![
+                ("Response hook".to_string(), "./save.sh".to_string()),
+                ("Pager".to_string(), "less -R".to_string()),
+                ("Viewer for text/html".to_string(), "firefox".to_string()),
+                ("Response hook".to_string(), "./notify.sh".to_string()),
+            ]
+        );
+    }
+}
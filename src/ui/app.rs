@@ -1,44 +1,96 @@
 use std::{
+    collections::{HashMap, HashSet},
+    env::set_current_dir,
     fmt::Write,
     fs::read_to_string,
     io,
     path::{Path, PathBuf},
     process::Command,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use crossterm::event::{self, Event, KeyEventKind};
 use ratatui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Margin, Rect},
     style::Stylize,
+    text::Line,
     widgets::Paragraph,
     Frame, Terminal,
 };
-use tokio::task::JoinHandle;
-use toml::Value;
+use reqwest::header::CONTENT_TYPE;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::{block_in_place, JoinHandle},
+};
+use toml::{Table, Value};
 
 use hitman::{
+    assertions::ResponseInfo,
+    clock,
+    curl::curl_command,
+    depends::run_dependencies,
+    diff::diff_responses,
+    directive::Directives,
+    discovery::RequestIndex,
+    download::{download_summary, is_download},
     env::{
-        find_available_requests, find_environments, find_root_dir, get_target,
-        load_env, set_target, update_data,
+        find_environments, find_pins, find_root_dir, get_settings, get_target,
+        is_secret_key, is_sensitive_key, load_env, load_settings, set_target,
+        update_data,
+    },
+    etag::load_etags,
+    extract::{
+        extract_header_variables, extract_markup_variables, extract_variables,
+        store_extracted,
+    },
+    format::{describe_binary, format_body, BodyFormat},
+    frecency::{record_use, request_scores},
+    history::{
+        load_history, record_response, responses_for, set_bookmark, set_note,
+        HistoryEntry, HistoryQuery,
     },
-    extract::extract_variables,
-    request::{build_client, do_request},
-    substitute::{substitute, SubstituteError},
+    hook::{request_method, run_response_hook, ResponseMeta},
+    link::{follow_up_request, response_links, Link},
+    lint::duplicate_requests,
+    oauth::with_access_token,
+    output::{output_file, save_response},
+    paginate::{Page, Pagination},
+    plugin::{apply_request_hooks, plugins_from},
+    request::{build_client, do_request, read_request, response_format},
+    retry::RetryPolicy,
+    script::RequestScripts,
+    scrub::remember_secrets,
+    state::migrate_legacy_state,
+    substitute::{is_json_placeholder, substitute, SubstituteError},
+    timeout::request_timeout,
+    trust::ensure_trusted,
+    viewer::open_response,
+    websocket::{
+        is_websocket_request, parse_ws_request, run_session, WsEvent, WsMessage,
+    },
+    workspace::{find_project, find_projects},
 };
 
 use crate::ui::{
-    output::{HttpRequestInfo, RequestStatus},
+    output::{HttpRequestInfo, RequestStatus, ResponseUpdate},
     PromptIntent,
 };
 
 use super::{
     centered,
     datepicker::DatePicker,
-    keymap::{mapkey, KeyMapping},
-    output::{HttpMessage, HttpRequestMessage, OutputView},
+    jobs::{JobEvent, JobItem, Jobs, RunningRequest},
+    jsonprompt::JsonPrompt,
+    keymap::{mapkey, set_keybindings, KeyMapping},
+    output::{
+        Content, HttpMessage, HttpRequestMessage, OutputIntent, OutputView,
+    },
     progress::Progress,
     prompt::SimplePrompt,
     select::{
@@ -47,6 +99,8 @@ use super::{
     Component, InteractiveComponent, PromptComponent,
 };
 
+const HELP: &str = "Ctrl+S: Select target, Ctrl+O: Switch project, Ctrl+F: Follow link, Tab: Select URL, Ctrl+V: Open response, Ctrl+G: Variables, Ctrl+Y: History, Ctrl+B: Diff, Alt+C: Curl, Alt+N/P: Next/previous page, Alt+J: Jobs, Alt+H: Hidden headers, Alt+F: Filter headers, Ctrl+E: Edit selected request, Ctrl+R: New request, [<>] Adjust width, [,] Tottle wrapping";
+
 pub trait Screen {
     type B: Backend;
 
@@ -58,8 +112,20 @@ pub trait Screen {
 pub struct App {
     root_dir: PathBuf,
     target: String,
+    last_file_path: Option<String>,
+    /// Values given in prompts, remembered per request for this session
+    overrides: HashMap<String, Vec<(String, String)>>,
+    remember_values: bool,
     request_selector: RequestSelector,
+    request_index: RequestIndex,
     output_view: OutputView,
+    /// Requests sent, running in the background or finished
+    jobs: Jobs,
+    /// The other projects opened in this session, by root dir
+    sessions: HashMap<PathBuf, ProjectSession>,
+    /// Handled before waiting for the first event, for a request given on
+    /// startup
+    startup_intent: Option<Intent>,
 
     state: AppState,
     error: Option<String>,
@@ -67,6 +133,16 @@ pub struct App {
     vsplit: u16,
 }
 
+/// What is kept of a project while another project is open, to continue
+/// where it was left when switching back
+#[derive(Default)]
+struct ProjectSession {
+    last_file_path: Option<String>,
+    overrides: HashMap<String, Vec<(String, String)>>,
+    selected_request: Option<String>,
+    output: Content,
+}
+
 pub enum AppState {
     Idle,
 
@@ -81,14 +157,66 @@ pub enum AppState {
         prompt: SimplePrompt,
     },
 
-    RunningRequest {
-        handle: JoinHandle<HttpRequestInfo>,
-        progress: Progress,
+    /// A pattern for the response headers to show
+    HeaderFilter {
+        prompt: SimplePrompt,
+    },
+
+    /// The requests running in the background, and those finished
+    Jobs {
+        component: Select<JobItem>,
     },
 
     SelectTarget {
         component: Select<String>,
     },
+
+    SelectProject {
+        component: Select<String>,
+    },
+
+    SelectLink {
+        component: Select<Link>,
+        request: String,
+    },
+
+    Variables {
+        component: Select<Variable>,
+        file_path: String,
+    },
+
+    History {
+        component: Select<HistoryEntry>,
+    },
+
+    HistoryNote {
+        entry: HistoryEntry,
+        prompt: SimplePrompt,
+    },
+
+    /// An open WebSocket connection, with a prompt for messages to send
+    WebSocket {
+        handle: JoinHandle<HttpRequestInfo>,
+        updates: UnboundedReceiver<ResponseUpdate>,
+        outgoing: UnboundedSender<String>,
+        prompt: SimplePrompt,
+    },
+
+    EditVariable {
+        file_path: String,
+        key: String,
+        prompt: SimplePrompt,
+    },
+}
+
+/// A value in the variable inspector
+#[derive(Clone)]
+pub struct Variable {
+    key: String,
+    value: String,
+    remembered: bool,
+    /// Hidden on screen, for secrets and sensitive values
+    hidden: bool,
 }
 
 pub enum Intent {
@@ -106,32 +234,80 @@ pub enum Intent {
     SendRequest {
         file_path: String,
         prepared_request: String,
+        /// The values the request was prepared with, for handling the
+        /// response the same way
+        env: Table,
     },
     ShowResult(HttpRequestInfo),
+    ShowJobs,
+    ShowJob(usize),
+    CancelJob(usize),
     SelectTarget,
     AcceptSelectTarget(String),
+    SelectProject,
+    AcceptSelectProject(String),
+    SelectLink,
+    AcceptSelectLink(String, Link),
+    OpenUrl(String),
+    OpenResponse,
+    InspectVariables,
+    ShowHistory,
+    ShowDiff,
+    ShowCurl,
+    Paginate(Page),
+    AcceptHistory(HistoryEntry),
+    ToggleBookmark(HistoryEntry),
+    EditHistoryNote(HistoryEntry),
+    AcceptHistoryNote(HistoryEntry, String),
+    EditVariable(String, Variable),
+    AcceptEditVariable(String, String, String),
+    ClearOverrides(String),
     EditRequest,
     NewRequest,
     AcceptNewRequest(String),
+    FilterHeaders,
+    AcceptFilterHeaders(String),
     ShowError(String),
 }
 
 pub enum AskForValueParams {
     Prompt { fallback: Option<String> },
+    Json { fallback: Option<String> },
     Select { values: Vec<Value> },
 }
 
 impl App {
     pub fn new() -> Result<Self> {
         let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+        migrate_legacy_state(&root_dir)?;
 
         let target = get_target(&root_dir);
 
+        let settings = load_settings(&root_dir)?;
+        if let Some(Value::Table(keys)) = settings.get("keys") {
+            set_keybindings(keys)?;
+        }
+        clock::configure(&settings)?;
+        let theme = settings.get("theme").and_then(|t| t.as_str());
+        let remember_values = remember_values(&settings);
+        let mut output_view = OutputView::new(theme);
+        output_view.set_hidden_headers(&settings);
+
+        let mut request_index = RequestIndex::new(&root_dir);
+        request_index.watch()?;
+
         let mut app = Self {
             root_dir,
             target,
+            last_file_path: None,
+            overrides: HashMap::new(),
+            remember_values,
             request_selector: RequestSelector::new(),
-            output_view: OutputView::new(),
+            request_index,
+            output_view,
+            jobs: Jobs::default(),
+            sessions: HashMap::new(),
+            startup_intent: None,
             state: AppState::Idle,
             error: None,
             should_quit: false,
@@ -153,7 +329,10 @@ impl App {
                 .terminal()
                 .draw(|frame| self.render_ui(frame, frame.area()))?;
 
-            let mut pending_intent = self.process_events().await?;
+            let mut pending_intent = match self.startup_intent.take() {
+                Some(intent) => Some(intent),
+                None => self.process_events().await?,
+            };
             while let Some(intent) = pending_intent {
                 pending_intent = match self.dispatch(intent, &mut screen) {
                     Ok(it) => it,
@@ -190,7 +369,7 @@ impl App {
                 return Ok(Some(PreviewRequest(selected)));
             }
             PrepareRequest(file_path, options) => {
-                return Ok(self.try_request(file_path, options)?);
+                return self.try_request(file_path, options);
             }
             PreviewRequest(file_path) => {
                 self.preview_request(file_path)?;
@@ -199,11 +378,19 @@ impl App {
             SendRequest {
                 file_path,
                 prepared_request,
+                env,
             } => {
                 let req = HttpRequestMessage(prepared_request.clone());
                 let info = HttpRequestInfo::new(req, RequestStatus::Running);
-                self.output_view.show_request(info);
-                self.send_request(file_path, prepared_request)?;
+                self.last_file_path = Some(file_path.clone());
+                // Only used to rank the requests, so not worth failing for
+                let _ = record_use(&self.root_dir, file_path.as_ref());
+                if is_websocket_request(file_path.as_ref(), &prepared_request) {
+                    self.output_view.show_request(info);
+                    self.open_websocket(prepared_request);
+                } else {
+                    self.send_request(file_path, prepared_request, env, info)?;
+                }
             }
             AskForValue {
                 key,
@@ -222,6 +409,13 @@ impl App {
                         ))
                     }
 
+                    AskForValueParams::Json { fallback } => Box::new(
+                        JsonPrompt::new(format!(
+                            "Enter JSON for {{{{{key}}}}}"
+                        ))
+                        .with_fallback(fallback),
+                    ),
+
                     AskForValueParams::Prompt { fallback } => {
                         if key.ends_with("_date") || key.ends_with("Date") {
                             Box::new(
@@ -252,6 +446,27 @@ impl App {
                 self.output_view.show_request(info);
                 self.set_state(AppState::Idle);
             }
+            ShowJobs => {
+                let component =
+                    Select::new("Jobs".into(), "job".into(), self.jobs.items());
+
+                self.set_state(AppState::Jobs { component });
+            }
+            ShowJob(id) => {
+                if let Some(job) = self.jobs.get(id) {
+                    let file_path = job.file_path().to_string();
+                    self.output_view.show_job(id, job.info().clone());
+                    self.request_selector.try_select(&file_path);
+                    self.last_file_path = Some(file_path);
+                }
+                self.set_state(AppState::Idle);
+            }
+            CancelJob(id) => {
+                self.jobs.cancel(id);
+                if let AppState::Jobs { component } = &mut self.state {
+                    refresh_jobs(component, &self.jobs);
+                }
+            }
             SelectTarget => {
                 let envs = find_environments(&self.root_dir)?;
                 let component =
@@ -264,13 +479,225 @@ impl App {
                 self.target = s;
                 self.set_state(AppState::Idle);
             }
+            SelectProject => {
+                let projects = find_projects()?;
+                let current = projects
+                    .iter()
+                    .find(|(_, dir)| {
+                        dir.canonicalize().is_ok_and(|dir| dir == self.root_dir)
+                    })
+                    .map(|(name, _)| name.clone());
+
+                let mut component = Select::new(
+                    "Select project".into(),
+                    "project".into(),
+                    projects.into_iter().map(|(name, _)| name).collect(),
+                );
+                if let Some(current) = current {
+                    component.try_select(&current);
+                }
+
+                self.set_state(AppState::SelectProject { component });
+            }
+            AcceptSelectProject(name) => {
+                self.switch_project(&find_project(&name)?)?;
+                self.set_state(AppState::Idle);
+            }
+            SelectLink => {
+                if let Some((request, links)) = self.output_view.links() {
+                    let component = Select::new(
+                        "Follow link".into(),
+                        "link".into(),
+                        links.to_vec(),
+                    );
+                    let request = request.to_string();
+
+                    self.set_state(AppState::SelectLink { component, request });
+                }
+            }
+            AcceptSelectLink(request, link) => {
+                let file_path = self.last_file_path.clone().unwrap_or_default();
+                let env = self.request_env(&file_path, &[])?;
+
+                return Ok(Some(SendRequest {
+                    file_path,
+                    prepared_request: follow_up_request(&request, &link.url),
+                    env,
+                }));
+            }
+            OpenUrl(url) => {
+                let request = self.output_view.request().unwrap_or_default();
+                let file_path = self.last_file_path.clone().unwrap_or_default();
+                let env = self.request_env(&file_path, &[])?;
+
+                return Ok(Some(SendRequest {
+                    file_path,
+                    prepared_request: follow_up_request(request, &url),
+                    env,
+                }));
+            }
+            OpenResponse => {
+                if let Some(response) = self.output_view.response() {
+                    let settings = load_settings(&self.root_dir)?;
+                    screen.leave()?;
+                    let result = open_response(
+                        &settings,
+                        &response.content_type,
+                        &response.data,
+                    );
+                    screen.enter()?;
+                    screen.terminal().clear()?;
+                    result?;
+                }
+            }
+            InspectVariables => {
+                let selected_item = self.request_selector.selected_path();
+                let Some(file_path) = selected_item else {
+                    return Ok(None);
+                };
+
+                let component = Select::new(
+                    format!("Variables for {file_path} (Ctrl+X: Clear)"),
+                    "variable".into(),
+                    self.variables(&file_path)?,
+                );
+                self.set_state(AppState::Variables {
+                    component,
+                    file_path,
+                });
+            }
+            ShowHistory => {
+                let component = Select::new(
+                    "History (Alt+B: Bookmark, Alt+M: Note)".into(),
+                    "response".into(),
+                    load_history(&self.root_dir)?,
+                )
+                .with_filter(|entry, query| {
+                    let query = HistoryQuery::parse(query).ok()?;
+                    Some(query.matches(entry))
+                });
+                self.set_state(AppState::History { component });
+            }
+            AcceptHistory(entry) => {
+                let request = HttpRequestMessage(entry.request.clone());
+                let status = RequestStatus::Complete {
+                    elapsed: entry.elapsed,
+                    response: history_response(&entry)?,
+                };
+                self.output_view
+                    .show_request(HttpRequestInfo::new(request, status));
+                self.set_state(AppState::Idle);
+            }
+            ShowDiff => {
+                let Some(file_path) = self.last_file_path.clone() else {
+                    return Ok(None);
+                };
+
+                let responses =
+                    responses_for(&self.root_dir, Path::new(&file_path))?;
+                let [current, previous, ..] = responses.as_slice() else {
+                    bail!("No earlier response to compare with");
+                };
+
+                let title = format!(
+                    "Diff against {} ({})",
+                    previous.local_time(),
+                    previous.target
+                );
+                self.output_view
+                    .show_diff(title, diff_responses(previous, current));
+            }
+            ShowCurl => {
+                let Some(request) = self.output_view.request() else {
+                    bail!("Send a request to get it as a curl command");
+                };
+
+                let command = curl_command(request, &load_etags())?;
+                self.output_view.show_text("curl".into(), command);
+            }
+            Paginate(page) => {
+                let (Some(file_path), Some(request), Some(response)) = (
+                    self.last_file_path.clone(),
+                    self.output_view.request(),
+                    self.output_view.response(),
+                ) else {
+                    return Ok(None);
+                };
+
+                let env = self.request_env(&file_path, &[])?;
+                let Some(pagination) = Pagination::from_env(&env)? else {
+                    bail!("No pagination configured for {file_path}");
+                };
+
+                let body = String::from_utf8_lossy(&response.data);
+                let prepared_request =
+                    pagination.page_request(request, &body, page)?;
+
+                return Ok(Some(SendRequest {
+                    file_path,
+                    prepared_request,
+                    env,
+                }));
+            }
+            EditVariable(file_path, variable) => {
+                let fallback = (!variable.hidden).then_some(variable.value);
+                let prompt = SimplePrompt::new(format!(
+                    "Enter value for {{{{{}}}}}",
+                    variable.key
+                ))
+                .with_fallback(fallback);
+
+                self.set_state(AppState::EditVariable {
+                    file_path,
+                    key: variable.key,
+                    prompt,
+                });
+            }
+            AcceptEditVariable(file_path, key, value) => {
+                self.remember(&file_path, key, value);
+                return Ok(Some(InspectVariables));
+            }
+            ToggleBookmark(entry) => {
+                let entry =
+                    set_bookmark(&self.root_dir, &entry.id, !entry.bookmarked)?;
+                if let AppState::History { component } = &mut self.state {
+                    component.set_items(load_history(&self.root_dir)?);
+                    component.try_select(&entry);
+                }
+            }
+            EditHistoryNote(entry) => {
+                let prompt = SimplePrompt::new("Note (empty to remove)".into());
+                self.set_state(AppState::HistoryNote { entry, prompt });
+            }
+            AcceptHistoryNote(entry, note) => {
+                let note = Some(note.trim()).filter(|n| !n.is_empty());
+                set_note(&self.root_dir, &entry.id, note)?;
+                return Ok(Some(ShowHistory));
+            }
+            ClearOverrides(file_path) => {
+                self.overrides.remove(&file_path);
+                if let AppState::Variables { .. } = self.state {
+                    return Ok(Some(InspectVariables));
+                }
+            }
             EditRequest => {
-                let selected_item =
-                    self.request_selector.selector.selected_item();
-                if let Some(selected) = selected_item {
-                    open_in_editor(selected, screen)?;
+                let selected_item = self.request_selector.selected_path();
+                let Some(selected) = selected_item else {
+                    return Ok(Some(PreviewRequest(None)));
+                };
+
+                open_in_editor(&self.root_dir, &selected, screen)?;
+
+                let send_after_edit = load_settings(&self.root_dir)?
+                    .get("send_after_edit")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                if send_after_edit {
+                    self.preview_request(Some(selected.clone()))?;
+                    return Ok(Some(PrepareRequest(selected, Vec::new())));
                 }
-                return Ok(Some(PreviewRequest(selected_item.cloned())));
+
+                return Ok(Some(PreviewRequest(Some(selected))));
             }
             NewRequest => {
                 self.set_state(AppState::NewRequestPrompt {
@@ -278,9 +705,19 @@ impl App {
                 });
             }
             AcceptNewRequest(file_path) => {
-                open_in_editor(&file_path, screen)?;
+                open_in_editor(&self.root_dir, &file_path, screen)?;
                 return Ok(Some(Update(Some(file_path))));
             }
+            FilterHeaders => {
+                let prompt = SimplePrompt::new(
+                    "Show headers matching (empty for all)".into(),
+                );
+                self.set_state(AppState::HeaderFilter { prompt });
+            }
+            AcceptFilterHeaders(pattern) => {
+                self.output_view.filter_headers(&pattern);
+                self.state = AppState::Idle;
+            }
             ShowError(err) => {
                 self.error = Some(err);
                 self.state = AppState::Idle;
@@ -290,19 +727,96 @@ impl App {
         Ok(None)
     }
 
+    /// Start with a request file selected, and sent right away if `send` is
+    /// set. The path may be relative to the current dir, or absolute.
+    pub fn open_request(&mut self, path: &Path, send: bool) -> Result<()> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("Could not find {}", path.display()))?;
+        let file_path = path
+            .strip_prefix(self.root_dir.canonicalize()?)
+            .ok()
+            .and_then(Path::to_str)
+            .with_context(|| {
+                format!("{} is not in this project", path.display())
+            })?
+            .to_string();
+
+        // Request paths are relative to the root dir
+        set_current_dir(&self.root_dir)?;
+        self.request_selector.try_select(&file_path);
+        self.startup_intent = Some(if send {
+            Intent::PrepareRequest(file_path, Vec::new())
+        } else {
+            Intent::PreviewRequest(Some(file_path))
+        });
+
+        Ok(())
+    }
+
     fn set_state(&mut self, state: AppState) {
         self.error = None;
         self.state = state;
     }
 
+    fn switch_project(&mut self, dir: &Path) -> Result<()> {
+        // Other parts of hitman look up the root dir from the current dir
+        set_current_dir(dir)?;
+        let root_dir = find_root_dir()?.context("No hitman.toml found")?;
+        if root_dir == self.root_dir {
+            return Ok(());
+        }
+
+        let session = ProjectSession {
+            last_file_path: self.last_file_path.take(),
+            overrides: std::mem::take(&mut self.overrides),
+            selected_request: self.request_selector.selected_path(),
+            output: self.output_view.take_content(),
+        };
+        self.sessions.insert(self.root_dir.clone(), session);
+
+        self.root_dir = root_dir;
+        migrate_legacy_state(&self.root_dir)?;
+        let settings = load_settings(&self.root_dir)?;
+        clock::configure(&settings)?;
+        self.remember_values = remember_values(&settings);
+        self.output_view.set_hidden_headers(&settings);
+        self.target = get_target(&self.root_dir);
+        self.request_index = RequestIndex::new(&self.root_dir);
+        self.request_index.watch()?;
+        self.populate_requests()?;
+
+        let session = self.sessions.remove(&self.root_dir).unwrap_or_default();
+        self.last_file_path = session.last_file_path;
+        self.overrides = session.overrides;
+        self.output_view.restore(session.output);
+        if let Some(selected) = session.selected_request {
+            self.request_selector.try_select(&selected);
+        }
+
+        Ok(())
+    }
+
     fn populate_requests(&mut self) -> Result<()> {
-        let reqs = find_available_requests(&self.root_dir)?;
-        let reqs: Vec<String> = reqs
+        let reqs = self.request_index.requests()?;
+        let paths: Vec<&Path> = reqs.iter().map(PathBuf::as_path).collect();
+        let scores = request_scores(&self.root_dir, &paths);
+        let duplicates: HashSet<String> =
+            duplicate_requests(&self.root_dir, &reqs)
+                .into_iter()
+                .flatten()
+                .filter_map(|p| Some(p.to_str()?.to_string()))
+                .collect();
+        let reqs: Vec<(String, i64)> = reqs
             .iter()
-            .filter_map(|p| p.to_str())
-            .map(String::from)
+            .zip(scores)
+            .filter_map(|(p, score)| Some((p.to_str()?.to_string(), score)))
             .collect();
-        self.request_selector.populate(reqs);
+        self.request_selector.populate(
+            reqs,
+            find_pins(&self.root_dir)?,
+            &duplicates,
+        );
 
         Ok(())
     }
@@ -310,7 +824,8 @@ impl App {
     async fn process_events(&mut self) -> Result<Option<Intent>> {
         // Don't waste so much CPU when idle
         let poll_timeout = match self.state {
-            AppState::RunningRequest { .. } => Duration::from_millis(50),
+            _ if self.jobs.running() > 0 => Duration::from_millis(50),
+            AppState::WebSocket { .. } => Duration::from_millis(50),
             _ => Duration::from_secs(1),
         };
 
@@ -320,7 +835,42 @@ impl App {
             return Ok(self.handle_event(&event));
         }
 
-        if let AppState::RunningRequest { handle, .. } = &mut self.state {
+        // Requests were added or removed outside of hitman
+        if self.request_index.take_changed() {
+            let selected = self.request_selector.selected_path();
+            return Ok(Some(Intent::Update(selected)));
+        }
+
+        // Only the request shown follows along, the others are kept until
+        // they are jumped to
+        for event in self.jobs.poll().await {
+            match event {
+                JobEvent::Update(id, update)
+                    if self.output_view.job() == Some(id) =>
+                {
+                    self.output_view.receive(update);
+                }
+                JobEvent::Finished(id, info)
+                    if self.output_view.job() == Some(id) =>
+                {
+                    self.output_view.show_job(id, info);
+                }
+                _ => (),
+            }
+        }
+
+        if let AppState::Jobs { component } = &mut self.state {
+            refresh_jobs(component, &self.jobs);
+        }
+
+        if let AppState::WebSocket {
+            handle, updates, ..
+        } = &mut self.state
+        {
+            while let Ok(update) = updates.try_recv() {
+                self.output_view.receive(update);
+            }
+
             if handle.is_finished() {
                 return Ok(match handle.await {
                     Ok(res) => Some(Intent::ShowResult(res)),
@@ -340,13 +890,31 @@ impl App {
         let root_dir = self.root_dir.clone();
 
         let path = PathBuf::from(file_path.clone());
-        let env = load_env(&root_dir, &path, &options)?;
 
-        let intent = match substitute(&read_to_string(path.clone())?, &env) {
-            Ok(prepared_request) => Some(Intent::SendRequest {
-                file_path,
-                prepared_request,
-            }),
+        // Values given in prompts come back here, after the dependencies
+        // have already run
+        if options.is_empty() {
+            let options = self.options_for(&file_path, &[]);
+            block_in_place(|| {
+                Handle::current()
+                    .block_on(run_dependencies(&root_dir, &path, &options))
+            })?;
+        }
+
+        let env = self.request_env(&file_path, &options)?;
+
+        let (buf, scripts) =
+            RequestScripts::extract(&read_request(&path, &env)?)?;
+        let env = scripts.before(&env)?;
+        let intent = match substitute(&buf, &env) {
+            Ok(prepared_request) => {
+                remember_secrets(&env);
+                Some(Intent::SendRequest {
+                    file_path,
+                    prepared_request,
+                    env,
+                })
+            }
             Err(err) => match err {
                 SubstituteError::MultipleValuesFound { key, values } => {
                     Some(Intent::AskForValue {
@@ -357,11 +925,18 @@ impl App {
                     })
                 }
                 SubstituteError::ValueNotFound { key, fallback } => {
+                    let params =
+                        if is_json_placeholder(&buf, &key, fallback.as_deref())
+                        {
+                            AskForValueParams::Json { fallback }
+                        } else {
+                            AskForValueParams::Prompt { fallback }
+                        };
                     Some(Intent::AskForValue {
                         key,
                         file_path,
                         pending_options: options,
-                        params: AskForValueParams::Prompt { fallback },
+                        params,
                     })
                 }
                 other_err => Some(Intent::ShowError(other_err.to_string())),
@@ -371,6 +946,73 @@ impl App {
         Ok(intent)
     }
 
+    /// The values for a request, with the remembered values, the given
+    /// values and the access token
+    fn request_env(
+        &self,
+        file_path: &str,
+        options: &[(String, String)],
+    ) -> Result<Table> {
+        let env = load_env(
+            &self.root_dir,
+            Path::new(file_path),
+            &self.options_for(file_path, options),
+        )?;
+
+        // Only waits for the token server when there is no valid token
+        block_in_place(|| Handle::current().block_on(with_access_token(&env)))
+    }
+
+    /// Remembered values for a request, followed by the given values
+    fn options_for(
+        &self,
+        file_path: &str,
+        options: &[(String, String)],
+    ) -> Vec<(String, String)> {
+        let mut all =
+            self.overrides.get(file_path).cloned().unwrap_or_default();
+        all.extend_from_slice(options);
+        all
+    }
+
+    fn remember(&mut self, file_path: &str, key: String, value: String) {
+        let overrides =
+            self.overrides.entry(file_path.to_string()).or_default();
+        overrides.retain(|(k, _)| *k != key);
+        overrides.push((key, value));
+    }
+
+    /// The values available to a request, with remembered values marked
+    fn variables(&self, file_path: &str) -> Result<Vec<Variable>> {
+        let env = load_env(
+            &self.root_dir,
+            Path::new(file_path),
+            &self.options_for(file_path, &[]),
+        )?;
+        let remembered = self.overrides.get(file_path);
+        let settings = get_settings(&env);
+
+        let mut variables: Vec<Variable> = env
+            .iter()
+            .filter(|(key, value)| !key.starts_with('_') && !value.is_table())
+            .map(|(key, value)| Variable {
+                key: key.clone(),
+                value: match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                },
+                remembered: remembered
+                    .is_some_and(|o| o.iter().any(|(k, _)| k == key)),
+                hidden: is_secret_key(key) || is_sensitive_key(&settings, key),
+            })
+            .collect();
+        variables.sort_by(|a, b| {
+            b.remembered.cmp(&a.remembered).then(a.key.cmp(&b.key))
+        });
+
+        Ok(variables)
+    }
+
     fn preview_request(&mut self, file_path: Option<String>) -> Result<()> {
         if let Some(file_path) = file_path {
             let path = PathBuf::from(file_path.clone());
@@ -389,37 +1031,77 @@ impl App {
         Ok(())
     }
 
+    /// Send the request in the background, and show it while it runs
     fn send_request(
         &mut self,
         file_path: String,
         prepared_request: String,
+        env: Table,
+        info: HttpRequestInfo,
     ) -> Result<()> {
         let root_dir = self.root_dir.clone();
-        let file_path = PathBuf::from(file_path);
+        let path = PathBuf::from(&file_path);
+
+        let directives = Directives::parse(&prepared_request)?;
+        let timeout = request_timeout(&directives, &env)?;
 
+        let (sender, updates) = unbounded_channel();
+        let (cancel, cancelled) = oneshot::channel();
         let handle = tokio::spawn(async move {
-            make_request(&prepared_request, &root_dir, &file_path).await
+            make_request(
+                &prepared_request,
+                &env,
+                &root_dir,
+                &path,
+                &sender,
+                cancelled,
+            )
+            .await
         });
 
-        let state = AppState::RunningRequest {
+        let request = RunningRequest {
             handle,
-            progress: Progress,
+            updates,
+            cancel,
         };
-        self.set_state(state);
+        let id = self.jobs.start(file_path, info.clone(), request, timeout);
+        self.output_view.show_job(id, info);
 
         Ok(())
     }
+
+    fn open_websocket(&mut self, prepared_request: String) {
+        let (sender, updates) = unbounded_channel();
+        let (outgoing, messages) = unbounded_channel();
+        let handle = tokio::spawn(async move {
+            websocket_request(&prepared_request, messages, &sender).await
+        });
+
+        self.set_state(AppState::WebSocket {
+            handle,
+            updates,
+            outgoing,
+            prompt: websocket_prompt(),
+        });
+    }
 }
 
 fn open_in_editor<S>(
+    root_dir: &Path,
     file_path: &String,
     screen: &mut S,
 ) -> Result<(), anyhow::Error>
 where
     S: Screen,
 {
-    let editor = std::env::var("EDITOR")
-        .context("EDITOR environment variable not set")?;
+    let editor = match load_settings(root_dir)?.get("editor") {
+        Some(Value::String(editor)) => {
+            ensure_trusted("Editor", editor)?;
+            editor.clone()
+        }
+        _ => std::env::var("EDITOR")
+            .context("EDITOR environment variable not set")?,
+    };
     screen.leave()?;
     let _ = Command::new(editor).arg(file_path).status();
     screen.enter()?;
@@ -457,12 +1139,21 @@ impl InteractiveComponent for App {
                         component,
                         ..
                     } => {
-                        if let Some(intent) = component.handle_prompt(&event) {
+                        if let Some(intent) = component.handle_prompt(event) {
                             match intent {
                                 PromptIntent::Abort => {
                                     return Some(Abort);
                                 }
                                 PromptIntent::Accept(value) => {
+                                    if self.remember_values {
+                                        let overrides = self
+                                            .overrides
+                                            .entry(file_path.clone())
+                                            .or_default();
+                                        overrides.retain(|(k, _)| k != key);
+                                        overrides
+                                            .push((key.clone(), value.clone()));
+                                    }
                                     pending_options.push((key.clone(), value));
                                     return Some(PrepareRequest(
                                         file_path.clone(),
@@ -474,8 +1165,15 @@ impl InteractiveComponent for App {
                         return None;
                     }
                     AppState::Idle => {
+                        // A selected URL in the output takes Enter
+                        if let Some(OutputIntent::OpenUrl(url)) =
+                            self.output_view.handle_event(event)
+                        {
+                            return Some(OpenUrl(url));
+                        }
+
                         if let Some(intent) =
-                            self.request_selector.handle_event(&event)
+                            self.request_selector.handle_event(event)
                         {
                             match intent {
                                 SelectIntent::Abort => (),
@@ -491,26 +1189,70 @@ impl InteractiveComponent for App {
                             }
                         }
 
-                        self.output_view.handle_event(&event);
-
-                        match mapkey(&event) {
+                        match mapkey(event) {
                             KeyMapping::Editor => {
                                 return Some(Intent::EditRequest)
                             }
                             KeyMapping::Reload => {
-                                let selected_item = self
-                                    .request_selector
-                                    .selector
-                                    .selected_item();
-                                return Some(Intent::Update(
-                                    selected_item.cloned(),
-                                ));
+                                let selected_item =
+                                    self.request_selector.selected_path();
+                                return Some(Intent::Update(selected_item));
                             }
                             KeyMapping::New => return Some(Intent::NewRequest),
-                            KeyMapping::Abort => return Some(Intent::Quit),
+                            KeyMapping::Abort => {
+                                // Cancels the request shown while it runs
+                                return match self.output_view.job() {
+                                    Some(id) if self.jobs.is_running(id) => {
+                                        Some(Intent::CancelJob(id))
+                                    }
+                                    _ => Some(Intent::Quit),
+                                };
+                            }
+                            KeyMapping::Jobs => {
+                                return Some(Intent::ShowJobs);
+                            }
+                            KeyMapping::FilterHeaders => {
+                                return Some(Intent::FilterHeaders);
+                            }
                             KeyMapping::SelectTarget => {
                                 return Some(Intent::SelectTarget);
                             }
+                            KeyMapping::SelectProject => {
+                                return Some(Intent::SelectProject);
+                            }
+                            KeyMapping::FollowLink => {
+                                return Some(Intent::SelectLink);
+                            }
+                            KeyMapping::OpenResponse => {
+                                return Some(Intent::OpenResponse);
+                            }
+                            KeyMapping::Variables => {
+                                return Some(Intent::InspectVariables);
+                            }
+                            KeyMapping::History => {
+                                return Some(Intent::ShowHistory);
+                            }
+                            KeyMapping::Diff => {
+                                return Some(Intent::ShowDiff);
+                            }
+                            KeyMapping::Curl => {
+                                return Some(Intent::ShowCurl);
+                            }
+                            KeyMapping::NextPage => {
+                                return Some(Intent::Paginate(Page::Next));
+                            }
+                            KeyMapping::PrevPage => {
+                                return Some(Intent::Paginate(Page::Prev));
+                            }
+                            KeyMapping::ClearOverrides => {
+                                let selected_item =
+                                    self.request_selector.selected_path();
+                                if let Some(file_path) = selected_item {
+                                    return Some(Intent::ClearOverrides(
+                                        file_path,
+                                    ));
+                                }
+                            }
                             KeyMapping::IncreaseWidth => {
                                 self.vsplit += 5;
                             }
@@ -525,15 +1267,50 @@ impl InteractiveComponent for App {
                         }
                     }
 
-                    AppState::RunningRequest { handle, .. } => {
-                        if let KeyMapping::Abort = mapkey(&event) {
-                            handle.abort();
-                            return Some(Abort);
+                    AppState::Jobs { component } => {
+                        let selected = component.selected_item();
+                        if let (KeyMapping::Cancel, Some(job)) =
+                            (mapkey(event), selected)
+                        {
+                            return Some(CancelJob(job.id));
+                        }
+                        if let Some(intent) = component.handle_event(event) {
+                            match intent {
+                                SelectIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                SelectIntent::Accept(job) => {
+                                    return Some(ShowJob(job.id));
+                                }
+                                SelectIntent::Change(_) => (),
+                            }
+                        }
+                    }
+
+                    AppState::WebSocket {
+                        handle,
+                        outgoing,
+                        prompt,
+                        ..
+                    } => {
+                        if let Some(intent) = prompt.handle_prompt(event) {
+                            match intent {
+                                PromptIntent::Abort => {
+                                    handle.abort();
+                                    return Some(Abort);
+                                }
+                                PromptIntent::Accept(text) => {
+                                    if !text.is_empty() {
+                                        let _ = outgoing.send(text);
+                                    }
+                                    *prompt = websocket_prompt();
+                                }
+                            }
                         }
                     }
 
                     AppState::NewRequestPrompt { prompt } => {
-                        if let Some(intent) = prompt.handle_prompt(&event) {
+                        if let Some(intent) = prompt.handle_prompt(event) {
                             match intent {
                                 PromptIntent::Abort => {
                                     return Some(Abort);
@@ -545,8 +1322,65 @@ impl InteractiveComponent for App {
                         }
                     }
 
+                    AppState::HeaderFilter { prompt } => {
+                        if let Some(intent) = prompt.handle_prompt(event) {
+                            match intent {
+                                PromptIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                PromptIntent::Accept(s) => {
+                                    return Some(AcceptFilterHeaders(s));
+                                }
+                            }
+                        }
+                    }
+
+                    AppState::Variables {
+                        component,
+                        file_path,
+                    } => {
+                        if let KeyMapping::ClearOverrides = mapkey(event) {
+                            return Some(ClearOverrides(file_path.clone()));
+                        }
+                        if let Some(intent) = component.handle_event(event) {
+                            match intent {
+                                SelectIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                SelectIntent::Accept(variable) => {
+                                    return Some(EditVariable(
+                                        file_path.clone(),
+                                        variable,
+                                    ));
+                                }
+                                SelectIntent::Change(_) => (),
+                            }
+                        }
+                    }
+
+                    AppState::EditVariable {
+                        file_path,
+                        key,
+                        prompt,
+                    } => {
+                        if let Some(intent) = prompt.handle_prompt(event) {
+                            match intent {
+                                PromptIntent::Abort => {
+                                    return Some(InspectVariables);
+                                }
+                                PromptIntent::Accept(value) => {
+                                    return Some(AcceptEditVariable(
+                                        file_path.clone(),
+                                        key.clone(),
+                                        value,
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
                     AppState::SelectTarget { component } => {
-                        if let Some(intent) = component.handle_event(&event) {
+                        if let Some(intent) = component.handle_event(event) {
                             match intent {
                                 SelectIntent::Abort => {
                                     return Some(Abort);
@@ -558,6 +1392,77 @@ impl InteractiveComponent for App {
                             }
                         }
                     }
+
+                    AppState::SelectProject { component } => {
+                        if let Some(intent) = component.handle_event(event) {
+                            match intent {
+                                SelectIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                SelectIntent::Accept(s) => {
+                                    return Some(AcceptSelectProject(s));
+                                }
+                                SelectIntent::Change(_) => (),
+                            }
+                        }
+                    }
+
+                    AppState::History { component } => {
+                        let selected = component.selected_item().cloned();
+                        match (mapkey(event), selected) {
+                            (KeyMapping::Bookmark, Some(entry)) => {
+                                return Some(ToggleBookmark(entry));
+                            }
+                            (KeyMapping::Note, Some(entry)) => {
+                                return Some(EditHistoryNote(entry));
+                            }
+                            _ => (),
+                        }
+                        if let Some(intent) = component.handle_event(event) {
+                            match intent {
+                                SelectIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                SelectIntent::Accept(entry) => {
+                                    return Some(AcceptHistory(entry));
+                                }
+                                SelectIntent::Change(_) => (),
+                            }
+                        }
+                    }
+
+                    AppState::HistoryNote { entry, prompt } => {
+                        if let Some(intent) = prompt.handle_prompt(event) {
+                            match intent {
+                                PromptIntent::Abort => {
+                                    return Some(ShowHistory);
+                                }
+                                PromptIntent::Accept(note) => {
+                                    return Some(AcceptHistoryNote(
+                                        entry.clone(),
+                                        note,
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
+                    AppState::SelectLink { component, request } => {
+                        if let Some(intent) = component.handle_event(event) {
+                            match intent {
+                                SelectIntent::Abort => {
+                                    return Some(Abort);
+                                }
+                                SelectIntent::Accept(link) => {
+                                    return Some(AcceptSelectLink(
+                                        request.clone(),
+                                        link,
+                                    ));
+                                }
+                                SelectIntent::Change(_) => (),
+                            }
+                        }
+                    }
                 }
             }
         }
@@ -604,12 +1509,13 @@ impl App {
         );
 
         // FIXME: <Ctrl+?> opens key mapping window
-        let status_line = match &self.error {
-            Some(msg) => Paragraph::new(msg.clone()).red().reversed(),
-            None => Paragraph::new(
-                "Ctrl+S: Select target, Ctrl+E: Edit selected request, Ctrl+R: New request, [<>] Adjust width, [,] Tottle wrapping",
-            )
-            .dark_gray(),
+        let status_line = match (&self.error, self.jobs.running()) {
+            (Some(msg), _) => Paragraph::new(msg.clone()).red().reversed(),
+            (None, 0) => Paragraph::new(HELP).dark_gray(),
+            (None, running) => Paragraph::new(Line::from(vec![
+                format!("{running} running ").yellow(),
+                HELP.dark_gray(),
+            ])),
         };
 
         frame.render_widget(status_line, layout[2]);
@@ -628,27 +1534,95 @@ impl App {
                 prompt.render_ui(frame, inner_area);
             }
 
+            AppState::HeaderFilter { prompt } => {
+                let inner_area = centered(area, 48, 30);
+                prompt.render_ui(frame, inner_area);
+            }
+
             AppState::SelectTarget { component } => {
                 let inner_area = centered(area, 30, 20);
                 component.render_ui(frame, inner_area);
             }
 
-            AppState::RunningRequest { progress, .. } => {
-                progress.render_ui(frame, frame.area());
+            AppState::SelectProject { component } => {
+                let inner_area = centered(area, 30, 20);
+                component.render_ui(frame, inner_area);
+            }
+
+            AppState::SelectLink { component, .. } => {
+                let inner_area = centered(area, 60, 20);
+                component.render_ui(frame, inner_area);
+            }
+
+            AppState::Variables { component, .. } => {
+                let inner_area = centered(area, 60, 20);
+                component.render_ui(frame, inner_area);
+            }
+
+            AppState::History { component } => {
+                let inner_area = centered(area, 80, 20);
+                component.render_ui(frame, inner_area);
+            }
+
+            AppState::HistoryNote { prompt, .. } => {
+                let inner_area = centered(area, 48, 30);
+                prompt.render_ui(frame, inner_area);
+            }
+
+            AppState::EditVariable { prompt, .. } => {
+                let inner_area = centered(area, 48, 30);
+                prompt.render_ui(frame, inner_area);
             }
 
-            _ => (),
+            AppState::WebSocket { prompt, .. } => {
+                // Below the messages, which keep arriving while typing
+                let inner_area = Rect {
+                    y: area.bottom().saturating_sub(5),
+                    height: 3,
+                    ..area
+                };
+                prompt.render_ui(frame, inner_area);
+            }
+
+            AppState::Jobs { component } => {
+                let inner_area = centered(area, 80, 20);
+                component.render_ui(frame, inner_area);
+            }
+
+            AppState::Idle => {
+                if let Some((received, total)) =
+                    self.output_view.download_progress()
+                {
+                    Progress::download(received, total)
+                        .render_ui(frame, frame.area());
+                } else if !self.output_view.is_receiving() {
+                    // The response itself shows progress once it arrives
+                    let running = self
+                        .output_view
+                        .job()
+                        .filter(|&id| self.jobs.is_running(id));
+                    if let Some(id) = running {
+                        Progress::new(self.jobs.remaining(id))
+                            .render_ui(frame, frame.area());
+                    }
+                }
+            }
         }
     }
 }
 
 async fn make_request(
     buf: &str,
+    env: &Table,
     root_dir: &Path,
     file_path: &Path,
+    updates: &UnboundedSender<ResponseUpdate>,
+    mut cancelled: oneshot::Receiver<()>,
 ) -> HttpRequestInfo {
     let request = HttpRequestMessage(buf.into());
-    let status = match do_make_request(buf, root_dir, file_path).await {
+    let response =
+        do_make_request(buf, env, root_dir, file_path, updates, &mut cancelled);
+    let status = match response.await {
         Ok((response, elapsed)) => {
             RequestStatus::Complete { response, elapsed }
         }
@@ -663,12 +1637,21 @@ async fn make_request(
 // FIXME: DRY request.rs
 async fn do_make_request(
     buf: &str,
+    env: &Table,
     root_dir: &Path,
     file_path: &Path,
+    updates: &UnboundedSender<ResponseUpdate>,
+    cancelled: &mut oneshot::Receiver<()>,
 ) -> Result<(HttpMessage, Duration)> {
-    let client = build_client()?;
-
-    let (res, elapsed) = do_request(&client, buf).await?;
+    let client = build_client(env)?;
+
+    let buf = &apply_request_hooks(env, buf)?;
+    let retry = RetryPolicy::for_request(&Directives::parse(buf)?, env)?;
+    let started = Instant::now();
+    let (mut res, elapsed) = tokio::select! {
+        res = do_request(&client, buf, &retry) => res?,
+        _ = &mut *cancelled => bail!("Cancelled after {:.2?}", started.elapsed()),
+    };
 
     let mut response = HttpMessage::default();
     writeln!(
@@ -682,18 +1665,288 @@ async fn do_make_request(
     }
     writeln!(response.header)?;
 
-    if let Ok(json) = res.json::<serde_json::Value>().await {
-        writeln!(response.body, "{}", serde_json::to_string_pretty(&json)?)?;
+    response.links = response_links(res.headers(), res.url());
+
+    let format = response_format(&res);
+    response.content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let meta = ResponseMeta {
+        file: file_path.display().to_string(),
+        method: request_method(buf),
+        url: res.url().to_string(),
+        status: res.status().as_u16(),
+        content_type: response.content_type.clone(),
+        elapsed,
+    };
 
-        let options = vec![];
-        let env = load_env(root_dir, file_path, &options)?;
-        let vars = extract_variables(&json, &env)?;
-        update_data(&vars)?;
+    let headers = res.headers().clone();
+    response.format = format;
+    response.content_length = res.content_length();
+
+    // Show the body while it downloads. The UI may be gone, which is fine.
+    let _ = updates.send(ResponseUpdate::Header(response.clone()));
+    let receiving = Instant::now();
+    let mut data = Vec::new();
+    let interrupted = loop {
+        let chunk = tokio::select! {
+            chunk = res.chunk() => chunk,
+            _ = &mut *cancelled => {
+                break Some(format!("Cancelled after {:.2?}", started.elapsed()));
+            }
+        };
+        match chunk {
+            Ok(Some(chunk)) => {
+                let _ = updates.send(ResponseUpdate::Chunk(chunk.to_vec()));
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => break None,
+            // Such as a timeout while the body downloads
+            Err(err) => break Some(err.to_string()),
+        }
+    };
+    // Closes the connection, when the body is not complete
+    drop(res);
+    response.data = data.clone();
+
+    // What has arrived is shown, but not kept or extracted from
+    if let Some(reason) = interrupted {
+        writeln!(response.header, "# {reason}, the body is incomplete")?;
+        response.body = match describe_binary(format, &data) {
+            Some(description) => description,
+            None => String::from_utf8_lossy(&data).to_string(),
+        };
+        return Ok((response, elapsed));
+    }
+
+    if let Some(output) = output_file(file_path)? {
+        match save_response(&output, file_path, env, meta.status, &data) {
+            Ok(path) => {
+                writeln!(response.header, "# Saved to {}", path.display())?
+            }
+            Err(err) => writeln!(response.header, "# {:#}", err)?,
+        }
+    }
+
+    if let Err(err) = record_response(root_dir, &meta, buf, &headers, &data) {
+        writeln!(response.header, "# Failed to save history: {}", err)?;
+    }
+
+    if let Err(err) = run_response_hook(env, &meta, &data) {
+        writeln!(response.header, "# {}", err)?;
+    }
+
+    let (_, scripts) = RequestScripts::extract(&read_request(file_path, env)?)?;
+    let info = ResponseInfo {
+        status: meta.status,
+        headers: &headers,
+        body: &String::from_utf8_lossy(&data),
+        elapsed,
+        plugins: &plugins_from(env)?,
+    };
+    match scripts.after(env, &info) {
+        Ok(values) => {
+            update_data(&values, env)?;
+        }
+        Err(err) => writeln!(response.header, "# {}", err)?,
+    }
+
+    store_extracted(&extract_header_variables(&headers, env)?, env, None)?;
+
+    if is_download(format) {
+        writeln!(response.header, "# {}", download_summary(&data, receiving))?;
+    }
+
+    if let Some(description) = describe_binary(format, &data) {
+        writeln!(response.body, "{}", description)?;
+        return Ok((response, elapsed));
+    }
+
+    let body = String::from_utf8_lossy(&data);
+
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&body) {
+        writeln!(response.body, "{}", serde_json::to_string_pretty(&json)?)?;
+        response.format = BodyFormat::Json;
+
+        let vars = extract_variables(&json, env)?;
+        store_extracted(&vars, env, Some(&json))?;
+    } else {
+        store_extracted(&extract_markup_variables(&body, env)?, env, None)?;
+        response.body = format_body(format, &body);
+        response.format = format;
     }
 
     Ok((response, elapsed))
 }
 
+/// Show the latest status of the jobs, keeping the one selected
+fn refresh_jobs(component: &mut Select<JobItem>, jobs: &Jobs) {
+    let selected = component.selected_item().cloned();
+    component.set_items(jobs.items());
+    match selected {
+        Some(item) => component.try_select(&item),
+        None => component.select_first(),
+    }
+}
+
+fn remember_values(settings: &Table) -> bool {
+    settings
+        .get("remember_values")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn websocket_prompt() -> SimplePrompt {
+    SimplePrompt::new("Send message (Esc: Close)".into())
+}
+
+/// Show the messages of a WebSocket connection as they arrive, with the
+/// whole conversation as the response when the connection is closed
+async fn websocket_request(
+    buf: &str,
+    messages: UnboundedReceiver<String>,
+    updates: &UnboundedSender<ResponseUpdate>,
+) -> HttpRequestInfo {
+    let request = HttpRequestMessage(buf.into());
+    let started = Instant::now();
+
+    let ws_request = match parse_ws_request(buf) {
+        Ok(ws_request) => ws_request,
+        Err(err) => {
+            let error = err.to_string();
+            return HttpRequestInfo::new(
+                request,
+                RequestStatus::Failed { error },
+            );
+        }
+    };
+
+    let (sender, mut events) = unbounded_channel();
+    let session = tokio::spawn(run_session(ws_request, messages, sender));
+
+    let mut response = HttpMessage::default();
+    let mut connected = false;
+    while let Some(event) = events.recv().await {
+        let line = match event {
+            WsEvent::Connected(head) => {
+                for line in head {
+                    response.header.push_str(&format!("< {line}\n"));
+                }
+                response.header.push('\n');
+                // The UI may be gone, which is fine
+                let _ = updates.send(ResponseUpdate::Header(response.clone()));
+                connected = true;
+                continue;
+            }
+            WsEvent::Sent(text) => format!("> {text}\n"),
+            WsEvent::Received(WsMessage::Text(text)) => format!("< {text}\n"),
+            WsEvent::Received(WsMessage::Binary(data)) => format!(
+                "< {}\n",
+                describe_binary(BodyFormat::Binary, &data).unwrap_or_default()
+            ),
+            WsEvent::Closed(Some(reason)) => {
+                format!("# Connection closed: {reason}\n")
+            }
+            WsEvent::Closed(None) => "# Connection closed\n".to_string(),
+        };
+        let _ = updates.send(ResponseUpdate::Chunk(line.clone().into_bytes()));
+        response.data.extend_from_slice(line.as_bytes());
+    }
+
+    let error = match session.await {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(err) => Some(err.to_string()),
+    };
+
+    let status = match error {
+        Some(error) if !connected => RequestStatus::Failed { error },
+        error => {
+            if let Some(error) = error {
+                response
+                    .data
+                    .extend_from_slice(format!("# {error}\n").as_bytes());
+            }
+            response.body = String::from_utf8_lossy(&response.data).to_string();
+            RequestStatus::Complete {
+                response,
+                elapsed: started.elapsed(),
+            }
+        }
+    };
+
+    HttpRequestInfo::new(request, status)
+}
+
+/// A response from the history, shown as when it was received
+fn history_response(entry: &HistoryEntry) -> Result<HttpMessage> {
+    let mut response = HttpMessage::default();
+    let status = reqwest::StatusCode::from_u16(entry.status)?;
+    writeln!(
+        response.header,
+        "< HTTP/1.1 {} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    )?;
+    for (name, value) in &entry.headers {
+        writeln!(response.header, "< {}: {}", name, value)?;
+    }
+    writeln!(response.header)?;
+    writeln!(
+        response.header,
+        "# From history: {} ({})",
+        entry.local_time(),
+        entry.target
+    )?;
+
+    response.content_type = entry.content_type.clone();
+    response.data = entry.body.clone();
+
+    let format = BodyFormat::from_content_type(&entry.content_type);
+    if let Some(description) = describe_binary(format, &entry.body) {
+        writeln!(response.body, "{}", description)?;
+        return Ok(response);
+    }
+
+    let body = String::from_utf8_lossy(&entry.body);
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&body) {
+        writeln!(response.body, "{}", serde_json::to_string_pretty(&json)?)?;
+        response.format = BodyFormat::Json;
+    } else {
+        response.body = format_body(format, &body);
+        response.format = format;
+    }
+
+    Ok(response)
+}
+
+impl SelectItem for HistoryEntry {
+    fn text(&self) -> String {
+        self.summary()
+    }
+}
+
+impl SelectItem for Variable {
+    fn text(&self) -> String {
+        let value = if self.hidden { "********" } else { &self.value };
+
+        if self.remembered {
+            format!("{} = {} (remembered)", self.key, value)
+        } else {
+            format!("{} = {}", self.key, value)
+        }
+    }
+}
+
+impl SelectItem for Link {
+    fn text(&self) -> String {
+        self.label()
+    }
+}
+
 impl SelectItem for Value {
     fn text(&self) -> String {
         match self {
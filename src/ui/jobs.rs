@@ -0,0 +1,249 @@
+use std::time::{Duration, Instant};
+
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, oneshot},
+    task::JoinHandle,
+};
+
+use super::{
+    output::{HttpRequestInfo, ResponseUpdate},
+    select::SelectItem,
+};
+
+/// Finished requests kept in the list, the oldest are dropped first
+const MAX_FINISHED: usize = 20;
+
+/// Requests sent from the TUI, running in the background while other
+/// requests are browsed and sent
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    next_id: usize,
+}
+
+pub struct Job {
+    id: usize,
+    file_path: String,
+    started: Instant,
+    timeout: Option<Duration>,
+    /// The request, and the response as far as it has arrived
+    info: HttpRequestInfo,
+    state: JobState,
+}
+
+/// A request sent in the background
+pub struct RunningRequest {
+    pub handle: JoinHandle<HttpRequestInfo>,
+    pub updates: UnboundedReceiver<ResponseUpdate>,
+    /// Stops the request, keeping the part of the response that has arrived
+    pub cancel: oneshot::Sender<()>,
+}
+
+enum JobState {
+    Running {
+        handle: JoinHandle<HttpRequestInfo>,
+        updates: UnboundedReceiver<ResponseUpdate>,
+        /// Taken when the job is cancelled, until the request has stopped
+        cancel: Option<oneshot::Sender<()>>,
+    },
+    Finished {
+        elapsed: Duration,
+    },
+    Cancelled {
+        elapsed: Duration,
+    },
+}
+
+/// What happened to a job since the last poll
+pub enum JobEvent {
+    Update(usize, ResponseUpdate),
+    Finished(usize, HttpRequestInfo),
+}
+
+/// A job in the jobs panel
+#[derive(Clone)]
+pub struct JobItem {
+    pub id: usize,
+    text: String,
+}
+
+impl PartialEq for JobItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Jobs {
+    pub fn start(
+        &mut self,
+        file_path: String,
+        info: HttpRequestInfo,
+        request: RunningRequest,
+        timeout: Option<Duration>,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.jobs.push(Job {
+            id,
+            file_path,
+            started: Instant::now(),
+            timeout,
+            info,
+            state: JobState::Running {
+                handle: request.handle,
+                updates: request.updates,
+                cancel: Some(request.cancel),
+            },
+        });
+        self.prune();
+
+        id
+    }
+
+    /// Take the parts of responses that arrived, and the results of the
+    /// requests that finished
+    pub async fn poll(&mut self) -> Vec<JobEvent> {
+        let mut events = Vec::new();
+
+        for job in &mut self.jobs {
+            let JobState::Running {
+                handle,
+                updates,
+                cancel,
+            } = &mut job.state
+            else {
+                continue;
+            };
+
+            while let Ok(update) = updates.try_recv() {
+                job.info.receive(update.clone());
+                events.push(JobEvent::Update(job.id, update));
+            }
+
+            if handle.is_finished() {
+                match handle.await {
+                    Ok(info) => job.info = info,
+                    Err(err) => job.info.fail(err.to_string()),
+                }
+                events.push(JobEvent::Finished(job.id, job.info.clone()));
+
+                let elapsed = job.started.elapsed();
+                job.state = match cancel {
+                    Some(_) => JobState::Finished { elapsed },
+                    None => JobState::Cancelled { elapsed },
+                };
+            }
+        }
+
+        events
+    }
+
+    /// Stop a request. It is still running until the connection is closed,
+    /// and the part of the response that arrived is kept.
+    pub fn cancel(&mut self, id: usize) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            if let JobState::Running { cancel, .. } = &mut job.state {
+                if let Some(cancel) = cancel.take() {
+                    let _ = cancel.send(());
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn is_running(&self, id: usize) -> bool {
+        self.get(id).is_some_and(Job::is_running)
+    }
+
+    /// The time left before a running request times out, if it has a
+    /// timeout
+    pub fn remaining(&self, id: usize) -> Option<Duration> {
+        let job = self.get(id).filter(|job| job.is_running())?;
+
+        Some(job.timeout?.saturating_sub(job.started.elapsed()))
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs.iter().filter(|job| job.is_running()).count()
+    }
+
+    /// The jobs for the jobs panel, the latest first
+    pub fn items(&self) -> Vec<JobItem> {
+        self.jobs
+            .iter()
+            .rev()
+            .map(|job| JobItem {
+                id: job.id,
+                text: job.describe(),
+            })
+            .collect()
+    }
+
+    fn prune(&mut self) {
+        let finished = self.jobs.iter().filter(|j| !j.is_running()).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED);
+
+        self.jobs.retain(|job| {
+            if excess > 0 && !job.is_running() {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl Job {
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    pub fn info(&self) -> &HttpRequestInfo {
+        &self.info
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(self.state, JobState::Running { .. })
+    }
+
+    fn describe(&self) -> String {
+        let (status, elapsed) = match &self.state {
+            JobState::Running { cancel: None, .. } => {
+                ("cancelling".to_string(), self.started.elapsed())
+            }
+            JobState::Running { .. } => {
+                (self.info.summary(), self.started.elapsed())
+            }
+            JobState::Finished { elapsed } => (self.info.summary(), *elapsed),
+            JobState::Cancelled { elapsed } => {
+                ("cancelled".to_string(), *elapsed)
+            }
+        };
+
+        let text = format!(
+            "{:<12} {:>7.1}s  {}",
+            status,
+            elapsed.as_secs_f64(),
+            self.file_path
+        );
+
+        match self.timeout {
+            Some(timeout) if self.is_running() => {
+                let left = timeout.saturating_sub(elapsed).as_secs();
+                format!("{text} (times out in {left}s)")
+            }
+            _ => text,
+        }
+    }
+}
+
+impl SelectItem for JobItem {
+    fn text(&self) -> String {
+        self.text.clone()
+    }
+}
@@ -0,0 +1,195 @@
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{
+    layout::Rect,
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+
+use hitman::prompt::validate_json;
+
+use super::{centered, Component, PromptComponent, PromptIntent};
+
+/// Multi-line prompt for a placeholder that stands for a whole JSON value.
+/// The value is validated before it is accepted.
+pub struct JsonPrompt {
+    title: String,
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    error: Option<String>,
+}
+
+impl JsonPrompt {
+    pub fn new(title: String) -> Self {
+        Self {
+            title,
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            error: None,
+        }
+    }
+
+    pub fn with_fallback(self, fallback: Option<String>) -> Self {
+        let Some(fallback) = fallback else {
+            return self;
+        };
+
+        let text = serde_json::from_str::<serde_json::Value>(&fallback)
+            .and_then(|json| serde_json::to_string_pretty(&json))
+            .unwrap_or(fallback);
+
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        Self { lines, ..self }
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn byte_pos(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(self.col)
+            .map(|(i, _)| i)
+            .unwrap_or(line.len())
+    }
+
+    fn insert(&mut self, c: char) {
+        let pos = self.byte_pos();
+        self.lines[self.row].insert(pos, c);
+        self.col += 1;
+    }
+
+    fn newline(&mut self) {
+        let pos = self.byte_pos();
+        let rest = self.lines[self.row].split_off(pos);
+        let indent: String = self.lines[self.row]
+            .chars()
+            .take_while(|c| *c == ' ')
+            .collect();
+
+        self.col = indent.chars().count();
+        self.row += 1;
+        self.lines.insert(self.row, indent + rest.as_str());
+    }
+
+    fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let pos = self.byte_pos();
+            self.lines[self.row].remove(pos);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len();
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.col < self.line_len() {
+            let pos = self.byte_pos();
+            self.lines[self.row].remove(pos);
+        } else if self.row + 1 < self.lines.len() {
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    fn move_to_row(&mut self, row: usize) {
+        self.row = row;
+        self.col = self.col.min(self.line_len());
+    }
+
+    fn accept(&mut self) -> Option<PromptIntent> {
+        match validate_json(&self.lines.join("\n")) {
+            Ok(value) => Some(PromptIntent::Accept(value)),
+            Err(err) => {
+                self.error = Some(err.to_string());
+                None
+            }
+        }
+    }
+}
+
+impl Component for JsonPrompt {
+    fn render_ui(&mut self, frame: &mut Frame, area: Rect) {
+        let area = centered(area, 48, 20);
+
+        let mut block = Block::bordered()
+            .cyan()
+            .title(self.title.clone())
+            .title_bottom("Ctrl+S: Accept, Esc: Cancel");
+        if let Some(error) = &self.error {
+            block = block.title_bottom(Line::from(error.clone()).red());
+        }
+        let inner = block.inner(area);
+
+        // Keep the cursor visible in long values
+        let height = inner.height as usize;
+        let top = (self.row + 1).saturating_sub(height);
+
+        let lines: Vec<Line> = self
+            .lines
+            .iter()
+            .skip(top)
+            .map(|l| Line::from(l.as_str()))
+            .collect();
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(Paragraph::new(lines).white().block(block), area);
+
+        frame.set_cursor_position((
+            inner.x + self.col as u16,
+            inner.y + (self.row - top) as u16,
+        ));
+    }
+}
+
+impl PromptComponent for JsonPrompt {
+    fn handle_prompt(&mut self, event: &Event) -> Option<PromptIntent> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+
+        match (key.modifiers, key.code) {
+            (KeyModifiers::CONTROL, KeyCode::Char('s')) => {
+                return self.accept();
+            }
+            (KeyModifiers::CONTROL, KeyCode::Char('c')) | (_, KeyCode::Esc) => {
+                return Some(PromptIntent::Abort);
+            }
+            (_, KeyCode::Enter) => self.newline(),
+            (_, KeyCode::Backspace) => self.backspace(),
+            (_, KeyCode::Delete) => self.delete(),
+            (_, KeyCode::Tab) => {
+                self.insert(' ');
+                self.insert(' ');
+            }
+            (_, KeyCode::Left) if self.col > 0 => self.col -= 1,
+            (_, KeyCode::Right) if self.col < self.line_len() => self.col += 1,
+            (_, KeyCode::Up) if self.row > 0 => self.move_to_row(self.row - 1),
+            (_, KeyCode::Down) if self.row + 1 < self.lines.len() => {
+                self.move_to_row(self.row + 1)
+            }
+            (_, KeyCode::Home) => self.col = 0,
+            (_, KeyCode::End) => self.col = self.line_len(),
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => {
+                self.insert(c)
+            }
+            _ => (),
+        }
+
+        None
+    }
+}
@@ -1,5 +1,10 @@
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use toml::{Table, Value};
 
+#[derive(Clone, Copy)]
 pub enum KeyMapping {
     None,
 
@@ -12,8 +17,26 @@ pub enum KeyMapping {
     ScrollUp,
     ScrollDown,
     SelectTarget,
+    SelectProject,
+    FollowLink,
+    NextLink,
+    PrevLink,
+    OpenResponse,
+    Variables,
+    History,
+    Bookmark,
+    Note,
+    Diff,
+    Curl,
+    NextPage,
+    PrevPage,
+    Jobs,
+    Cancel,
+    ClearOverrides,
     ToggleWrap,
     ToggleHeaders,
+    ToggleHiddenHeaders,
+    FilterHeaders,
     Reload,
     Editor,
     New,
@@ -31,9 +54,123 @@ pub fn mapkey(event: &Event) -> KeyMapping {
     KeyMapping::None
 }
 
+type KeyBinding = (KeyModifiers, KeyCode, KeyMapping);
+
+static KEYBINDINGS: OnceLock<Vec<KeyBinding>> = OnceLock::new();
+
+/// Set custom key bindings from the `keys` table in settings, mapping action
+/// names to keys, such as `select_target = "ctrl+t"`. These take precedence
+/// over the default key bindings.
+pub fn set_keybindings(keys: &Table) -> Result<()> {
+    let mut bindings = Vec::new();
+
+    for (action, key) in keys {
+        let mapping = parse_action(action)?;
+        let Value::String(key) = key else {
+            bail!("Invalid key for {action}: {key}");
+        };
+        let (modifiers, code) = parse_key(key)?;
+        bindings.push((modifiers, code, mapping));
+    }
+
+    let _ = KEYBINDINGS.set(bindings);
+
+    Ok(())
+}
+
+fn parse_action(action: &str) -> Result<KeyMapping> {
+    let mapping = match action {
+        "up" => KeyMapping::Up,
+        "down" => KeyMapping::Down,
+        "left" => KeyMapping::Left,
+        "right" => KeyMapping::Right,
+        "abort" => KeyMapping::Abort,
+        "accept" => KeyMapping::Accept,
+        "scroll_up" => KeyMapping::ScrollUp,
+        "scroll_down" => KeyMapping::ScrollDown,
+        "select_target" => KeyMapping::SelectTarget,
+        "select_project" => KeyMapping::SelectProject,
+        "follow_link" => KeyMapping::FollowLink,
+        "next_link" => KeyMapping::NextLink,
+        "prev_link" => KeyMapping::PrevLink,
+        "open_response" => KeyMapping::OpenResponse,
+        "variables" => KeyMapping::Variables,
+        "history" => KeyMapping::History,
+        "bookmark" => KeyMapping::Bookmark,
+        "note" => KeyMapping::Note,
+        "diff" => KeyMapping::Diff,
+        "curl" => KeyMapping::Curl,
+        "next_page" => KeyMapping::NextPage,
+        "prev_page" => KeyMapping::PrevPage,
+        "jobs" => KeyMapping::Jobs,
+        "cancel" => KeyMapping::Cancel,
+        "clear_overrides" => KeyMapping::ClearOverrides,
+        "toggle_wrap" => KeyMapping::ToggleWrap,
+        "toggle_headers" => KeyMapping::ToggleHeaders,
+        "toggle_hidden_headers" => KeyMapping::ToggleHiddenHeaders,
+        "filter_headers" => KeyMapping::FilterHeaders,
+        "reload" => KeyMapping::Reload,
+        "editor" => KeyMapping::Editor,
+        "new" => KeyMapping::New,
+        "increase_width" => KeyMapping::IncreaseWidth,
+        "decrease_width" => KeyMapping::DecreaseWitdh,
+        _ => bail!("Unknown key binding action: {action}"),
+    };
+
+    Ok(mapping)
+}
+
+/// Parse a key such as `ctrl+t`, `alt+enter` or `f5`
+fn parse_key(key: &str) -> Result<(KeyModifiers, KeyCode)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut parts: Vec<&str> = key.split('+').collect();
+    let Some(name) = parts.pop() else {
+        bail!("Invalid key: {key}");
+    };
+
+    for part in parts {
+        modifiers |= match part.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => bail!("Invalid key modifier in {key}"),
+        };
+    }
+
+    let code = match name.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        f if f.len() > 1 && f.starts_with('f') => match f[1..].parse() {
+            Ok(n) => KeyCode::F(n),
+            Err(_) => bail!("Invalid key: {key}"),
+        },
+        _ => match name.chars().collect::<Vec<_>>()[..] {
+            [c] => KeyCode::Char(c),
+            _ => bail!("Invalid key: {key}"),
+        },
+    };
+
+    Ok((modifiers, code))
+}
+
 fn mapkey_keypress(key: &KeyEvent) -> KeyMapping {
     use KeyCode::*;
 
+    let custom = KEYBINDINGS.get().and_then(|bindings| {
+        bindings
+            .iter()
+            .find(|(m, c, _)| *m == key.modifiers && *c == key.code)
+    });
+    if let Some((_, _, mapping)) = custom {
+        return *mapping;
+    }
+
     match (key.modifiers, key.code) {
         (KeyModifiers::NONE, Up) => KeyMapping::Up,
         (KeyModifiers::NONE, Down) => KeyMapping::Down,
@@ -41,6 +178,8 @@ fn mapkey_keypress(key: &KeyEvent) -> KeyMapping {
         (KeyModifiers::NONE, Right) => KeyMapping::Right,
         (KeyModifiers::NONE, Esc) => KeyMapping::Abort,
         (KeyModifiers::NONE, Enter) => KeyMapping::Accept,
+        (KeyModifiers::NONE, Tab) => KeyMapping::NextLink,
+        (KeyModifiers::SHIFT, BackTab) => KeyMapping::PrevLink,
         (KeyModifiers::CONTROL, Char('k')) => KeyMapping::Up,
         (KeyModifiers::CONTROL, Char('j')) => KeyMapping::Down,
         (KeyModifiers::CONTROL, Char('h')) => KeyMapping::Left,
@@ -51,6 +190,22 @@ fn mapkey_keypress(key: &KeyEvent) -> KeyMapping {
         (KeyModifiers::CONTROL, Char('u')) => KeyMapping::ScrollUp,
         (KeyModifiers::CONTROL, Char('d')) => KeyMapping::ScrollDown,
         (KeyModifiers::CONTROL, Char('s')) => KeyMapping::SelectTarget,
+        (KeyModifiers::CONTROL, Char('o')) => KeyMapping::SelectProject,
+        (KeyModifiers::CONTROL, Char('f')) => KeyMapping::FollowLink,
+        (KeyModifiers::CONTROL, Char('v')) => KeyMapping::OpenResponse,
+        (KeyModifiers::CONTROL, Char('g')) => KeyMapping::Variables,
+        (KeyModifiers::CONTROL, Char('y')) => KeyMapping::History,
+        (KeyModifiers::CONTROL, Char('b')) => KeyMapping::Diff,
+        (KeyModifiers::ALT, Char('b')) => KeyMapping::Bookmark,
+        (KeyModifiers::ALT, Char('m')) => KeyMapping::Note,
+        (KeyModifiers::ALT, Char('c')) => KeyMapping::Curl,
+        (KeyModifiers::ALT, Char('n')) => KeyMapping::NextPage,
+        (KeyModifiers::ALT, Char('p')) => KeyMapping::PrevPage,
+        (KeyModifiers::ALT, Char('j')) => KeyMapping::Jobs,
+        (KeyModifiers::ALT, Char('x')) => KeyMapping::Cancel,
+        (KeyModifiers::ALT, Char('h')) => KeyMapping::ToggleHiddenHeaders,
+        (KeyModifiers::ALT, Char('f')) => KeyMapping::FilterHeaders,
+        (KeyModifiers::CONTROL, Char('x')) => KeyMapping::ClearOverrides,
         (KeyModifiers::CONTROL, Char('r')) => KeyMapping::Reload,
         (KeyModifiers::CONTROL, Char('e')) => KeyMapping::Editor,
         (KeyModifiers::CONTROL, Char('a')) => KeyMapping::New,
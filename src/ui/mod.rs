@@ -6,6 +6,8 @@ use ratatui::{
 
 pub mod app;
 pub mod datepicker;
+pub mod jobs;
+pub mod jsonprompt;
 pub mod keymap;
 pub mod output;
 pub mod progress;
@@ -1,9 +1,14 @@
-use std::time::Duration;
+use std::{
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
 
 use crossterm::event::Event;
 use ratatui::{
+    buffer::Buffer,
     layout::Rect,
-    style::{Style, Stylize},
+    style::{Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{Block, Borders, Paragraph, Wrap},
     Frame,
@@ -15,6 +20,16 @@ use syntect::{
     util::LinesWithEndings,
 };
 use syntect_tui::into_span;
+use toml::Table;
+
+use hitman::{
+    diff::DiffLine,
+    download::is_download,
+    format::BodyFormat,
+    headers::HeaderFilter,
+    link::{find_urls, Link},
+    util::format_size,
+};
 
 use super::{
     keymap::{mapkey, KeyMapping},
@@ -28,8 +43,16 @@ pub struct HttpRequestMessage(pub String);
 pub struct HttpMessage {
     pub header: String,
     pub body: String,
+    pub format: BodyFormat,
+    pub links: Vec<Link>,
+    pub content_type: String,
+    /// The body as received, before formatting
+    pub data: Vec<u8>,
+    /// The size of the body, when the server gave it
+    pub content_length: Option<u64>,
 }
 
+#[derive(Clone)]
 pub struct HttpRequestInfo {
     request: HttpRequestMessage,
     status: RequestStatus,
@@ -39,10 +62,62 @@ impl HttpRequestInfo {
     pub fn new(request: HttpRequestMessage, status: RequestStatus) -> Self {
         Self { request, status }
     }
+
+    /// Add a part of the response as it downloads
+    pub fn receive(&mut self, update: ResponseUpdate) {
+        match (update, &mut self.status) {
+            (ResponseUpdate::Header(response), RequestStatus::Running) => {
+                self.status = RequestStatus::Receiving {
+                    response,
+                    started: Instant::now(),
+                };
+            }
+            (
+                ResponseUpdate::Chunk(chunk),
+                RequestStatus::Receiving { response, .. },
+            ) => {
+                response.data.extend_from_slice(&chunk);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn fail(&mut self, error: String) {
+        self.status = RequestStatus::Failed { error };
+    }
+
+    /// A short status, such as `200 OK`
+    pub fn summary(&self) -> String {
+        match &self.status {
+            RequestStatus::Running => "running".to_string(),
+            RequestStatus::Receiving { .. } => "receiving".to_string(),
+            RequestStatus::Complete { response, .. } => response
+                .header
+                .lines()
+                .next()
+                .and_then(|line| line.trim_start_matches("< ").split_once(' '))
+                .map(|(_, status)| status.trim().to_string())
+                .unwrap_or_default(),
+            RequestStatus::Failed { .. } => "failed".to_string(),
+        }
+    }
+}
+
+/// Parts of a response, sent while it downloads
+#[derive(Clone)]
+pub enum ResponseUpdate {
+    Header(HttpMessage),
+    Chunk(Vec<u8>),
 }
 
+#[derive(Clone)]
 pub enum RequestStatus {
     Running,
+    /// The body is shown as it arrives, until the response is complete
+    Receiving {
+        response: HttpMessage,
+        started: Instant,
+    },
     Complete {
         response: HttpMessage,
         elapsed: Duration,
@@ -58,46 +133,311 @@ pub enum Content {
     Empty,
     Preview(String),
     Request(HttpRequestInfo),
+    /// Changes between two responses, with a description of what is compared
+    Diff(String, Vec<DiffLine>),
+    /// Plain text to copy from, with a title
+    Text(String, String),
+}
+
+/// Bodies larger than this are rendered one window at a time, without syntax
+/// highlighting, so that scrolling stays smooth
+const LARGE_BODY: usize = 1 << 20;
+
+/// Where each line of a body starts, to find the visible lines of a large
+/// body without going through all of it
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .filter(|start| *start < text.len())
+            .collect();
+
+        Self { starts }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn line<'a>(&self, text: &'a str, i: usize) -> Option<&'a str> {
+        let start = *self.starts.get(i)?;
+        let end = self.starts.get(i + 1).map_or(text.len(), |end| end - 1);
+
+        Some(text[start..end].trim_end_matches(['\r', '\n']))
+    }
 }
 
 pub struct OutputView {
     content: Content,
-    scroll: (u16, u16),
+    scroll: (usize, u16),
+    /// Line index of a large body, and its URLs
+    index: Option<LineIndex>,
+    pending_index: Option<Receiver<(LineIndex, Vec<String>)>>,
     noheaders: bool,
+    /// Which response headers are shown, when headers are shown
+    header_filter: HeaderFilter,
     nowrap: bool,
     highlighter: SyntaxHighlighter,
+    urls: Vec<String>,
+    selected_url: Option<usize>,
+    /// The background request shown, if any
+    job: Option<usize>,
+}
+
+pub enum OutputIntent {
+    OpenUrl(String),
 }
 
 impl OutputView {
-    pub fn new() -> Self {
+    pub fn new(theme: Option<&str>) -> Self {
         Self {
             content: Content::Empty,
             scroll: (0, 0),
+            index: None,
+            pending_index: None,
             noheaders: false,
+            header_filter: HeaderFilter::default(),
             nowrap: false,
-            highlighter: SyntaxHighlighter::new(),
+            highlighter: SyntaxHighlighter::new(theme),
+            urls: Vec::new(),
+            selected_url: None,
+            job: None,
         }
     }
 
     pub fn show_preview(&mut self, text: String) {
         self.scroll = (0, 0);
+        self.clear_index();
+        self.set_urls(Vec::new());
+        self.job = None;
         self.content = Content::Preview(text);
     }
 
+    pub fn show_diff(&mut self, title: String, diff: Vec<DiffLine>) {
+        self.scroll = (0, 0);
+        self.clear_index();
+        self.set_urls(Vec::new());
+        self.job = None;
+        self.content = Content::Diff(title, diff);
+    }
+
+    pub fn show_text(&mut self, title: String, text: String) {
+        self.scroll = (0, 0);
+        self.clear_index();
+        self.set_urls(Vec::new());
+        self.job = None;
+        self.content = Content::Text(title, text);
+    }
+
     pub fn show_request(&mut self, info: HttpRequestInfo) {
-        if let RequestStatus::Complete { response, .. } = &info.status {
-            self.highlighter.update("json", &response.body);
+        self.clear_index();
+
+        match &info.status {
+            RequestStatus::Complete { response, .. }
+                if response.body.len() > LARGE_BODY =>
+            {
+                self.highlighter.clear();
+                self.set_urls(Vec::new());
+                self.index_in_background(response.body.clone());
+            }
+            RequestStatus::Complete { response, .. } => {
+                match response.format.syntax_extension() {
+                    Some(ext) => self.highlighter.update(ext, &response.body),
+                    None => self.highlighter.clear(),
+                }
+
+                let urls = find_urls(&response.body)
+                    .into_iter()
+                    .map(|(_, url)| url.to_string())
+                    .collect();
+                self.set_urls(urls);
+            }
+            _ => (),
         }
 
         self.scroll = (0, 0);
+        self.job = None;
         self.content = Content::Request(info);
     }
 
+    /// Show a request running in the background, to follow as it runs
+    pub fn show_job(&mut self, job: usize, info: HttpRequestInfo) {
+        self.show_request(info);
+        self.job = Some(job);
+    }
+
+    /// The background request shown, if any
+    pub fn job(&self) -> Option<usize> {
+        self.job
+    }
+
+    /// Index the lines of a large body without blocking the UI
+    fn index_in_background(&mut self, body: String) {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let urls = find_urls(&body)
+                .into_iter()
+                .map(|(_, url)| url.to_string())
+                .collect();
+            let _ = sender.send((LineIndex::new(&body), urls));
+        });
+
+        self.pending_index = Some(receiver);
+    }
+
+    /// Take the line index when it is ready
+    fn poll_index(&mut self) {
+        let Some(receiver) = &self.pending_index else {
+            return;
+        };
+
+        if let Ok((index, urls)) = receiver.try_recv() {
+            self.index = Some(index);
+            self.pending_index = None;
+            self.set_urls(urls);
+        }
+    }
+
+    fn clear_index(&mut self) {
+        self.index = None;
+        self.pending_index = None;
+    }
+
+    /// Whether the body is too large to render all at once
+    fn is_large(&self) -> bool {
+        match &self.content {
+            Content::Request(info) => match &info.status {
+                RequestStatus::Complete { response, .. } => {
+                    response.body.len() > LARGE_BODY
+                }
+                RequestStatus::Receiving { response, .. } => {
+                    response.data.len() > LARGE_BODY
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Show a response while its body downloads
+    pub fn receive(&mut self, update: ResponseUpdate) {
+        if let Content::Request(info) = &mut self.content {
+            info.receive(update);
+        }
+    }
+
+    /// The bytes received of a binary body that is downloading, and its
+    /// size if known
+    pub fn download_progress(&self) -> Option<(u64, Option<u64>)> {
+        match &self.content {
+            Content::Request(HttpRequestInfo {
+                status: RequestStatus::Receiving { response, .. },
+                ..
+            }) if is_download(response.format) => {
+                Some((response.data.len() as u64, response.content_length))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether part of a response is shown
+    pub fn is_receiving(&self) -> bool {
+        matches!(
+            &self.content,
+            Content::Request(HttpRequestInfo {
+                status: RequestStatus::Receiving { .. },
+                ..
+            })
+        )
+    }
+
+    /// The request and the links of the response currently shown
+    pub fn links(&self) -> Option<(&str, &[Link])> {
+        match &self.content {
+            Content::Request(HttpRequestInfo {
+                request,
+                status: RequestStatus::Complete { response, .. },
+            }) if !response.links.is_empty() => {
+                Some((request.0.as_str(), response.links.as_slice()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The response currently shown
+    pub fn response(&self) -> Option<&HttpMessage> {
+        match &self.content {
+            Content::Request(HttpRequestInfo {
+                status: RequestStatus::Complete { response, .. },
+                ..
+            }) => Some(response),
+            _ => None,
+        }
+    }
+
+    /// The request currently shown
+    pub fn request(&self) -> Option<&str> {
+        match &self.content {
+            Content::Request(info) => Some(info.request.0.as_str()),
+            _ => None,
+        }
+    }
+
+    fn set_urls(&mut self, mut urls: Vec<String>) {
+        let mut seen = std::collections::HashSet::new();
+        urls.retain(|url| seen.insert(url.clone()));
+
+        self.urls = urls;
+        self.selected_url = None;
+    }
+
+    fn selected_url(&self) -> Option<&String> {
+        self.selected_url.and_then(|i| self.urls.get(i))
+    }
+
+    fn select_next_url(&mut self, forward: bool) {
+        let len = self.urls.len();
+        if len == 0 {
+            return;
+        }
+
+        self.selected_url = Some(match (self.selected_url, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (len + i - 1) % len,
+        });
+    }
+
     pub fn reset(&mut self) {
         self.scroll = (0, 0);
+        self.clear_index();
+        self.set_urls(Vec::new());
+        self.job = None;
         self.content = Content::Empty;
     }
 
+    /// Take what is shown, to show it again later with [Self::restore]
+    pub fn take_content(&mut self) -> Content {
+        let content = std::mem::take(&mut self.content);
+        self.reset();
+        content
+    }
+
+    pub fn restore(&mut self, content: Content) {
+        match content {
+            Content::Request(info) => self.show_request(info),
+            content => {
+                self.reset();
+                self.content = content;
+            }
+        }
+    }
+
     pub fn scroll_up(&mut self) {
         if self.scroll.0 <= 15 {
             self.scroll.0 = 0;
@@ -110,13 +450,36 @@ impl OutputView {
         self.scroll.0 += 15;
     }
 
-    fn title(&self) -> &'static str {
-        let title = match &self.content {
-            Content::Empty => "",
-            Content::Preview(_) => "Preview",
-            Content::Request(_) => "Output",
-        };
-        title
+    fn title(&self) -> String {
+        match &self.content {
+            Content::Empty => String::new(),
+            Content::Preview(_) => "Preview".to_string(),
+            Content::Diff(title, _) | Content::Text(title, _) => title.clone(),
+            Content::Request(_) => match &self.index {
+                Some(index) => format!(
+                    "Output (line {} of {})",
+                    self.scroll.0 + 1,
+                    index.len()
+                ),
+                None if self.pending_index.is_some() => {
+                    "Output (indexing lines)".to_string()
+                }
+                None => "Output".to_string(),
+            },
+        }
+    }
+
+    /// Hide the response headers in the `hide_headers` setting
+    pub fn set_hidden_headers(&mut self, settings: &Table) {
+        self.header_filter = HeaderFilter::from_settings(settings);
+    }
+
+    /// Only show the response headers matching a pattern, or all headers
+    /// when the pattern is empty
+    pub fn filter_headers(&mut self, pattern: &str) {
+        let pattern = pattern.trim();
+        self.header_filter.only =
+            Some(pattern.to_string()).filter(|p| !p.is_empty());
     }
 
     fn mode_string(&self) -> String {
@@ -124,6 +487,9 @@ impl OutputView {
         if !self.noheaders {
             s.push('H');
         }
+        if self.header_filter.is_active() {
+            s.push('F');
+        }
         if !self.nowrap {
             s.push('W');
         }
@@ -131,8 +497,69 @@ impl OutputView {
         s
     }
 
-    fn make_lines(&self) -> Vec<Line> {
+    /// The lines of a body, from `start`. Large bodies use the line index
+    /// when it is ready.
+    fn body_lines<'a>(
+        &self,
+        body: &'a str,
+        start: usize,
+        count: usize,
+    ) -> Vec<Line<'a>> {
+        match &self.index {
+            Some(index) => (start..(start + count).min(index.len()))
+                .filter_map(|i| index.line(body, i))
+                .map(Line::from)
+                .collect(),
+            None => body
+                .lines()
+                .skip(start)
+                .take(count)
+                .map(Line::from)
+                .collect(),
+        }
+    }
+
+    /// The lines of a response head, without the headers that are hidden
+    fn header_lines<'a>(&self, header: &'a str) -> Vec<Line<'a>> {
+        let green = Style::new().green();
+        let mut lines = header.lines();
+        let mut out: Vec<Line> = lines
+            .next()
+            .map(|l| Line::styled(l, green))
+            .into_iter()
+            .collect();
+        if self.noheaders {
+            return out;
+        }
+
+        let mut hidden = 0;
+        for line in lines {
+            let name = line
+                .trim_start_matches("< ")
+                .split_once(':')
+                .map_or("", |(name, _)| name);
+            if name.is_empty() || self.header_filter.shows(name) {
+                out.push(Line::styled(line, green));
+            } else {
+                hidden += 1;
+            }
+        }
+        if hidden > 0 {
+            out.push(Line::styled(
+                format!("< ({hidden} headers hidden)"),
+                Style::new().dark_gray(),
+            ));
+        }
+
+        out
+    }
+
+    /// All lines of the content, or only the lines of a window given as
+    /// `(start, height)`
+    fn make_lines(&self, window: Option<(usize, usize)>) -> Vec<Line<'_>> {
         let mut lines: Vec<Line> = Vec::new();
+        let (start, height) = window.unwrap_or((0, usize::MAX));
+        let mut head_len = 0;
 
         match &self.content {
             Content::Empty => {}
@@ -152,26 +579,40 @@ impl OutputView {
 
                 match &info.status {
                     RequestStatus::Running => (),
-                    RequestStatus::Complete { response, .. } => {
-                        let green = Style::new().green();
-                        let res_lines = response
-                            .header
-                            .lines()
-                            .take(if self.noheaders { 1 } else { usize::MAX })
-                            .map(|line| Line::styled(line, green));
-                        lines.extend(res_lines);
+                    RequestStatus::Receiving { response, .. } => {
+                        lines.extend(self.header_lines(&response.header));
+
+                        head_len = lines.len();
 
-                        if let Some(highlighted_lines) =
+                        // Shown by the progress bar instead
+                        if !is_download(response.format) {
+                            let body = String::from_utf8_lossy(&response.data);
+                            let skip = start.saturating_sub(head_len);
+                            lines.extend(
+                                body.lines()
+                                    .skip(skip)
+                                    .take(height)
+                                    .map(|line| Line::from(line.to_string())),
+                            );
+                        }
+                    }
+                    RequestStatus::Complete { response, .. } => {
+                        lines.extend(self.header_lines(&response.header));
+
+                        head_len = lines.len();
+                        if window.is_some() {
+                            let skip = start.saturating_sub(head_len);
+                            lines.extend(self.body_lines(
+                                &response.body,
+                                skip,
+                                height,
+                            ));
+                        } else if let Some(highlighted_lines) =
                             self.highlighter.lines()
                         {
                             lines.extend(highlighted_lines);
                         } else {
-                            lines.extend(
-                                response
-                                    .body
-                                    .lines()
-                                    .map(|line| Line::from(line)),
-                            );
+                            lines.extend(response.body.lines().map(Line::from));
                         }
                     }
                     RequestStatus::Failed { error } => {
@@ -190,24 +631,82 @@ impl OutputView {
                     .map(|line| Line::styled(format!("> {line}"), blue));
                 lines.extend(req_lines);
             }
+            Content::Text(_, text) => {
+                lines.extend(text.lines().map(Line::from));
+            }
+            Content::Diff(_, diff) => {
+                lines.extend(diff.iter().map(|line| match line {
+                    DiffLine::Same(text) => Line::from(format!("  {text}")),
+                    DiffLine::Added(text) => {
+                        Line::styled(format!("+ {text}"), Style::new().green())
+                    }
+                    DiffLine::Removed(text) => {
+                        Line::styled(format!("- {text}"), Style::new().red())
+                    }
+                    DiffLine::Skipped(n) => Line::styled(
+                        format!("@@ {n} unchanged lines @@"),
+                        Style::new().dark_gray(),
+                    ),
+                }));
+            }
         }
+
+        // The body is already cut to the window, but not the lines before it
+        if window.is_some() {
+            lines.drain(..start.min(head_len));
+            lines.truncate(height);
+        }
+
         lines
     }
 }
 
 impl Component for OutputView {
     fn render_ui(&mut self, frame: &mut Frame, area: Rect) {
-        let title_bottom = if let Content::Request(info) = &self.content {
-            if let RequestStatus::Complete { elapsed, .. } = &info.status {
-                format!("Elapsed: {:.2?}", elapsed)
-            } else {
-                String::new()
+        self.poll_index();
+
+        let title_bottom = match &self.content {
+            Content::Request(HttpRequestInfo {
+                status: RequestStatus::Complete { elapsed, .. },
+                ..
+            }) => format!("Elapsed: {:.2?}", elapsed),
+            Content::Request(HttpRequestInfo {
+                status: RequestStatus::Receiving { response, started },
+                ..
+            }) => {
+                let received = response.data.len() as u64;
+                let secs = started.elapsed().as_secs_f64().max(0.001);
+                format!(
+                    "Received: {} ({}/s)",
+                    format_size(received),
+                    format_size((received as f64 / secs) as u64)
+                )
             }
-        } else {
-            String::new()
+            _ => String::new(),
         };
 
-        let lines = self.make_lines();
+        let title_bottom = match self.selected_url() {
+            Some(url) => format!("Enter: GET {url}"),
+            None => title_bottom,
+        };
+
+        let block = Block::default()
+            .title(self.title())
+            .title_bottom(title_bottom)
+            .title_bottom(Line::from(self.mode_string()).right_aligned())
+            .borders(Borders::ALL)
+            .border_set(ratatui::symbols::border::ROUNDED);
+        let inner = block.inner(area);
+
+        // Large bodies only render the visible lines, and are scrolled by
+        // lines instead of by rows
+        let (lines, scroll) = if self.is_large() {
+            let window = (self.scroll.0, inner.height as usize);
+            (self.make_lines(Some(window)), (0, self.scroll.1))
+        } else {
+            let rows = self.scroll.0.min(u16::MAX as usize) as u16;
+            (self.make_lines(None), (rows, self.scroll.1))
+        };
         let para = Paragraph::new(Text::from(lines));
 
         let para = if self.nowrap {
@@ -216,24 +715,81 @@ impl Component for OutputView {
             para.wrap(Wrap::default())
         };
 
-        let para = para.scroll(self.scroll).block(
-            Block::default()
-                .title(self.title())
-                .title_bottom(title_bottom)
-                .title_bottom(Line::from(self.mode_string()).right_aligned())
-                .borders(Borders::ALL)
-                .border_set(ratatui::symbols::border::ROUNDED),
-        );
+        frame.render_widget(para.scroll(scroll).block(block), area);
+
+        let selected = self.selected_url().cloned();
+        for y in inner.top()..inner.bottom() {
+            render_hyperlinks(
+                frame.buffer_mut(),
+                inner,
+                y,
+                selected.as_deref(),
+            );
+        }
+    }
+}
 
-        frame.render_widget(para, area);
+/// Make URLs in a row of the buffer clickable, with OSC 8 escape sequences.
+/// Ratatui counts the escape sequences in the width of a cell, so each cell
+/// gets two characters of the URL, and the next cell is skipped (see
+/// https://github.com/ratatui/ratatui/issues/902).
+fn render_hyperlinks(
+    buffer: &mut Buffer,
+    area: Rect,
+    y: u16,
+    selected: Option<&str>,
+) {
+    let mut row = String::new();
+    let mut columns = Vec::new();
+    for x in area.left()..area.right() {
+        let symbol = buffer[(x, y)].symbol();
+        if symbol.chars().count() != 1 {
+            // Wide characters don't map to single cells
+            return;
+        }
+        for _ in 0..symbol.len() {
+            columns.push(x);
+        }
+        row.push_str(symbol);
+    }
+
+    for (start, url) in find_urls(&row) {
+        let first = columns[start];
+        let cells = url.chars().count() as u16;
+
+        if selected == Some(url) {
+            for x in first..first + cells {
+                buffer[(x, y)]
+                    .set_style(Style::new().add_modifier(Modifier::REVERSED));
+            }
+        }
+
+        for (i, pair) in url.chars().collect::<Vec<_>>().chunks(2).enumerate() {
+            let x = first + i as u16 * 2;
+            let text: String = pair.iter().collect();
+            let hyperlink = format!("\x1B]8;;{url}\x07{text}\x1B]8;;\x07");
+            buffer[(x, y)].set_symbol(&hyperlink);
+            if pair.len() == 2 {
+                buffer[(x + 1, y)].set_skip(true);
+            }
+        }
     }
 }
 
 impl InteractiveComponent for OutputView {
-    type Intent = ();
+    type Intent = OutputIntent;
 
-    fn handle_event(&mut self, event: &Event) -> Option<()> {
+    fn handle_event(&mut self, event: &Event) -> Option<OutputIntent> {
         match mapkey(event) {
+            KeyMapping::NextLink => {
+                self.select_next_url(true);
+            }
+            KeyMapping::PrevLink => {
+                self.select_next_url(false);
+            }
+            KeyMapping::Accept => {
+                return self.selected_url().cloned().map(OutputIntent::OpenUrl);
+            }
             KeyMapping::ScrollUp => {
                 self.scroll_up();
             }
@@ -246,6 +802,10 @@ impl InteractiveComponent for OutputView {
             KeyMapping::ToggleHeaders => {
                 self.noheaders = !self.noheaders;
             }
+            KeyMapping::ToggleHiddenHeaders => {
+                self.header_filter.show_hidden =
+                    !self.header_filter.show_hidden;
+            }
             _ => (),
         }
 
@@ -261,12 +821,15 @@ struct SyntaxHighlighter {
 }
 
 impl SyntaxHighlighter {
-    pub fn new() -> Self {
+    pub fn new(theme: Option<&str>) -> Self {
         let ps = SyntaxSet::load_defaults_newlines();
 
         // Load built-in theme
         let ts = ThemeSet::load_defaults();
-        let mut theme = ts.themes["Solarized (dark)"].clone();
+        let mut theme = theme
+            .and_then(|name| ts.themes.get(name))
+            .unwrap_or(&ts.themes["Solarized (dark)"])
+            .clone();
 
         // Set theme background to transparent
         let mut bg = theme.settings.background.unwrap_or(Color::BLACK);
@@ -280,30 +843,32 @@ impl SyntaxHighlighter {
         }
     }
 
-    fn lines(&self) -> Option<Vec<Line>> {
-        match &self.cache {
-            Some(lines) => Some(
-                lines
-                    .iter()
-                    .map(|line| {
-                        let line_spans: Vec<Span> = line
-                            .iter()
-                            .filter_map(|seg| {
-                                into_span((seg.0, seg.1.as_str())).ok()
-                            })
-                            .collect();
-
-                        Line::from(line_spans)
-                    })
-                    .collect(),
-            ),
-            None => None,
-        }
+    fn lines(&self) -> Option<Vec<Line<'_>>> {
+        self.cache.as_ref().map(|lines| {
+            lines
+                .iter()
+                .map(|line| {
+                    let line_spans: Vec<Span> = line
+                        .iter()
+                        .filter_map(|seg| {
+                            into_span((seg.0, seg.1.as_str())).ok()
+                        })
+                        .collect();
+
+                    Line::from(line_spans)
+                })
+                .collect()
+        })
+    }
+
+    fn clear(&mut self) {
+        self.cache = None;
     }
 
     fn update(&mut self, extension: &str, text: &str) {
         let Some(syntax) = self.syntax_set.find_syntax_by_extension(extension)
         else {
+            self.clear();
             return;
         };
 
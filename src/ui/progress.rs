@@ -1,4 +1,4 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use ratatui::{
     layout::Alignment,
@@ -7,26 +7,72 @@ use ratatui::{
     widgets::{Block, BorderType, Clear, Paragraph},
 };
 
+use hitman::download::progress_bar;
+
 use super::{centered, Component};
 
-pub struct Progress;
+pub struct Progress {
+    /// The time left before the request times out
+    remaining: Option<Duration>,
+    /// The bytes of a binary body received so far, and its size if known
+    download: Option<(u64, Option<u64>)>,
+}
+
+impl Progress {
+    pub fn new(remaining: Option<Duration>) -> Self {
+        Self {
+            remaining,
+            download: None,
+        }
+    }
+
+    /// Progress of a binary body that is downloading
+    pub fn download(received: u64, total: Option<u64>) -> Self {
+        Self {
+            remaining: None,
+            download: Some((received, total)),
+        }
+    }
+}
 
 impl Component for Progress {
     fn render_ui(&mut self, frame: &mut Frame, area: Rect) {
+        if let Some((received, total)) = self.download {
+            let block = Block::bordered()
+                .title("Downloading")
+                .title_alignment(Alignment::Center)
+                .border_type(BorderType::Rounded);
+            let bar = Paragraph::new(progress_bar(received, total))
+                .centered()
+                .block(block)
+                .style(Style::new().yellow());
+
+            let inner_area = centered(area, 52, 3);
+            frame.render_widget(Clear, inner_area);
+            frame.render_widget(bar, inner_area);
+            return;
+        }
+
         let t = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map(|d| d.as_millis())
             .unwrap_or(0);
 
         let pos = (t as usize / 50) % PATTERN.len();
+        let mut block = Block::bordered()
+            .title("Running")
+            .title_alignment(Alignment::Center)
+            .border_type(BorderType::Rounded);
+        if let Some(remaining) = self.remaining {
+            // Rounded up, to reach zero when the request times out
+            let secs =
+                remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            block = block.title_bottom(format!("Timeout in {secs}s"));
+        }
+
         let loading = Paragraph::new(PATTERN[pos])
             .centered()
-            .block(
-                Block::bordered()
-                    .title("Running")
-                    .title_alignment(Alignment::Center)
-                    .border_type(BorderType::Rounded),
-            )
+            .block(block)
             .style(Style::new().yellow());
 
         let inner_area = centered(area, 18, 3);
@@ -1,5 +1,8 @@
+use std::collections::{HashMap, HashSet};
+
 use crossterm::event::Event;
 use fuzzy_matcher::skim::SkimMatcherV2;
+use hitman::frecency::match_score;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Style, Stylize},
@@ -18,9 +21,46 @@ use super::{
     Component, InteractiveComponent, PromptComponent, PromptIntent,
 };
 
+/// A request file, the target it is pinned to, if any, and how often and
+/// recently it was used
+#[derive(Default, Clone, PartialEq)]
+pub struct RequestItem {
+    pub path: String,
+    pub pin: Option<String>,
+    pub frecency: i64,
+    /// Another request has the same method and URL
+    pub duplicate: bool,
+}
+
+impl SelectItem for RequestItem {
+    fn text(&self) -> String {
+        self.path.clone()
+    }
+
+    fn rank(&self) -> i64 {
+        self.frecency
+    }
+
+    fn render<'a>(&self) -> ListItem<'a> {
+        self.render_highlighted(&[])
+    }
+
+    fn render_highlighted<'a>(&self, highlight: &[usize]) -> ListItem<'a> {
+        let mut line = highlighted_line(self.text(), highlight);
+        if let Some(pin) = &self.pin {
+            line.push_span(Span::from(format!(" @{pin}")).dark_gray());
+        }
+        if self.duplicate {
+            line.push_span(Span::from(" (duplicate)").yellow());
+        }
+
+        line.into()
+    }
+}
+
 #[derive(Default)]
 pub struct RequestSelector {
-    pub selector: Select<String>,
+    selector: Select<RequestItem>,
 }
 
 impl RequestSelector {
@@ -34,12 +74,38 @@ impl RequestSelector {
         }
     }
 
-    pub fn populate(&mut self, reqs: Vec<String>) {
-        self.selector.set_items(reqs);
+    pub fn populate(
+        &mut self,
+        reqs: Vec<(String, i64)>,
+        mut pins: HashMap<String, String>,
+        duplicates: &HashSet<String>,
+    ) {
+        let items = reqs
+            .into_iter()
+            .map(|(path, frecency)| RequestItem {
+                pin: pins.remove(&path),
+                duplicate: duplicates.contains(&path),
+                path,
+                frecency,
+            })
+            .collect();
+        self.selector.set_items(items);
     }
 
-    pub fn try_select(&mut self, selected: &String) {
-        self.selector.try_select(selected);
+    pub fn selected_path(&self) -> Option<String> {
+        self.selector.selected_item().map(|it| it.path.clone())
+    }
+
+    pub fn try_select(&mut self, selected: &str) {
+        let item = self
+            .selector
+            .items
+            .iter()
+            .find(|it| it.path == selected)
+            .cloned();
+        if let Some(item) = item {
+            self.selector.try_select(&item);
+        }
     }
 }
 
@@ -53,7 +119,15 @@ impl InteractiveComponent for RequestSelector {
     type Intent = SelectIntent<String>;
 
     fn handle_event(&mut self, event: &Event) -> Option<Self::Intent> {
-        self.selector.handle_event(event)
+        let intent = match self.selector.handle_event(event)? {
+            SelectIntent::Abort => SelectIntent::Abort,
+            SelectIntent::Accept(item) => SelectIntent::Accept(item.path),
+            SelectIntent::Change(item) => {
+                SelectIntent::Change(item.map(|it| it.path))
+            }
+        };
+
+        Some(intent)
     }
 }
 
@@ -61,6 +135,10 @@ fn format_item<'a>(text: String, indexes: &[usize]) -> ListItem<'a> {
     // FIXME: Make '.http' part dark gray
     // For this, we need to implement SelectItem specifically for request paths
 
+    highlighted_line(text, indexes).into()
+}
+
+fn highlighted_line<'a>(text: String, indexes: &[usize]) -> Line<'a> {
     Line::from(
         text.chars()
             .enumerate()
@@ -73,12 +151,17 @@ fn format_item<'a>(text: String, indexes: &[usize]) -> ListItem<'a> {
             })
             .collect::<Vec<_>>(),
     )
-    .into()
 }
 
 pub trait SelectItem {
     fn text(&self) -> String;
 
+    /// Items with a higher rank are shown first, and win over equally good
+    /// matches when searching
+    fn rank(&self) -> i64 {
+        0
+    }
+
     fn render<'a>(&self) -> ListItem<'a> {
         self.text().into()
     }
@@ -108,6 +191,9 @@ where
     items: Vec<T>,
     list_state: ListState,
     search_input: Input,
+    /// Used instead of the fuzzy search, when given. Gives `None` when the
+    /// search can't be used, to fall back to the fuzzy search.
+    filter: Option<fn(&T, &str) -> Option<bool>>,
 }
 
 #[derive(Debug, Clone)]
@@ -131,9 +217,15 @@ where
             items,
             list_state: ListState::default().with_selected(Some(0)),
             search_input: Input::default(),
+            filter: None,
         }
     }
 
+    pub fn with_filter(mut self, filter: fn(&T, &str) -> Option<bool>) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
     pub fn set_items(&mut self, items: Vec<T>) {
         self.items = items;
         self.list_state.select(None);
@@ -171,7 +263,21 @@ where
     fn get_filtered_items(&self) -> Vec<(&T, Option<Vec<usize>>)> {
         let term = self.search_input.value();
         if term.is_empty() {
-            self.items.iter().map(|i| (i, None)).collect()
+            let mut items: Vec<_> = self.items.iter().collect();
+            items.sort_by_key(|i| -i.rank());
+
+            items.into_iter().map(|i| (i, None)).collect()
+        } else if let Some(matches) = self.filter.and_then(|filter| {
+            self.items
+                .iter()
+                .map(|item| filter(item, term).map(|m| (item, m)))
+                .collect::<Option<Vec<_>>>()
+        }) {
+            matches
+                .into_iter()
+                .filter(|(_, matches)| *matches)
+                .map(|(item, _)| (item, None))
+                .collect()
         } else {
             let matcher = SkimMatcherV2::default();
 
@@ -179,9 +285,11 @@ where
                 .items
                 .iter()
                 .filter_map(|s| {
-                    matcher
-                        .fuzzy(&s.text(), term, true)
-                        .map(|(score, indexes)| (s, score, indexes))
+                    matcher.fuzzy(&s.text(), term, true).map(
+                        |(score, indexes)| {
+                            (s, match_score(score, s.rank()), indexes)
+                        },
+                    )
                 })
                 .collect();
 
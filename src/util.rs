@@ -1,5 +1,10 @@
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use toml::Value;
 
 const TRUNC_COLUMN: usize = 92;
 
@@ -10,6 +15,125 @@ pub fn truncate(s: &str) -> String {
     }
 }
 
+/// Parse a duration like `500ms`, `30s`, `5m`, `2h` or `7d`. A plain number is
+/// taken as seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let pos = s.find(|c: char| !(c.is_ascii_digit() || c == '.'));
+    let (num, unit) = s.split_at(pos.unwrap_or(s.len()));
+    let num: f64 = num.parse().ok()?;
+
+    let secs = match unit.trim() {
+        "ms" => num / 1000.0,
+        "" | "s" => num,
+        "m" => num * 60.0,
+        "h" => num * 3600.0,
+        "d" => num * 24.0 * 3600.0,
+        _ => return None,
+    };
+
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// Format a duration in the largest whole units, like `2d 4h` or `90s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let units = [("d", 24 * 3600), ("h", 3600), ("m", 60), ("s", 1)];
+
+    let parts: Vec<String> = units
+        .iter()
+        .scan(secs, |rest, &(unit, size)| {
+            let n = *rest / size;
+            *rest %= size;
+            Some((n, unit))
+        })
+        .filter(|(n, _)| *n > 0)
+        .take(2)
+        .map(|(n, unit)| format!("{n}{unit}"))
+        .collect();
+
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Parse a size like `512KB`, `100MB` or `1GB`. A plain number is taken as
+/// bytes.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let pos = s.find(|c: char| !c.is_ascii_digit());
+    let (num, unit) = s.split_at(pos.unwrap_or(s.len()));
+    let num: u64 = num.parse().ok()?;
+
+    let factor = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None,
+    };
+
+    Some(num * factor)
+}
+
+/// Format a size in bytes with the largest fitting unit, like `1.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    let units = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)];
+
+    match units.iter().find(|(_, size)| bytes >= *size) {
+        Some((unit, size)) => {
+            format!("{:.1} {unit}", bytes as f64 / *size as f64)
+        }
+        None => format!("{bytes} B"),
+    }
+}
+
+/// Get a duration from a config value, given either as a number of seconds,
+/// or as a string accepted by `parse_duration`.
+pub fn duration_value(value: &Value) -> Option<Duration> {
+    match value {
+        Value::Integer(i) if *i >= 0 => Some(Duration::from_secs(*i as u64)),
+        Value::Float(f) if *f >= 0.0 => Some(Duration::from_secs_f64(*f)),
+        Value::String(s) => parse_duration(s),
+        _ => None,
+    }
+}
+
+/// Open a file with the default application of the operating system
+pub fn open_with_system_viewer(path: &Path) -> io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/c", "start", ""]);
+        c
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command.arg(path).spawn()?;
+
+    Ok(())
+}
+
+/// A command that runs a line with the shell of the operating system
+pub fn shell_command(line: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    command.arg(line);
+
+    command
+}
+
 pub trait IterExt
 where
     Self: Iterator + Sized,
@@ -85,6 +209,39 @@ mod tests {
         assert_eq!(truncate(&long), expected);
     }
 
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 << 20), "3.0 MB");
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("5 days"), None);
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m 30s");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1h");
+        assert_eq!(format_duration(Duration::from_secs(100_000)), "1d 3h");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("2KB"), Some(2048));
+        assert_eq!(parse_size("100 MB"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_size("lots"), None);
+    }
+
     #[test]
     fn counted_numbers() {
         let values: Vec<i32> = vec![100, 200, 200, 300, 200];
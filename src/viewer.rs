@@ -0,0 +1,153 @@
+use std::{fs, path::PathBuf, process::Command};
+
+use anyhow::{Context, Result};
+use toml::{Table, Value};
+
+use crate::{
+    format::{magic_type, BodyFormat},
+    trust::ensure_trusted,
+};
+
+/// Find the command for viewing a response of the given content type. A
+/// viewer can be configured per content type in `[_settings.viewers]`, such
+/// as `"text/html" = "firefox"` or `"image/*" = "feh"`. Otherwise the
+/// configured editor, or `$EDITOR`, is used. Configured commands only run
+/// once they are trusted.
+pub fn viewer_command(
+    settings: &Table,
+    content_type: &str,
+) -> Result<Option<String>> {
+    match configured_viewer(settings, content_type) {
+        Some((what, viewer)) => {
+            ensure_trusted(&what, viewer)?;
+            Ok(Some(viewer.to_string()))
+        }
+        None => Ok(std::env::var("EDITOR").ok()),
+    }
+}
+
+/// The viewer or editor given in the settings, with what it is used for
+fn configured_viewer<'a>(
+    settings: &'a Table,
+    content_type: &str,
+) -> Option<(String, &'a str)> {
+    let mime = mime_type(content_type);
+    let wildcard = format!("{}/*", mime.split('/').next().unwrap_or(""));
+
+    let viewer = match settings.get("viewers") {
+        Some(Value::Table(viewers)) => viewers
+            .get(&mime)
+            .map(|viewer| (mime.clone(), viewer))
+            .or_else(|| viewers.get(&wildcard).map(|v| (wildcard, v)))
+            .and_then(|(mime, viewer)| Some((mime, viewer.as_str()?))),
+        _ => None,
+    };
+
+    match viewer {
+        Some((mime, viewer)) => Some((format!("Viewer for {mime}"), viewer)),
+        None => settings
+            .get("editor")
+            .and_then(Value::as_str)
+            .map(|editor| ("Editor".to_string(), editor)),
+    }
+}
+
+/// A file extension matching the content of a response, so that viewers
+/// recognize the format
+pub fn file_extension(content_type: &str, data: &[u8]) -> &'static str {
+    let mime = mime_type(content_type);
+
+    match BodyFormat::from_content_type(content_type) {
+        BodyFormat::Json => "json",
+        BodyFormat::Csv => "csv",
+        BodyFormat::Yaml => "yaml",
+        BodyFormat::Image | BodyFormat::Binary => {
+            magic_type(data).map(|(_, ext)| ext).unwrap_or("bin")
+        }
+        BodyFormat::Text if mime.contains("html") => "html",
+        BodyFormat::Text if mime.contains("xml") => "xml",
+        BodyFormat::Text => "txt",
+    }
+}
+
+/// Save a response body to a temporary file, and open it in the viewer for
+/// its content type. Waits until the viewer exits.
+pub fn open_response(
+    settings: &Table,
+    content_type: &str,
+    data: &[u8],
+) -> Result<()> {
+    let viewer = viewer_command(settings, content_type)?
+        .context("No viewer configured, and EDITOR is not set")?;
+
+    let path = std::env::temp_dir().join(format!(
+        "hitman-response-{}.{}",
+        std::process::id(),
+        file_extension(content_type, data)
+    ));
+    fs::write(&path, data)?;
+
+    run_viewer(&viewer, path)
+}
+
+fn run_viewer(viewer: &str, path: PathBuf) -> Result<()> {
+    let mut args = viewer.split_whitespace();
+    let program = args.next().context("Invalid viewer setting")?;
+
+    Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start {viewer}"))?;
+
+    Ok(())
+}
+
+fn mime_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_viewer_for_content_type() {
+        let settings: Table = toml::from_str(
+            r#"
+            editor = "nvim"
+
+            [viewers]
+            "text/html" = "firefox"
+            "image/*" = "feh"
+            "#,
+        )
+        .unwrap();
+
+        let viewer = |ct| configured_viewer(&settings, ct).unwrap();
+
+        assert_eq!(
+            viewer("text/html; charset=utf-8"),
+            ("Viewer for text/html".to_string(), "firefox")
+        );
+        assert_eq!(
+            viewer("image/png"),
+            ("Viewer for image/*".to_string(), "feh")
+        );
+        assert_eq!(viewer("application/json"), ("Editor".to_string(), "nvim"));
+        assert_eq!(configured_viewer(&Table::new(), "text/html"), None);
+    }
+
+    #[test]
+    fn uses_extension_matching_content() {
+        assert_eq!(file_extension("application/json", b"{}"), "json");
+        assert_eq!(file_extension("text/html", b"<html>"), "html");
+        assert_eq!(file_extension("application/pdf", b"%PDF-1.4"), "pdf");
+        assert_eq!(file_extension("text/plain", b"hello"), "txt");
+    }
+}
@@ -0,0 +1,337 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        handshake::client::{Request, Response},
+        http::{
+            header::USER_AGENT as USER_AGENT_HEADER, HeaderName, HeaderValue,
+        },
+        protocol::CloseFrame,
+        Message,
+    },
+};
+
+use crate::{directive::Directives, request::USER_AGENT};
+
+/// Request files with this extension open a WebSocket connection
+pub const WEBSOCKET_EXTENSION: &str = "ws";
+
+/// Headers of the handshake, which can't be given in the request
+const HANDSHAKE_HEADERS: [&str; 5] = [
+    "host",
+    "upgrade",
+    "connection",
+    "sec-websocket-key",
+    "sec-websocket-version",
+];
+
+/// A request that opens a WebSocket connection, and sends the body as the
+/// first message
+#[derive(Debug, Clone, PartialEq)]
+pub struct WsRequest {
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// What happens on a connection, in order
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsEvent {
+    /// The status line and headers of the handshake response
+    Connected(Vec<String>),
+    Sent(String),
+    Received(WsMessage),
+    /// The connection was closed, with the code and reason from the server
+    Closed(Option<String>),
+}
+
+/// Whether a request opens a WebSocket connection, which is the case for
+/// `.ws` files, the `WS` method, and `ws://` or `wss://` URLs
+pub fn is_websocket_request(file_path: &Path, request: &str) -> bool {
+    if file_path
+        .extension()
+        .is_some_and(|ext| ext == WEBSOCKET_EXTENSION)
+    {
+        return true;
+    }
+
+    let mut parts = request_line(request).split_whitespace().take(2);
+    let opens_websocket = |part: &str| {
+        part.eq_ignore_ascii_case("WS")
+            || part.starts_with("ws://")
+            || part.starts_with("wss://")
+    };
+
+    parts.any(opens_websocket)
+}
+
+/// The first line of a request that isn't a comment
+pub(crate) fn request_line(request: &str) -> &str {
+    request
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default()
+}
+
+/// Parse a WebSocket request. The method is optional, and `http` URLs are
+/// connected to as `ws`.
+pub fn parse_ws_request(buf: &str) -> Result<WsRequest> {
+    let buf = Directives::parse(buf)?.request();
+    let mut lines = buf
+        .lines()
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'));
+
+    let line = lines.next().context("Invalid input: URL not found")?;
+    let mut parts = line.split_whitespace();
+    let url = match (parts.next(), parts.next()) {
+        (Some(url), _) if url.contains("://") => url,
+        (_, Some(url)) => url,
+        _ => bail!("Invalid input: URL not found"),
+    };
+
+    let mut url = Url::parse(url)?;
+    let scheme = match url.scheme() {
+        "ws" | "http" => "ws",
+        "wss" | "https" => "wss",
+        other => bail!("Unsupported WebSocket scheme: {other}"),
+    };
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow!("Invalid WebSocket URL: {url}"))?;
+
+    let mut headers = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("Invalid header: {line}"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+    Ok(WsRequest { url, headers, body })
+}
+
+/// Connect, and send the body of the request followed by each outgoing
+/// message, until the server closes the connection. Everything that happens
+/// is sent as events.
+pub async fn run_session(
+    request: WsRequest,
+    mut outgoing: UnboundedReceiver<String>,
+    events: UnboundedSender<WsEvent>,
+) -> Result<()> {
+    let host = request.url.host_str().unwrap_or_default().to_string();
+    let (stream, response) = connect_async(handshake_request(&request)?)
+        .await
+        .with_context(|| format!("Failed to connect to {host}"))?;
+    let _ = events.send(WsEvent::Connected(response_head(&response)));
+
+    let (mut writer, mut reader) = stream.split();
+
+    if !request.body.is_empty() {
+        writer.send(Message::Text(request.body.clone())).await?;
+        let _ = events.send(WsEvent::Sent(request.body.clone()));
+    }
+
+    // Pings are answered, and fragmented messages put together, by the
+    // connection itself
+    let mut outgoing_open = true;
+    loop {
+        tokio::select! {
+            message = reader.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let _ = events.send(WsEvent::Received(WsMessage::Text(text)));
+                }
+                Some(Ok(Message::Binary(data))) => {
+                    let message = WsMessage::Binary(data);
+                    let _ = events.send(WsEvent::Received(message));
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    let _ = events.send(WsEvent::Closed(close_reason(frame)));
+                    break;
+                }
+                Some(Ok(_)) => (),
+                Some(Err(err)) => return Err(err.into()),
+                None => {
+                    let _ = events.send(WsEvent::Closed(None));
+                    break;
+                }
+            },
+            text = outgoing.recv(), if outgoing_open => match text {
+                Some(text) => {
+                    writer.send(Message::Text(text.clone())).await?;
+                    let _ = events.send(WsEvent::Sent(text));
+                }
+                // Nothing more to send, but the server may still have more
+                // to say
+                None => outgoing_open = false,
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// The status code and reason of a close frame
+fn close_reason(frame: Option<CloseFrame>) -> Option<String> {
+    let frame = frame?;
+    let code = u16::from(frame.code);
+
+    Some(format!("{code} {}", frame.reason).trim_end().to_string())
+}
+
+/// The handshake request, with the headers of the request. The headers of
+/// the handshake itself are set by the connection.
+fn handshake_request(request: &WsRequest) -> Result<Request> {
+    let mut handshake = request.url.as_str().into_client_request()?;
+    let headers = handshake.headers_mut();
+
+    headers.insert(USER_AGENT_HEADER, HeaderValue::from_static(USER_AGENT));
+    for (name, value) in &request.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header: {name}"))?;
+        if HANDSHAKE_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        if name == USER_AGENT_HEADER {
+            headers.remove(USER_AGENT_HEADER);
+        }
+        headers.append(name, HeaderValue::from_str(value)?);
+    }
+
+    Ok(handshake)
+}
+
+/// The status line and headers of the handshake response
+fn response_head(response: &Response) -> Vec<String> {
+    let status = response.status();
+    let mut head = vec![format!(
+        "{:?} {} {}",
+        response.version(),
+        status.as_str(),
+        status.canonical_reason().unwrap_or_default()
+    )];
+    head.extend(response.headers().iter().map(|(name, value)| {
+        format!("{name}: {}", String::from_utf8_lossy(value.as_bytes()))
+    }));
+
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_websocket_requests() {
+        let http = "GET https://example.com/ HTTP/1.1\n";
+
+        assert!(is_websocket_request(Path::new("chat.ws"), http));
+        assert!(is_websocket_request(
+            Path::new("chat.http"),
+            "WS wss://example.com/chat\n"
+        ));
+        assert!(is_websocket_request(
+            Path::new("chat.http"),
+            "# Chat\nGET ws://localhost:8080/chat HTTP/1.1\n"
+        ));
+        assert!(!is_websocket_request(Path::new("get.http"), http));
+    }
+
+    #[test]
+    fn parses_websocket_requests() {
+        let request = parse_ws_request(
+            "WS https://example.com/chat?room=1\nAuthorization: Bearer abc\n\n{\"hello\": true}\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.url.as_str(), "wss://example.com/chat?room=1");
+        assert_eq!(
+            request.headers,
+            vec![("Authorization".into(), "Bearer abc".into())]
+        );
+        assert_eq!(request.body, "{\"hello\": true}");
+
+        let request = parse_ws_request("ws://localhost:8080/\n").unwrap();
+        assert_eq!(request.url.as_str(), "ws://localhost:8080/");
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn adds_headers_to_handshake() {
+        let request = parse_ws_request(
+            "WS wss://example.com/chat\nAuthorization: Bearer abc\nUpgrade: h2c\n",
+        )
+        .unwrap();
+
+        let handshake = handshake_request(&request).unwrap();
+
+        let headers = handshake.headers();
+        assert_eq!(headers["authorization"], "Bearer abc");
+        assert_eq!(headers["upgrade"], "websocket");
+        assert_eq!(headers["user-agent"], USER_AGENT);
+    }
+
+    #[test]
+    fn reads_close_reasons() {
+        let frame = CloseFrame {
+            code: 1000.into(),
+            reason: "bye".into(),
+        };
+
+        assert_eq!(close_reason(Some(frame)), Some("1000 bye".into()));
+        assert_eq!(close_reason(None), None);
+    }
+
+    #[tokio::test]
+    async fn talks_with_servers() {
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let message = ws.next().await.unwrap().unwrap();
+            ws.send(message).await.unwrap();
+            ws.close(None).await.unwrap();
+        });
+
+        let request =
+            parse_ws_request(&format!("WS ws://{addr}/\n\nhi")).unwrap();
+        let (_sender, outgoing) = tokio::sync::mpsc::unbounded_channel();
+        let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+
+        run_session(request, outgoing, events).await.unwrap();
+
+        let mut all = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            all.push(event);
+        }
+        assert!(
+            matches!(&all[0], WsEvent::Connected(head) if head[0].contains("101"))
+        );
+        assert_eq!(
+            all[1..],
+            [
+                WsEvent::Sent("hi".into()),
+                WsEvent::Received(WsMessage::Text("hi".into())),
+                WsEvent::Closed(None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+use toml::Value;
+
+use crate::env::read_user_config;
+
+const PROJECTS_KEY: &str = "_projects";
+
+/// Find the projects listed in the user configuration file, as a table of
+/// project names and root directories:
+///
+/// ```toml
+/// [_projects]
+/// payments = "~/src/payments/api"
+/// ```
+pub fn find_projects() -> Result<Vec<(String, PathBuf)>> {
+    let config = read_user_config()?;
+
+    let projects = match config.get(PROJECTS_KEY) {
+        Some(Value::Table(t)) => t
+            .iter()
+            .filter_map(|(name, path)| {
+                Some((name.clone(), expand_home(path.as_str()?)))
+            })
+            .collect(),
+        Some(_) => bail!("Invalid {} section", PROJECTS_KEY),
+        None => Vec::new(),
+    };
+
+    Ok(projects)
+}
+
+pub fn find_project(name: &str) -> Result<PathBuf> {
+    let projects = find_projects()?;
+
+    match projects.into_iter().find(|(n, _)| n == name) {
+        Some((_, path)) => Ok(path),
+        None => bail!("Project `{}` not found in user config", name),
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_absolute_paths() {
+        assert_eq!(expand_home("/src/api"), PathBuf::from("/src/api"));
+    }
+
+    #[test]
+    fn expands_home_directory() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(expand_home("~/src/api"), home.join("src/api"));
+    }
+}